@@ -48,10 +48,16 @@ function addEntry() {
         }
         updatePending();
     }
-    const message = {
-        data : value_to_add
+    if (document.getElementById("structured").checked) {
+        let object;
+        try { object = JSON.parse(value_to_add); } catch (e) { status("Tried to add "+value_to_add+" which is not valid JSON : "+e); return; }
+        getWebJSON("submit_structured_leaf",success,failure,JSON.stringify({object:object}),"application/json")
+    } else {
+        const message = {
+            data : value_to_add
+        }
+        getWebJSON("submit_leaf",success,failure,JSON.stringify(message),"application/json")
     }
-    getWebJSON("submit_leaf",success,failure,JSON.stringify(message),"application/json")
 }
 
 window.onload = function () {
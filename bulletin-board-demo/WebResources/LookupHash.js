@@ -2,6 +2,71 @@
 
 const hashForThisPage = (new URL(document.location.href)).searchParams.get("hash");
 
+/** The start of the data of a structured leaf, whose fields are committed to individually. */
+const STRUCTURED_LEAF_PREFIX = "bulletin-board-structured-leaf:";
+
+/**
+ * Get the structure of a structured leaf from its data.
+ * @param leaf{{timestamp:number,data:string?,structured:boolean?}} The leaf
+ * @returns {?{fields:[{name:string,salt:string?,value:string?,commitment:string?}]}} The structure, or null if not an uncensored structured leaf.
+ */
+function parseStructuredLeaf(leaf) {
+    if (leaf.structured && leaf.data && leaf.data.startsWith(STRUCTURED_LEAF_PREFIX)) {
+        try { return JSON.parse(leaf.data.substring(STRUCTURED_LEAF_PREFIX.length)); } catch (e) { console.log(e); }
+    }
+    return null;
+}
+
+/**
+ * Whether a leaf is censored, or is a structured leaf with some fields censored.
+ * @param leaf{{timestamp:number,data:string?,structured:boolean?}}
+ * @returns {boolean}
+ */
+function isCensoredOrRedacted(leaf) {
+    if (!(leaf.hasOwnProperty("data") && leaf.data!==null)) return true;
+    const structured = parseStructuredLeaf(leaf);
+    return structured!==null && structured.fields.some(field => field.hasOwnProperty("commitment"));
+}
+
+/**
+ * Compute the sha256 hash of some bytes.
+ * @param bytes{number[]}
+ * @returns {Promise<?string>} The hash as a hex string, or null if not available on this browser.
+ */
+async function sha256Hex(bytes) {
+    if (!(crypto && crypto.subtle)) return null;
+    const hashBuffer = await crypto.subtle.digest('SHA-256', Uint8Array.from(bytes));
+    return Array.from(new Uint8Array(hashBuffer)).map(b => b.toString(16).padStart(2, '0')).join('');
+}
+
+/**
+ * Make a table explaining how the commitment to each field of a structured leaf was computed.
+ * @param where{HTMLElement} Where the text should go.
+ * @param structured{{fields:[{name:string,salt:string?,value:string?,commitment:string?}]}}
+ * @returns {Promise<string[]>} The commitment for each field, or null if it could not be computed.
+ */
+async function explainFieldCommitments(where,structured) {
+    add(where,"h5").innerText="How the commitment to each field was computed";
+    add(where,"div").innerText="Field commitment = sha256(04|salt|name|00|value). A censored field just has its commitment.";
+    const table = add(where,"table");
+    const commitments = [];
+    for (const field of structured.fields) {
+        const tr = add(table,"tr");
+        add(tr,"td").innerText=field.name;
+        let commitment = field.commitment;
+        if (field.hasOwnProperty("commitment")) {
+            add(tr,"td","Censored").innerText="CENSORED";
+        } else {
+            add(tr,"td").innerText="salt "+field.salt+" value "+field.value;
+            const encoder = new TextEncoder();
+            commitment = await sha256Hex([4,...field.salt.match(/[\da-f]{2}/gi).map(h => parseInt(h, 16)),...encoder.encode(field.name),0,...encoder.encode(field.value)]);
+        }
+        add(tr,"td").innerText=commitment || "Not available on your browser";
+        commitments.push(commitment);
+    }
+    return commitments;
+}
+
 function addTimestamp(where,timestamp) {
     const div = add(where,"div");
     const date = new Date(timestamp*1000);
@@ -11,7 +76,7 @@ function addTimestamp(where,timestamp) {
 /**
  * Make a div explaining where the hash for some explained hash has come from
  * @param where{HTMLElement} Where the text should go.
- * @param source{{Leaf:{timestamp:number,data:string?},Branch:{left:string,right:string},Root:{timestamp:number,prior:string?,elements:[string]}}}
+ * @param source{{Leaf:{timestamp:number,data:string?},Branch:{left:string,right:string},Root:{timestamp:number,prior:string?,elements:[string],skips:[string]?}}}
  * @param expecting{?string} Optional hash that we are expecting.
  * @param lookingFor{?string} Optional hash that should be included in this explanation and which we want to highlight.
  * @returns {Promise<{computedHashLocation:HTMLElement,foundLookingFor:HTMLElement}>} The HTML element containing the computed hash, and the element we were looking for. Or null if not found.
@@ -46,8 +111,18 @@ async function explainHowHashWasComputed(where,source,expecting,lookingFor) {
         hexStrBuildup = "";
     }
     let censored = false;
-    if (source.Leaf) {
-        hashHex("Leaf prefix",0,1);
+    const structured = source.Leaf ? parseStructuredLeaf(source.Leaf) : null;
+    if (structured) {
+        const commitments = await explainFieldCommitments(where,structured);
+        hashHex("Structured leaf prefix",3,1);
+        hashHex("Timestamp",source.Leaf.timestamp,8);
+        for (let i=0;i<commitments.length;i++) {
+            if (commitments[i]) hashHex("Field "+structured.fields[i].name+" commitment",commitments[i],32);
+            else censored=true; // can't compute on this browser.
+        }
+    } else if (source.Leaf) {
+        if (source.Leaf.structured) hashHex("Structured leaf prefix",3,1);
+        else hashHex("Leaf prefix",0,1);
         hashHex("Timestamp",source.Leaf.timestamp,8);
         if (source.Leaf.hasOwnProperty("data") && source.Leaf.data!==null) {
             hashString("Posted Data",source.Leaf.data);
@@ -64,10 +139,15 @@ async function explainHowHashWasComputed(where,source,expecting,lookingFor) {
         hashHex("Left hash",source.Branch.left,32);
         hashHex("Right hash",source.Branch.right,32);
     } else if (source.Root) {
-        hashHex("Published Root prefix",2,1);
+        const skips = source.Root.skips || [];
+        hashHex(skips.length?"Published Root with skips prefix":"Published Root prefix",skips.length?5:2,1);
         hashHex("Timestamp",source.Root.timestamp,8);
         if (source.Root.prior) hashHex("Prior",source.Root.prior,32);
         else hashHex("Prior",0,1);
+        if (skips.length) {
+            hashHex("Number of skips",skips.length,1);
+            for (const skip of skips) hashHex("Skip",skip,32);
+        }
         for (const element of source.Root.elements) hashHex("Element",element,32);
     }
     // hash it.
@@ -244,7 +324,14 @@ function describeNode(where,source) {
     if (source.Leaf) {
         add(where, "h5").innerText = "Leaf";
         addTimestamp(where, source.Leaf.timestamp);
-        if (source.Leaf.hasOwnProperty("data") && source.Leaf.data!==null) {
+        const structured = parseStructuredLeaf(source.Leaf);
+        if (structured) {
+            add(where, "div").innerText = "Structured data, with fields :";
+            for (const field of structured.fields) {
+                if (field.hasOwnProperty("value")) add(where, "div").innerText = field.name+" : "+field.value;
+                else add(where, "div", "Censored").innerText = field.name+" is censored!";
+            }
+        } else if (source.Leaf.hasOwnProperty("data") && source.Leaf.data!==null) {
             add(where, "div").innerText = "Data : " + source.Leaf.data;
         } else {
             add(where,"div","Censored").innerText = "Data is censored!"
@@ -258,7 +345,8 @@ function describeNode(where,source) {
     if (source.Root) {
         add(where, "h5").innerText = "Published Root";
         addTimestamp(where, source.Root.timestamp);
-        addLabeledLink(add(where, "div"), "Prior ", source.Root.prior)
+        addLabeledLink(add(where, "div"), "Prior ", source.Root.prior);
+        (source.Root.skips || []).forEach((skip,i) => addLabeledLink(add(where, "div"), (2<<i)+" roots back ", skip));
         for (const line of source.Root.elements) addLabeledLink(add(where, "div"), "Reference ", line);
     }
 
@@ -284,6 +372,10 @@ window.onload = function () {
                     const censorButton = add(status,"button");
                     censorButton.innerText="Censor!";
                     censorButton.onclick=function () {
+                        const reason = prompt("Reason, or legal basis, for censorship");
+                        if (reason===null) return;
+                        const authority = prompt("Who ordered the censorship");
+                        if (authority===null) return;
                         function success(data) {
                             if (data.Err) { alert(data.Err); }
                             else {
@@ -291,9 +383,43 @@ window.onload = function () {
                             }
                         }
                         function failure(message) { alert(message); }
-                        getWebJSON("censor_leaf",success,failure,JSON.stringify({leaf_to_censor:hashForThisPage}),"application/json")
+                        getWebJSON("censor_leaf",success,failure,JSON.stringify({leaf_to_censor:hashForThisPage,reason:reason,authority:authority}),"application/json")
+                    }
+                    const structured = parseStructuredLeaf(result.source.Leaf);
+                    if (structured) for (const field of structured.fields) if (field.hasOwnProperty("value")) {
+                        const redactButton = add(status,"button");
+                        redactButton.innerText="Censor field "+field.name;
+                        redactButton.onclick=function () {
+                            const reason = prompt("Reason, or legal basis, for censorship of field "+field.name);
+                            if (reason===null) return;
+                            const authority = prompt("Who ordered the censorship");
+                            if (authority===null) return;
+                            function success(data) {
+                                if (data.Err) { alert(data.Err); }
+                                else { location.reload(); }
+                            }
+                            function failure(message) { alert(message); }
+                            getWebJSON("redact_field",success,failure,JSON.stringify({leaf:hashForThisPage,field:field.name,reason:reason,authority:authority}),"application/json")
+                        }
+                    }
+                }
+                if (result.source.Leaf&&isCensoredOrRedacted(result.source.Leaf)) {
+                    const uncensorButton = add(status,"button");
+                    uncensorButton.innerText="Uncensor";
+                    uncensorButton.onclick=function () {
+                        const data = prompt("Original text of the censored leaf (for a structured leaf, the full original data)");
+                        if (data===null) return;
+                        const reason = prompt("Reason for reversing the censorship");
+                        if (reason===null) return;
+                        const authority = prompt("Who ordered the censorship reversed");
+                        if (authority===null) return;
+                        function success(data) {
+                            if (data.Err) { alert(data.Err); }
+                            else { location.reload(); }
+                        }
+                        function failure(message) { alert(message); }
+                        getWebJSON("uncensor_leaf",success,failure,JSON.stringify({leaf_to_uncensor:hashForThisPage,data:data,reason:reason,authority:authority}),"application/json")
                     }
-
                 }
                 if (!result.source.Root) { // add text proof option
                     const textProofDiv = add(status,"div");
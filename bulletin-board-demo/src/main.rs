@@ -5,51 +5,137 @@
 use actix_web::{HttpServer, middleware, web};
 use actix_web::web::Json;
 use actix_web::{get, post};
-use merkle_tree_bulletin_board::BulletinBoard;
+use merkle_tree_bulletin_board::async_board::{AsyncBulletinBoard, AsyncBulletinBoardReader};
 use merkle_tree_bulletin_board::hash::HashValue;
 use async_std::sync::Mutex;
-use merkle_tree_bulletin_board::hash_history::{HashInfo, FullProof};
+use merkle_tree_bulletin_board::hash_history::{HashInfo, FullProof, LeafCountProof, OrderingProof, AncestryProof, Timestamp};
 use std::path::PathBuf;
 use merkle_tree_bulletin_board::backend_flatfile::BackendFlatfile;
-use merkle_tree_bulletin_board::backend_journal::{BackendJournal, StartupVerification};
+use merkle_tree_bulletin_board::backend_journal::StartupVerification;
+use merkle_tree_bulletin_board::async_backend_journal::AsyncBackendJournal;
+use merkle_tree_bulletin_board::async_backend_blocking::BackendBlocking;
+use merkle_tree_bulletin_board::receipt::{ReceiptEvidence, ReceiptPublicKey, ReceiptSigner, SignedReceipt, SubmissionReceipt};
+use merkle_tree_bulletin_board::retention::RetentionPolicy;
+use merkle_tree_bulletin_board::scheduler::{PublicationSchedule, run_publication_schedule_async};
+use merkle_tree_bulletin_board::validation::{CharacterSet, MaxLength};
+use merkle_tree_bulletin_board::content_index::DuplicatePolicy;
+use std::io::Write;
+use std::time::Duration;
 
-type OurBulletinBoard = BulletinBoard<BackendJournal<BackendFlatfile>>; // the actual type of the bulletin board.
+type OurBulletinBoard = AsyncBulletinBoard<AsyncBackendJournal<BackendBlocking<BackendFlatfile>>>; // the actual type of the bulletin board. There is one of these, which does all the writing.
+type OurBulletinBoardReader = AsyncBulletinBoardReader<BackendFlatfile>; // used for reading, without needing to lock the writer.
+type OurDatabase = BackendBlocking<BackendFlatfile>; // the file the bulletin board is stored in, used to compact it after censorship.
 
 #[derive(serde::Deserialize)]
 struct Publish {
     data : String,
+    /// if present, a retried submission with the same key returns the original leaf rather than adding a new one.
+    #[serde(default)]
+    idempotency_key : Option<String>,
 }
 
 #[derive(serde::Deserialize)]
 struct Censor {
     leaf_to_censor : HashValue,
+    /// the reason, or legal basis, for the censorship.
+    reason : String,
+    /// who ordered the censorship.
+    authority : String,
 }
 
 #[post("/submit_leaf")]
 async fn submit_leaf(command : web::Json<Publish>, datasource: web::Data<Mutex<OurBulletinBoard>>) -> Json<Result<HashValue,String>> {
-    Json(datasource.lock().await.submit_leaf(&command.data).map_err(|e|e.to_string()))
+    let mut board = datasource.lock().await;
+    let res = match &command.idempotency_key {
+        Some(key) => board.submit_leaf_with_idempotency_key(&command.data,key).await,
+        None => board.submit_leaf(&command.data).await,
+    };
+    Json(res.map_err(|e|e.to_string()))
+}
+
+#[post("/submit_leaf_with_receipt")]
+async fn submit_leaf_with_receipt(command : web::Json<Publish>, datasource: web::Data<Mutex<OurBulletinBoard>>) -> Json<Result<SignedReceipt,String>> {
+    Json(datasource.lock().await.submit_leaf_with_receipt(&command.data).await.map_err(|e|e.to_string()))
+}
+
+#[get("/get_receipt_public_key")]
+async fn get_receipt_public_key(public_key: web::Data<Option<ReceiptPublicKey>>) -> Json<Result<ReceiptPublicKey,String>> {
+    Json(public_key.ok_or_else(||"This bulletin board does not give receipts".to_string()))
+}
+
+#[post("/get_receipt_evidence")]
+async fn get_receipt_evidence(receipt : web::Json<SubmissionReceipt>, reader: web::Data<OurBulletinBoardReader>) -> Json<Result<ReceiptEvidence,String>> {
+    Json(reader.get_receipt_evidence(&receipt).await.map_err(|e|e.to_string()))
 }
 
 #[post("/censor_leaf")]
-async fn censor_leaf(command : web::Json<Censor>, datasource: web::Data<Mutex<OurBulletinBoard>>) -> Json<Result<(),String>> {
-    Json(datasource.lock().await.censor_leaf(command.leaf_to_censor).map_err(|e|e.to_string()))
+async fn censor_leaf(command : web::Json<Censor>, datasource: web::Data<Mutex<OurBulletinBoard>>, database: web::Data<OurDatabase>) -> Json<Result<HashValue,String>> {
+    let res = datasource.lock().await.censor_leaf(command.leaf_to_censor,&command.reason,&command.authority).await;
+    if res.is_ok() { // remove the censored text from the file now, rather than when the next root is published.
+        if let Err(e) = database.write(|b|b.compact()).await { eprintln!("Could not compact database after censorship : {}",e); }
+    }
+    Json(res.map_err(|e|e.to_string()))
+}
+
+#[derive(serde::Deserialize)]
+struct PublishStructured {
+    /// the fields of the structured leaf.
+    object : serde_json::Map<String,serde_json::Value>,
+}
+
+#[post("/submit_structured_leaf")]
+async fn submit_structured_leaf(command : web::Json<PublishStructured>, datasource: web::Data<Mutex<OurBulletinBoard>>) -> Json<Result<HashValue,String>> {
+    Json(datasource.lock().await.submit_structured_leaf(&command.object).await.map_err(|e|e.to_string()))
+}
+
+#[derive(serde::Deserialize)]
+struct RedactField {
+    leaf : HashValue,
+    /// the name of the field of the structured leaf to censor.
+    field : String,
+    /// the reason, or legal basis, for the censorship.
+    reason : String,
+    /// who ordered the censorship.
+    authority : String,
+}
+
+#[post("/redact_field")]
+async fn redact_field(command : web::Json<RedactField>, datasource: web::Data<Mutex<OurBulletinBoard>>, database: web::Data<OurDatabase>) -> Json<Result<HashValue,String>> {
+    let res = datasource.lock().await.redact_field(command.leaf,&command.field,&command.reason,&command.authority).await;
+    if res.is_ok() { // remove the redacted text from the file now, rather than when the next root is published.
+        if let Err(e) = database.write(|b|b.compact()).await { eprintln!("Could not compact database after redaction : {}",e); }
+    }
+    Json(res.map_err(|e|e.to_string()))
+}
+
+#[derive(serde::Deserialize)]
+struct Uncensor {
+    leaf_to_uncensor : HashValue,
+    /// the original text of the leaf.
+    data : String,
+    reason : String,
+    authority : String,
 }
 
+#[post("/uncensor_leaf")]
+async fn uncensor_leaf(command : web::Json<Uncensor>, datasource: web::Data<Mutex<OurBulletinBoard>>) -> Json<Result<HashValue,String>> {
+    Json(datasource.lock().await.uncensor_leaf(command.leaf_to_uncensor,&command.data,&command.reason,&command.authority).await.map_err(|e|e.to_string()))
+}
 
 #[get("/get_parentless_unpublished_hash_values")]
-async fn get_parentless_unpublished_hash_values(datasource: web::Data<Mutex<OurBulletinBoard>>) -> Json<Result<Vec<HashValue>,String>> {
-    Json(datasource.lock().await.get_parentless_unpublished_hash_values().map_err(|e|e.to_string()))
+async fn get_parentless_unpublished_hash_values(reader: web::Data<OurBulletinBoardReader>) -> Json<Result<Vec<HashValue>,String>> {
+    Json(reader.get_parentless_unpublished_hash_values().await.map_err(|e|e.to_string()))
 }
 
 #[get("/get_most_recent_published_root")]
-async fn get_most_recent_published_root(datasource: web::Data<Mutex<OurBulletinBoard>>) -> Json<Result<Option<HashValue>,String>> {
-    Json(datasource.lock().await.get_most_recent_published_root().map_err(|e|e.to_string()))
+async fn get_most_recent_published_root(reader: web::Data<OurBulletinBoardReader>) -> Json<Result<Option<HashValue>,String>> {
+    Json(reader.get_most_recent_published_root().await.map_err(|e|e.to_string()))
 }
 
 
 #[post("/order_new_published_root")]
 async fn order_new_published_root(datasource: web::Data<Mutex<OurBulletinBoard>>) -> Json<Result<HashValue,String>> {
-    Json(datasource.lock().await.order_new_published_root().map_err(|e|e.to_string()))
+    Json(datasource.lock().await.order_new_published_root().await.map_err(|e|e.to_string()))
 }
 
 #[derive(serde::Deserialize)]
@@ -58,18 +144,129 @@ struct QueryHash {
 }
 
 #[get("/get_hash_info")]
-async fn get_hash_info(query:web::Query<QueryHash>, datasource: web::Data<Mutex<OurBulletinBoard>>) -> Json<Result<HashInfo,String>> {
-    Json(datasource.lock().await.get_hash_info(query.hash).map_err(|e|e.to_string()))
+async fn get_hash_info(query:web::Query<QueryHash>, reader: web::Data<OurBulletinBoardReader>) -> Json<Result<HashInfo,String>> {
+    Json(reader.get_hash_info(query.hash).await.map_err(|e|e.to_string()))
 }
 
 #[get("/get_proof_chain")]
-async fn get_proof_chain(query:web::Query<QueryHash>, datasource: web::Data<Mutex<OurBulletinBoard>>) -> Json<Result<FullProof,String>> {
-    Json(datasource.lock().await.get_proof_chain(query.hash).map_err(|e|e.to_string()))
+async fn get_proof_chain(query:web::Query<QueryHash>, reader: web::Data<OurBulletinBoardReader>) -> Json<Result<FullProof,String>> {
+    Json(reader.get_proof_chain(query.hash).await.map_err(|e|e.to_string()))
+}
+
+/// The proof chain to the first published root that included the hash, or None if not yet published.
+#[get("/first_including_root")]
+async fn first_including_root(query:web::Query<QueryHash>, reader: web::Data<OurBulletinBoardReader>) -> Json<Result<Option<FullProof>,String>> {
+    Json(reader.first_including_root(query.hash).await.map_err(|e|e.to_string()))
+}
+
+#[derive(serde::Deserialize)]
+struct QueryOrdering {
+    earlier : HashValue,
+    later : HashValue,
+}
+
+#[get("/get_ordering_proof")]
+async fn get_ordering_proof(query:web::Query<QueryOrdering>, reader: web::Data<OurBulletinBoardReader>) -> Json<Result<OrderingProof,String>> {
+    Json(reader.get_ordering_proof(query.earlier,query.later).await.map_err(|e|e.to_string()))
+}
+
+/// `earlier` and `later` are published roots.
+#[get("/get_ancestry_proof")]
+async fn get_ancestry_proof(query:web::Query<QueryOrdering>, reader: web::Data<OurBulletinBoardReader>) -> Json<Result<AncestryProof,String>> {
+    Json(reader.get_ancestry_proof(query.earlier,query.later).await.map_err(|e|e.to_string()))
+}
+
+#[get("/get_leaf_count_proof")]
+async fn get_leaf_count_proof(query:web::Query<QueryHash>, reader: web::Data<OurBulletinBoardReader>) -> Json<Result<LeafCountProof,String>> {
+    Json(reader.get_leaf_count_proof(query.hash).await.map_err(|e|e.to_string()))
+}
+
+#[get("/get_leaf_count")]
+async fn get_leaf_count(reader: web::Data<OurBulletinBoardReader>) -> Json<Result<u64,String>> {
+    Json(reader.get_leaf_count().await.map_err(|e|e.to_string()))
+}
+
+#[derive(serde::Deserialize)]
+struct QueryIndex {
+    index : u64,
+}
+
+#[get("/get_leaf_by_index")]
+async fn get_leaf_by_index(query:web::Query<QueryIndex>, reader: web::Data<OurBulletinBoardReader>) -> Json<Result<Option<HashValue>,String>> {
+    Json(reader.get_leaf_by_index(query.index).await.map_err(|e|e.to_string()))
+}
+
+/// The most leaves that can be asked for in one call to list_leaves.
+const MAX_LIST_LEAVES : u64 = 1000;
+
+#[derive(serde::Deserialize)]
+struct ListLeaves {
+    start : u64,
+    /// at most [MAX_LIST_LEAVES].
+    count : u64,
+}
+
+#[get("/list_leaves")]
+async fn list_leaves(query:web::Query<ListLeaves>, reader: web::Data<OurBulletinBoardReader>) -> Json<Result<Vec<HashValue>,String>> {
+    Json(reader.list_leaves(query.start,query.count.min(MAX_LIST_LEAVES)).await.map_err(|e|e.to_string()))
+}
+
+#[derive(serde::Deserialize)]
+struct TimeRange {
+    /// seconds since the epoch, inclusive.
+    t0 : Timestamp,
+    /// seconds since the epoch, exclusive.
+    t1 : Timestamp,
+}
+
+#[get("/list_leaves_between")]
+async fn list_leaves_between(query:web::Query<TimeRange>, reader: web::Data<OurBulletinBoardReader>) -> Json<Result<Vec<HashValue>,String>> {
+    Json(reader.list_leaves_between(query.t0,query.t1).await.map_err(|e|e.to_string()))
+}
+
+#[derive(serde::Deserialize)]
+struct QueryTime {
+    /// seconds since the epoch.
+    t : Timestamp,
+}
+
+#[get("/get_published_root_at")]
+async fn get_published_root_at(query:web::Query<QueryTime>, reader: web::Data<OurBulletinBoardReader>) -> Json<Result<Option<HashValue>,String>> {
+    Json(reader.get_published_root_at(query.t).await.map_err(|e|e.to_string()))
+}
+
+#[derive(serde::Deserialize)]
+struct FindLeaves {
+    /// the original text of the leaves to find.
+    data : String,
+}
+
+/// A POST rather than a GET as the text may be long.
+#[post("/find_leaves_by_data")]
+async fn find_leaves_by_data(command : web::Json<FindLeaves>, reader: web::Data<OurBulletinBoardReader>) -> Json<Result<Vec<HashValue>,String>> {
+    Json(reader.find_leaves_by_data(&command.data).await.map_err(|e|e.to_string()))
+}
+
+#[derive(serde::Deserialize)]
+struct WaitForInclusion {
+    hash : HashValue,
+    /// the maximum time to wait, in seconds. Limited to MAX_WAIT_FOR_INCLUSION_SECONDS.
+    timeout : u64,
+}
+
+/// The longest a client may ask /wait_for_inclusion to wait.
+const MAX_WAIT_FOR_INCLUSION_SECONDS : u64 = 300;
+
+#[get("/wait_for_inclusion")]
+async fn wait_for_inclusion(query:web::Query<WaitForInclusion>, reader: web::Data<OurBulletinBoardReader>, datasource: web::Data<Mutex<OurBulletinBoard>>) -> Json<Result<FullProof,String>> {
+    let events = datasource.lock().await.subscribe();
+    let timeout = Duration::from_secs(query.timeout.min(MAX_WAIT_FOR_INCLUSION_SECONDS));
+    Json(reader.wait_for_inclusion(&events,query.hash,timeout).await.map_err(|e|e.to_string()))
 }
 
 #[get("/get_all_published_roots")]
-async fn get_all_published_roots(datasource: web::Data<Mutex<OurBulletinBoard>>) -> Json<Result<Vec<HashValue>,String>> {
-    Json(datasource.lock().await.get_all_published_roots().map_err(|e|e.to_string()))
+async fn get_all_published_roots(reader: web::Data<OurBulletinBoardReader>) -> Json<Result<Vec<HashValue>,String>> {
+    Json(reader.get_all_published_roots().await.map_err(|e|e.to_string()))
 }
 
 /// find the path containing web resources, static web files that will be served.
@@ -86,24 +283,156 @@ fn find_web_resources() -> PathBuf {
     panic!("Could not find WebResources. Please run in a directory containing it.")
 }
 
+/// Options given on the command line.
+struct Options {
+    retention_policy : Option<RetentionPolicy>,
+    publication_schedule : PublicationSchedule,
+    /// If receipts are given, the promised maximum delay in seconds before a leaf is included in a published root.
+    receipt_delay : Option<u64>,
+    /// What to do with a submission whose data is already on the board.
+    duplicate_policy : DuplicatePolicy,
+    /// Whether published roots include skip references to earlier roots.
+    root_skip_list : bool,
+}
+
+/// Get the options from the command line arguments.
+///   --retention-days N : censor leaves more than N days old.
+///   --legal-hold HASH : do not censor the given leaf. May be repeated.
+///   --publish-every N : publish a new root every N seconds, if anything has changed.
+///   --publish-after-leaves N : publish a new root when N leaves have been added.
+///   --receipt-delay N : give signed receipts promising inclusion in a published root within N seconds.
+///   --duplicates allow|reject|return-existing : what to do with data that is already on the board. Default allow.
+///   --root-skip-list : include skip references to earlier roots in published roots, for short ancestry proofs.
+fn options_from_args() -> anyhow::Result<Options> {
+    let mut max_age_days : Option<u64> = None;
+    let mut legal_holds = std::collections::HashSet::new();
+    let mut publication_schedule = PublicationSchedule::default();
+    let mut receipt_delay : Option<u64> = None;
+    let mut duplicate_policy = DuplicatePolicy::Allow;
+    let mut root_skip_list = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(||anyhow::anyhow!("Missing value for {}",arg));
+        match arg.as_str() {
+            "--retention-days" => { max_age_days = Some(value()?.parse()?); }
+            "--legal-hold" => { legal_holds.insert(value()?.parse::<HashValue>()?); }
+            "--publish-every" => { publication_schedule.interval = Some(Duration::from_secs(value()?.parse()?)); }
+            "--publish-after-leaves" => { publication_schedule.max_leaves = Some(value()?.parse()?); }
+            "--receipt-delay" => { receipt_delay = Some(value()?.parse()?); }
+            "--duplicates" => {
+                duplicate_policy = match value()?.as_str() {
+                    "allow" => DuplicatePolicy::Allow,
+                    "reject" => DuplicatePolicy::Reject,
+                    "return-existing" => DuplicatePolicy::ReturnExisting,
+                    other => anyhow::bail!("Unknown duplicate policy {}",other),
+                };
+            }
+            "--root-skip-list" => { root_skip_list = true; }
+            _ => anyhow::bail!("Unknown argument {}",arg),
+        }
+    }
+    let retention_policy = max_age_days.map(|days|RetentionPolicy{ max_age: days*24*60*60, authority: "Demo retention policy".to_string(), legal_holds });
+    if let Some(delay) = receipt_delay {
+        // publish often enough to keep the promise made in receipts, with some margin.
+        let interval = Duration::from_secs((delay/2).max(1));
+        if publication_schedule.interval.map(|i|i>interval).unwrap_or(true) { publication_schedule.interval=Some(interval); }
+    }
+    Ok(Options{ retention_policy, publication_schedule, receipt_delay, duplicate_policy, root_skip_list })
+}
+
+/// Load the receipt signing key from receipt_key.txt (hex), creating it if it does not exist.
+fn load_or_create_receipt_signer(delay:u64) -> anyhow::Result<ReceiptSigner> {
+    let path = std::path::Path::new("receipt_key.txt");
+    if path.exists() {
+        let mut secret_key = [0u8;32];
+        hex::decode_to_slice(std::fs::read_to_string(path)?.trim(),&mut secret_key)?;
+        Ok(ReceiptSigner::new(secret_key,delay))
+    } else {
+        let signer = ReceiptSigner::generate(delay)?;
+        std::fs::write(path,hex::encode(signer.secret_key()))?;
+        Ok(signer)
+    }
+}
+
+/// Apply the retention policy hourly, appending each report as a line of JSON to retention_reports.jsonl for audit.
+/// The database is compacted after anything is censored, so the expired text is removed from the file promptly.
+async fn apply_retention_policy_periodically(policy:RetentionPolicy,datasource: web::Data<Mutex<OurBulletinBoard>>,database: web::Data<OurDatabase>) {
+    loop {
+        match datasource.lock().await.apply_retention_policy(&policy).await {
+            Ok(report) => {
+                if !report.redactions.is_empty() {
+                    if let Err(e) = database.write(|b|b.compact()).await { eprintln!("Could not compact database after retention : {}",e); }
+                }
+                let line = serde_json::to_string(&report).unwrap()+"\n";
+                if let Err(e) = std::fs::OpenOptions::new().append(true).create(true).open("retention_reports.jsonl").and_then(|mut f|f.write_all(line.as_bytes())) {
+                    eprintln!("Could not write retention report : {}",e);
+                }
+            }
+            Err(e) => eprintln!("Error applying retention policy : {}",e),
+        }
+        async_std::task::sleep(Duration::from_secs(60*60)).await;
+    }
+}
 
 #[actix_web::main]
 async fn main() -> anyhow::Result<()> {
+    let options = options_from_args()?;
     let backend_flatfile = BackendFlatfile::new("database.csv")?;
-    let backend_journal = BackendJournal::new(backend_flatfile,"journal",StartupVerification::SanityCheckAndRepairPending)?;
-    let datasource = web::Data::new(Mutex::new(BulletinBoard::new(backend_journal)?));
+    let backend = BackendBlocking::new(backend_flatfile);
+    let reader = web::Data::new(AsyncBulletinBoardReader::new(backend.clone()));
+    let database = web::Data::new(backend.clone());
+    let backend_journal = AsyncBackendJournal::new(backend,"journal",StartupVerification::SanityCheckAndRepairPending).await?;
+    let mut board = AsyncBulletinBoard::new(backend_journal).await?;
+    board.add_validator(MaxLength(10_000));
+    board.add_validator(CharacterSet::NoControlCharacters);
+    board.set_duplicate_policy(options.duplicate_policy);
+    board.set_root_skip_list(options.root_skip_list);
+    let mut receipt_public_key : Option<ReceiptPublicKey> = None;
+    if let Some(delay) = options.receipt_delay {
+        let signer = load_or_create_receipt_signer(delay)?;
+        receipt_public_key = Some(signer.public_key());
+        board.set_receipt_signer(signer);
+    }
+    let receipt_public_key = web::Data::new(receipt_public_key);
+    let datasource = web::Data::new(Mutex::new(board));
+    if let Some(policy) = options.retention_policy {
+        async_std::task::spawn(apply_retention_policy_periodically(policy,datasource.clone(),database.clone()));
+    }
+    if options.publication_schedule!=PublicationSchedule::default() {
+        async_std::task::spawn(run_publication_schedule_async(datasource.clone().into_inner(),options.publication_schedule,|e|eprintln!("Error publishing scheduled root : {}",e)));
+    }
     println!("Running demo webserver on http://localhost:8090");
     HttpServer::new(move|| {
         actix_web::App::new()
             .app_data(datasource.clone())
+            .app_data(reader.clone())
+            .app_data(database.clone())
+            .app_data(receipt_public_key.clone())
             .wrap(middleware::Compress::default())
             .service(submit_leaf)
+            .service(submit_leaf_with_receipt)
+            .service(get_receipt_public_key)
+            .service(get_receipt_evidence)
+            .service(submit_structured_leaf)
             .service(censor_leaf)
+            .service(redact_field)
+            .service(uncensor_leaf)
             .service(get_parentless_unpublished_hash_values)
             .service(get_most_recent_published_root)
             .service(order_new_published_root)
             .service(get_hash_info)
             .service(get_proof_chain)
+            .service(first_including_root)
+            .service(find_leaves_by_data)
+            .service(get_leaf_count)
+            .service(get_leaf_count_proof)
+            .service(get_ordering_proof)
+            .service(get_ancestry_proof)
+            .service(get_leaf_by_index)
+            .service(list_leaves)
+            .service(list_leaves_between)
+            .service(get_published_root_at)
+            .service(wait_for_inclusion)
             .service(get_all_published_roots)
             .service(actix_files::Files::new("/journal/", "journal").use_last_modified(true).use_etag(true).show_files_listing())
             .service(actix_files::Files::new("/", find_web_resources()).use_last_modified(true).use_etag(true).index_file("index.html"))
@@ -5,7 +5,8 @@ create table if not exists PUBLISHED_ROOTS
     hash       BINARY(32) PRIMARY KEY NOT NULL,
     prior_hash BINARY(32) NULL,
     timestamp  BIGINT UNSIGNED NOT NULL,
-    serial     SERIAL
+    serial     SERIAL,
+    INDEX (timestamp)
 );
 
 create table if not exists PUBLISHED_ROOT_REFERENCES (
@@ -15,12 +16,20 @@ create table if not exists PUBLISHED_ROOT_REFERENCES (
     INDEX (published)
     );
 
+create table if not exists PUBLISHED_ROOT_SKIPS (  # skip references of roots that have them. See RootHashHistory::skips.
+    published    BINARY(32) NOT NULL,
+    referenced   BINARY(32) NOT NULL,
+    position     INT,
+    INDEX (published)
+    );
+
 
 create table if not exists BRANCH (
     hash           BINARY(32) PRIMARY KEY NOT NULL,
     left_child     BINARY(32) UNIQUE NOT NULL,  # left and right are reserved words.
     right_child    BINARY(32) UNIQUE NOT NULL,
     parent         BINARY(32) NULL,
+    depth          INT UNSIGNED NULL, # NULL only for databases created before this column existed.
     INDEX (parent)
     );
 
@@ -29,5 +38,20 @@ create table if not exists LEAF (
     timestamp BIGINT UNSIGNED NOT NULL,
     data      TEXT NULL,
     parent    BINARY(32) NULL,
-    INDEX (parent)
-    );
\ No newline at end of file
+    structured BOOLEAN NOT NULL DEFAULT FALSE, # see LeafHashHistory::structured.
+    sequence  BIGINT UNSIGNED NULL UNIQUE, # NULL only for databases created before this column existed.
+    INDEX (parent),
+    INDEX (timestamp)
+    );
+
+create table if not exists IDEMPOTENCY_KEYS (
+    idempotency_key VARBINARY(255) PRIMARY KEY NOT NULL, # binary, so keys differing in case or trailing spaces are different.
+    leaf            BINARY(32) NOT NULL
+    );
+
+create table if not exists CONTENT_INDEX (
+    content_hash BINARY(32) NOT NULL,  # sha256 of the leaf's data. Kept when the leaf is censored.
+    leaf         BINARY(32) NOT NULL,
+    serial       SERIAL,
+    INDEX (content_hash)
+    );
@@ -1,7 +1,7 @@
 use mysql::{Conn, Opts};
 use mysql::prelude::Queryable;
 use std::sync::Mutex;
-use merkle_tree_bulletin_board::{BulletinBoard, BulletinBoardBackend};
+use merkle_tree_bulletin_board::{BulletinBoard, BulletinBoardBackend, BulletinBoardError};
 use merkle_tree_bulletin_board::hash_history::{HashSource, LeafHashHistory, HashInfo, BranchHashHistory, RootHashHistory};
 use merkle_tree_bulletin_board::hash::HashValue;
 
@@ -37,8 +37,11 @@ fn main() -> Result<(),mysql::Error> {
 
     conn.query_drop("drop table if exists PUBLISHED_ROOTS")?;
     conn.query_drop("drop table if exists PUBLISHED_ROOT_REFERENCES")?;
+    conn.query_drop("drop table if exists PUBLISHED_ROOT_SKIPS")?;
     conn.query_drop("drop table if exists BRANCH")?;
     conn.query_drop("drop table if exists LEAF")?;
+    conn.query_drop("drop table if exists IDEMPOTENCY_KEYS")?;
+    conn.query_drop("drop table if exists CONTENT_INDEX")?;
 
     let schema = merkle_tree_bulletin_board_backend_mysql::SCHEMA;
     // println!("Running schema\n{}",&schema);
@@ -51,7 +54,7 @@ fn main() -> Result<(),mysql::Error> {
     // utility function to check that something is indeed a leaf with the expected data.
     fn assert_is_leaf(source:HashSource,expected_data:&str) {
        match source {
-         HashSource::Leaf(LeafHashHistory{data:Some(d),timestamp:_}) => assert_eq!(d,expected_data),
+         HashSource::Leaf(LeafHashHistory{data:Some(d),..}) => assert_eq!(d,expected_data),
          _ => panic!("Not a leaf"),
        }
     }
@@ -95,7 +98,7 @@ fn main() -> Result<(),mysql::Error> {
     // now publish! This will publish branch_AB and hash_C.
     let published1 : HashValue = board.order_new_published_root().unwrap();
     match board.get_hash_info(published1).unwrap().source {
-        HashSource::Root(RootHashHistory{timestamp:_,elements:e,prior:None}) =>
+        HashSource::Root(RootHashHistory{timestamp:_,elements:e,prior:None,..}) =>
            assert_eq!(e,vec![branch_AB,hash_C]),
         _ => panic!("Should be a root"),
     }
@@ -116,7 +119,7 @@ fn main() -> Result<(),mysql::Error> {
     assert_eq!(board.get_hash_info(hash_C).unwrap().parent,None);
     assert_is_leaf(board.get_hash_info(hash_C).unwrap().source,"C");
     match board.get_hash_info(published1).unwrap().source {
-        HashSource::Root(RootHashHistory{timestamp:_,elements:e,prior:None}) =>
+        HashSource::Root(RootHashHistory{timestamp:_,elements:e,prior:None,..}) =>
             assert_eq!(e,vec![branch_AB,hash_C]),
         _ => panic!("Should be a root"),
     }
@@ -147,12 +150,15 @@ fn main() -> Result<(),mysql::Error> {
         parent: None});
     assert_eq!(board.get_all_published_roots().unwrap(),vec![published1]);
     assert_eq!(board.get_parentless_unpublished_hash_values().unwrap(),vec![branch_ABCD]);
+    assert_eq!(board.backend.get_depth(hash_D).unwrap(),0);
+    assert_eq!(board.backend.get_depth(branch_CD).unwrap(),1);
+    assert_eq!(board.backend.get_depth(branch_ABCD).unwrap(),2);
 
     // do another publication, which now only has to contain branchABCD which includes everything,
     // including things from before the last publication.
     let published2 = board.order_new_published_root().unwrap();
     match board.get_hash_info(published2).unwrap().source {
-        HashSource::Root(RootHashHistory{timestamp:_,elements:e,prior:Some(prior)}) => {
+        HashSource::Root(RootHashHistory{timestamp:_,elements:e,prior:Some(prior),..}) => {
             assert_eq!(e,vec![branch_ABCD]);
             assert_eq!(prior,published1);
         }
@@ -165,11 +171,23 @@ fn main() -> Result<(),mysql::Error> {
 
     println!("Censoring the evil A.");
     // test censorship
-    board.censor_leaf(hash_A).unwrap();
+    board.censor_leaf(hash_A,"Evil","The test program").unwrap();
     match board.get_hash_info(hash_A).unwrap().source {
         HashSource::Leaf(LeafHashHistory{ data : None, .. }) => {}
         _ => panic!("hash_A should be a leaf with no data!"),
     }
+    println!("Restoring A.");
+    board.uncensor_leaf(hash_A,"A","Appeal","The test program").unwrap();
+    match board.get_hash_info(hash_A).unwrap().source {
+        HashSource::Leaf(LeafHashHistory{ data : Some(data), .. }) => assert_eq!(data,"A"),
+        _ => panic!("hash_A should be a leaf with data!"),
+    }
+
+    println!("Testing idempotency keys.");
+    let keyed = board.submit_leaf_with_idempotency_key("E","Abc").unwrap();
+    assert_ne!(board.submit_leaf_with_idempotency_key("F","abc ").unwrap(),keyed); // a different key, despite mysql's usual collation.
+    assert_eq!(board.submit_leaf_with_idempotency_key("E","Abc").unwrap(),keyed);
+    assert_eq!(board.submit_leaf_with_idempotency_key("G",&"k".repeat(256)),Err(BulletinBoardError::InvalidIdempotencyKey));
 
     println!("All seems to work fine.");
     Ok(())
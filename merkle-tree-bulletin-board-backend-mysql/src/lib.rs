@@ -1,8 +1,9 @@
 use mysql::{Conn, from_value, Value, TxOpts};
 use std::ops::DerefMut;
-use merkle_tree_bulletin_board::{BulletinBoardBackend, DatabaseTransaction, BulletinBoardError};
+use merkle_tree_bulletin_board::{BulletinBoardBackend, DatabaseTransaction, BulletinBoardError, MAX_IDEMPOTENCY_KEY_LENGTH};
 use merkle_tree_bulletin_board::hash::HashValue;
-use merkle_tree_bulletin_board::hash_history::{HashInfo, HashSource, LeafHashHistory, BranchHashHistory, RootHashHistory};
+use merkle_tree_bulletin_board::content_index::content_hash;
+use merkle_tree_bulletin_board::hash_history::{HashInfo, HashSource, LeafHashHistory, BranchHashHistory, RootHashHistory, Timestamp};
 use mysql::prelude::{Queryable};
 use std::sync::{Mutex, MutexGuard};
 use std::convert::TryInto;
@@ -15,6 +16,9 @@ use std::convert::TryInto;
 ///
 /// There is a demo program in bin/test_mysql.rs which uses this on a small dataset.
 ///
+/// Multiple bulletin boards, e.g. several web servers, may share the same database; writes are
+/// serialized with a mysql advisory lock, and a board whose view is out of date will reload and retry.
+///
 /// This uses the schema:
 /// ```sql
 #[doc = include_str!("Schema.sql")]
@@ -33,6 +37,47 @@ impl <C:DerefMut<Target=Conn>> BackendMysql<C> {
         Ok(guard)
     }
 
+    /// Take a mysql advisory lock, used so that multiple writers (possibly in different processes) sharing the same
+    /// database do not interleave their checks and writes. The lock name includes the database name, so different
+    /// bulletin boards on the same server do not contend.
+    fn get_writer_lock(&self) -> Result<(),BulletinBoardError> {
+        let got : Option<Option<i64>> = self.get_connection_lock()?.query_first(format!("SELECT GET_LOCK(CONCAT('{}',DATABASE()),{})",WRITER_LOCK_PREFIX,WRITER_LOCK_TIMEOUT_SECONDS)).map_err(mysql_to_bb_error)?;
+        match got {
+            Some(Some(1)) => Ok(()),
+            _ => Err(BulletinBoardError::BackendIOError("Could not obtain the bulletin board writer lock".to_string())),
+        }
+    }
+
+    fn release_writer_lock(&self) -> Result<(),BulletinBoardError> {
+        self.get_connection_lock()?.query_drop(format!("DO RELEASE_LOCK(CONCAT('{}',DATABASE()))",WRITER_LOCK_PREFIX)).map_err(mysql_to_bb_error)
+    }
+
+    /// The depth of a node, from the depth column of the BRANCH table where possible. Branches created before that column
+    /// existed have NULL there, in which case this walks down the left side of the tree until it gets to a leaf (depth 0)
+    /// or a branch with a depth.
+    fn find_depth(conn:&mut impl Queryable,hash:HashValue) -> Result<u32,BulletinBoardError> {
+        let mut node = hash;
+        let mut walked = 0;
+        loop {
+            let row : Option<(Value,Option<u32>)> = conn.exec_first("SELECT left_child,depth from BRANCH WHERE hash=?",(node.0,)).map_err(mysql_to_bb_error)?;
+            match row {
+                None => return Ok(walked), // a leaf.
+                Some((_,Some(depth))) => return Ok(walked+depth),
+                Some((left_child,None)) => { node=hash_from_value(left_child)?; walked+=1; }
+            }
+        }
+    }
+
+    /// The number of leaves, found from the largest sequence number using its index, rather than counting every leaf.
+    /// Falls back to counting if no leaf has a sequence number, i.e. the database is empty or was created before that
+    /// column existed and no leaf has been added since.
+    fn count_leaves(conn:&mut impl Queryable) -> Result<u64,BulletinBoardError> {
+        let largest : Option<Option<u64>> = conn.query_first("SELECT MAX(sequence) from LEAF").map_err(mysql_to_bb_error)?;
+        if let Some(Some(largest)) = largest { return Ok(largest+1); }
+        let count : Option<u64> = conn.query_first("SELECT count(*) from LEAF").map_err(mysql_to_bb_error)?;
+        Ok(count.unwrap_or(0))
+    }
+
     fn query_hashes(&self, query : &'_ str) -> Result<Vec<HashValue>,BulletinBoardError> {
         let res : mysql::Result<Vec<Result<HashValue,InvalidHashFormatError>>> = self.get_connection_lock()?.query_map(query,|(v,)| hash_from_value(v));
         if let Err(e) = &res {
@@ -43,8 +88,13 @@ impl <C:DerefMut<Target=Conn>> BackendMysql<C> {
     }
 }
 
+/// Prefix of the name of the mysql advisory lock taken while writing. See [BackendMysql::publish_if_unchanged].
+const WRITER_LOCK_PREFIX : &str = "merkle_tree_bulletin_board:";
+/// How long to wait for another writer to finish before giving up.
+const WRITER_LOCK_TIMEOUT_SECONDS : u32 = 60;
+
 /// The schema used; can be used to initialize the database.
-pub const SCHEMA : &'static str = include_str!("Schema.sql");
+pub const SCHEMA : &str = include_str!("Schema.sql");
 
 pub struct InvalidHashFormatError {}
 
@@ -96,8 +146,8 @@ impl <C:DerefMut<Target=Conn>> BulletinBoardBackend for BackendMysql<C> {
     fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>,BulletinBoardError> {
         let mut lock = self.get_connection_lock()?;
         // see if it is a leaf
-        if let Some((timestamp,data,parent)) = lock.exec_first("SELECT timestamp,data,parent from LEAF WHERE hash=?",(query.0,)).map_err(mysql_to_bb_error)? {
-            return Ok(Some(HashInfo{ source: HashSource::Leaf(LeafHashHistory{ timestamp: from_value(timestamp), data: from_value(data) }), parent : opt_hash_from_value(parent)? }))
+        if let Some((timestamp,data,structured,parent)) = lock.exec_first("SELECT timestamp,data,structured,parent from LEAF WHERE hash=?",(query.0,)).map_err(mysql_to_bb_error)? {
+            return Ok(Some(HashInfo{ source: HashSource::Leaf(LeafHashHistory{ timestamp: from_value(timestamp), data: from_value(data), structured: from_value(structured) }), parent : opt_hash_from_value(parent)? }))
         }
         // see if it is a branch
         if let Some((left_child,right_child,parent)) = lock.exec_first("SELECT left_child,right_child,parent from BRANCH WHERE hash=?",(query.0,)).map_err(mysql_to_bb_error)? {
@@ -108,11 +158,20 @@ impl <C:DerefMut<Target=Conn>> BulletinBoardBackend for BackendMysql<C> {
             let elements : Vec<Result<HashValue,InvalidHashFormatError>> = lock.exec_map("SELECT referenced from PUBLISHED_ROOT_REFERENCES where published=? order by position",(query.0,),|(v,)|hash_from_value(v)).map_err(mysql_to_bb_error)?;
             let elements : Result<Vec<HashValue>,InvalidHashFormatError> = elements.into_iter().collect();
             let elements : Vec<HashValue> = elements?;
-            return Ok(Some(HashInfo{ source: HashSource::Root(RootHashHistory{ timestamp: from_value(timestamp), prior: opt_hash_from_value(prior_hash)?, elements }), parent : None }))
+            let skips : Vec<Result<HashValue,InvalidHashFormatError>> = lock.exec_map("SELECT referenced from PUBLISHED_ROOT_SKIPS where published=? order by position",(query.0,),|(v,)|hash_from_value(v)).map_err(mysql_to_bb_error)?;
+            let skips : Result<Vec<HashValue>,InvalidHashFormatError> = skips.into_iter().collect();
+            let skips : Vec<HashValue> = skips?;
+            return Ok(Some(HashInfo{ source: HashSource::Root(RootHashHistory{ timestamp: from_value(timestamp), prior: opt_hash_from_value(prior_hash)?, elements, skips }), parent : None }))
         }
         Ok(None)
     }
 
+    /// Uses the depth column of the BRANCH table. Databases created before that column existed
+    /// will have NULL there for old branches, in which case this falls back to walking down the left side of the tree.
+    fn get_depth(&self, hash: HashValue) -> Result<usize,BulletinBoardError> {
+        Ok(Self::find_depth(&mut **self.get_connection_lock()?,hash)? as usize)
+    }
+
     fn publish(&mut self, transaction: &DatabaseTransaction) -> Result<(),BulletinBoardError> {
         let mut lock = self.get_connection_lock()?;
         let mut tx = lock.start_transaction(TxOpts::default()).map_err(mysql_to_bb_error)?;
@@ -120,10 +179,17 @@ impl <C:DerefMut<Target=Conn>> BulletinBoardBackend for BackendMysql<C> {
             match source {
                 HashSource::Leaf(history) => {
                     // println!("Publishing leaf {} data {}",hash,history.data.as_ref().unwrap());
-                    tx.exec_drop("insert into LEAF (hash,timestamp,data) values (?,?,?)",(hash.0,history.timestamp,&history.data)).map_err(mysql_to_bb_error)?;
+                    // the sequence number is the number of prior leaves, whether or not they have sequence numbers themselves.
+                    let sequence = Self::count_leaves(&mut tx)?;
+                    tx.exec_drop("insert into LEAF (hash,timestamp,data,structured,sequence) values (?,?,?,?,?)",(hash.0,history.timestamp,&history.data,history.structured,sequence)).map_err(mysql_to_bb_error)?;
+                    if let Some(data) = &history.data {
+                        tx.exec_drop("insert into CONTENT_INDEX (content_hash,leaf) values (?,?)",(content_hash(data).0,hash.0)).map_err(mysql_to_bb_error)?;
+                    }
                 }
                 HashSource::Branch(history) => {
-                    tx.exec_drop("insert into BRANCH (hash,left_child,right_child) values (?,?,?)",(hash.0,history.left.0,history.right.0)).map_err(mysql_to_bb_error)?;
+                    // the depth is one more than the left child's, which is computed if the left child predates the depth column.
+                    let depth = Self::find_depth(&mut tx,history.left)?+1;
+                    tx.exec_drop("insert into BRANCH (hash,left_child,right_child,depth) values (?,?,?,?)",(hash.0,history.left.0,history.right.0,depth)).map_err(mysql_to_bb_error)?;
                     // update parents. Could optimize as prior insert probably has one of them.
                     tx.exec_drop("update BRANCH set parent=? where hash=? or hash=?",(hash.0,history.left.0,history.right.0)).map_err(mysql_to_bb_error)?;
                     tx.exec_drop("update LEAF set parent=? where hash=? or hash=?",(hash.0,history.left.0,history.right.0)).map_err(mysql_to_bb_error)?;
@@ -135,16 +201,108 @@ impl <C:DerefMut<Target=Conn>> BulletinBoardBackend for BackendMysql<C> {
                         let referenced = history.elements[position];
                         tx.exec_drop("insert into PUBLISHED_ROOT_REFERENCES (published,referenced,position) values (?,?,?)",(hash.0,referenced.0,position)).map_err(mysql_to_bb_error)?;
                     }
+                    for (position,skip) in history.skips.iter().enumerate() {
+                        tx.exec_drop("insert into PUBLISHED_ROOT_SKIPS (published,referenced,position) values (?,?,?)",(hash.0,skip.0,position)).map_err(mysql_to_bb_error)?;
+                    }
                 }
             }
         }
+        if let Some((key,leaf)) = transaction.idempotency_key_and_leaf() {
+            if key.len()>MAX_IDEMPOTENCY_KEY_LENGTH { return Err(BulletinBoardError::InvalidIdempotencyKey); } // would not fit in the column.
+            tx.exec_drop("insert into IDEMPOTENCY_KEYS (idempotency_key,leaf) values (?,?)",(key,leaf.0)).map_err(mysql_to_bb_error)?;
+        }
         tx.commit().map_err(mysql_to_bb_error)?;
         Ok(())
     }
 
+    /// Check and publish while holding a mysql advisory lock, so that multiple [merkle_tree_bulletin_board::BulletinBoard]s,
+    /// possibly in different processes, can safely use the same database.
+    fn publish_if_unchanged(&mut self, transaction: &DatabaseTransaction, expected_parentless: &[HashValue]) -> Result<(),BulletinBoardError> {
+        self.get_writer_lock()?;
+        let res = self.check_unchanged(transaction,expected_parentless).and_then(|_|self.publish(transaction));
+        let released = self.release_writer_lock();
+        res?;
+        released
+    }
+
+    fn get_leaf_for_idempotency_key(&self, key: &str) -> Result<Option<HashValue>,BulletinBoardError> {
+        let leaf : Option<Value> = self.get_connection_lock()?.exec_first("SELECT leaf from IDEMPOTENCY_KEYS WHERE idempotency_key=?",(key,)).map_err(mysql_to_bb_error)?;
+        Ok(match leaf { Some(v) => Some(hash_from_value(v)?), None => None })
+    }
+
+    fn store_idempotency_key(&mut self, key: &str, leaf: HashValue) -> Result<(),BulletinBoardError> {
+        if key.len()>MAX_IDEMPOTENCY_KEY_LENGTH { return Err(BulletinBoardError::InvalidIdempotencyKey); } // would not fit in the column.
+        self.get_connection_lock()?.exec_drop("insert ignore into IDEMPOTENCY_KEYS (idempotency_key,leaf) values (?,?)",(key,leaf.0)).map_err(mysql_to_bb_error)
+    }
+
+    fn get_leaf_count(&self) -> Result<u64,BulletinBoardError> {
+        Self::count_leaves(&mut **self.get_connection_lock()?)
+    }
+
+    /// Leaves added before the sequence column existed have NULL there, in which case this falls back to walking down the tree.
+    fn get_leaf_by_index(&self, index: u64) -> Result<Option<HashValue>,BulletinBoardError> {
+        let leaf : Option<Value> = self.get_connection_lock()?.exec_first("SELECT hash from LEAF WHERE sequence=?",(index,)).map_err(mysql_to_bb_error)?;
+        match leaf {
+            Some(v) => Ok(Some(hash_from_value(v)?)),
+            None => self.leaf_by_index_from_tree(index),
+        }
+    }
+
+    /// Leaves added before the sequence column existed have NULL there, in which case this falls back to walking up the tree.
+    fn get_leaf_index(&self, leaf: HashValue) -> Result<Option<u64>,BulletinBoardError> {
+        let sequence : Option<Option<u64>> = self.get_connection_lock()?.exec_first("SELECT sequence from LEAF WHERE hash=?",(leaf.0,)).map_err(mysql_to_bb_error)?;
+        match sequence {
+            None => Ok(None), // not a leaf.
+            Some(Some(sequence)) => Ok(Some(sequence)),
+            Some(None) => self.leaf_index_from_tree(leaf),
+        }
+    }
+
+    /// Falls back to looking up each leaf separately if some leaves in the range have no sequence number.
+    fn list_leaves(&self, start: u64, count: u64) -> Result<Vec<HashValue>,BulletinBoardError> {
+        let end = start.saturating_add(count).min(self.get_leaf_count()?);
+        let res : Vec<Result<HashValue,InvalidHashFormatError>> = self.get_connection_lock()?.exec_map("SELECT hash from LEAF WHERE sequence>=? and sequence<? order by sequence",(start,end),|(v,)| hash_from_value(v)).map_err(mysql_to_bb_error)?;
+        let res : Result<Vec<HashValue>,InvalidHashFormatError> = res.into_iter().collect();
+        let res = res?;
+        if start>=end || res.len() as u64==end-start { return Ok(res); }
+        let mut res = vec![];
+        for index in start..end {
+            match self.get_leaf_by_index(index)? {
+                Some(leaf) => res.push(leaf),
+                None => break,
+            }
+        }
+        Ok(res)
+    }
+
+    /// Leaves with no sequence number (added before that column existed) come before others with the same timestamp.
+    fn list_leaves_between(&self, t0: Timestamp, t1: Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> {
+        let res : Vec<Result<HashValue,InvalidHashFormatError>> = self.get_connection_lock()?.exec_map("SELECT hash from LEAF WHERE timestamp>=? and timestamp<? order by timestamp,sequence",(t0,t1),|(v,)| hash_from_value(v)).map_err(mysql_to_bb_error)?;
+        let res : Result<Vec<HashValue>,InvalidHashFormatError> = res.into_iter().collect();
+        Ok(res?)
+    }
+
+    fn get_published_root_at(&self, t: Timestamp) -> Result<Option<HashValue>,BulletinBoardError> {
+        let root : Option<Value> = self.get_connection_lock()?.exec_first("SELECT hash from PUBLISHED_ROOTS WHERE timestamp<=? order by timestamp DESC, serial DESC LIMIT 1",(t,)).map_err(mysql_to_bb_error)?;
+        Ok(match root { Some(v) => Some(hash_from_value(v)?), None => None })
+    }
+
+    /// Leaves added before the CONTENT_INDEX table existed are not in it; see the README for how to add them.
+    fn get_leaves_with_content_hash(&self, content_hash: HashValue) -> Result<Vec<HashValue>,BulletinBoardError> {
+        let res : Vec<Result<HashValue,InvalidHashFormatError>> = self.get_connection_lock()?.exec_map("SELECT leaf from CONTENT_INDEX WHERE content_hash=? order by serial",(content_hash.0,),|(v,)| hash_from_value(v)).map_err(mysql_to_bb_error)?;
+        let res : Result<Vec<HashValue>,InvalidHashFormatError> = res.into_iter().collect();
+        Ok(res?)
+    }
+
     fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(),BulletinBoardError> {
         let mut lock = self.get_connection_lock()?;
         lock.exec_drop("update LEAF set data=null where hash=?",(leaf_to_censor.0,)).map_err(mysql_to_bb_error)?;
         Ok(())
     }
+
+    fn uncensor_leaf(&mut self, leaf_to_uncensor: HashValue, data: &str) -> Result<(),BulletinBoardError> {
+        let mut lock = self.get_connection_lock()?;
+        lock.exec_drop("update LEAF set data=? where hash=?",(data,leaf_to_uncensor.0)).map_err(mysql_to_bb_error)?;
+        Ok(())
+    }
 }
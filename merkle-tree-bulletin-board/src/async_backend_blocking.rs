@@ -0,0 +1,129 @@
+//! Use a synchronous backend from async code.
+
+use std::sync::{Arc, RwLock};
+use crate::{BulletinBoardBackend, BulletinBoardError, DatabaseTransaction};
+use crate::async_board::AsyncBulletinBoardBackend;
+use crate::growing_forest::GrowingForest;
+use crate::hash::HashValue;
+use crate::hash_history::{HashInfo, Timestamp};
+
+/// Wrap a synchronous [BulletinBoardBackend] (e.g. a database connection or [crate::backend_flatfile::BackendFlatfile])
+/// so that it can be used as an [AsyncBulletinBoardBackend].
+///
+/// Each call is run on async-std's thread pool for blocking operations, so the blocking I/O done by the
+/// synchronous backend does not hold up the async executor. Reads are done under a shared lock so can
+/// proceed in parallel; writes are done under an exclusive lock.
+///
+/// Cloning a BackendBlocking produces another handle to the same underlying backend. This is how an
+/// [crate::async_board::AsyncBulletinBoardReader] shares the backend of an [crate::async_board::AsyncBulletinBoard].
+///
+/// # Example
+///
+/// ```
+/// use merkle_tree_bulletin_board::async_backend_blocking::BackendBlocking;
+/// use merkle_tree_bulletin_board::async_board::AsyncBulletinBoard;
+/// use merkle_tree_bulletin_board::backend_flatfile::BackendFlatfile;
+///
+/// let dir = tempdir::TempDir::new("flatfile").unwrap();
+/// let backend = BackendBlocking::new(BackendFlatfile::new(dir.path().join("database.csv")).unwrap());
+/// async_std::task::block_on(async {
+///     let mut board = AsyncBulletinBoard::new(backend).await.unwrap();
+///     let hash = board.submit_leaf("a").await.unwrap();
+///     assert_eq!(board.get_parentless_unpublished_hash_values().await.unwrap(),vec![hash]);
+/// });
+/// ```
+pub struct BackendBlocking<B:BulletinBoardBackend+Send+Sync+'static> {
+    inner : Arc<RwLock<B>>,
+}
+
+impl <B:BulletinBoardBackend+Send+Sync+'static> Clone for BackendBlocking<B> {
+    fn clone(&self) -> Self { BackendBlocking { inner: self.inner.clone() } }
+}
+
+impl <B:BulletinBoardBackend+Send+Sync+'static> BackendBlocking<B> {
+    /// Wrap a synchronous backend.
+    pub fn new(backend:B) -> Self { BackendBlocking { inner: Arc::new(RwLock::new(backend)) } }
+
+    /// Run some read only function on the underlying backend on the thread pool for blocking operations.
+    pub async fn read<R,F>(&self,f:F) -> Result<R,BulletinBoardError>
+    where F: FnOnce(&B) -> Result<R,BulletinBoardError> + Send + 'static, R: Send + 'static {
+        let inner = self.inner.clone();
+        async_std::task::spawn_blocking(move || {
+            let backend = inner.read().map_err(|_|BulletinBoardError::BackendInconsistentError("A prior operation on the backend panicked".to_string()))?;
+            f(&backend)
+        }).await
+    }
+
+    /// Run some function that may modify the underlying backend on the thread pool for blocking operations.
+    pub async fn write<R,F>(&self,f:F) -> Result<R,BulletinBoardError>
+    where F: FnOnce(&mut B) -> Result<R,BulletinBoardError> + Send + 'static, R: Send + 'static {
+        let inner = self.inner.clone();
+        async_std::task::spawn_blocking(move || {
+            let mut backend = inner.write().map_err(|_|BulletinBoardError::BackendInconsistentError("A prior operation on the backend panicked".to_string()))?;
+            f(&mut backend)
+        }).await
+    }
+}
+
+impl <B:BulletinBoardBackend+Send+Sync+'static> AsyncBulletinBoardBackend for BackendBlocking<B> {
+    async fn get_all_published_roots(&self) -> Result<Vec<HashValue>,BulletinBoardError> { self.read(|b|b.get_all_published_roots()).await }
+
+    async fn get_most_recent_published_root(&self) -> Result<Option<HashValue>,BulletinBoardError> { self.read(|b|b.get_most_recent_published_root()).await }
+
+    async fn get_all_leaves_and_branches_without_a_parent(&self) -> Result<Vec<HashValue>,BulletinBoardError> { self.read(|b|b.get_all_leaves_and_branches_without_a_parent()).await }
+
+    async fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>,BulletinBoardError> { self.read(move |b|b.get_hash_info(query)).await }
+
+    async fn publish(&mut self, transaction: &DatabaseTransaction) -> Result<(),BulletinBoardError> {
+        let transaction = transaction.clone();
+        self.write(move |b|b.publish(&transaction)).await
+    }
+
+    /// The check and publication are done while holding the exclusive lock, so nothing else using this BackendBlocking can get in between.
+    async fn publish_if_unchanged(&mut self, transaction: &DatabaseTransaction, expected_parentless: &[HashValue]) -> Result<(),BulletinBoardError> {
+        let transaction = transaction.clone();
+        let expected_parentless = expected_parentless.to_vec();
+        self.write(move |b|b.publish_if_unchanged(&transaction,&expected_parentless)).await
+    }
+
+    async fn check_unchanged(&self, transaction: &DatabaseTransaction, expected_parentless: &[HashValue]) -> Result<(),BulletinBoardError> {
+        let transaction = transaction.clone();
+        let expected_parentless = expected_parentless.to_vec();
+        self.read(move |b|b.check_unchanged(&transaction,&expected_parentless)).await
+    }
+
+    async fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(),BulletinBoardError> { self.write(move |b|b.censor_leaf(leaf_to_censor)).await }
+
+    async fn uncensor_leaf(&mut self, leaf_to_uncensor: HashValue, data: &str) -> Result<(),BulletinBoardError> {
+        let data = data.to_string();
+        self.write(move |b|b.uncensor_leaf(leaf_to_uncensor,&data)).await
+    }
+
+    async fn get_depth(&self, hash: HashValue) -> Result<usize,BulletinBoardError> { self.read(move |b|b.get_depth(hash)).await }
+
+    async fn get_leaf_for_idempotency_key(&self, key: &str) -> Result<Option<HashValue>,BulletinBoardError> {
+        let key = key.to_string();
+        self.read(move |b|b.get_leaf_for_idempotency_key(&key)).await
+    }
+
+    async fn store_idempotency_key(&mut self, key: &str, leaf: HashValue) -> Result<(),BulletinBoardError> {
+        let key = key.to_string();
+        self.write(move |b|b.store_idempotency_key(&key,leaf)).await
+    }
+
+    async fn get_leaves_with_content_hash(&self, content_hash: HashValue) -> Result<Vec<HashValue>,BulletinBoardError> { self.read(move |b|b.get_leaves_with_content_hash(content_hash)).await }
+
+    async fn get_leaf_count(&self) -> Result<u64,BulletinBoardError> { self.read(|b|b.get_leaf_count()).await }
+
+    async fn get_leaf_by_index(&self, index: u64) -> Result<Option<HashValue>,BulletinBoardError> { self.read(move |b|b.get_leaf_by_index(index)).await }
+
+    async fn get_leaf_index(&self, leaf: HashValue) -> Result<Option<u64>,BulletinBoardError> { self.read(move |b|b.get_leaf_index(leaf)).await }
+
+    async fn list_leaves(&self, start: u64, count: u64) -> Result<Vec<HashValue>,BulletinBoardError> { self.read(move |b|b.list_leaves(start,count)).await }
+
+    async fn list_leaves_between(&self, t0: Timestamp, t1: Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> { self.read(move |b|b.list_leaves_between(t0,t1)).await }
+
+    async fn get_published_root_at(&self, t: Timestamp) -> Result<Option<HashValue>,BulletinBoardError> { self.read(move |b|b.get_published_root_at(t)).await }
+
+    async fn compute_current_forest(&self) -> Result<GrowingForest,BulletinBoardError> { self.read(|b|b.compute_current_forest()).await }
+}
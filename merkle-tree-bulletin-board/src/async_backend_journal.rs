@@ -0,0 +1,153 @@
+//! The async equivalent of [crate::backend_journal].
+
+use std::path::PathBuf;
+use crate::{BulletinBoardError, DatabaseTransaction};
+use crate::async_board::AsyncBulletinBoardBackend;
+use crate::backend_access::AsyncAccess;
+use crate::backend_journal::{JournalFiles, StartupVerification};
+use crate::hash::HashValue;
+use crate::hash_history::{HashInfo, Timestamp};
+
+/// Add journalling suitable for bulk verification to some other async backend.
+///
+/// This produces exactly the same files as [crate::backend_journal::BackendJournal], which describes
+/// their format and use, but does the file I/O asynchronously.
+///
+/// # Example
+///
+/// ```
+/// use merkle_tree_bulletin_board::async_backend_journal::AsyncBackendJournal;
+/// use merkle_tree_bulletin_board::async_board::AsyncBulletinBoard;
+/// use merkle_tree_bulletin_board::backend_journal::StartupVerification;
+/// use merkle_tree_bulletin_board::backend_memory::BackendMemory;
+///
+/// let dir = tempdir::TempDir::new("journal").unwrap();
+/// async_std::task::block_on(async {
+///     let journal = AsyncBackendJournal::new(BackendMemory::default(),dir.path(),
+///         StartupVerification::SanityCheckAndRepairPending).await.unwrap();
+///     let mut board = AsyncBulletinBoard::new(journal).await.unwrap();
+///     board.submit_leaf("a").await.unwrap();
+///     assert_eq!(true,dir.path().join("pending.csv").exists());
+///     let hash = board.order_new_published_root().await.unwrap();
+///     assert_eq!(false,dir.path().join("pending.csv").exists());
+///     assert_eq!(true,dir.path().join(&(hash.to_string()+".csv")).exists());
+/// });
+/// ```
+pub struct AsyncBackendJournal<B:AsyncBulletinBoardBackend> {
+    main_backend: B,
+    files : JournalFiles,
+}
+
+impl <B:AsyncBulletinBoardBackend> AsyncBulletinBoardBackend for AsyncBackendJournal<B> {
+    async fn get_all_published_roots(&self) -> Result<Vec<HashValue>,BulletinBoardError> { self.main_backend.get_all_published_roots().await }
+
+    async fn get_most_recent_published_root(&self) -> Result<Option<HashValue>,BulletinBoardError> { self.main_backend.get_most_recent_published_root().await }
+
+    async fn get_all_leaves_and_branches_without_a_parent(&self) -> Result<Vec<HashValue>,BulletinBoardError> { self.main_backend.get_all_leaves_and_branches_without_a_parent().await }
+
+    async fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>,BulletinBoardError> { self.main_backend.get_hash_info(query).await }
+
+    async fn get_depth(&self, hash: HashValue) -> Result<usize,BulletinBoardError> { self.main_backend.get_depth(hash).await }
+
+    /// Idempotency keys are not journaled; they are stored by the original backend.
+    async fn get_leaf_for_idempotency_key(&self, key: &str) -> Result<Option<HashValue>,BulletinBoardError> { self.main_backend.get_leaf_for_idempotency_key(key).await }
+
+    async fn store_idempotency_key(&mut self, key: &str, leaf: HashValue) -> Result<(),BulletinBoardError> { self.main_backend.store_idempotency_key(key,leaf).await }
+
+    async fn get_leaves_with_content_hash(&self, content_hash: HashValue) -> Result<Vec<HashValue>,BulletinBoardError> { self.main_backend.get_leaves_with_content_hash(content_hash).await }
+
+    async fn get_leaf_count(&self) -> Result<u64,BulletinBoardError> { self.main_backend.get_leaf_count().await }
+
+    async fn get_leaf_by_index(&self, index: u64) -> Result<Option<HashValue>,BulletinBoardError> { self.main_backend.get_leaf_by_index(index).await }
+
+    async fn get_leaf_index(&self, leaf: HashValue) -> Result<Option<u64>,BulletinBoardError> { self.main_backend.get_leaf_index(leaf).await }
+
+    async fn list_leaves(&self, start: u64, count: u64) -> Result<Vec<HashValue>,BulletinBoardError> { self.main_backend.list_leaves(start,count).await }
+
+    async fn list_leaves_between(&self, t0: Timestamp, t1: Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> { self.main_backend.list_leaves_between(t0,t1).await }
+
+    async fn get_published_root_at(&self, t: Timestamp) -> Result<Option<HashValue>,BulletinBoardError> { self.main_backend.get_published_root_at(t).await }
+
+    /// Publish both to the original backend, and the journal. See [crate::backend_journal::BackendJournal].
+    async fn publish(&mut self, transaction: &DatabaseTransaction) -> Result<(),BulletinBoardError> {
+        self.main_backend.publish(transaction).await?;
+        self.files.publish(&AsyncAccess(&self.main_backend),transaction).await
+    }
+
+    async fn publish_if_unchanged(&mut self, transaction: &DatabaseTransaction, expected_parentless: &[HashValue]) -> Result<(),BulletinBoardError> {
+        self.main_backend.publish_if_unchanged(transaction,expected_parentless).await?;
+        self.files.publish(&AsyncAccess(&self.main_backend),transaction).await
+    }
+
+    /// The journal file containing the leaf is rewritten when the next root is published.
+    async fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(),BulletinBoardError> {
+        self.main_backend.censor_leaf(leaf_to_censor).await?;
+        self.files.mark_changed(leaf_to_censor);
+        Ok(())
+    }
+
+    /// The journal file containing the leaf is rewritten when the next root is published.
+    async fn uncensor_leaf(&mut self, leaf_to_uncensor: HashValue, data: &str) -> Result<(),BulletinBoardError> {
+        self.main_backend.uncensor_leaf(leaf_to_uncensor,data).await?;
+        self.files.mark_changed(leaf_to_uncensor);
+        Ok(())
+    }
+}
+
+impl <B:AsyncBulletinBoardBackend> AsyncBackendJournal<B> {
+
+    /// Verify that the pending data file is consistent with the database. Return Err if not.
+    /// See [crate::backend_journal::BackendJournal::verify_current_consistent].
+    pub async fn verify_current_consistent(&self) -> Result<(),BulletinBoardError> {
+        self.files.verify_current_consistent(&AsyncAccess(&self.main_backend)).await
+    }
+
+    /// Get the underlying backend. Used mainly for testing.
+    pub fn into_inner(self) -> B { self.main_backend }
+
+    /// Add journalling to an existing backend, keeping journals in the provided directory (which may not exist).
+    ///
+    /// This will create the directory if it does not exist, and run verification based on the verification
+    /// flag. See [StartupVerification] for details.
+    pub async fn new<P>(main_backend:B,directory: P,verification:StartupVerification) -> Result<Self,BulletinBoardError>
+    where PathBuf: From<P>
+    {
+        let directory = PathBuf::from(directory);
+        let files = async_std::task::spawn_blocking(move ||JournalFiles::new(directory)).await?;
+        files.start(&AsyncAccess(&main_backend),verification).await?;
+        Ok(AsyncBackendJournal{ main_backend, files })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::async_backend_journal::AsyncBackendJournal;
+    use crate::async_board::AsyncBulletinBoard;
+    use crate::backend_journal::StartupVerification;
+    use crate::backend_memory::BackendMemory;
+
+    #[test]
+    /// Test that a missing pending file is repaired on startup, and that censorship rewrites the journals at the next publication.
+    fn test_repair_and_censor() {
+        let dir = tempdir::TempDir::new("journal").unwrap();
+        async_std::task::block_on(async {
+            let journal = AsyncBackendJournal::new(BackendMemory::default(),dir.path(),StartupVerification::SanityCheckAndRepairPending).await.unwrap();
+            let mut board = AsyncBulletinBoard::new(journal).await.unwrap();
+            let hash_a = board.submit_leaf("a").await.unwrap();
+            let root = board.order_new_published_root().await.unwrap();
+            board.submit_leaf("b").await.unwrap();
+            let pending_file = dir.path().join("pending.csv");
+            let root_file = dir.path().join(&(root.to_string()+".csv"));
+            let data_pending = std::fs::read_to_string(&pending_file).unwrap();
+            std::fs::remove_file(&pending_file).unwrap();
+            let journal = AsyncBackendJournal::new(board.backend.into_inner(),dir.path(),StartupVerification::SanityCheckAndRepairPending).await.unwrap();
+            assert_eq!(data_pending,std::fs::read_to_string(&pending_file).unwrap());
+            let mut board = AsyncBulletinBoard::new(journal).await.unwrap();
+            assert!(std::fs::read_to_string(&root_file).unwrap().contains(",a\n"));
+            board.censor_leaf(hash_a,"test","test").await.unwrap();
+            assert!(std::fs::read_to_string(&root_file).unwrap().contains(",a\n"));
+            board.order_new_published_root().await.unwrap();
+            assert!(!std::fs::read_to_string(&root_file).unwrap().contains(",a\n"));
+        });
+    }
+}
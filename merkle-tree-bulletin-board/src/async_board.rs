@@ -0,0 +1,557 @@
+//! Async versions of [crate::BulletinBoard] and [crate::BulletinBoardBackend].
+//!
+//! This is only available with the `async` cargo feature.
+//!
+//! The synchronous [crate::BulletinBoard] does its I/O (database queries, file writes) on whatever
+//! thread calls it, which blocks the executor if called from async code such as a web server.
+//! [AsyncBulletinBoard] provides the same API with async functions, using an [AsyncBulletinBoardBackend].
+//! Available backends are
+//!  * [crate::backend_memory::BackendMemory], which never blocks.
+//!  * [crate::async_backend_journal::AsyncBackendJournal], the equivalent of [crate::backend_journal::BackendJournal], wrapping some other async backend.
+//!  * [crate::async_backend_blocking::BackendBlocking], which runs any synchronous [crate::BulletinBoardBackend] (e.g. a database) on a thread pool for blocking operations.
+
+use std::future::Future;
+use std::time::Duration;
+use crate::{backend_access, first_including_root_with_proof, get_ancestry_proof, get_leaf_count_proof, get_ordering_proof, get_proof_chain, remove_published, BoardState, BulletinBoardBackend, BulletinBoardError, DatabaseTransaction};
+use crate::async_backend_blocking::BackendBlocking;
+use crate::backend_access::{run_ready, AsyncAccess, SyncAccess};
+use crate::censorship::{CensorshipEvent, get_censorship_records_between_using};
+use crate::growing_forest::GrowingForest;
+use crate::hash::HashValue;
+use crate::hash_history::{AncestryProof, FullProof, HashInfo, LeafCountProof, OrderingProof, Timestamp};
+use crate::validation::SubmissionValidator;
+use crate::events::{BulletinBoardEvent, BulletinBoardListener, ListenerHandle};
+use crate::inclusion::wait_for_inclusion_async;
+use crate::content_index::{find_leaves_by_data_using, DuplicatePolicy};
+use crate::receipt::{get_receipt_evidence_using, ReceiptEvidence, ReceiptSigner, SignedReceipt, SubmissionReceipt};
+
+/// The async equivalent of [crate::BulletinBoardBackend]. See that for the meaning of each function.
+///
+/// The futures returned must be Send so that an [AsyncBulletinBoard] can be used from multithreaded executors.
+pub trait AsyncBulletinBoardBackend : Send + Sync {
+    /// Get all published roots, for all time.
+    fn get_all_published_roots(&self) -> impl Future<Output=Result<Vec<HashValue>,BulletinBoardError>> + Send;
+    /// Get the most recently published root, should it exist.
+    fn get_most_recent_published_root(&self) -> impl Future<Output=Result<Option<HashValue>,BulletinBoardError>> + Send;
+    /// Get all leaves and branches without a parent branch. Published nodes do not count as a parent.
+    fn get_all_leaves_and_branches_without_a_parent(&self) -> impl Future<Output=Result<Vec<HashValue>,BulletinBoardError>> + Send;
+    /// given a hash, get information about what it represents, if anything.
+    fn get_hash_info(&self, query:HashValue) -> impl Future<Output=Result<Option<HashInfo>,BulletinBoardError>> + Send;
+
+    /// Store a transaction in the database.
+    fn publish(&mut self,transaction:&DatabaseTransaction) -> impl Future<Output=Result<(),BulletinBoardError>> + Send;
+
+    /// Store a transaction in the database, but only if no other writer has changed the board since the transaction was computed.
+    /// See [crate::BulletinBoardBackend::publish_if_unchanged].
+    fn publish_if_unchanged(&mut self,transaction:&DatabaseTransaction,expected_parentless:&[HashValue]) -> impl Future<Output=Result<(),BulletinBoardError>> + Send {
+        async move {
+            self.check_unchanged(transaction,expected_parentless).await?;
+            self.publish(transaction).await
+        }
+    }
+
+    /// Check that the state of the backend is the state a transaction was computed against.
+    /// See [crate::BulletinBoardBackend::check_unchanged].
+    fn check_unchanged(&self,transaction:&DatabaseTransaction,expected_parentless:&[HashValue]) -> impl Future<Output=Result<(),BulletinBoardError>> + Send {
+        async move { backend_access::check_unchanged(&AsyncAccess(self),transaction,expected_parentless).await }
+    }
+
+    /// Get the leaf that was submitted with a given idempotency key, if any.
+    /// See [crate::BulletinBoardBackend::get_leaf_for_idempotency_key].
+    fn get_leaf_for_idempotency_key(&self,_key:&str) -> impl Future<Output=Result<Option<HashValue>,BulletinBoardError>> + Send {
+        async move { Err(BulletinBoardError::IdempotencyKeysNotSupported) }
+    }
+
+    /// Record that an idempotency key identifies a leaf already in the board.
+    /// See [crate::BulletinBoardBackend::store_idempotency_key].
+    fn store_idempotency_key(&mut self,_key:&str,_leaf:HashValue) -> impl Future<Output=Result<(),BulletinBoardError>> + Send {
+        async move { Err(BulletinBoardError::IdempotencyKeysNotSupported) }
+    }
+
+    /// Get the leaves whose data has the given content hash, for the content index.
+    /// See [crate::BulletinBoardBackend::get_leaves_with_content_hash].
+    fn get_leaves_with_content_hash(&self,_content_hash:HashValue) -> impl Future<Output=Result<Vec<HashValue>,BulletinBoardError>> + Send {
+        async move { Err(BulletinBoardError::ContentIndexNotSupported) }
+    }
+
+    /// Get the number of leaves in the board. See [crate::BulletinBoardBackend::get_leaf_count].
+    fn get_leaf_count(&self) -> impl Future<Output=Result<u64,BulletinBoardError>> + Send {
+        async move { Ok(self.compute_current_forest().await?.leaf_count()) }
+    }
+
+    /// Get the leaf with a given sequence number, if there is one. See [crate::BulletinBoardBackend::get_leaf_by_index].
+    /// The default implementation walks down the tree containing it, like [crate::BulletinBoardBackend::leaf_by_index_from_tree].
+    fn get_leaf_by_index(&self,index:u64) -> impl Future<Output=Result<Option<HashValue>,BulletinBoardError>> + Send {
+        async move { backend_access::leaf_by_index_from_tree(&AsyncAccess(self),index).await }
+    }
+
+    /// Get the sequence number of a leaf, or None if the hash is not a leaf. See [crate::BulletinBoardBackend::get_leaf_index].
+    /// The default implementation walks up the tree containing it, like [crate::BulletinBoardBackend::leaf_index_from_tree].
+    fn get_leaf_index(&self,leaf:HashValue) -> impl Future<Output=Result<Option<u64>,BulletinBoardError>> + Send {
+        async move { backend_access::leaf_index_from_tree(&AsyncAccess(self),leaf).await }
+    }
+
+    /// Get up to `count` leaves, in order, starting with the one with sequence number `start`. See [crate::BulletinBoardBackend::list_leaves].
+    fn list_leaves(&self,start:u64,count:u64) -> impl Future<Output=Result<Vec<HashValue>,BulletinBoardError>> + Send {
+        async move { backend_access::list_leaves(&AsyncAccess(self),start,count).await }
+    }
+
+    /// Get the leaves with timestamp `t0<=timestamp<t1`, ordered by timestamp. See [crate::BulletinBoardBackend::list_leaves_between].
+    /// The default implementation looks at every leaf.
+    fn list_leaves_between(&self,t0:Timestamp,t1:Timestamp) -> impl Future<Output=Result<Vec<HashValue>,BulletinBoardError>> + Send {
+        async move { backend_access::list_leaves_between(&AsyncAccess(self),t0,t1).await }
+    }
+
+    /// Get the published root that was current at time `t`. See [crate::BulletinBoardBackend::get_published_root_at].
+    /// The default implementation looks at every published root.
+    fn get_published_root_at(&self,t:Timestamp) -> impl Future<Output=Result<Option<HashValue>,BulletinBoardError>> + Send {
+        async move { backend_access::get_published_root_at(&AsyncAccess(self),t).await }
+    }
+
+    /// Remove the text associated with a leaf.
+    fn censor_leaf(&mut self,leaf_to_censor:HashValue) -> impl Future<Output=Result<(),BulletinBoardError>> + Send;
+
+    /// Restore the text associated with a previously censored leaf.
+    /// The default implementation fails with [BulletinBoardError::UncensoringNotSupported].
+    fn uncensor_leaf(&mut self,_leaf_to_uncensor:HashValue,_data:&str) -> impl Future<Output=Result<(),BulletinBoardError>> + Send {
+        async { Err(BulletinBoardError::UncensoringNotSupported) }
+    }
+
+    /// Get the depth of a subtree rooted at a given leaf or branch node.
+    /// The default implementation follows elements down the left side of each branch, like [crate::BulletinBoardBackend::left_depth].
+    fn get_depth(&self,hash:HashValue) -> impl Future<Output=Result<usize,BulletinBoardError>> + Send {
+        async move { backend_access::left_depth(&AsyncAccess(self),hash).await }
+    }
+
+    /// Deduce the current forest structure. See [crate::BulletinBoardBackend::compute_current_forest].
+    fn compute_current_forest(&self) -> impl Future<Output=Result<GrowingForest,BulletinBoardError>> + Send {
+        async move { backend_access::compute_current_forest(&AsyncAccess(self)).await }
+    }
+}
+
+/// The async equivalent of [crate::BulletinBoard]. The API is the same except that functions are async.
+///
+/// The logic is shared with [crate::BulletinBoard]; only the way the backend is called differs.
+///
+/// # Example
+///
+/// ```
+/// use merkle_tree_bulletin_board::async_board::AsyncBulletinBoard;
+/// use merkle_tree_bulletin_board::backend_memory::BackendMemory;
+/// use merkle_tree_bulletin_board::verifier::verify_proof;
+///
+/// async_std::task::block_on(async {
+///     let mut board = AsyncBulletinBoard::new(BackendMemory::default()).await.unwrap();
+///     let hash_a = board.submit_leaf("a").await.unwrap();
+///     board.submit_leaf("b").await.unwrap();
+///     let root = board.order_new_published_root().await.unwrap();
+///     assert_eq!(board.get_most_recent_published_root().await.unwrap(),Some(root));
+///     let proof = board.get_proof_chain(hash_a).await.unwrap();
+///     assert_eq!(verify_proof("a",root,&proof),None);
+/// });
+/// ```
+pub struct AsyncBulletinBoard<B:AsyncBulletinBoardBackend> {
+    pub backend : B,
+    pub(crate) state : BoardState,
+}
+
+impl <B:AsyncBulletinBoardBackend> AsyncBulletinBoard<B> {
+
+    /// Create a new bulletin board from a backend.
+    pub async fn new(backend:B) -> Result<Self,BulletinBoardError> {
+        let mut res = AsyncBulletinBoard { backend, state : BoardState::default() };
+        res.state.reload_current_forest(&AsyncAccess(&res.backend)).await?;
+        Ok(res)
+    }
+
+    /// Submit some data to be included in the bulletin board, and get back a HashValue that the
+    /// board commits to having in the history. See [crate::BulletinBoard::submit_leaf].
+    pub async fn submit_leaf(&mut self,data:&str) -> Result<HashValue,BulletinBoardError> {
+        self.state.submit_leaf(&mut AsyncAccess(&mut self.backend),data).await
+    }
+
+    /// Submit a leaf with a client supplied idempotency key, so it can be safely retried.
+    /// See [crate::BulletinBoard::submit_leaf_with_idempotency_key].
+    pub async fn submit_leaf_with_idempotency_key(&mut self,data:&str,idempotency_key:&str) -> Result<HashValue,BulletinBoardError> {
+        self.state.submit_leaf_with_idempotency_key(&mut AsyncAccess(&mut self.backend),data,idempotency_key).await
+    }
+
+    /// Set what to do with submissions whose data is already in the board. See [crate::BulletinBoard::set_duplicate_policy].
+    pub fn set_duplicate_policy(&mut self,policy:DuplicatePolicy) {
+        self.state.duplicate_policy = policy;
+    }
+
+    /// Set whether subsequently published roots include skip references. See [crate::BulletinBoard::set_root_skip_list].
+    pub fn set_root_skip_list(&mut self,enabled:bool) {
+        self.state.root_skip_list = enabled;
+    }
+
+    /// Add a check that will be done on all subsequent submissions. See [crate::BulletinBoard::add_validator].
+    pub fn add_validator(&mut self,validator:impl SubmissionValidator+'static) {
+        self.state.validators.push(Box::new(validator));
+    }
+
+    /// Add a listener that will be told about all subsequent changes. See [crate::BulletinBoard::add_listener].
+    pub fn add_listener(&mut self,listener:impl BulletinBoardListener+'static) -> ListenerHandle {
+        self.state.events.add_listener(listener)
+    }
+
+    /// Remove a listener. See [crate::BulletinBoard::remove_listener].
+    pub fn remove_listener(&mut self,handle:ListenerHandle) -> bool {
+        self.state.events.remove_listener(handle)
+    }
+
+    /// Get a channel on which all subsequent changes will be sent. See [crate::BulletinBoard::subscribe].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::async_board::AsyncBulletinBoard;
+    /// use merkle_tree_bulletin_board::backend_memory::BackendMemory;
+    /// use merkle_tree_bulletin_board::events::BulletinBoardEvent;
+    ///
+    /// async_std::task::block_on(async {
+    ///     let mut board = AsyncBulletinBoard::new(BackendMemory::default()).await.unwrap();
+    ///     let events = board.subscribe();
+    ///     let a = board.submit_leaf("a").await.unwrap();
+    ///     assert!(matches!(events.recv().await.unwrap(),BulletinBoardEvent::LeafAdded{leaf,..} if leaf==a));
+    /// });
+    /// ```
+    pub fn subscribe(&mut self) -> async_std::channel::Receiver<BulletinBoardEvent> {
+        self.state.events.subscribe()
+    }
+
+    /// Submit a JSON object as a structured leaf. See [crate::BulletinBoard::submit_structured_leaf].
+    pub async fn submit_structured_leaf(&mut self,object:&serde_json::Map<String,serde_json::Value>) -> Result<HashValue,BulletinBoardError> {
+        self.state.submit_structured_leaf(&mut AsyncAccess(&mut self.backend),object).await
+    }
+
+    /// Sign receipts for submissions with the given signer. See [crate::BulletinBoard::set_receipt_signer].
+    pub fn set_receipt_signer(&mut self,signer:ReceiptSigner) {
+        self.state.receipt_signer = Some(signer);
+    }
+
+    /// Submit a leaf and get a signed receipt for it. See [crate::BulletinBoard::submit_leaf_with_receipt].
+    pub async fn submit_leaf_with_receipt(&mut self,data:&str) -> Result<SignedReceipt,BulletinBoardError> {
+        self.state.submit_leaf_with_receipt(&mut AsyncAccess(&mut self.backend),data).await
+    }
+
+    /// Get a signed receipt for an existing leaf. See [crate::BulletinBoard::get_receipt].
+    pub async fn get_receipt(&self,leaf:HashValue) -> Result<SignedReceipt,BulletinBoardError> {
+        self.state.get_receipt(&AsyncAccess(&self.backend),leaf).await
+    }
+
+    /// Get the evidence needed to check whether a receipt was honoured. See [crate::BulletinBoard::get_receipt_evidence].
+    pub async fn get_receipt_evidence(&self,receipt:&SubmissionReceipt) -> Result<ReceiptEvidence,BulletinBoardError> {
+        get_receipt_evidence_using(&AsyncAccess(&self.backend),receipt).await
+    }
+
+    /// See [crate::BulletinBoard::get_ordering_proof].
+    pub async fn get_ordering_proof(&self,earlier:HashValue,later:HashValue) -> Result<OrderingProof,BulletinBoardError> {
+        get_ordering_proof(&AsyncAccess(&self.backend),earlier,later).await
+    }
+
+    /// See [crate::BulletinBoard::get_ancestry_proof].
+    pub async fn get_ancestry_proof(&self,earlier:HashValue,later:HashValue) -> Result<AncestryProof,BulletinBoardError> {
+        get_ancestry_proof(&AsyncAccess(&self.backend),earlier,later).await
+    }
+
+    /// See [crate::BulletinBoard::get_leaf_count_proof].
+    pub async fn get_leaf_count_proof(&self,published_root:HashValue) -> Result<LeafCountProof,BulletinBoardError> {
+        get_leaf_count_proof(&AsyncAccess(&self.backend),published_root).await
+    }
+
+    /// See [crate::BulletinBoard::get_leaf_count].
+    pub async fn get_leaf_count(&self) -> Result<u64,BulletinBoardError> {
+        self.backend.get_leaf_count().await
+    }
+
+    /// See [crate::BulletinBoard::get_leaf_by_index].
+    pub async fn get_leaf_by_index(&self,index:u64) -> Result<Option<HashValue>,BulletinBoardError> {
+        self.backend.get_leaf_by_index(index).await
+    }
+
+    /// See [crate::BulletinBoard::list_leaves].
+    pub async fn list_leaves(&self,start:u64,count:u64) -> Result<Vec<HashValue>,BulletinBoardError> {
+        self.backend.list_leaves(start,count).await
+    }
+
+    /// See [crate::BulletinBoard::list_leaves_between].
+    pub async fn list_leaves_between(&self,t0:Timestamp,t1:Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> {
+        self.backend.list_leaves_between(t0,t1).await
+    }
+
+    /// See [crate::BulletinBoard::get_published_root_at].
+    pub async fn get_published_root_at(&self,t:Timestamp) -> Result<Option<HashValue>,BulletinBoardError> {
+        self.backend.get_published_root_at(t).await
+    }
+
+    /// See [crate::BulletinBoard::find_leaves_by_data].
+    pub async fn find_leaves_by_data(&self,data:&str) -> Result<Vec<HashValue>,BulletinBoardError> {
+        find_leaves_by_data_using(&AsyncAccess(&self.backend),data).await
+    }
+
+    /// Get the current published head that everyone knows. See [crate::BulletinBoard::get_most_recent_published_root].
+    pub async fn get_most_recent_published_root(&self) -> Result<Option<HashValue>,BulletinBoardError> {
+        self.backend.get_most_recent_published_root().await
+    }
+
+    /// Get a list of all published roots, ordered oldest to newest.
+    pub async fn get_all_published_roots(&self) -> Result<Vec<HashValue>,BulletinBoardError> {
+        self.backend.get_all_published_roots().await
+    }
+
+    /// Get the currently committed to, but not yet published, hash values. See [crate::BulletinBoard::get_parentless_unpublished_hash_values].
+    pub async fn get_parentless_unpublished_hash_values(&self) -> Result<Vec<HashValue>,BulletinBoardError> {
+        self.state.get_parentless_unpublished_hash_values(&AsyncAccess(&self.backend)).await
+    }
+
+    /// Request a new published root if anything has changed. See [crate::BulletinBoard::order_new_published_root_if_changed].
+    pub async fn order_new_published_root_if_changed(&mut self) -> Result<Option<HashValue>,BulletinBoardError> {
+        self.state.order_new_published_root_if_changed(&mut AsyncAccess(&mut self.backend)).await
+    }
+
+    /// Request a new published root. See [crate::BulletinBoard::order_new_published_root].
+    pub async fn order_new_published_root(&mut self) -> Result<HashValue,BulletinBoardError> {
+        self.state.order_new_published_root(&mut AsyncAccess(&mut self.backend)).await
+    }
+
+    /// Get information about a HashValue, assuming it exists. See [crate::BulletinBoard::get_hash_info].
+    pub async fn get_hash_info(&self, query:HashValue) -> Result<HashInfo,BulletinBoardError> {
+        self.backend.get_hash_info(query).await?.ok_or(BulletinBoardError::NoSuchHash)
+    }
+
+    /// Get a whole proof chain at once. See [crate::BulletinBoard::get_proof_chain].
+    pub async fn get_proof_chain(&self,query:HashValue) -> Result<FullProof,BulletinBoardError> {
+        get_proof_chain(&AsyncAccess(&self.backend),query).await
+    }
+
+    /// See [crate::BulletinBoard::first_including_root].
+    pub async fn first_including_root(&self,query:HashValue) -> Result<Option<FullProof>,BulletinBoardError> {
+        first_including_root_with_proof(&AsyncAccess(&self.backend),query).await
+    }
+
+    /// Censor a leaf, adding a record of the censorship to the board. See [crate::BulletinBoard::censor_leaf].
+    pub async fn censor_leaf(&mut self,leaf_to_censor:HashValue,reason:&str,authority:&str) -> Result<HashValue,BulletinBoardError> {
+        self.state.censor_leaf(&mut AsyncAccess(&mut self.backend),leaf_to_censor,reason,authority).await
+    }
+
+    /// Censor one field of a structured leaf. See [crate::BulletinBoard::redact_field].
+    pub async fn redact_field(&mut self,leaf:HashValue,field:&str,reason:&str,authority:&str) -> Result<HashValue,BulletinBoardError> {
+        self.state.redact_field(&mut AsyncAccess(&mut self.backend),leaf,field,reason,authority).await
+    }
+
+    /// Reverse a censorship by supplying the original text of the leaf. See [crate::BulletinBoard::uncensor_leaf].
+    pub async fn uncensor_leaf(&mut self,leaf_to_uncensor:HashValue,data:&str,reason:&str,authority:&str) -> Result<HashValue,BulletinBoardError> {
+        self.state.uncensor_leaf(&mut AsyncAccess(&mut self.backend),leaf_to_uncensor,data,reason,authority).await
+    }
+
+    /// Get the records of censorship between two published roots. See [crate::BulletinBoard::get_censorship_records_between].
+    pub async fn get_censorship_records_between(&self,from:Option<HashValue>,to:HashValue) -> Result<Vec<CensorshipEvent>,BulletinBoardError> {
+        get_censorship_records_between_using(&AsyncAccess(&self.backend),from,to).await
+    }
+}
+
+/// The async equivalent of [crate::shared_backend::BulletinBoardReader]; a cloneable read only handle to a bulletin board.
+///
+/// The backend given should be a clone of the [BackendBlocking] that the writer, an [AsyncBulletinBoard], uses.
+/// Reads do not go through the writer, so can proceed in parallel with it and with each other. Each function
+/// is done under a single read lock on the backend, so a proof is consistent even if the writer publishes meanwhile.
+///
+/// # Example
+///
+/// ```
+/// use merkle_tree_bulletin_board::async_backend_blocking::BackendBlocking;
+/// use merkle_tree_bulletin_board::async_board::{AsyncBulletinBoard, AsyncBulletinBoardReader};
+/// use merkle_tree_bulletin_board::backend_memory::BackendMemory;
+///
+/// let backend = BackendBlocking::new(BackendMemory::default());
+/// let reader = AsyncBulletinBoardReader::new(backend.clone());
+/// async_std::task::block_on(async {
+///     let mut board = AsyncBulletinBoard::new(backend).await.unwrap();
+///     let hash = board.submit_leaf("a").await.unwrap();
+///     assert_eq!(reader.get_parentless_unpublished_hash_values().await.unwrap(),vec![hash]);
+///     let root = board.order_new_published_root().await.unwrap();
+///     assert_eq!(reader.get_proof_chain(hash).await.unwrap().published_root.unwrap().hash,root);
+/// });
+/// ```
+#[derive(Clone)]
+pub struct AsyncBulletinBoardReader<B:BulletinBoardBackend+Send+Sync+'static> {
+    backend : BackendBlocking<B>,
+}
+
+impl <B:BulletinBoardBackend+Send+Sync+'static> AsyncBulletinBoardReader<B> {
+    /// Make a reader using the given backend, which should be a clone of the one the writer uses.
+    pub fn new(backend:BackendBlocking<B>) -> Self { AsyncBulletinBoardReader { backend } }
+
+    /// See [crate::BulletinBoard::get_most_recent_published_root].
+    pub async fn get_most_recent_published_root(&self) -> Result<Option<HashValue>,BulletinBoardError> {
+        self.backend.read(|b|b.get_most_recent_published_root()).await
+    }
+
+    /// See [crate::BulletinBoard::get_all_published_roots].
+    pub async fn get_all_published_roots(&self) -> Result<Vec<HashValue>,BulletinBoardError> {
+        self.backend.read(|b|b.get_all_published_roots()).await
+    }
+
+    /// See [crate::BulletinBoard::get_parentless_unpublished_hash_values].
+    /// As a reader does not track the current forest, this recomputes it from the backend.
+    pub async fn get_parentless_unpublished_hash_values(&self) -> Result<Vec<HashValue>,BulletinBoardError> {
+        self.backend.read(|b|run_ready(remove_published(&SyncAccess(b),b.compute_current_forest()?.get_subtrees()))).await
+    }
+
+    /// See [crate::BulletinBoard::get_hash_info].
+    pub async fn get_hash_info(&self, query:HashValue) -> Result<HashInfo,BulletinBoardError> {
+        self.backend.read(move |b|b.get_hash_info(query)).await?.ok_or(BulletinBoardError::NoSuchHash)
+    }
+
+    /// See [crate::BulletinBoard::get_proof_chain].
+    pub async fn get_proof_chain(&self,query:HashValue) -> Result<FullProof,BulletinBoardError> {
+        self.backend.read(move |b|run_ready(get_proof_chain(&SyncAccess(b),query))).await
+    }
+
+    /// See [crate::BulletinBoard::first_including_root]. Computed under one lock, so is consistent.
+    pub async fn first_including_root(&self,query:HashValue) -> Result<Option<FullProof>,BulletinBoardError> {
+        self.backend.read(move |b|run_ready(first_including_root_with_proof(&SyncAccess(b),query))).await
+    }
+
+    /// See [crate::BulletinBoard::get_receipt_evidence]. Computed under one lock, so is consistent.
+    pub async fn get_receipt_evidence(&self,receipt:&SubmissionReceipt) -> Result<ReceiptEvidence,BulletinBoardError> {
+        let receipt = *receipt;
+        self.backend.read(move |b|run_ready(get_receipt_evidence_using(&SyncAccess(b),&receipt))).await
+    }
+
+    /// See [crate::BulletinBoard::get_ordering_proof]. Computed under one lock, so both proofs are for the same root.
+    pub async fn get_ordering_proof(&self,earlier:HashValue,later:HashValue) -> Result<OrderingProof,BulletinBoardError> {
+        self.backend.read(move |b|run_ready(get_ordering_proof(&SyncAccess(b),earlier,later))).await
+    }
+
+    /// See [crate::BulletinBoard::get_ancestry_proof]. Computed under one lock, so is consistent.
+    pub async fn get_ancestry_proof(&self,earlier:HashValue,later:HashValue) -> Result<AncestryProof,BulletinBoardError> {
+        self.backend.read(move |b|run_ready(get_ancestry_proof(&SyncAccess(b),earlier,later))).await
+    }
+
+    /// See [crate::BulletinBoard::get_leaf_count_proof]. Computed under one lock, so is consistent.
+    pub async fn get_leaf_count_proof(&self,published_root:HashValue) -> Result<LeafCountProof,BulletinBoardError> {
+        self.backend.read(move |b|run_ready(get_leaf_count_proof(&SyncAccess(b),published_root))).await
+    }
+
+    /// See [crate::BulletinBoard::get_leaf_count].
+    pub async fn get_leaf_count(&self) -> Result<u64,BulletinBoardError> {
+        self.backend.read(|b|b.get_leaf_count()).await
+    }
+
+    /// See [crate::BulletinBoard::get_leaf_by_index].
+    pub async fn get_leaf_by_index(&self,index:u64) -> Result<Option<HashValue>,BulletinBoardError> {
+        self.backend.read(move |b|b.get_leaf_by_index(index)).await
+    }
+
+    /// See [crate::BulletinBoard::list_leaves].
+    pub async fn list_leaves(&self,start:u64,count:u64) -> Result<Vec<HashValue>,BulletinBoardError> {
+        self.backend.read(move |b|b.list_leaves(start,count)).await
+    }
+
+    /// See [crate::BulletinBoard::list_leaves_between].
+    pub async fn list_leaves_between(&self,t0:Timestamp,t1:Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> {
+        self.backend.read(move |b|b.list_leaves_between(t0,t1)).await
+    }
+
+    /// See [crate::BulletinBoard::get_published_root_at].
+    pub async fn get_published_root_at(&self,t:Timestamp) -> Result<Option<HashValue>,BulletinBoardError> {
+        self.backend.read(move |b|b.get_published_root_at(t)).await
+    }
+
+    /// See [crate::BulletinBoard::find_leaves_by_data].
+    pub async fn find_leaves_by_data(&self,data:&str) -> Result<Vec<HashValue>,BulletinBoardError> {
+        let data = data.to_string();
+        self.backend.read(move |b|run_ready(find_leaves_by_data_using(&SyncAccess(b),&data))).await
+    }
+
+    /// Wait until `leaf` is included in a published root, returning its proof chain. `events` should come from
+    /// the writer's [AsyncBulletinBoard::subscribe]. See [crate::inclusion::wait_for_inclusion_async].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::async_board::{AsyncBulletinBoard, AsyncBulletinBoardReader};
+    /// use merkle_tree_bulletin_board::async_backend_blocking::BackendBlocking;
+    /// use merkle_tree_bulletin_board::backend_memory::BackendMemory;
+    /// use std::time::Duration;
+    ///
+    /// async_std::task::block_on(async {
+    ///     let backend = BackendBlocking::new(BackendMemory::default());
+    ///     let reader = AsyncBulletinBoardReader::new(backend.clone());
+    ///     let mut board = AsyncBulletinBoard::new(backend).await.unwrap();
+    ///     let leaf = board.submit_leaf("a").await.unwrap();
+    ///     let events = board.subscribe();
+    ///     let waiting = async_std::task::spawn(async move { reader.wait_for_inclusion(&events,leaf,Duration::from_secs(10)).await });
+    ///     let root = board.order_new_published_root().await.unwrap(); // the waiter is told of this even if it has not started yet.
+    ///     assert_eq!(waiting.await.unwrap().published_root.unwrap().hash,root);
+    /// });
+    /// ```
+    pub async fn wait_for_inclusion(&self,events:&async_std::channel::Receiver<BulletinBoardEvent>,leaf:HashValue,timeout:Duration) -> Result<FullProof,BulletinBoardError> {
+        wait_for_inclusion_async(events,leaf,timeout,|hash|self.get_proof_chain(hash)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::async_backend_blocking::BackendBlocking;
+    use crate::async_board::{AsyncBulletinBoard, AsyncBulletinBoardBackend};
+    use crate::backend_memory::BackendMemory;
+    use crate::{BulletinBoardBackend, BulletinBoardError, DatabaseTransaction};
+    use crate::hash::HashValue;
+    use crate::hash_history::{BranchHashHistory, HashInfo, HashSource};
+    use crate::verifier::verify_proof;
+
+    /// A memory backend using the default publish_if_unchanged, so it can be shared between boards via [BackendBlocking].
+    #[derive(Default)]
+    struct CheckedMemory(BackendMemory);
+
+    impl BulletinBoardBackend for CheckedMemory {
+        fn get_all_published_roots(&self) -> Result<Vec<HashValue>, BulletinBoardError> { BulletinBoardBackend::get_all_published_roots(&self.0) }
+        fn get_most_recent_published_root(&self) -> Result<Option<HashValue>, BulletinBoardError> { BulletinBoardBackend::get_most_recent_published_root(&self.0) }
+        fn get_all_leaves_and_branches_without_a_parent(&self) -> Result<Vec<HashValue>, BulletinBoardError> { BulletinBoardBackend::get_all_leaves_and_branches_without_a_parent(&self.0) }
+        fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>, BulletinBoardError> { BulletinBoardBackend::get_hash_info(&self.0,query) }
+        fn publish(&mut self, transaction: &DatabaseTransaction) -> Result<(), BulletinBoardError> { BulletinBoardBackend::publish(&mut self.0,transaction) }
+        fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(), BulletinBoardError> { BulletinBoardBackend::censor_leaf(&mut self.0,leaf_to_censor) }
+    }
+
+    #[test]
+    /// Test that two async boards writing to the same backend build a single consistent tree.
+    fn test_two_writers() {
+        async_std::task::block_on(async {
+            let backend = BackendBlocking::new(CheckedMemory::default());
+            let mut board1 = AsyncBulletinBoard::new(backend.clone()).await.unwrap();
+            let mut board2 = AsyncBulletinBoard::new(backend.clone()).await.unwrap();
+            let hash_a = board1.submit_leaf("a").await.unwrap();
+            let hash_b = board2.submit_leaf("b").await.unwrap(); // board2 does not know about "a" until it tries to write.
+            let branch_ab = board2.get_hash_info(hash_a).await.unwrap().parent.unwrap();
+            assert_eq!(board2.get_hash_info(branch_ab).await.unwrap().source,HashSource::Branch(BranchHashHistory{left:hash_a,right:hash_b}));
+            assert_eq!(backend.get_all_leaves_and_branches_without_a_parent().await.unwrap(),vec![branch_ab]);
+            let root1 = board2.order_new_published_root().await.unwrap();
+            board1.submit_leaf("c").await.unwrap(); // board1 does not know about root1.
+            let root2 = board1.order_new_published_root().await.unwrap();
+            match board1.get_hash_info(root2).await.unwrap().source {
+                HashSource::Root(history) => assert_eq!(history.prior,Some(root1)),
+                _ => panic!("Not a root"),
+            }
+        });
+    }
+
+    #[test]
+    /// Test that a board can be moved into a spawned task, which needs its futures to be Send, and used there.
+    fn test_board_in_spawned_task() {
+        let backend = BackendBlocking::new(BackendMemory::default());
+        let (hash,root,proof) = async_std::task::block_on(async_std::task::spawn(async move {
+            let mut board = AsyncBulletinBoard::new(backend).await.unwrap();
+            let hash = board.submit_leaf("a").await.unwrap();
+            board.submit_leaf("b").await.unwrap();
+            let root = board.order_new_published_root().await.unwrap();
+            assert_eq!(board.get_parentless_unpublished_hash_values().await.unwrap(),vec![]);
+            (hash,root,board.get_proof_chain(hash).await.unwrap())
+        }));
+        assert_eq!(proof.chain[0].hash,hash);
+        assert_eq!(verify_proof("a",root,&proof),None);
+    }
+}
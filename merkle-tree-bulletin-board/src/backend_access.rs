@@ -0,0 +1,218 @@
+//! Access to a backend by the logic shared between [crate::BulletinBoard] and, with the `async` feature,
+//! [crate::async_board::AsyncBulletinBoard].
+//!
+//! The logic of the bulletin board (and of the journal and default backend functions) is written once, as
+//! async functions using [ReadAccess] and [WriteAccess]. A synchronous [BulletinBoardBackend] is used through
+//! [SyncAccess], whose futures never wait for anything, so they are run to completion with [run_ready].
+//! An [AsyncBulletinBoardBackend] is used through [AsyncAccess], and the futures awaited.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::iter::FromIterator;
+use std::ops::{Deref, DerefMut};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use crate::{BulletinBoardBackend, BulletinBoardError, DatabaseTransaction};
+use crate::growing_forest::GrowingForest;
+use crate::hash::HashValue;
+use crate::hash_history::{HashInfo, HashSource, Timestamp};
+#[cfg(feature = "async")]
+use crate::async_board::AsyncBulletinBoardBackend;
+
+/// Read only access to a backend. See [BulletinBoardBackend] for the meaning of each function.
+pub(crate) trait ReadAccess {
+    async fn get_all_published_roots(&self) -> Result<Vec<HashValue>,BulletinBoardError>;
+    async fn get_most_recent_published_root(&self) -> Result<Option<HashValue>,BulletinBoardError>;
+    async fn get_all_leaves_and_branches_without_a_parent(&self) -> Result<Vec<HashValue>,BulletinBoardError>;
+    async fn get_hash_info(&self, query:HashValue) -> Result<Option<HashInfo>,BulletinBoardError>;
+    async fn get_depth(&self,hash:HashValue) -> Result<usize,BulletinBoardError>;
+    async fn compute_current_forest(&self) -> Result<GrowingForest,BulletinBoardError>;
+    async fn get_leaf_for_idempotency_key(&self,key:&str) -> Result<Option<HashValue>,BulletinBoardError>;
+    async fn get_leaves_with_content_hash(&self,content_hash:HashValue) -> Result<Vec<HashValue>,BulletinBoardError>;
+    async fn get_leaf_by_index(&self,index:u64) -> Result<Option<HashValue>,BulletinBoardError>;
+    async fn get_leaf_index(&self,leaf:HashValue) -> Result<Option<u64>,BulletinBoardError>;
+    async fn list_leaves_between(&self,t0:Timestamp,t1:Timestamp) -> Result<Vec<HashValue>,BulletinBoardError>;
+    /// Do some blocking work, such as file I/O. Async code does it on a thread pool for blocking operations.
+    async fn run_blocking<R:Send+'static>(&self,work:impl FnOnce()->R+Send+'static) -> R;
+}
+
+/// Access to a backend that can change it.
+pub(crate) trait WriteAccess : ReadAccess {
+    async fn publish_if_unchanged(&mut self,transaction:&DatabaseTransaction,expected_parentless:&[HashValue]) -> Result<(),BulletinBoardError>;
+    async fn censor_leaf(&mut self,leaf_to_censor:HashValue) -> Result<(),BulletinBoardError>;
+    async fn uncensor_leaf(&mut self,leaf_to_uncensor:HashValue,data:&str) -> Result<(),BulletinBoardError>;
+    async fn store_idempotency_key(&mut self,key:&str,leaf:HashValue) -> Result<(),BulletinBoardError>;
+    /// Wait a while before retrying something.
+    async fn sleep(&self,duration:Duration);
+}
+
+/// Access to a synchronous backend, or a reference to one.
+pub(crate) struct SyncAccess<B>(pub(crate) B);
+
+impl <B:Deref<Target:BulletinBoardBackend>> ReadAccess for SyncAccess<B> {
+    async fn get_all_published_roots(&self) -> Result<Vec<HashValue>,BulletinBoardError> { self.0.get_all_published_roots() }
+    async fn get_most_recent_published_root(&self) -> Result<Option<HashValue>,BulletinBoardError> { self.0.get_most_recent_published_root() }
+    async fn get_all_leaves_and_branches_without_a_parent(&self) -> Result<Vec<HashValue>,BulletinBoardError> { self.0.get_all_leaves_and_branches_without_a_parent() }
+    async fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>,BulletinBoardError> { self.0.get_hash_info(query) }
+    async fn get_depth(&self, hash: HashValue) -> Result<usize,BulletinBoardError> { self.0.get_depth(hash) }
+    async fn compute_current_forest(&self) -> Result<GrowingForest,BulletinBoardError> { self.0.compute_current_forest() }
+    async fn get_leaf_for_idempotency_key(&self, key: &str) -> Result<Option<HashValue>,BulletinBoardError> { self.0.get_leaf_for_idempotency_key(key) }
+    async fn get_leaves_with_content_hash(&self, content_hash: HashValue) -> Result<Vec<HashValue>,BulletinBoardError> { self.0.get_leaves_with_content_hash(content_hash) }
+    async fn get_leaf_by_index(&self, index: u64) -> Result<Option<HashValue>,BulletinBoardError> { self.0.get_leaf_by_index(index) }
+    async fn get_leaf_index(&self, leaf: HashValue) -> Result<Option<u64>,BulletinBoardError> { self.0.get_leaf_index(leaf) }
+    async fn list_leaves_between(&self, t0: Timestamp, t1: Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> { self.0.list_leaves_between(t0,t1) }
+    async fn run_blocking<R:Send+'static>(&self,work:impl FnOnce()->R+Send+'static) -> R { work() }
+}
+
+impl <B:DerefMut<Target:BulletinBoardBackend>> WriteAccess for SyncAccess<B> {
+    async fn publish_if_unchanged(&mut self, transaction: &DatabaseTransaction, expected_parentless: &[HashValue]) -> Result<(),BulletinBoardError> { self.0.publish_if_unchanged(transaction,expected_parentless) }
+    async fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(),BulletinBoardError> { self.0.censor_leaf(leaf_to_censor) }
+    async fn uncensor_leaf(&mut self, leaf_to_uncensor: HashValue, data: &str) -> Result<(),BulletinBoardError> { self.0.uncensor_leaf(leaf_to_uncensor,data) }
+    async fn store_idempotency_key(&mut self, key: &str, leaf: HashValue) -> Result<(),BulletinBoardError> { self.0.store_idempotency_key(key,leaf) }
+    async fn sleep(&self, duration: Duration) { std::thread::sleep(duration) }
+}
+
+/// Access to an async backend, or a reference to one.
+#[cfg(feature = "async")]
+pub(crate) struct AsyncAccess<B>(pub(crate) B);
+
+#[cfg(feature = "async")]
+impl <B:Deref<Target:AsyncBulletinBoardBackend>> ReadAccess for AsyncAccess<B> {
+    async fn get_all_published_roots(&self) -> Result<Vec<HashValue>,BulletinBoardError> { self.0.get_all_published_roots().await }
+    async fn get_most_recent_published_root(&self) -> Result<Option<HashValue>,BulletinBoardError> { self.0.get_most_recent_published_root().await }
+    async fn get_all_leaves_and_branches_without_a_parent(&self) -> Result<Vec<HashValue>,BulletinBoardError> { self.0.get_all_leaves_and_branches_without_a_parent().await }
+    async fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>,BulletinBoardError> { self.0.get_hash_info(query).await }
+    async fn get_depth(&self, hash: HashValue) -> Result<usize,BulletinBoardError> { self.0.get_depth(hash).await }
+    async fn compute_current_forest(&self) -> Result<GrowingForest,BulletinBoardError> { self.0.compute_current_forest().await }
+    async fn get_leaf_for_idempotency_key(&self, key: &str) -> Result<Option<HashValue>,BulletinBoardError> { self.0.get_leaf_for_idempotency_key(key).await }
+    async fn get_leaves_with_content_hash(&self, content_hash: HashValue) -> Result<Vec<HashValue>,BulletinBoardError> { self.0.get_leaves_with_content_hash(content_hash).await }
+    async fn get_leaf_by_index(&self, index: u64) -> Result<Option<HashValue>,BulletinBoardError> { self.0.get_leaf_by_index(index).await }
+    async fn get_leaf_index(&self, leaf: HashValue) -> Result<Option<u64>,BulletinBoardError> { self.0.get_leaf_index(leaf).await }
+    async fn list_leaves_between(&self, t0: Timestamp, t1: Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> { self.0.list_leaves_between(t0,t1).await }
+    async fn run_blocking<R:Send+'static>(&self,work:impl FnOnce()->R+Send+'static) -> R { async_std::task::spawn_blocking(work).await }
+}
+
+#[cfg(feature = "async")]
+impl <B:DerefMut<Target:AsyncBulletinBoardBackend>> WriteAccess for AsyncAccess<B> {
+    async fn publish_if_unchanged(&mut self, transaction: &DatabaseTransaction, expected_parentless: &[HashValue]) -> Result<(),BulletinBoardError> { self.0.publish_if_unchanged(transaction,expected_parentless).await }
+    async fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(),BulletinBoardError> { self.0.censor_leaf(leaf_to_censor).await }
+    async fn uncensor_leaf(&mut self, leaf_to_uncensor: HashValue, data: &str) -> Result<(),BulletinBoardError> { self.0.uncensor_leaf(leaf_to_uncensor,data).await }
+    async fn store_idempotency_key(&mut self, key: &str, leaf: HashValue) -> Result<(),BulletinBoardError> { self.0.store_idempotency_key(key,leaf).await }
+    async fn sleep(&self, duration: Duration) { async_std::task::sleep(duration).await }
+}
+
+/// Get the result of a future that never waits for anything, such as the shared logic run through a [SyncAccess].
+pub(crate) fn run_ready<T>(future:impl Future<Output=T>) -> T {
+    let mut future = std::pin::pin!(future);
+    match future.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(res) => res,
+        Poll::Pending => panic!("A synchronous bulletin board operation tried to wait"),
+    }
+}
+
+// The default implementations of backend functions, shared by BulletinBoardBackend and AsyncBulletinBoardBackend.
+
+/// See [BulletinBoardBackend::check_unchanged].
+pub(crate) async fn check_unchanged(backend:&impl ReadAccess,transaction:&DatabaseTransaction,expected_parentless:&[HashValue]) -> Result<(),BulletinBoardError> {
+    let actual : HashSet<HashValue> = HashSet::from_iter(backend.get_all_leaves_and_branches_without_a_parent().await?);
+    let expected : HashSet<HashValue> = HashSet::from_iter(expected_parentless.iter().cloned());
+    if actual!=expected { return Err(BulletinBoardError::ConcurrentModification); }
+    for (_,source) in &transaction.pending {
+        if let HashSource::Root(history) = source {
+            if history.prior!=backend.get_most_recent_published_root().await? { return Err(BulletinBoardError::ConcurrentModification); }
+        }
+    }
+    Ok(())
+}
+
+/// See [BulletinBoardBackend::left_depth].
+pub(crate) async fn left_depth(backend:&impl ReadAccess,hash:HashValue) -> Result<usize,BulletinBoardError> {
+    let mut res = 0;
+    let mut hash = hash;
+    while let Some(HashInfo{source:HashSource::Branch(history),..}) = backend.get_hash_info(hash).await? {
+        res+=1;
+        hash = history.left;
+    }
+    Ok(res)
+}
+
+/// See [BulletinBoardBackend::compute_current_forest].
+pub(crate) async fn compute_current_forest(backend:&impl ReadAccess) -> Result<GrowingForest,BulletinBoardError> {
+    let hashes = backend.get_all_leaves_and_branches_without_a_parent().await?;
+    let mut depths : HashMap<HashValue,usize> = HashMap::default();
+    for hash in &hashes {
+        depths.insert(*hash,backend.get_depth(*hash).await?);
+    }
+    GrowingForest::new(&hashes,|h|Ok(depths[&h]))
+}
+
+/// See [BulletinBoardBackend::list_leaves].
+pub(crate) async fn list_leaves(backend:&impl ReadAccess,start:u64,count:u64) -> Result<Vec<HashValue>,BulletinBoardError> {
+    let mut res = vec![];
+    for index in start..start.saturating_add(count) {
+        match backend.get_leaf_by_index(index).await? {
+            Some(leaf) => res.push(leaf),
+            None => break,
+        }
+    }
+    Ok(res)
+}
+
+/// See [BulletinBoardBackend::list_leaves_between].
+pub(crate) async fn list_leaves_between(backend:&impl ReadAccess,t0:Timestamp,t1:Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> {
+    let mut res = vec![];
+    let mut to_visit = backend.compute_current_forest().await?.get_subtrees();
+    to_visit.reverse(); // used as a stack, so the leftmost tree is visited first.
+    while let Some(hash) = to_visit.pop() {
+        match backend.get_hash_info(hash).await? {
+            Some(HashInfo{source:HashSource::Branch(history),..}) => { to_visit.push(history.right); to_visit.push(history.left); }
+            Some(HashInfo{source:HashSource::Leaf(history),..}) => if history.timestamp>=t0 && history.timestamp<t1 { res.push((history.timestamp,hash)); },
+            _ => return Err(BulletinBoardError::BackendInconsistentError(format!("{} should be a leaf or branch",hash))),
+        }
+    }
+    res.sort_by_key(|(timestamp,_)|*timestamp); // stable, so sequence order is kept for equal timestamps.
+    Ok(res.into_iter().map(|(_,leaf)|leaf).collect())
+}
+
+/// See [BulletinBoardBackend::get_published_root_at].
+pub(crate) async fn get_published_root_at(backend:&impl ReadAccess,t:Timestamp) -> Result<Option<HashValue>,BulletinBoardError> {
+    let mut res : Option<(Timestamp,HashValue)> = None;
+    for root in backend.get_all_published_roots().await? {
+        if let Some(HashInfo{source:HashSource::Root(history),..}) = backend.get_hash_info(root).await? {
+            if history.timestamp<=t && res.map(|(best,_)|history.timestamp>=best).unwrap_or(true) { res=Some((history.timestamp,root)); }
+        }
+    }
+    Ok(res.map(|(_,root)|root))
+}
+
+/// See [BulletinBoardBackend::leaf_by_index_from_tree].
+pub(crate) async fn leaf_by_index_from_tree(backend:&impl ReadAccess,index:u64) -> Result<Option<HashValue>,BulletinBoardError> {
+    let Some((mut hash,depth,mut index)) = backend.compute_current_forest().await?.find_tree_containing(index) else { return Ok(None) };
+    for level in (0..depth).rev() {
+        match backend.get_hash_info(hash).await? {
+            Some(HashInfo{source:HashSource::Branch(history),..}) => {
+                let half = 1u64<<level;
+                if index<half { hash=history.left } else { hash=history.right; index-=half; }
+            }
+            _ => return Err(BulletinBoardError::BackendInconsistentError(format!("{} should be a branch of depth {}",hash,level+1))),
+        }
+    }
+    Ok(Some(hash))
+}
+
+/// See [BulletinBoardBackend::leaf_index_from_tree].
+pub(crate) async fn leaf_index_from_tree(backend:&impl ReadAccess,leaf:HashValue) -> Result<Option<u64>,BulletinBoardError> {
+    let Some(HashInfo{source:HashSource::Leaf(_),mut parent}) = backend.get_hash_info(leaf).await? else { return Ok(None) };
+    let (mut node,mut position,mut level) = (leaf,0u64,0);
+    while let Some(branch) = parent {
+        match backend.get_hash_info(branch).await? {
+            Some(HashInfo{source:HashSource::Branch(history),parent:grandparent}) => {
+                if history.right==node { position+=1u64<<level; }
+                (node,parent,level) = (branch,grandparent,level+1);
+            }
+            _ => return Err(BulletinBoardError::BackendInconsistentError(format!("The parent {} of {} is not a branch",branch,node))),
+        }
+    }
+    let earlier = backend.compute_current_forest().await?.leaves_before_tree(node).ok_or_else(||BulletinBoardError::BackendInconsistentError(format!("{} has no parent but is not in the current forest",node)))?;
+    Ok(Some(earlier+position))
+}
@@ -0,0 +1,218 @@
+//! A caching wrapper around some other backend.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+use crate::{BulletinBoardBackend, BulletinBoardError, DatabaseTransaction};
+use crate::hash::HashValue;
+use crate::hash_history::{HashInfo, HashSource, Timestamp};
+
+/// Add a read-through cache to some other backend.
+///
+/// Generating a proof chain looks up the most recent published root and the same few upper branches
+/// on every request, which for a database backend means repeated queries for the same data. This keeps
+/// a bounded, least recently used, cache of [HashInfo] results, and the list of published roots.
+///
+/// The cache is kept correct by invalidating entries as writes go through it:
+///  * Publishing a branch changes the parent of its two children, so they are removed from the cache.
+///  * Publishing a root changes the list of published roots.
+///  * Censoring a leaf removes the leaf from the cache, so the censored text is never returned. Uncensoring does likewise.
+///
+/// This means that all writes to the underlying backend must go through this cache. Do not use it
+/// when other processes write to the same database (see [BulletinBoardBackend::publish_if_unchanged]).
+///
+/// # Example
+///
+/// ```
+/// use merkle_tree_bulletin_board::backend_cache::BackendCache;
+/// use merkle_tree_bulletin_board::backend_memory::BackendMemory;
+/// use merkle_tree_bulletin_board::BulletinBoard;
+/// use merkle_tree_bulletin_board::hash_history::{HashSource, LeafHashHistory};
+///
+/// let mut board = BulletinBoard::new(BackendCache::new(BackendMemory::default(),1000)).unwrap();
+/// let hash = board.submit_leaf("a").unwrap();
+/// board.order_new_published_root().unwrap();
+/// assert!(board.get_proof_chain(hash).is_ok()); // the leaf and the root are now cached.
+/// board.censor_leaf(hash,"Spam","Moderator").unwrap();
+/// match board.get_hash_info(hash).unwrap().source {
+///     HashSource::Leaf(LeafHashHistory{data:None,..}) => {}
+///     _ => panic!("Censored leaf should not come from the cache"),
+/// }
+/// ```
+pub struct BackendCache<B:BulletinBoardBackend> {
+    main_backend: B,
+    cache : Mutex<Cache>,
+}
+
+/// The contents of the cache. A least recently used cache is implemented by giving each
+/// entry a sequence number when used, and removing the entry with the smallest sequence number.
+struct Cache {
+    /// The maximum number of [HashInfo] entries to keep.
+    capacity : usize,
+    /// hash -> (info, sequence number when last used)
+    hash_info : HashMap<HashValue,(HashInfo,u64)>,
+    /// sequence number when last used -> hash. Inverse of the sequence number in hash_info.
+    last_used : BTreeMap<u64,HashValue>,
+    /// The next sequence number.
+    next_use : u64,
+    /// All published roots, if known.
+    published_roots : Option<Vec<HashValue>>,
+}
+
+impl Cache {
+    fn get(&mut self,hash:HashValue) -> Option<HashInfo> {
+        let use_number = self.next_use;
+        let (info,last_used) = self.hash_info.get_mut(&hash)?;
+        self.last_used.remove(last_used);
+        *last_used = use_number;
+        self.last_used.insert(use_number,hash);
+        self.next_use+=1;
+        Some(info.clone())
+    }
+
+    fn insert(&mut self,hash:HashValue,info:HashInfo) {
+        if self.capacity==0 { return; }
+        self.remove(hash);
+        while self.hash_info.len()>=self.capacity {
+            if let Some((_,oldest)) = self.last_used.pop_first() { self.hash_info.remove(&oldest); }
+        }
+        self.hash_info.insert(hash,(info,self.next_use));
+        self.last_used.insert(self.next_use,hash);
+        self.next_use+=1;
+    }
+
+    fn remove(&mut self,hash:HashValue) {
+        if let Some((_,last_used)) = self.hash_info.remove(&hash) { self.last_used.remove(&last_used); }
+    }
+
+    /// Remove anything that may have been changed by a transaction.
+    fn invalidate(&mut self,transaction:&DatabaseTransaction) {
+        for (hash,source) in &transaction.pending {
+            self.remove(*hash);
+            match source {
+                HashSource::Leaf(_) => {}
+                HashSource::Branch(history) => { // the children now have a parent.
+                    self.remove(history.left);
+                    self.remove(history.right);
+                }
+                HashSource::Root(_) => { self.published_roots=None; }
+            }
+        }
+    }
+}
+
+impl <B:BulletinBoardBackend> BulletinBoardBackend for BackendCache<B> {
+    fn get_all_published_roots(&self) -> Result<Vec<HashValue>,BulletinBoardError> {
+        self.published_roots()
+    }
+
+    fn get_most_recent_published_root(&self) -> Result<Option<HashValue>,BulletinBoardError> {
+        Ok(self.published_roots()?.last().cloned())
+    }
+
+    fn get_all_leaves_and_branches_without_a_parent(&self) -> Result<Vec<HashValue>,BulletinBoardError> { self.main_backend.get_all_leaves_and_branches_without_a_parent() }
+
+    fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>,BulletinBoardError> {
+        if let Some(info) = self.lock()?.get(query) { return Ok(Some(info)); }
+        let res = self.main_backend.get_hash_info(query)?;
+        if let Some(info) = &res { self.lock()?.insert(query,info.clone()); }
+        Ok(res)
+    }
+
+    fn publish(&mut self, transaction: &DatabaseTransaction) -> Result<(),BulletinBoardError> {
+        let res = self.main_backend.publish(transaction);
+        self.lock()?.invalidate(transaction);
+        res
+    }
+
+    /// Passed on to the underlying backend, as it may do the check and publication atomically.
+    fn publish_if_unchanged(&mut self, transaction: &DatabaseTransaction, expected_parentless: &[HashValue]) -> Result<(),BulletinBoardError> {
+        let res = self.main_backend.publish_if_unchanged(transaction,expected_parentless);
+        self.lock()?.invalidate(transaction);
+        res
+    }
+
+    fn check_unchanged(&self, transaction: &DatabaseTransaction, expected_parentless: &[HashValue]) -> Result<(),BulletinBoardError> {
+        self.main_backend.check_unchanged(transaction,expected_parentless)
+    }
+
+    fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(),BulletinBoardError> {
+        let res = self.main_backend.censor_leaf(leaf_to_censor);
+        self.lock()?.remove(leaf_to_censor);
+        res
+    }
+
+    fn uncensor_leaf(&mut self, leaf_to_uncensor: HashValue, data: &str) -> Result<(),BulletinBoardError> {
+        let res = self.main_backend.uncensor_leaf(leaf_to_uncensor,data);
+        self.lock()?.remove(leaf_to_uncensor);
+        res
+    }
+
+    fn get_depth(&self, hash: HashValue) -> Result<usize,BulletinBoardError> { self.main_backend.get_depth(hash) }
+
+    fn get_leaf_for_idempotency_key(&self, key: &str) -> Result<Option<HashValue>,BulletinBoardError> { self.main_backend.get_leaf_for_idempotency_key(key) }
+
+    fn store_idempotency_key(&mut self, key: &str, leaf: HashValue) -> Result<(),BulletinBoardError> { self.main_backend.store_idempotency_key(key,leaf) }
+
+    fn get_leaves_with_content_hash(&self, content_hash: HashValue) -> Result<Vec<HashValue>,BulletinBoardError> { self.main_backend.get_leaves_with_content_hash(content_hash) }
+
+    fn get_leaf_count(&self) -> Result<u64,BulletinBoardError> { self.main_backend.get_leaf_count() }
+
+    fn get_leaf_by_index(&self, index: u64) -> Result<Option<HashValue>,BulletinBoardError> { self.main_backend.get_leaf_by_index(index) }
+
+    fn get_leaf_index(&self, leaf: HashValue) -> Result<Option<u64>,BulletinBoardError> { self.main_backend.get_leaf_index(leaf) }
+
+    fn list_leaves(&self, start: u64, count: u64) -> Result<Vec<HashValue>,BulletinBoardError> { self.main_backend.list_leaves(start,count) }
+
+    fn list_leaves_between(&self, t0: Timestamp, t1: Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> { self.main_backend.list_leaves_between(t0,t1) }
+
+    fn get_published_root_at(&self, t: Timestamp) -> Result<Option<HashValue>,BulletinBoardError> { self.main_backend.get_published_root_at(t) }
+}
+
+impl <B:BulletinBoardBackend> BackendCache<B> {
+    /// Add a cache to an existing backend, holding at most `capacity` [HashInfo] entries.
+    pub fn new(main_backend:B,capacity:usize) -> Self {
+        let cache = Cache{ capacity, hash_info: HashMap::default(), last_used: BTreeMap::default(), next_use: 0, published_roots: None };
+        BackendCache { main_backend, cache: Mutex::new(cache) }
+    }
+
+    /// Get the underlying backend.
+    pub fn into_inner(self) -> B { self.main_backend }
+
+    fn lock(&self) -> Result<MutexGuard<'_,Cache>,BulletinBoardError> {
+        self.cache.lock().map_err(|_|BulletinBoardError::BackendInconsistentError("A prior operation on the cache panicked".to_string()))
+    }
+
+    /// Get the list of published roots, from the cache if possible.
+    fn published_roots(&self) -> Result<Vec<HashValue>,BulletinBoardError> {
+        if let Some(roots) = &self.lock()?.published_roots { return Ok(roots.clone()); }
+        let roots = self.main_backend.get_all_published_roots()?;
+        self.lock()?.published_roots = Some(roots.clone());
+        Ok(roots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend_cache::BackendCache;
+    use crate::backend_memory::BackendMemory;
+    use crate::BulletinBoard;
+    use crate::verifier::verify_proof;
+
+    #[test]
+    /// Test that proofs stay valid as the cache fills up and parents and roots change.
+    fn test_cache_invalidation() {
+        let mut board = BulletinBoard::new(BackendCache::new(BackendMemory::default(),5)).unwrap();
+        let mut leaves = vec![];
+        for i in 0..20 {
+            let data = i.to_string();
+            leaves.push((board.submit_leaf(&data).unwrap(),data));
+            let root = board.order_new_published_root().unwrap();
+            assert_eq!(board.get_most_recent_published_root().unwrap(),Some(root));
+            for (hash,data) in &leaves {
+                assert_eq!(verify_proof(data,root,&board.get_proof_chain(*hash).unwrap()),None);
+            }
+        }
+        assert_eq!(board.get_all_published_roots().unwrap().len(),20);
+        assert!(board.backend.cache.lock().unwrap().hash_info.len()<=5);
+    }
+}
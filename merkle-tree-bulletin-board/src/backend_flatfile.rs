@@ -1,7 +1,7 @@
 //! A backend for the database based on csv files.
 
 use crate::backend_memory::BackendMemory;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use crate::{DatabaseTransaction, BulletinBoardBackend, BulletinBoardError};
 use csv::{WriterBuilder, ReaderBuilder, StringRecord};
 use std::io::{Write, Read};
@@ -19,7 +19,23 @@ use crate::deduce_journal::deduce_journal;
 /// Data is stored in a file in the format used by [write_transaction_to_csv]. The file is appended to for each transaction,
 /// and not held open, although this may change in the future for performance reasons.
 ///
-/// Censorship is supported but is horrendously inefficient - the entire file is rewritten after each censorship.
+/// Censorship (and uncensorship) is recorded by appending to a separate, small, tombstone file, which is applied on top of the
+/// main file when it is loaded. The tombstone file is in the same format as the main file; each censorship is a leaf with no data,
+/// and each uncensorship is a leaf with its restored data. The tombstone file for `database.csv` is `database.tombstones.csv`.
+///
+/// Note that this means that censored text remains in the main file until [BackendFlatfile::compact] is called, which
+/// rewrites the main file with all censorship applied, and removes the tombstone file. This is done automatically
+/// when the next root is published, so that all the censorship between two publications costs one rewrite.
+///
+/// Idempotency keys (see [crate::BulletinBoard::submit_leaf_with_idempotency_key]) are stored in another file, each line
+/// being the key and the leaf hash. It is written before the main file, so if a key is present its leaf may not be,
+/// in which case a retry will submit it afresh. The idempotency key file for `database.csv` is `database.idempotency_keys.csv`.
+///
+/// The content index (see [crate::content_index]) is computed from the data in the main file when it is loaded.
+/// Censored leaves, and redacted structured leaves, must stay in the content index under their original data, so when
+/// [BackendFlatfile::compact] removes that data from the main file,
+/// it first writes their entries to a content index file, each line being the content hash and the leaf hash.
+/// The content index file for `database.csv` is `database.content_index.csv`.
 pub struct BackendFlatfile {
     memory : BackendMemory,
     file : PathBuf,
@@ -34,29 +50,161 @@ impl BulletinBoardBackend for BackendFlatfile {
 
     fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>,BulletinBoardError> { self.memory.get_hash_info(query) }
 
+    /// Depths are not stored in the file, but are recomputed in memory when the file is loaded.
+    fn get_depth(&self, hash: HashValue) -> Result<usize,BulletinBoardError> { self.memory.get_depth(hash) }
+
+    /// If this publishes a root and there has been censorship since the last compaction, then [BackendFlatfile::compact] is called.
     fn publish(&mut self, transaction: &DatabaseTransaction) -> Result<(),BulletinBoardError> {
+        if let Some((key,leaf)) = transaction.idempotency_key_and_leaf() { self.append_idempotency_key(key,leaf)?; }
         let file = OpenOptions::new().append(true).create(true).open(&self.file)?;
-        write_transaction_to_csv(&transaction,&file)?;
+        write_transaction_to_csv(transaction,&file)?;
         file.sync_data()?;
-        self.memory.publish(transaction)
+        self.memory.publish(transaction)?;
+        if transaction.publishes_root() && self.tombstone_path().exists() { self.compact()?; }
+        Ok(())
+    }
+
+    /// The file is not designed to be shared between processes, so there can be no other writer. Skip the (O(n)) check.
+    fn publish_if_unchanged(&mut self, transaction: &DatabaseTransaction, _expected_parentless: &[HashValue]) -> Result<(),BulletinBoardError> {
+        self.publish(transaction)
     }
 
-    /// Horrendously inefficient - re-deduce order and write out whole file.
+    fn get_leaf_for_idempotency_key(&self, key: &str) -> Result<Option<HashValue>,BulletinBoardError> { self.memory.get_leaf_for_idempotency_key(key) }
+
+    fn store_idempotency_key(&mut self, key: &str, leaf: HashValue) -> Result<(),BulletinBoardError> {
+        if self.memory.get_leaf_for_idempotency_key(key)?.is_some() { return Ok(()); } // later lines in the file would take precedence.
+        self.append_idempotency_key(key,leaf)?;
+        self.memory.store_idempotency_key(key,leaf)
+    }
+
+    fn get_leaves_with_content_hash(&self, content_hash: HashValue) -> Result<Vec<HashValue>,BulletinBoardError> { self.memory.get_leaves_with_content_hash(content_hash) }
+
+    fn get_leaf_count(&self) -> Result<u64,BulletinBoardError> { self.memory.get_leaf_count() }
+
+    fn get_leaf_by_index(&self, index: u64) -> Result<Option<HashValue>,BulletinBoardError> { self.memory.get_leaf_by_index(index) }
+
+    fn get_leaf_index(&self, leaf: HashValue) -> Result<Option<u64>,BulletinBoardError> { self.memory.get_leaf_index(leaf) }
+
+    fn list_leaves(&self, start: u64, count: u64) -> Result<Vec<HashValue>,BulletinBoardError> { self.memory.list_leaves(start,count) }
+
+    fn list_leaves_between(&self, t0: Timestamp, t1: Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> { self.memory.list_leaves_between(t0,t1) }
+
+    fn get_published_root_at(&self, t: Timestamp) -> Result<Option<HashValue>,BulletinBoardError> { self.memory.get_published_root_at(t) }
+
+    /// Appends to the tombstone file. The censored text remains in the main file until [BackendFlatfile::compact] is called,
+    /// which happens automatically when the next root is published.
     fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(),BulletinBoardError> {
-        self.memory.censor_leaf(leaf_to_censor)?;
-        let file = OpenOptions::new().write(true).truncate(true).create(true).open(&self.file)?; // Don't append!
-        for transaction in deduce_journal(&self.memory,&vec![],&self.get_all_leaves_and_branches_without_a_parent()?,true)? {
-            write_transaction_to_csv(&transaction,&file)?;
-        }
-        file.sync_data()?;
-        Ok(())
+        self.append_tombstone(leaf_to_censor,None)?;
+        self.memory.censor_leaf(leaf_to_censor)
+    }
+
+    /// Appends to the tombstone file.
+    fn uncensor_leaf(&mut self, leaf_to_uncensor: HashValue, data: &str) -> Result<(),BulletinBoardError> {
+        self.append_tombstone(leaf_to_uncensor,Some(data))?;
+        self.memory.uncensor_leaf(leaf_to_uncensor,data)
     }
 }
 
 impl BackendFlatfile {
+    /// The path of the tombstone file.
+    fn tombstone_path(&self) -> PathBuf { self.file.with_extension("tombstones.csv") }
+
+    /// Append the state a leaf is about to be changed to, by censorship (data None) or uncensorship, to the tombstone file.
+    /// This is done before the change is made in memory, so that the change is only made if it is durably recorded.
+    fn append_tombstone(&self,leaf:HashValue,data:Option<&str>) -> Result<(),BulletinBoardError> {
+        let history = match self.memory.get_hash_info(leaf)?.ok_or(BulletinBoardError::NoSuchHash)?.source {
+            HashSource::Leaf(history) => history,
+            _ => return Err(BulletinBoardError::CanOnlyCensorLeaves),
+        };
+        let source = HashSource::Leaf(LeafHashHistory{ data: data.map(|d|d.to_string()), ..history });
+        let file = OpenOptions::new().append(true).create(true).open(self.tombstone_path())?;
+        write_transaction_to_csv(&DatabaseTransaction::singleton(leaf,source),&file)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// The path of the idempotency key file.
+    fn idempotency_key_path(&self) -> PathBuf { self.file.with_extension("idempotency_keys.csv") }
+
+    /// Append an idempotency key and the leaf it identifies to the idempotency key file.
+    fn append_idempotency_key(&self,key:&str,leaf:HashValue) -> Result<(),BulletinBoardError> {
+        let file = OpenOptions::new().append(true).create(true).open(self.idempotency_key_path())?;
+        let mut writer = WriterBuilder::new().has_headers(false).from_writer(&file);
+        writer.write_record([key,&leaf.to_string()]).map_err(std::io::Error::from)?;
+        writer.flush()?;
+        drop(writer);
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// The path of the content index file, holding content index entries for leaves whose data is not in the main file.
+    fn content_index_path(&self) -> PathBuf { self.file.with_extension("content_index.csv") }
+
+    /// Rewrite the content index file with the entries for all currently censored leaves.
+    fn write_censored_content_index(&self) -> Result<(),BulletinBoardError> {
+        replace_file(&self.content_index_path(),|file|{
+            let mut writer = WriterBuilder::new().has_headers(false).from_writer(file);
+            for (content_hash,leaf) in self.memory.censored_content_index_entries() {
+                writer.write_record([content_hash.to_string(),leaf.to_string()]).map_err(std::io::Error::from)?;
+            }
+            writer.flush()?;
+            Ok(())
+        })
+    }
+
+    /// Rewrite the main file with all censorship applied, and remove the tombstone file.
+    /// After this, censored text is no longer present anywhere in the files.
+    ///
+    /// This re-deduces the order of the entire history, so takes time proportional to the size of the board.
+    /// The new main file is written under a different name and then renamed over the old one, so a failure
+    /// part way through leaves the old main file and tombstone file intact.
+    ///
+    /// This is called automatically when a root is published after some censorship, but may be called sooner
+    /// if the censored text should be removed from the file immediately.
+    pub fn compact(&self) -> Result<(),BulletinBoardError> {
+        self.write_censored_content_index()?; // before the censored data is removed from the main file.
+        let transactions = deduce_journal(&self.memory,&vec![],&self.get_all_leaves_and_branches_without_a_parent()?,true)?;
+        replace_file(&self.file,|file|{
+            for transaction in &transactions {
+                write_transaction_to_csv(transaction,file)?;
+            }
+            Ok(())
+        })?;
+        if self.tombstone_path().exists() { std::fs::remove_file(self.tombstone_path())?; }
+        Ok(())
+    }
+
     /// Create a new flat file backed backend, storing data in the provided file.
-    /// The file will be read if it exists, and used to initialize the database.
+    /// The file will be read if it exists, and used to initialize the database, and then
+    /// the tombstone file (if it exists) applied.
     /// When new elements are published, the file will be appended.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::backend_flatfile::BackendFlatfile;
+    /// use merkle_tree_bulletin_board::BulletinBoard;
+    /// use merkle_tree_bulletin_board::hash_history::{HashSource, LeafHashHistory};
+    ///
+    /// let dir = tempdir::TempDir::new("flatfile").unwrap();
+    /// let path = dir.path().join("database.csv");
+    /// let mut board = BulletinBoard::new(BackendFlatfile::new(&path).unwrap()).unwrap();
+    /// let hash = board.submit_leaf("a").unwrap();
+    /// board.censor_leaf(hash,"Spam","Moderator").unwrap();
+    /// assert!(dir.path().join("database.tombstones.csv").exists());
+    /// fn is_censored(board:&BulletinBoard<BackendFlatfile>,hash:merkle_tree_bulletin_board::hash::HashValue) -> bool {
+    ///     matches!(board.get_hash_info(hash).unwrap().source,HashSource::Leaf(LeafHashHistory{data:None,..}))
+    /// }
+    /// // reload
+    /// let board = BulletinBoard::new(BackendFlatfile::new(&path).unwrap()).unwrap();
+    /// assert!(is_censored(&board,hash));
+    /// assert!(std::fs::read_to_string(&path).unwrap().contains(",a\n")); // still in the main file
+    /// board.backend.compact().unwrap();
+    /// assert!(!std::fs::read_to_string(&path).unwrap().contains(",a\n"));
+    /// assert!(!dir.path().join("database.tombstones.csv").exists());
+    /// let board = BulletinBoard::new(BackendFlatfile::new(&path).unwrap()).unwrap();
+    /// assert!(is_censored(&board,hash));
+    /// ```
     pub fn new<P>(path: P) -> Result<Self,BulletinBoardError>
     where PathBuf: From<P>
     {
@@ -67,19 +215,80 @@ impl BackendFlatfile {
                 memory.publish(&transaction?)?
             }
         }
-        Ok(BackendFlatfile{ memory, file })
+        let res = BackendFlatfile{ memory, file };
+        res.apply_tombstones()?.load_idempotency_keys()?.load_content_index()
+    }
+
+    /// Load the content index file, if present.
+    fn load_content_index(mut self) -> Result<Self,BulletinBoardError> {
+        if let Ok(file_reader) = File::open(self.content_index_path()) { // file may not exist.
+            let mut reader = ReaderBuilder::new().has_headers(false).from_reader(file_reader);
+            for record in reader.records() {
+                let record = record.map_err(|e|BulletinBoardError::BackendParsingError(e.to_string()))?;
+                if record.len()!=2 { return Err(BulletinBoardError::BackendParsingError("Content index file should have two fields per line".to_string())); }
+                self.memory.add_to_content_index(HashValue::from_str(&record[0])?,HashValue::from_str(&record[1])?);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Load the idempotency key file, if present. Later lines take precedence.
+    fn load_idempotency_keys(mut self) -> Result<Self,BulletinBoardError> {
+        if let Ok(file_reader) = File::open(self.idempotency_key_path()) { // file may not exist.
+            let mut reader = ReaderBuilder::new().has_headers(false).from_reader(file_reader);
+            for record in reader.records() {
+                let record = record.map_err(|e|BulletinBoardError::BackendParsingError(e.to_string()))?;
+                if record.len()!=2 { return Err(BulletinBoardError::BackendParsingError("Idempotency key file should have two fields per line".to_string())); }
+                self.memory.add_idempotency_key(&record[0],HashValue::from_str(&record[1])?);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Apply the tombstone file, if present, to the data loaded from the main file.
+    fn apply_tombstones(mut self) -> Result<Self,BulletinBoardError> {
+        if let Ok(file_reader) = File::open(self.tombstone_path()) { // file may not exist.
+            for transaction in TransactionIterator::new(file_reader) {
+                for (hash,source) in transaction?.pending {
+                    match source {
+                        HashSource::Leaf(LeafHashHistory{data:None,..}) => self.memory.censor_leaf(hash)?,
+                        HashSource::Leaf(LeafHashHistory{data:Some(data),..}) => self.memory.uncensor_leaf(hash,&data)?,
+                        _ => return Err(BulletinBoardError::BackendInconsistentError(format!("Tombstone file contains a non-leaf {}",hash))),
+                    }
+                }
+            }
+        }
+        Ok(self)
     }
 }
 
 
+/// Replace the contents of a file by writing them to a temporary file alongside it, syncing it, and renaming it over the original,
+/// so that the original is never left partly written.
+fn replace_file(path:&Path,write:impl FnOnce(&File)->Result<(),BulletinBoardError>) -> Result<(),BulletinBoardError> {
+    let mut temp_name = path.as_os_str().to_owned();
+    temp_name.push(".new");
+    let temp_path = PathBuf::from(temp_name);
+    {
+        let file = File::create(&temp_path)?;
+        write(&file)?;
+        file.sync_data()?;
+    }
+    std::fs::rename(temp_path,path)?;
+    Ok(())
+}
+
 /// Write out a transaction to a csv file. The format is
 /// * Blank lines represent the end of a transaction.
-/// * Otherwise, the first field is an integer 0, 1 or 2 specifying the type of the node being created,
+/// * Otherwise, the first field is an integer 0, 1, 2, 3 or 5 specifying the type of the node being created,
 ///   and the second field is the hash value. After that are fields specifying how the object was created.
 ///   * 0 means a leaf, history is the timestamp (seconds since epoch) and then the string it was created from (appropriately csv escaped).
 ///      - If the leaf data has been censored, then there is only a timestamp field, no fourth field.
+///   * 3 means a structured leaf (see [LeafHashHistory::structured]), with the same fields as a leaf.
 ///   * 1 means a branch, history is the left and right hashes.
 ///   * 2 means a published root, history is the timestamp, then the prior published root or empty field, and then the hashes in this node.
+///   * 5 means a published root with [RootHashHistory::skips], history is the timestamp, then the prior published root or empty field,
+///     then the number of skips, then the skips, and then the hashes in this node.
 ///
 /// To read in transactions from a file, into an iterator, see [TransactionIterator::new]
 ///
@@ -93,7 +302,7 @@ impl BackendFlatfile {
 /// use merkle_tree_bulletin_board::backend_flatfile::write_transaction_to_csv;
 /// let mut output : Vec<u8> = vec![];
 /// let mut transaction : DatabaseTransaction = DatabaseTransaction::default();
-/// let history = LeafHashHistory{timestamp: 42 ,data: Some("The answer".to_string()) };
+/// let history = LeafHashHistory{timestamp: 42 ,data: Some("The answer".to_string()), structured: false };
 /// let hash = history.compute_hash().unwrap();
 /// assert_eq!(hash.to_string(),"68c3cefbe5b64fc51713cabe524cd35f2be6e52148a0f201476f16f378cb1aee");
 /// transaction.add_leaf_hash(hash,history);
@@ -110,12 +319,12 @@ impl BackendFlatfile {
 /// use merkle_tree_bulletin_board::backend_flatfile::write_transaction_to_csv;
 /// let mut output : Vec<u8> = vec![];
 /// let mut transaction : DatabaseTransaction = DatabaseTransaction::default();
-/// let history = LeafHashHistory{timestamp: 42 ,data: Some("The answer".to_string()) };
+/// let history = LeafHashHistory{timestamp: 42 ,data: Some("The answer".to_string()), structured: false };
 /// let hash = history.compute_hash().unwrap();
 /// assert_eq!(hash.to_string(),"68c3cefbe5b64fc51713cabe524cd35f2be6e52148a0f201476f16f378cb1aee");
 /// transaction.add_leaf_hash(hash,history);
 /// let history = LeafHashHistory{timestamp: 43 ,data: Some(r#"The new improved, "web 2.0" answer
-/// with a newline in the middle"#.to_string()) };
+/// with a newline in the middle"#.to_string()), structured: false };
 /// let hash = history.compute_hash().unwrap();
 /// assert_eq!(hash.to_string(),"1d1633c405293e54ac8434c34dfa2532d59172979d1dc38a6389485b35f51762");
 /// transaction.add_leaf_hash(hash,history);
@@ -132,25 +341,32 @@ pub fn write_transaction_to_csv<W: Write>(transaction:&DatabaseTransaction, writ
     for (hash,source) in &transaction.pending {
         match source {
             HashSource::Leaf(history) => {
+                let node_type = if history.structured { "3" } else { "0" };
                 if let Some(uncensored_data) = &history.data {
-                    csv_writer.write_record(&["0",&hash.to_string(),&history.timestamp.to_string(),uncensored_data])?;
+                    csv_writer.write_record([node_type,&hash.to_string(),&history.timestamp.to_string(),uncensored_data])?;
                 } else {
-                    csv_writer.write_record(&["0",&hash.to_string(),&history.timestamp.to_string()])?;
+                    csv_writer.write_record([node_type,&hash.to_string(),&history.timestamp.to_string()])?;
                 }
             }
             HashSource::Branch(history) => {
-                csv_writer.write_record(&["1",&hash.to_string(),&history.left.to_string(),&history.right.to_string()])?;
+                csv_writer.write_record(["1",&hash.to_string(),&history.left.to_string(),&history.right.to_string()])?;
             }
             HashSource::Root(history) => {
-                csv_writer.write_field("2")?;
-                csv_writer.write_field(&hash.to_string())?;
-                csv_writer.write_field(&history.timestamp.to_string())?;
+                csv_writer.write_field(if history.skips.is_empty() { "2" } else { "5" })?;
+                csv_writer.write_field(hash.to_string())?;
+                csv_writer.write_field(history.timestamp.to_string())?;
                 match history.prior {
                     None => csv_writer.write_field("")?,
-                    Some(prior) => csv_writer.write_field(&prior.to_string())?,
+                    Some(prior) => csv_writer.write_field(prior.to_string())?,
+                }
+                if !history.skips.is_empty() {
+                    csv_writer.write_field(history.skips.len().to_string())?;
+                    for s in &history.skips {
+                        csv_writer.write_field(s.to_string())?;
+                    }
                 }
                 for e in &history.elements {
-                    csv_writer.write_field(&e.to_string())?;
+                    csv_writer.write_field(e.to_string())?;
                 }
                 csv_writer.write_record(None::<&[u8]>)?;
             }
@@ -159,7 +375,7 @@ pub fn write_transaction_to_csv<W: Write>(transaction:&DatabaseTransaction, writ
     // flush and put a blank line at end to delimit transactions. Doing this via the csv writer write_record causes it to write a single blank field.
     match csv_writer.into_inner() { // can't just use ? as embeds w in the error which causes all sorts of thread issues.
         Ok(mut w) => w.write_all(b"\n")?,
-        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Could not execute into_inner on csv writer")),
+        Err(_) => return Err(std::io::Error::other("Could not execute into_inner on csv writer")),
     }
     Ok(())
 }
@@ -191,7 +407,7 @@ impl<R: Read> TransactionIterator<R> {
     /// let (hash,source) = trans1.pending[0].clone();
     /// assert_eq!(hash.to_string(),"68c3cefbe5b64fc51713cabe524cd35f2be6e52148a0f201476f16f378cb1aee");
     /// assert_eq!(source,HashSource::Leaf(
-    ///       LeafHashHistory{timestamp:42,data:Some("The answer".to_string())}));
+    ///       LeafHashHistory{timestamp:42,data:Some("The answer".to_string()),structured:false}));
     /// ```
     pub fn new(reader: R) -> TransactionIterator<R> {
         TransactionIterator { csv_reader : ReaderBuilder::new().has_headers(false).flexible(true).from_reader(reader), record: StringRecord::new() , read_ahead:None }
@@ -200,35 +416,51 @@ impl<R: Read> TransactionIterator<R> {
 }
 
 
-impl<'r, R: Read> Iterator for TransactionIterator<R> {
+impl<R: Read> Iterator for TransactionIterator<R> {
     type Item = Result<DatabaseTransaction,BulletinBoardError>;
 
     fn next(&mut self) -> Option<Result<DatabaseTransaction,BulletinBoardError>> { // or maybe not?
         fn parse_record(record:&StringRecord) -> Result<(HashValue,HashSource),BulletinBoardError> {
             let hash = match record.get(1) {
                 Some(s) => HashValue::from_str(s)?,
-                None => return Err(BulletinBoardError::BackendInconsistentError(format!("No hash"))),
+                None => return Err(BulletinBoardError::BackendInconsistentError("No hash".to_string())),
             };
             let history = match record.get(0) {
-                Some("0") => { // leaf
-                    if record.len()<3 || record.len()>4 { return Err(BulletinBoardError::BackendInconsistentError(format!("Leaf node should have 3 or 4 fields"))); }
-                    HashSource::Leaf(LeafHashHistory{ timestamp : Timestamp::from_str(record.get(2).unwrap())?, data: record.get(3).map(|e|e.to_string()) })
+                Some(node_type@("0"|"3")) => { // leaf or structured leaf
+                    if record.len()<3 || record.len()>4 { return Err(BulletinBoardError::BackendInconsistentError("Leaf node should have 3 or 4 fields".to_string())); }
+                    HashSource::Leaf(LeafHashHistory{ timestamp : Timestamp::from_str(record.get(2).unwrap())?, data: record.get(3).map(|e|e.to_string()), structured: node_type=="3" })
                 }
                 Some("1") => { // branch
-                    if record.len()!=4 { return Err(BulletinBoardError::BackendInconsistentError(format!("Branch node should have 4 fields"))); }
+                    if record.len()!=4 { return Err(BulletinBoardError::BackendInconsistentError("Branch node should have 4 fields".to_string())); }
                     HashSource::Branch(BranchHashHistory{ left : HashValue::from_str(record.get(2).unwrap())?, right: HashValue::from_str(record.get(3).unwrap())?})
                 }
                 Some("2") => { // published
-                    if record.len()<4 { return Err(BulletinBoardError::BackendInconsistentError(format!("Publish node should have at least 4 fields"))); }
+                    if record.len()<4 { return Err(BulletinBoardError::BackendInconsistentError("Publish node should have at least 4 fields".to_string())); }
                     let mut elements = vec![];
                     for contained_hash in record.iter().skip(4) {
                         elements.push(HashValue::from_str(contained_hash)?);
                     }
                     let prior_str = record.get(3).unwrap();
                     let prior = if prior_str.is_empty() { None } else { Some(HashValue::from_str(prior_str)?)};
-                    HashSource::Root(RootHashHistory{ timestamp : Timestamp::from_str(record.get(2).unwrap())?, prior, elements })
+                    HashSource::Root(RootHashHistory{ timestamp : Timestamp::from_str(record.get(2).unwrap())?, prior, elements, skips: vec![] })
+                }
+                Some("5") => { // published, with skips
+                    if record.len()<5 { return Err(BulletinBoardError::BackendInconsistentError("Publish node with skips should have at least 5 fields".to_string())); }
+                    let num_skips = usize::from_str(record.get(4).unwrap())?;
+                    if record.len()<5+num_skips { return Err(BulletinBoardError::BackendInconsistentError("Publish node with skips has too few skips".to_string())); }
+                    let mut skips = vec![];
+                    for skip in record.iter().skip(5).take(num_skips) {
+                        skips.push(HashValue::from_str(skip)?);
+                    }
+                    let mut elements = vec![];
+                    for contained_hash in record.iter().skip(5+num_skips) {
+                        elements.push(HashValue::from_str(contained_hash)?);
+                    }
+                    let prior_str = record.get(3).unwrap();
+                    let prior = if prior_str.is_empty() { None } else { Some(HashValue::from_str(prior_str)?)};
+                    HashSource::Root(RootHashHistory{ timestamp : Timestamp::from_str(record.get(2).unwrap())?, prior, elements, skips })
                 }
-                _ => return Err(BulletinBoardError::BackendInconsistentError(format!("Invalid type specifier"))),
+                _ => return Err(BulletinBoardError::BackendInconsistentError("Invalid type specifier".to_string())),
             };
             Ok((hash,history))
         }
@@ -277,3 +509,150 @@ impl<'r, R: Read> Iterator for TransactionIterator<R> {
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use crate::backend_flatfile::BackendFlatfile;
+    use crate::{BulletinBoard, BulletinBoardBackend};
+    use crate::hash_history::{HashSource, LeafHashHistory};
+    use crate::content_index::{content_hash, DuplicatePolicy};
+    use crate::verifier::verify_ancestry_proof;
+
+    #[test]
+    /// Test that stored depths agree with walking down the left side of the tree, including after reloading from the file.
+    fn test_depth_after_reload() {
+        let dir = tempdir::TempDir::new("flatfile").unwrap();
+        let path = dir.path().join("database.csv");
+        let mut board = BulletinBoard::new(BackendFlatfile::new(path.clone()).unwrap()).unwrap();
+        for data in ["a","b","c","d","e","f","g"] { board.submit_leaf(data).unwrap(); }
+        let check = |backend:&BackendFlatfile| {
+            let mut depths : Vec<usize> = vec![];
+            for hash in backend.get_all_leaves_and_branches_without_a_parent().unwrap() {
+                assert_eq!(backend.left_depth(hash).unwrap(),backend.get_depth(hash).unwrap());
+                depths.push(backend.get_depth(hash).unwrap());
+            }
+            depths.sort();
+            assert_eq!(depths,vec![0,1,2]);
+        };
+        check(&board.backend);
+        drop(board);
+        check(&BackendFlatfile::new(path).unwrap());
+    }
+
+    #[test]
+    /// Test that censored text stays in the main file until compaction, which happens when the next root is published,
+    /// and that compaction leaves no temporary files behind.
+    fn test_censored_text_removed_by_compact() {
+        let dir = tempdir::TempDir::new("flatfile").unwrap();
+        let path = dir.path().join("database.csv");
+        let mut board = BulletinBoard::new(BackendFlatfile::new(path.clone()).unwrap()).unwrap();
+        let a = board.submit_leaf("a secret").unwrap();
+        board.submit_leaf("b").unwrap();
+        board.order_new_published_root().unwrap();
+        board.censor_leaf(a,"Spam","Moderator").unwrap();
+        let main_file = || std::fs::read_to_string(&path).unwrap();
+        assert!(main_file().contains("a secret"));
+        assert!(path.with_extension("tombstones.csv").exists());
+        match board.get_hash_info(a).unwrap().source { HashSource::Leaf(history) => assert_eq!(history.data,None), _ => panic!("Not a leaf") }
+        assert_eq!(BulletinBoard::new(BackendFlatfile::new(path.clone()).unwrap()).unwrap().get_hash_info(a).unwrap(),board.get_hash_info(a).unwrap()); // tombstone applied on load.
+        assert_eq!(board.backend.censor_leaf(a),Ok(())); // only the board refuses to censor twice.
+        assert!(board.backend.censor_leaf(board.get_most_recent_published_root().unwrap().unwrap()).is_err()); // not a leaf, so nothing written.
+        board.order_new_published_root().unwrap();
+        assert!(!main_file().contains("a secret"));
+        let mut files : Vec<String> = std::fs::read_dir(dir.path()).unwrap().map(|e|e.unwrap().file_name().to_string_lossy().to_string()).collect();
+        files.sort();
+        assert_eq!(files,vec!["database.content_index.csv","database.csv"]);
+        let reloaded = BulletinBoard::new(BackendFlatfile::new(path.clone()).unwrap()).unwrap();
+        assert_eq!(reloaded.get_hash_info(a).unwrap(),board.get_hash_info(a).unwrap());
+        assert_eq!(reloaded.get_most_recent_published_root().unwrap(),board.get_most_recent_published_root().unwrap());
+    }
+
+    #[test]
+    /// Test that structured leaves are still marked as structured after reloading, including after censorship.
+    fn test_structured_leaves_after_reload() {
+        let dir = tempdir::TempDir::new("flatfile").unwrap();
+        let path = dir.path().join("database.csv");
+        let mut board = BulletinBoard::new(BackendFlatfile::new(path.clone()).unwrap()).unwrap();
+        let object = serde_json::json!({"a":1,"b":2});
+        let a = board.submit_structured_leaf(object.as_object().unwrap()).unwrap();
+        let b = board.submit_structured_leaf(object.as_object().unwrap()).unwrap();
+        board.redact_field(a,"a","reason","authority").unwrap();
+        board.censor_leaf(b,"reason","authority").unwrap();
+        let reloaded = BulletinBoard::new(BackendFlatfile::new(path).unwrap()).unwrap();
+        for leaf in [a,b] {
+            assert_eq!(reloaded.get_hash_info(leaf).unwrap(),board.get_hash_info(leaf).unwrap());
+            match reloaded.get_hash_info(leaf).unwrap().source { HashSource::Leaf(history) => assert!(history.structured), _ => panic!("Not a leaf") }
+        }
+    }
+
+    #[test]
+    /// Test that censored leaves, and redacted structured leaves, stay in the content index after compaction and reloading.
+    fn test_content_index_after_compact() {
+        let dir = tempdir::TempDir::new("flatfile").unwrap();
+        let path = dir.path().join("database.csv");
+        let mut board = BulletinBoard::new(BackendFlatfile::new(path.clone()).unwrap()).unwrap();
+        let a = board.submit_leaf("a").unwrap();
+        let b = board.submit_leaf("b").unwrap();
+        board.censor_leaf(a,"Spam","Moderator").unwrap();
+        let object = serde_json::json!({"name":"Fred","address":"123 Example Street"});
+        let structured = board.submit_structured_leaf(object.as_object().unwrap()).unwrap();
+        let HashSource::Leaf(LeafHashHistory{data:Some(structured_data),..}) = board.get_hash_info(structured).unwrap().source else { panic!("Not a leaf") };
+        board.redact_field(structured,"address","Privacy","Moderator").unwrap();
+        board.backend.compact().unwrap();
+        drop(board);
+        let board = BulletinBoard::new(BackendFlatfile::new(path).unwrap()).unwrap();
+        assert_eq!(board.find_leaves_by_data(&structured_data).unwrap(),vec![structured]);
+        assert_eq!(board.backend.get_leaves_with_content_hash(content_hash("a")).unwrap(),vec![a]);
+        assert_eq!(board.backend.get_leaves_with_content_hash(content_hash("b")).unwrap(),vec![b]);
+        assert_eq!(board.backend.get_leaves_with_content_hash(content_hash("c")).unwrap(),vec![]);
+        assert_eq!(board.list_leaves(0,2).unwrap(),vec![a,b]); // sequence numbers survive compaction.
+    }
+
+    #[test]
+    /// Test that idempotency keys are remembered after reloading from the file.
+    fn test_idempotency_keys_after_reload() {
+        let dir = tempdir::TempDir::new("flatfile").unwrap();
+        let path = dir.path().join("database.csv");
+        let mut board = BulletinBoard::new(BackendFlatfile::new(path.clone()).unwrap()).unwrap();
+        let a = board.submit_leaf_with_idempotency_key("a","request, with a comma").unwrap();
+        board.submit_leaf("b").unwrap();
+        drop(board);
+        let mut board = BulletinBoard::new(BackendFlatfile::new(path.clone()).unwrap()).unwrap();
+        assert_eq!(board.submit_leaf_with_idempotency_key("a","request, with a comma").unwrap(),a);
+        assert_eq!(board.backend.get_all_leaves_and_branches_without_a_parent().unwrap().len(),1); // nothing new added.
+        assert!(board.submit_leaf_with_idempotency_key("c","request, with a comma").is_err());
+        board.set_duplicate_policy(DuplicatePolicy::ReturnExisting);
+        assert_eq!(board.submit_leaf_with_idempotency_key("a","request 2").unwrap(),a); // a duplicate, so the existing leaf.
+        drop(board);
+        let mut board = BulletinBoard::new(BackendFlatfile::new(path).unwrap()).unwrap();
+        assert_eq!(board.submit_leaf_with_idempotency_key("a","request 2").unwrap(),a);
+        assert!(board.submit_leaf_with_idempotency_key("c","request 2").is_err());
+        assert_eq!(board.backend.get_all_leaves_and_branches_without_a_parent().unwrap().len(),1);
+    }
+
+    #[test]
+    /// Test that root skip references are stored in the file, so that roots still have the right hashes after reloading.
+    fn test_root_skips_after_reload() {
+        let dir = tempdir::TempDir::new("flatfile").unwrap();
+        let path = dir.path().join("database.csv");
+        let mut board = BulletinBoard::new(BackendFlatfile::new(path.clone()).unwrap()).unwrap();
+        board.set_root_skip_list(true);
+        for data in ["a","b","c","d","e","f"] {
+            board.submit_leaf(data).unwrap();
+            board.order_new_published_root().unwrap();
+        }
+        let roots = board.get_all_published_roots().unwrap();
+        let proof = board.get_ancestry_proof(roots[0],roots[5]).unwrap();
+        drop(board);
+        let board = BulletinBoard::new(BackendFlatfile::new(path).unwrap()).unwrap();
+        for root in &roots {
+            match board.get_hash_info(*root).unwrap().source {
+                HashSource::Root(history) => assert_eq!(history.compute_hash(),*root),
+                _ => panic!("Not a root"),
+            }
+        }
+        let reloaded_proof = board.get_ancestry_proof(roots[0],roots[5]).unwrap();
+        assert_eq!(reloaded_proof.chain.len(),proof.chain.len());
+        assert_eq!(verify_ancestry_proof(roots[0],roots[5],&reloaded_proof),None);
+    }
+}
@@ -1,13 +1,15 @@
 //! A journalling backend for the database based on csv files.
 
 use std::path::PathBuf;
-use crate::{DatabaseTransaction, BulletinBoardBackend, BulletinBoardError};
-use crate::hash_history::{HashSource, HashInfo};
+use crate::{DatabaseTransaction, BulletinBoardBackend, BulletinBoardError, first_including_root};
+use crate::hash_history::{HashSource, HashInfo, Timestamp};
 use crate::hash::HashValue;
 use std::fs::{OpenOptions, File};
 use crate::backend_flatfile::{write_transaction_to_csv, TransactionIterator};
 use std::collections::HashSet;
 use std::iter::FromIterator;
+use crate::backend_access::{run_ready, ReadAccess, SyncAccess};
+use crate::deduce_journal::{deduce_journal_from_prior_root_to_given_root_using, deduce_journal_last_published_root_to_present_using};
 
 /// Add journalling suitable for bulk verification to some other backend.
 ///
@@ -28,7 +30,7 @@ use std::iter::FromIterator;
 ///     - Get the file *S*.csv which contains all transactions between *R* and *S* (assuming *R* is the root before *S*)
 ///     - Play said file, checking all the hashings it implies, and whatever else you want to check about the content (content specific).
 ///     - Compare the resulting nodes to [crate::BulletinBoard::get_hash_info] applied to *S* and check its hash.
-///     This is implemented in [crate::verifier::bulk_verify_between_two_consecutive_published_roots].
+///       This is implemented in [crate::verifier::bulk_verify_between_two_consecutive_published_roots].
 ///   - The complete list of current actions between now and the last published root *R*. There
 ///     is not much point in doing this for security purposes, since different people could
 ///     be given current states by a malicious adversary. However it is worth doing from a file
@@ -44,10 +46,18 @@ use std::iter::FromIterator;
 ///     - Get a list of published roots via [crate::BulletinBoard::get_all_published_roots]
 ///     - Iterate the above steps for each consecutive pair of roots.
 ///
-/// Note that the journal backend does not support censorship efficiently, and rebuilds everything.
+/// When a leaf is censored or uncensored, the one journal file containing it - the file for the first published root
+/// including it, or pending.csv if it has not been published yet - is rewritten when the next root is published. Each
+/// file is rewritten at most once however many of its leaves were censored since the last publication. Until then, and
+/// if the process stops before then, the journal files still contain the censored text; a restart with
+/// [StartupVerification::RebuildAllJournals] removes it.
+///
+/// The journal files are written by the process doing the writing, so if multiple processes share the same
+/// underlying database (see [BulletinBoardBackend::publish_if_unchanged]) then each will only see its own
+/// transactions. Use [crate::deduce_journal] to produce journals in that case.
 pub struct BackendJournal<B:BulletinBoardBackend> {
     main_backend: B,
-    directory : PathBuf,
+    files : JournalFiles,
 }
 
 impl <B:BulletinBoardBackend> BulletinBoardBackend for BackendJournal<B> {
@@ -59,56 +69,56 @@ impl <B:BulletinBoardBackend> BulletinBoardBackend for BackendJournal<B> {
 
     fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>,BulletinBoardError> { self.main_backend.get_hash_info(query) }
 
+    fn get_depth(&self, hash: HashValue) -> Result<usize,BulletinBoardError> { self.main_backend.get_depth(hash) }
+
+    /// Idempotency keys are not journaled; they are stored by the original backend.
+    fn get_leaf_for_idempotency_key(&self, key: &str) -> Result<Option<HashValue>,BulletinBoardError> { self.main_backend.get_leaf_for_idempotency_key(key) }
+
+    fn store_idempotency_key(&mut self, key: &str, leaf: HashValue) -> Result<(),BulletinBoardError> { self.main_backend.store_idempotency_key(key,leaf) }
+
+    fn get_leaves_with_content_hash(&self, content_hash: HashValue) -> Result<Vec<HashValue>,BulletinBoardError> { self.main_backend.get_leaves_with_content_hash(content_hash) }
+
+    fn get_leaf_count(&self) -> Result<u64,BulletinBoardError> { self.main_backend.get_leaf_count() }
+
+    fn get_leaf_by_index(&self, index: u64) -> Result<Option<HashValue>,BulletinBoardError> { self.main_backend.get_leaf_by_index(index) }
+
+    fn get_leaf_index(&self, leaf: HashValue) -> Result<Option<u64>,BulletinBoardError> { self.main_backend.get_leaf_index(leaf) }
+
+    fn list_leaves(&self, start: u64, count: u64) -> Result<Vec<HashValue>,BulletinBoardError> { self.main_backend.list_leaves(start,count) }
+
+    fn list_leaves_between(&self, t0: Timestamp, t1: Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> { self.main_backend.list_leaves_between(t0,t1) }
+
+    fn get_published_root_at(&self, t: Timestamp) -> Result<Option<HashValue>,BulletinBoardError> { self.main_backend.get_published_root_at(t) }
+
     /// Publish both to the original backend, and the journal.
     /// The original is published to first; this means that in the case of an unfortunate power loss or similar, the journal may miss the last record.
     fn publish(&mut self, transaction: &DatabaseTransaction) -> Result<(),BulletinBoardError> {
         self.main_backend.publish(transaction)?;
-        {
-            let file = OpenOptions::new().append(true).create(true).open(&self.pending_path())?;
-            write_transaction_to_csv(&transaction,&file)?;
-            file.sync_data()?;
-        }
-        fn is_root(source:&HashSource) -> bool {
-            match source {
-                HashSource::Root(_) => true,
-                _ => false,
-            }
-        }
-        if let Some((last_hash,last_source)) = &transaction.pending.last() {
-            if is_root(last_source) {
-                // this is a published root.
-                if self.pending_path().exists() {
-                    std::fs::rename(&self.pending_path(),&self.hash_path(*last_hash))?;
-                } else {
-                    OpenOptions::new().append(true).create(true).open(&self.hash_path(*last_hash))?; // create a blank file.
-                }
-            }
-        }
-        Ok(())
+        run_ready(self.files.publish(&SyncAccess(&self.main_backend),transaction))
+    }
+
+    /// Check and publish atomically in the original backend (if it supports that), then write to the journal.
+    fn publish_if_unchanged(&mut self, transaction: &DatabaseTransaction, expected_parentless: &[HashValue]) -> Result<(),BulletinBoardError> {
+        self.main_backend.publish_if_unchanged(transaction,expected_parentless)?;
+        run_ready(self.files.publish(&SyncAccess(&self.main_backend),transaction))
     }
 
-    /// Horrendously inefficient - rebuilds all.
+    /// The journal file containing the leaf is rewritten when the next root is published.
     fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(),BulletinBoardError> {
-        // Err(anyhow!("BackendJournal does not support censorship! Keep {} free!",leaf_to_censor)) // OK, I could have just prepended leaf_to_censor by an underscore to stop the compiler complaining about leaf_to_censor not being used, and that would have produced slightly smaller code. But...I also could have just returned RTFM which would be shorter still and who would want that?
         self.main_backend.censor_leaf(leaf_to_censor)?;
-        self.rebuild_all_journals()
+        self.files.mark_changed(leaf_to_censor);
+        Ok(())
     }
-}
 
-impl <B:BulletinBoardBackend> BackendJournal<B> {
-
-    /// The path of a file named name
-    fn rel_path(&self,name:&str) -> PathBuf {
-        let mut res = self.directory.clone();
-        res.push(name);
-        res
+    /// The journal file containing the leaf is rewritten when the next root is published.
+    fn uncensor_leaf(&mut self, leaf_to_uncensor: HashValue, data: &str) -> Result<(),BulletinBoardError> {
+        self.main_backend.uncensor_leaf(leaf_to_uncensor,data)?;
+        self.files.mark_changed(leaf_to_uncensor);
+        Ok(())
     }
+}
 
-    /// the path of the pending data file.
-    fn pending_path(&self) -> PathBuf { self.rel_path("pending.csv") }
-
-    /// the path for a given hash
-    fn hash_path(&self,hash:HashValue) -> PathBuf { self.rel_path(&(hash.to_string()+".csv")) }
+impl <B:BulletinBoardBackend> BackendJournal<B> {
 
     /// Verify that the pending data file is consistent with the database. Return Err if not.
     /// This is *not* a security check, it does *not* check any hashes. Rather it checks that
@@ -120,50 +130,7 @@ impl <B:BulletinBoardBackend> BackendJournal<B> {
     /// (which should not be in the pending file anyway), this is a reasonable check for a truncated
     /// pending file, which may have resulted from a bad shutdown.
     pub fn verify_current_consistent(&self) -> Result<(),BulletinBoardError> {
-        // First get the nodes that are left over from the last publication.
-        let preexisting_nodes : Vec<HashValue> = if let Some(last_root) = self.main_backend.get_most_recent_published_root()? {
-            if !std::path::Path::new(&self.hash_path(last_root)).exists() { return Err(BulletinBoardError::BackendInconsistentError(format!("Last published root hash does not exist"))); } // TODO should these not be top level roots?
-            match self.main_backend.get_hash_info(last_root)? {
-                Some(HashInfo { source: HashSource::Root(history), .. }) => history.elements,
-                _ => return Err(BulletinBoardError::BackendInconsistentError(format!("Last oublished root hash is not actually a root node"))),
-            }
-        } else {vec![]};
-        let mut current_nodes : HashSet<HashValue> = HashSet::from_iter(preexisting_nodes.into_iter());
-        // process the pending file.
-        if let Ok(file_reader) = File::open(&self.pending_path()) { // file may not exist.
-            for transaction in TransactionIterator::new(file_reader) {
-                for (hash,source) in transaction?.pending {
-                    current_nodes.insert(hash);
-                    match source {
-                        HashSource::Leaf(_) => { }
-                        HashSource::Branch(history) => {
-                            if !current_nodes.remove(&history.left) { return Err(BulletinBoardError::BackendInconsistentError(format!("Pending file contains a branch with unexpected left hash {}",history.left))); }
-                            if !current_nodes.remove(&history.right) { return Err(BulletinBoardError::BackendInconsistentError(format!("Pending file contains a branch with unexpected right hash {}",history.right))); }
-                        }
-                        HashSource::Root(_) => return Err(BulletinBoardError::BackendInconsistentError(format!("Pending file contains a root"))),
-                    }
-                }
-            }
-        }
-        let expected : Vec<HashValue> = self.main_backend.get_all_leaves_and_branches_without_a_parent()?;
-        let expected_set : HashSet<HashValue> = HashSet::from_iter(expected.into_iter());
-        if expected_set != current_nodes { return Err(BulletinBoardError::BackendInconsistentError(format!("Expecting to get {:#?} as nodes without parents; actually got {:#?}.",&expected_set,&current_nodes)))}
-        Ok(())
-    }
-
-    /// recreate a data file from a set of transactions.
-    fn recreate(&self,name:PathBuf,should_be:Vec<DatabaseTransaction>) -> Result<(),BulletinBoardError> {
-        let recreate_name = self.rel_path("recreating.csv");
-        { // make the file in a different name to prevent clobbering something of possible diagnostic use if all is stuffed up to badly to recover.
-            let file = File::create(&recreate_name)?;
-            for transaction in should_be {
-                write_transaction_to_csv(&transaction,&file)?;
-            }
-            file.sync_data()?;
-        }
-        std::fs::rename(recreate_name,&name)?;
-        println!("Successfully recreated {}.",name.file_name().unwrap().to_string_lossy());
-        Ok(())
+        run_ready(self.files.verify_current_consistent(&SyncAccess(&self.main_backend)))
     }
 
     /// Get the underlying backend. Used mainly for testing.
@@ -204,9 +171,9 @@ impl <B:BulletinBoardBackend> BackendJournal<B> {
     /// let dir = tempdir::TempDir::new("journal").unwrap();
     /// let mut  journal = BackendJournal::new(BackendMemory::default(),dir.path(),
     ///     StartupVerification::SanityCheckAndRepairPending).unwrap();
-    /// let history = LeafHashHistory{timestamp: 42 ,data: Some("The answer".to_string()) };
+    /// let history = LeafHashHistory{timestamp: 42 ,data: Some("The answer".to_string()), structured: false };
     /// let hash = history.compute_hash().unwrap();
-    /// journal.publish(&DatabaseTransaction{pending:vec![(hash,HashSource::Leaf(history))]});
+    /// journal.publish(&DatabaseTransaction::singleton(hash,HashSource::Leaf(history)));
     /// assert_eq!(
     ///     "0,68c3cefbe5b64fc51713cabe524cd35f2be6e52148a0f201476f16f378cb1aee,42,The answer\n\n",
     ///     std::fs::read_to_string(dir.path().join("pending.csv")).unwrap()
@@ -231,35 +198,186 @@ impl <B:BulletinBoardBackend> BackendJournal<B> {
     pub fn new<P>(main_backend:B,directory: P,verification:StartupVerification) -> Result<Self,BulletinBoardError>
     where PathBuf: From<P>
     {
-        let directory = PathBuf::from(directory);
+        let files = JournalFiles::new(PathBuf::from(directory))?;
+        run_ready(files.start(&SyncAccess(&main_backend),verification))?;
+        Ok(BackendJournal{ main_backend, files })
+    }
+}
+
+/// The journal files in a directory, and the logic for keeping them consistent with a backend, shared by
+/// [BackendJournal] and [crate::async_backend_journal::AsyncBackendJournal].
+///
+/// File I/O is blocking, so the functions that use a backend do it via [ReadAccess::run_blocking].
+#[derive(Clone)]
+pub(crate) struct JournalFiles {
+    directory : PathBuf,
+    /// Leaves censored or uncensored since the last published root, whose journal files need rewriting.
+    changed_leaves : Vec<HashValue>,
+}
+
+impl JournalFiles {
+    /// Use journals in the given directory, creating it if it does not exist.
+    pub(crate) fn new(directory:PathBuf) -> Result<Self,BulletinBoardError> {
         std::fs::create_dir_all(&directory)?;
-        let res = BackendJournal{ main_backend, directory };
+        Ok(JournalFiles{ directory, changed_leaves: vec![] })
+    }
+
+    /// Write a transaction, which has already been published to the main backend, to the journal files.
+    /// If it publishes a root, then rewrite the journal files containing leaves censored or uncensored since the last one.
+    pub(crate) async fn publish(&mut self,backend:&impl ReadAccess,transaction:&DatabaseTransaction) -> Result<(),BulletinBoardError> {
+        let files = self.clone();
+        let transaction_copy = transaction.clone();
+        backend.run_blocking(move ||files.write_to_journal(&transaction_copy)).await?;
+        if transaction.publishes_root() { self.rebuild_changed_journals(backend).await?; }
+        Ok(())
+    }
+
+    /// Note that a leaf has been censored or uncensored, so the journal file containing it needs rewriting.
+    pub(crate) fn mark_changed(&mut self,leaf:HashValue) { self.changed_leaves.push(leaf); }
+
+    /// Rewrite, once each, the journal files containing leaves censored or uncensored since this was last called.
+    async fn rebuild_changed_journals(&mut self,backend:&impl ReadAccess) -> Result<(),BulletinBoardError> {
+        let mut files_to_rebuild : HashSet<Option<HashValue>> = HashSet::default();
+        for leaf in &self.changed_leaves {
+            files_to_rebuild.insert(first_including_root(backend,*leaf).await?);
+        }
+        for root in files_to_rebuild {
+            match root {
+                Some(root) => self.recreate_using(backend,self.hash_path(root),deduce_journal_from_prior_root_to_given_root_using(backend,root).await?).await?,
+                None => self.recreate_using(backend,self.pending_path(),deduce_journal_last_published_root_to_present_using(backend).await?).await?,
+            }
+        }
+        self.changed_leaves.clear(); // only once successful, so a failure is retried at the next publication.
+        Ok(())
+    }
+
+    /// Append a transaction, which has already been published to the main backend, to the pending file,
+    /// and if it is a published root, rename the pending file to the root's name.
+    fn write_to_journal(&self, transaction: &DatabaseTransaction) -> Result<(),BulletinBoardError> {
+        {
+            let file = OpenOptions::new().append(true).create(true).open(self.pending_path())?;
+            write_transaction_to_csv(transaction,&file)?;
+            file.sync_data()?;
+        }
+        if let Some((last_hash,_)) = &transaction.pending.last() {
+            if transaction.publishes_root() {
+                // this is a published root.
+                if self.pending_path().exists() {
+                    std::fs::rename(self.pending_path(),self.hash_path(*last_hash))?;
+                } else {
+                    OpenOptions::new().append(true).create(true).open(self.hash_path(*last_hash))?; // create a blank file.
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The path of a file named name
+    fn rel_path(&self,name:&str) -> PathBuf {
+        let mut res = self.directory.clone();
+        res.push(name);
+        res
+    }
+
+    /// the path of the pending data file.
+    fn pending_path(&self) -> PathBuf { self.rel_path("pending.csv") }
+
+    /// the path for a given hash
+    fn hash_path(&self,hash:HashValue) -> PathBuf { self.rel_path(&(hash.to_string()+".csv")) }
+
+    /// See [BackendJournal::verify_current_consistent].
+    pub(crate) async fn verify_current_consistent(&self,backend:&impl ReadAccess) -> Result<(),BulletinBoardError> {
+        // First get the nodes that are left over from the last publication.
+        let last_root = backend.get_most_recent_published_root().await?;
+        let preexisting_nodes : Vec<HashValue> = if let Some(last_root) = last_root {
+            match backend.get_hash_info(last_root).await? {
+                Some(HashInfo { source: HashSource::Root(history), .. }) => history.elements,
+                _ => return Err(BulletinBoardError::BackendInconsistentError("Last oublished root hash is not actually a root node".to_string())),
+            }
+        } else {vec![]};
+        let files = self.clone();
+        let current_nodes = backend.run_blocking(move ||files.replay_pending(last_root,preexisting_nodes)).await?;
+        let expected : Vec<HashValue> = backend.get_all_leaves_and_branches_without_a_parent().await?;
+        let expected_set : HashSet<HashValue> = HashSet::from_iter(expected);
+        if expected_set != current_nodes { return Err(BulletinBoardError::BackendInconsistentError(format!("Expecting to get {:#?} as nodes without parents; actually got {:#?}.",&expected_set,&current_nodes)))}
+        Ok(())
+    }
+
+    /// Check that the journal for the last published root, if any, exists, and
+    /// play the pending file on top of the nodes in that root, returning the resulting nodes without parents.
+    fn replay_pending(&self,last_root:Option<HashValue>,preexisting_nodes:Vec<HashValue>) -> Result<HashSet<HashValue>,BulletinBoardError> {
+        if let Some(last_root) = last_root {
+            if !std::path::Path::new(&self.hash_path(last_root)).exists() { return Err(BulletinBoardError::BackendInconsistentError("Last published root hash does not exist".to_string())); } // TODO should these not be top level roots?
+        }
+        let mut current_nodes : HashSet<HashValue> = HashSet::from_iter(preexisting_nodes);
+        // process the pending file.
+        if let Ok(file_reader) = File::open(self.pending_path()) { // file may not exist.
+            for transaction in TransactionIterator::new(file_reader) {
+                for (hash,source) in transaction?.pending {
+                    current_nodes.insert(hash);
+                    match source {
+                        HashSource::Leaf(_) => { }
+                        HashSource::Branch(history) => {
+                            if !current_nodes.remove(&history.left) { return Err(BulletinBoardError::BackendInconsistentError(format!("Pending file contains a branch with unexpected left hash {}",history.left))); }
+                            if !current_nodes.remove(&history.right) { return Err(BulletinBoardError::BackendInconsistentError(format!("Pending file contains a branch with unexpected right hash {}",history.right))); }
+                        }
+                        HashSource::Root(_) => return Err(BulletinBoardError::BackendInconsistentError("Pending file contains a root".to_string())),
+                    }
+                }
+            }
+        }
+        Ok(current_nodes)
+    }
+
+    /// recreate a data file from a set of transactions.
+    fn recreate(&self,name:PathBuf,should_be:Vec<DatabaseTransaction>) -> Result<(),BulletinBoardError> {
+        let recreate_name = self.rel_path("recreating.csv");
+        { // make the file in a different name to prevent clobbering something of possible diagnostic use if all is stuffed up to badly to recover.
+            let file = File::create(&recreate_name)?;
+            for transaction in should_be {
+                write_transaction_to_csv(&transaction,&file)?;
+            }
+            file.sync_data()?;
+        }
+        std::fs::rename(recreate_name,&name)?;
+        eprintln!("Successfully recreated {}.",name.file_name().unwrap().to_string_lossy());
+        Ok(())
+    }
+
+    /// [JournalFiles::recreate], via [ReadAccess::run_blocking].
+    async fn recreate_using(&self,backend:&impl ReadAccess,name:PathBuf,should_be:Vec<DatabaseTransaction>) -> Result<(),BulletinBoardError> {
+        let files = self.clone();
+        backend.run_blocking(move ||files.recreate(name,should_be)).await
+    }
+
+    /// Run the startup verification. See [StartupVerification] for details.
+    pub(crate) async fn start(&self,backend:&impl ReadAccess,verification:StartupVerification) -> Result<(),BulletinBoardError> {
         match verification {
             StartupVerification::None => {}
-            StartupVerification::SanityCheckPending => { res.verify_current_consistent()? }
+            StartupVerification::SanityCheckPending => { self.verify_current_consistent(backend).await? }
             StartupVerification::SanityCheckAndRepairPending => {
-                match res.verify_current_consistent() {
+                match self.verify_current_consistent(backend).await {
                     Ok(()) => {}
                     Err(e) => {
-                        println!("The pending journal is corrupt. Attempting to recreate. Error was {}",e);
-                        res.recreate(res.pending_path(),crate::deduce_journal::deduce_journal_last_published_root_to_present(&res)?)?;
-                        res.verify_current_consistent()?; // check again, just to be sure.
+                        eprintln!("The pending journal is corrupt. Attempting to recreate. Error was {}",e);
+                        self.recreate_using(backend,self.pending_path(),deduce_journal_last_published_root_to_present_using(backend).await?).await?;
+                        self.verify_current_consistent(backend).await?; // check again, just to be sure.
                     }
                 }
             }
             StartupVerification::RebuildAllJournals => {
-                res.rebuild_all_journals()?;
+                self.rebuild_all_journals(backend).await?;
             }
         }
-        Ok(res)
+        Ok(())
     }
 
-    fn rebuild_all_journals(&self) -> Result<(),BulletinBoardError> {
-        for root in self.get_all_published_roots()?.into_iter().rev() {
-            self.recreate(self.hash_path(root),crate::deduce_journal::deduce_journal_from_prior_root_to_given_root(self,root)?)?;
+    pub(crate) async fn rebuild_all_journals(&self,backend:&impl ReadAccess) -> Result<(),BulletinBoardError> {
+        for root in backend.get_all_published_roots().await?.into_iter().rev() {
+            self.recreate_using(backend,self.hash_path(root),deduce_journal_from_prior_root_to_given_root_using(backend,root).await?).await?;
         }
-        self.recreate(self.pending_path(),crate::deduce_journal::deduce_journal_last_published_root_to_present(self)?)?;
-        self.verify_current_consistent()?; // check again, just to be sure.
+        self.recreate_using(backend,self.pending_path(),deduce_journal_last_published_root_to_present_using(backend).await?).await?;
+        self.verify_current_consistent(backend).await?; // check again, just to be sure.
         Ok(())
     }
 }
@@ -282,6 +400,8 @@ pub enum StartupVerification {
 mod tests {
     use crate::backend_journal::{BackendJournal, StartupVerification};
     use crate::backend_memory::BackendMemory;
+    use crate::backend_flatfile::BackendFlatfile;
+    use crate::hash_history::{HashSource, LeafHashHistory};
     use crate::BulletinBoard;
 
     #[test]
@@ -292,19 +412,19 @@ mod tests {
         let mut board = BulletinBoard::new(journal).unwrap();
         board.submit_leaf("a").unwrap();
         let pending_file = dir.path().join("pending.csv");
-        assert_eq!(true,pending_file.exists());
+        assert!(pending_file.exists());
         let hash1 = board.order_new_published_root().unwrap();
         let root_file1 = dir.path().join(&(hash1.to_string()+".csv"));
-        assert_eq!(false,pending_file.exists());
-        assert_eq!(true,root_file1.exists());
+        assert!(!pending_file.exists());
+        assert!(root_file1.exists());
         board.submit_leaf("b").unwrap();
-        assert_eq!(true,pending_file.exists());
+        assert!(pending_file.exists());
         let hash2 = board.order_new_published_root().unwrap();
         let root_file2 = dir.path().join(&(hash2.to_string()+".csv"));
-        assert_eq!(false,pending_file.exists());
-        assert_eq!(true,root_file2.exists());
+        assert!(!pending_file.exists());
+        assert!(root_file2.exists());
         board.submit_leaf("c").unwrap();
-        assert_eq!(true,pending_file.exists());
+        assert!(pending_file.exists());
         let data_root1 =  std::fs::read_to_string(&root_file1).unwrap();
         let data_root2 =  std::fs::read_to_string(&root_file2).unwrap();
         let data_pending =  std::fs::read_to_string(&pending_file).unwrap();
@@ -317,4 +437,62 @@ mod tests {
         assert_eq!(data_root2,std::fs::read_to_string(&root_file2).unwrap());
         assert_eq!(data_pending,std::fs::read_to_string(&pending_file).unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    /// Test that censoring leaves only rewrites the journal files containing them, once each, when the next root is published.
+    fn test_censor_rewrites_changed_files_on_publication() {
+        let dir = tempdir::TempDir::new("journal").unwrap();
+        let journal = BackendJournal::new(BackendMemory::default(),dir.path(),StartupVerification::SanityCheckAndRepairPending).unwrap();
+        let mut board = BulletinBoard::new(journal).unwrap();
+        let hash_a1 = board.submit_leaf("a1").unwrap();
+        let hash_a2 = board.submit_leaf("a2").unwrap();
+        let root1 = board.order_new_published_root().unwrap();
+        let hash_b = board.submit_leaf("b").unwrap();
+        let root2 = board.order_new_published_root().unwrap();
+        let hash_c = board.submit_leaf("c").unwrap();
+        let root_file1 = dir.path().join(&(root1.to_string()+".csv"));
+        let root_file2 = dir.path().join(&(root2.to_string()+".csv"));
+        let data_root2 = std::fs::read_to_string(&root_file2).unwrap();
+        std::fs::remove_file(&root_file2).unwrap(); // so we can tell if it is recreated.
+        board.censor_leaf(hash_a1,"reason","authority").unwrap();
+        board.censor_leaf(hash_a2,"reason","authority").unwrap();
+        board.censor_leaf(hash_c,"reason","authority").unwrap();
+        assert!(std::fs::read_to_string(&root_file1).unwrap().contains(",a1\n")); // not until the next publication.
+        let root3 = board.order_new_published_root().unwrap();
+        let root_file3 = dir.path().join(&(root3.to_string()+".csv"));
+        assert!(!std::fs::read_to_string(&root_file1).unwrap().contains(",a1\n"));
+        assert!(!std::fs::read_to_string(&root_file1).unwrap().contains(",a2\n"));
+        assert!(!std::fs::read_to_string(&root_file3).unwrap().contains(",c\n")); // was pending when censored.
+        assert!(!root_file2.exists());
+        std::fs::write(&root_file2,&data_root2).unwrap();
+        board.censor_leaf(hash_b,"reason","authority").unwrap();
+        board.order_new_published_root().unwrap();
+        assert!(!std::fs::read_to_string(&root_file2).unwrap().contains(",b\n"));
+        let _journal = BackendJournal::new(board.backend.into_inner(),dir.path(),StartupVerification::SanityCheckPending).unwrap();
+    }
+
+    #[test]
+    /// Test that uncensoring a leaf restores the journal files, and the flatfile database, to what they were before censorship.
+    fn test_uncensor_restores_journals() {
+        let dir = tempdir::TempDir::new("journal").unwrap();
+        let flatfile = dir.path().join("database.csv");
+        let journal = BackendJournal::new(BackendFlatfile::new(&flatfile).unwrap(),dir.path().join("journal"),StartupVerification::SanityCheckAndRepairPending).unwrap();
+        let mut board = BulletinBoard::new(journal).unwrap();
+        let hash_a = board.submit_leaf("a").unwrap();
+        let root = board.order_new_published_root().unwrap();
+        let root_file = dir.path().join("journal").join(&(root.to_string()+".csv"));
+        let data_root = std::fs::read_to_string(&root_file).unwrap();
+        board.censor_leaf(hash_a,"reason","authority").unwrap();
+        board.order_new_published_root().unwrap();
+        assert_ne!(data_root,std::fs::read_to_string(&root_file).unwrap());
+        board.uncensor_leaf(hash_a,"a","reason","authority").unwrap();
+        board.order_new_published_root().unwrap();
+        assert_eq!(data_root,std::fs::read_to_string(&root_file).unwrap());
+        // reload from the flatfile.
+        let board = BulletinBoard::new(BackendFlatfile::new(&flatfile).unwrap()).unwrap();
+        match board.get_hash_info(hash_a).unwrap().source {
+            HashSource::Leaf(LeafHashHistory{data:Some(d),..}) => assert_eq!(d,"a"),
+            _ => panic!("Not an uncensored leaf"),
+        }
+    }
+}
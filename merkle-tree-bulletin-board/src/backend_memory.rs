@@ -1,13 +1,28 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use crate::hash::HashValue;
-use crate::hash_history::{HashInfo, HashSource, LeafHashHistory};
+use crate::hash_history::{HashInfo, HashSource, LeafHashHistory, Timestamp};
 use crate::{BulletinBoardBackend, BulletinBoardError, DatabaseTransaction};
+use crate::content_index::content_hash;
 
 /// Store the contents of the "database" holding what has happened in memory. Useful for tests, but not for production.
 #[derive(Default)]
 pub struct BackendMemory {
     hash_lookup : HashMap<HashValue,HashInfo>,
     published : Vec<HashValue>,
+    /// the depth of each branch. Leaves are not stored as they always have depth 0.
+    branch_depth : HashMap<HashValue,usize>,
+    /// the leaf submitted with each idempotency key.
+    idempotency_keys : HashMap<String,HashValue>,
+    /// the leaves with each content hash. See [crate::content_index].
+    content_index : HashMap<HashValue,Vec<HashValue>>,
+    /// the leaves, in the order they were published. The position is the sequence number.
+    leaves : Vec<HashValue>,
+    /// the sequence number of each leaf.
+    leaf_indices : HashMap<HashValue,u64>,
+    /// the leaves, by (timestamp, sequence number).
+    leaves_by_timestamp : BTreeMap<(Timestamp,u64),HashValue>,
+    /// the published roots, by (timestamp, position in published).
+    roots_by_timestamp : BTreeMap<(Timestamp,usize),HashValue>,
 }
 
 impl BulletinBoardBackend for BackendMemory {
@@ -16,7 +31,7 @@ impl BulletinBoardBackend for BackendMemory {
     }
 
     fn get_most_recent_published_root(&self) -> Result<Option<HashValue>,BulletinBoardError> {
-        Ok(self.published.last().map(|h|*h))
+        Ok(self.published.last().copied())
     }
 
     fn get_all_leaves_and_branches_without_a_parent(&self) -> Result<Vec<HashValue>,BulletinBoardError> {
@@ -27,11 +42,11 @@ impl BulletinBoardBackend for BackendMemory {
                 HashSource::Root(_) => false,
             }
         }
-        Ok(self.hash_lookup.iter().filter(|(_,info)|ok(&info)).map(|(hash,_)|(*hash)).collect())
+        Ok(self.hash_lookup.iter().filter(|(_,info)|ok(info)).map(|(hash,_)|*hash ).collect())
     }
 
     fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>,BulletinBoardError> {
-        Ok(self.hash_lookup.get(&query).map(|r|r.clone()))
+        Ok(self.hash_lookup.get(&query).cloned())
     }
 
     fn publish(&mut self, transaction: &DatabaseTransaction) -> Result<(),BulletinBoardError> {
@@ -39,29 +54,125 @@ impl BulletinBoardBackend for BackendMemory {
             match source {
                 HashSource::Leaf(history) => {
                     self.hash_lookup.insert(*new_hash,HashInfo{ source: HashSource::Leaf(history.clone()), parent: None });
+                    if let Some(data) = &history.data { self.add_to_content_index(content_hash(data),*new_hash); }
+                    if !self.leaf_indices.contains_key(new_hash) {
+                        let index = self.leaves.len() as u64;
+                        self.leaf_indices.insert(*new_hash,index);
+                        self.leaves_by_timestamp.insert((history.timestamp,index),*new_hash);
+                        self.leaves.push(*new_hash);
+                    }
                 }
                 HashSource::Branch(history) => {
-                    self.hash_lookup.insert(*new_hash,HashInfo{ source: HashSource::Branch(history.clone()), parent: None });
+                    self.hash_lookup.insert(*new_hash,HashInfo{ source: HashSource::Branch(*history), parent: None });
+                    self.branch_depth.insert(*new_hash,self.get_depth(history.left)?+1);
                     self.add_parent(&history.left,*new_hash);
                     self.add_parent(&history.right,*new_hash);
 
                 }
                 HashSource::Root(history) => {
                     self.hash_lookup.insert(*new_hash,HashInfo{ source: HashSource::Root(history.clone()), parent: None });
+                    self.roots_by_timestamp.insert((history.timestamp,self.published.len()),*new_hash);
                     self.published.push(*new_hash);
                 }
             }
         }
+        if let Some((key,leaf)) = transaction.idempotency_key_and_leaf() { self.add_idempotency_key(key,leaf); }
+        Ok(())
+    }
+
+    /// A BackendMemory is owned by a single [crate::BulletinBoard], so there can be no other writer. Skip the (O(n)) check.
+    fn publish_if_unchanged(&mut self, transaction: &DatabaseTransaction, _expected_parentless: &[HashValue]) -> Result<(),BulletinBoardError> {
+        self.publish(transaction)
+    }
+
+    fn get_depth(&self, hash: HashValue) -> Result<usize,BulletinBoardError> {
+        Ok(self.branch_depth.get(&hash).cloned().unwrap_or(0))
+    }
+
+    fn get_leaf_for_idempotency_key(&self, key: &str) -> Result<Option<HashValue>,BulletinBoardError> {
+        Ok(self.idempotency_keys.get(key).cloned())
+    }
+
+    fn store_idempotency_key(&mut self, key: &str, leaf: HashValue) -> Result<(),BulletinBoardError> {
+        self.idempotency_keys.entry(key.to_string()).or_insert(leaf);
         Ok(())
     }
 
+    fn get_leaves_with_content_hash(&self, content_hash: HashValue) -> Result<Vec<HashValue>,BulletinBoardError> {
+        Ok(self.content_index.get(&content_hash).cloned().unwrap_or_default())
+    }
+
+    fn get_leaf_count(&self) -> Result<u64,BulletinBoardError> {
+        Ok(self.leaves.len() as u64)
+    }
+
+    fn get_leaf_by_index(&self, index: u64) -> Result<Option<HashValue>,BulletinBoardError> {
+        Ok(usize::try_from(index).ok().and_then(|i|self.leaves.get(i)).cloned())
+    }
+
+    fn get_leaf_index(&self, leaf: HashValue) -> Result<Option<u64>,BulletinBoardError> {
+        Ok(self.leaf_indices.get(&leaf).cloned())
+    }
+
+    fn list_leaves(&self, start: u64, count: u64) -> Result<Vec<HashValue>,BulletinBoardError> {
+        let start = usize::try_from(start).unwrap_or(usize::MAX).min(self.leaves.len());
+        let end = start.saturating_add(usize::try_from(count).unwrap_or(usize::MAX)).min(self.leaves.len());
+        Ok(self.leaves[start..end].to_vec())
+    }
+
+    fn list_leaves_between(&self, t0: Timestamp, t1: Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> {
+        if t0>=t1 { return Ok(vec![]); }
+        Ok(self.leaves_by_timestamp.range((t0,0)..(t1,0)).map(|(_,leaf)|*leaf).collect())
+    }
+
+    fn get_published_root_at(&self, t: Timestamp) -> Result<Option<HashValue>,BulletinBoardError> {
+        Ok(self.roots_by_timestamp.range(..=(t,usize::MAX)).next_back().map(|(_,root)|*root))
+    }
+
+    /// The leaf stays in the content index.
     fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(),BulletinBoardError> {
-        match self.hash_lookup.get_mut(&leaf_to_censor) {
+        self.set_leaf_data(leaf_to_censor,None)
+    }
+
+    fn uncensor_leaf(&mut self, leaf_to_uncensor: HashValue, data: &str) -> Result<(),BulletinBoardError> {
+        self.set_leaf_data(leaf_to_uncensor,Some(data.to_string()))
+    }
+}
+
+impl BackendMemory {
+    /// Record the leaf submitted with an idempotency key, replacing any prior leaf with that key.
+    pub(crate) fn add_idempotency_key(&mut self,key:&str,leaf:HashValue) {
+        self.idempotency_keys.insert(key.to_string(),leaf);
+    }
+
+    /// Record that a leaf has data with the given content hash, if not already recorded.
+    pub(crate) fn add_to_content_index(&mut self,content_hash:HashValue,leaf:HashValue) {
+        let leaves = self.content_index.entry(content_hash).or_default();
+        if !leaves.contains(&leaf) { leaves.push(leaf); }
+    }
+
+    /// The content index entries (content hash, leaf) for leaves that are currently censored, or structured leaves with
+    /// redacted fields; that is, the entries that can not be recomputed from the leaves' current data.
+    pub(crate) fn censored_content_index_entries(&self) -> Vec<(HashValue,HashValue)> {
+        let censored = |indexed:&HashValue,leaf:&HashValue| match self.hash_lookup.get(leaf) {
+            Some(HashInfo{source:HashSource::Leaf(LeafHashHistory{data,..}),..}) => data.as_ref().map(|data|content_hash(data))!=Some(*indexed),
+            _ => false,
+        };
+        self.content_index.iter().flat_map(|(content_hash,leaves)|leaves.iter().filter(|leaf|censored(content_hash,leaf)).map(move |leaf|(*content_hash,*leaf))).collect()
+    }
+
+    fn add_parent(&mut self,child:&HashValue,parent:HashValue) {
+        self.hash_lookup.get_mut(child).unwrap().parent=Some(parent);
+    }
+
+    /// Change the data for a leaf, for censorship (None) or uncensorship.
+    fn set_leaf_data(&mut self,leaf:HashValue,data:Option<String>) -> Result<(),BulletinBoardError> {
+        match self.hash_lookup.get_mut(&leaf) {
             None => Err(BulletinBoardError::NoSuchHash),
             Some(info) => {
-                match &info.source {
-                    HashSource::Leaf(LeafHashHistory{timestamp,..}) => {
-                        info.source=HashSource::Leaf(LeafHashHistory{timestamp:*timestamp,data:None});
+                match &mut info.source {
+                    HashSource::Leaf(history) => {
+                        history.data=data;
                         Ok(())
                     }
                     _ => Err(BulletinBoardError::CanOnlyCensorLeaves),
@@ -69,11 +180,45 @@ impl BulletinBoardBackend for BackendMemory {
             }
         }
     }
+
 }
 
-impl BackendMemory {
-    fn add_parent(&mut self,child:&HashValue,parent:HashValue) {
-        self.hash_lookup.get_mut(child).unwrap().parent=Some(parent);
-    }
+/// BackendMemory never blocks, so can be used directly as an async backend.
+#[cfg(feature = "async")]
+impl crate::async_board::AsyncBulletinBoardBackend for BackendMemory {
+    async fn get_all_published_roots(&self) -> Result<Vec<HashValue>,BulletinBoardError> { BulletinBoardBackend::get_all_published_roots(self) }
+
+    async fn get_most_recent_published_root(&self) -> Result<Option<HashValue>,BulletinBoardError> { BulletinBoardBackend::get_most_recent_published_root(self) }
+
+    async fn get_all_leaves_and_branches_without_a_parent(&self) -> Result<Vec<HashValue>,BulletinBoardError> { BulletinBoardBackend::get_all_leaves_and_branches_without_a_parent(self) }
+
+    async fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>,BulletinBoardError> { BulletinBoardBackend::get_hash_info(self,query) }
+
+    async fn publish(&mut self, transaction: &DatabaseTransaction) -> Result<(),BulletinBoardError> { BulletinBoardBackend::publish(self,transaction) }
+
+    async fn publish_if_unchanged(&mut self, transaction: &DatabaseTransaction, expected_parentless: &[HashValue]) -> Result<(),BulletinBoardError> { BulletinBoardBackend::publish_if_unchanged(self,transaction,expected_parentless) }
+
+    async fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(),BulletinBoardError> { BulletinBoardBackend::censor_leaf(self,leaf_to_censor) }
+
+    async fn uncensor_leaf(&mut self, leaf_to_uncensor: HashValue, data: &str) -> Result<(),BulletinBoardError> { BulletinBoardBackend::uncensor_leaf(self,leaf_to_uncensor,data) }
+
+    async fn get_depth(&self, hash: HashValue) -> Result<usize,BulletinBoardError> { BulletinBoardBackend::get_depth(self,hash) }
+
+    async fn get_leaf_for_idempotency_key(&self, key: &str) -> Result<Option<HashValue>,BulletinBoardError> { BulletinBoardBackend::get_leaf_for_idempotency_key(self,key) }
+
+    async fn store_idempotency_key(&mut self, key: &str, leaf: HashValue) -> Result<(),BulletinBoardError> { BulletinBoardBackend::store_idempotency_key(self,key,leaf) }
+
+    async fn get_leaves_with_content_hash(&self, content_hash: HashValue) -> Result<Vec<HashValue>,BulletinBoardError> { BulletinBoardBackend::get_leaves_with_content_hash(self,content_hash) }
+
+    async fn get_leaf_count(&self) -> Result<u64,BulletinBoardError> { BulletinBoardBackend::get_leaf_count(self) }
+
+    async fn get_leaf_by_index(&self, index: u64) -> Result<Option<HashValue>,BulletinBoardError> { BulletinBoardBackend::get_leaf_by_index(self,index) }
+
+    async fn get_leaf_index(&self, leaf: HashValue) -> Result<Option<u64>,BulletinBoardError> { BulletinBoardBackend::get_leaf_index(self,leaf) }
+
+    async fn list_leaves(&self, start: u64, count: u64) -> Result<Vec<HashValue>,BulletinBoardError> { BulletinBoardBackend::list_leaves(self,start,count) }
+
+    async fn list_leaves_between(&self, t0: Timestamp, t1: Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> { BulletinBoardBackend::list_leaves_between(self,t0,t1) }
 
+    async fn get_published_root_at(&self, t: Timestamp) -> Result<Option<HashValue>,BulletinBoardError> { BulletinBoardBackend::get_published_root_at(self,t) }
 }
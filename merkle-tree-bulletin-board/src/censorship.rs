@@ -0,0 +1,105 @@
+//! Records of censorship, committed to in the bulletin board itself.
+//!
+//! Each time a leaf is censored via [crate::BulletinBoard::censor_leaf], a [CensorshipRecord] saying which leaf
+//! was censored, why, and on whose authority, is added to the board as a leaf, before the
+//! censorship happens. The next published root therefore commits to the fact that the
+//! censorship occurred, and the timestamp of the record's leaf says when.
+//!
+//! Likewise, reversing a censorship via [crate::BulletinBoard::uncensor_leaf] adds a record with `uncensored` set.
+//!
+//! The record is stored as the leaf's data, being [CENSORSHIP_RECORD_PREFIX] followed by the JSON encoding of
+//! the record. So that records can't be forged, ordinary submissions starting with that prefix are rejected.
+
+use serde::{Serialize,Deserialize};
+use crate::{BulletinBoardBackend, BulletinBoardError, DatabaseTransaction};
+use crate::backend_access::{run_ready, ReadAccess, SyncAccess};
+use crate::deduce_journal::{deduce_journal_using, get_hashes_for_optional_root};
+use crate::hash::HashValue;
+use crate::hash_history::{HashSource, LeafHashHistory, Timestamp};
+
+/// The start of the data of a leaf containing a [CensorshipRecord].
+pub const CENSORSHIP_RECORD_PREFIX : &str = "bulletin-board-censorship-record:";
+
+/// Why a leaf was censored.
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub struct CensorshipRecord {
+    /// The hash of the leaf that was censored.
+    pub censored_leaf : HashValue,
+    /// A code for the reason, or legal basis, for the censorship.
+    pub reason : String,
+    /// Who ordered or authorized the censorship.
+    pub authority : String,
+    /// If only one field of a structured leaf was censored, the name of that field.
+    /// See [crate::BulletinBoard::redact_field].
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub field : Option<String>,
+    /// True if this records the reversal of an earlier censorship of `censored_leaf`, rather than the censorship itself.
+    #[serde(default,skip_serializing_if="std::ops::Not::not")]
+    pub uncensored : bool,
+}
+
+impl CensorshipRecord {
+    /// The data for the leaf containing this record.
+    pub fn to_leaf_data(&self) -> String {
+        CENSORSHIP_RECORD_PREFIX.to_string()+&serde_json::to_string(self).unwrap()
+    }
+
+    /// Get the record from the data for a leaf, if it is a censorship record.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::censorship::CensorshipRecord;
+    /// use merkle_tree_bulletin_board::hash::HashValue;
+    ///
+    /// let record = CensorshipRecord{ censored_leaf: HashValue([0;32]), reason: "court order 42".to_string(), authority: "Judge Dredd".to_string(), field: None, uncensored: false };
+    /// assert_eq!(CensorshipRecord::from_leaf_data(&record.to_leaf_data()),Some(record));
+    /// assert_eq!(CensorshipRecord::from_leaf_data("Just some data"),None);
+    /// ```
+    pub fn from_leaf_data(data:&str) -> Option<Self> {
+        serde_json::from_str(data.strip_prefix(CENSORSHIP_RECORD_PREFIX)?).ok()
+    }
+}
+
+/// A censorship record found in the bulletin board.
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub struct CensorshipEvent {
+    /// The hash of the leaf containing the record.
+    pub record_leaf : HashValue,
+    /// The timestamp of the leaf containing the record, which is when the censorship occurred.
+    pub timestamp : Timestamp,
+    /// The record itself.
+    pub record : CensorshipRecord,
+}
+
+/// Get all the censorship records added to the board after the published root `from` (or the beginning of
+/// time, if None), up to and including those in the published root `to`, oldest first.
+///
+/// This uses [crate::deduce_journal::deduce_journal], and so takes time proportional to the number of leaves added in that interval.
+/// Censorship records can not be censored (see [crate::BulletinBoard::censor_leaf]). A record censored by an earlier version
+/// of this library is not included, as its text is not available.
+pub fn get_censorship_records_between(backend:&impl BulletinBoardBackend,from:Option<HashValue>,to:HashValue) -> Result<Vec<CensorshipEvent>,BulletinBoardError> {
+    run_ready(get_censorship_records_between_using(&SyncAccess(backend),from,to))
+}
+
+/// [get_censorship_records_between], for any kind of backend.
+pub(crate) async fn get_censorship_records_between_using(backend:&impl ReadAccess,from:Option<HashValue>,to:HashValue) -> Result<Vec<CensorshipEvent>,BulletinBoardError> {
+    let from_nodes = get_hashes_for_optional_root(backend,from).await?;
+    let to_nodes = get_hashes_for_optional_root(backend,Some(to)).await?;
+    Ok(find_censorship_records(deduce_journal_using(backend,&from_nodes,&to_nodes,false).await?))
+}
+
+/// Find the censorship records in the leaves of a journal.
+fn find_censorship_records(journal:Vec<DatabaseTransaction>) -> Vec<CensorshipEvent> {
+    let mut res = vec![];
+    for transaction in journal {
+        for (hash,source) in transaction.pending {
+            if let HashSource::Leaf(LeafHashHistory{timestamp,data:Some(data),..}) = source {
+                if let Some(record) = CensorshipRecord::from_leaf_data(&data) {
+                    res.push(CensorshipEvent{ record_leaf: hash, timestamp, record });
+                }
+            }
+        }
+    }
+    res
+}
@@ -0,0 +1,181 @@
+//! Detecting when the same data is submitted more than once.
+//!
+//! [crate::BulletinBoard::submit_leaf] only rejects data identical to a leaf submitted in the same second, as that
+//! would produce the same leaf hash. Some boards need to never accept the same data twice. For these, backends
+//! can keep a content index, mapping the [content_hash] of each leaf's data to the leaves with that data,
+//! and the board can be told what to do with a duplicate with [crate::BulletinBoard::set_duplicate_policy].
+//!
+//! The content index is keyed by the hash of the data alone, without the timestamp. It covers all leaves,
+//! including structured leaves and censorship records. Structured leaves contain random salts, so will not in practice
+//! be duplicates, but censorship records can be, e.g. if a leaf is censored, uncensored, and censored again for the
+//! same reason on the same authority. The duplicate policy therefore only applies to submissions, not to censorship
+//! records, which the board adds itself and which must be added for every censorship.
+//!
+//! Censoring a leaf does not remove it from the content index. So a censored leaf's data counts as a duplicate,
+//! and cannot be resubmitted to get around the censorship. With [DuplicatePolicy::ReturnExisting], resubmitting
+//! it returns the censored leaf. The index only contains the hash of the data, so no censored text is retained.
+//!
+//! The content index also lets someone who has their original text, but not the leaf hash or timestamp, find their
+//! leaf with [crate::BulletinBoard::find_leaves_by_data].
+//!
+//! Not all backends have a content index; see [crate::BulletinBoardBackend::get_leaves_with_content_hash].
+//!
+//! # Example
+//!
+//! ```
+//! use merkle_tree_bulletin_board::BulletinBoard;
+//! use merkle_tree_bulletin_board::BulletinBoardError;
+//! use merkle_tree_bulletin_board::backend_memory::BackendMemory;
+//! use merkle_tree_bulletin_board::content_index::DuplicatePolicy;
+//!
+//! let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+//! board.set_duplicate_policy(DuplicatePolicy::Reject);
+//! let a = board.submit_leaf("Is it raining?").unwrap();
+//! assert_eq!(board.submit_leaf("Is it raining?"),Err(BulletinBoardError::DuplicateData(a)));
+//! board.set_duplicate_policy(DuplicatePolicy::ReturnExisting);
+//! assert_eq!(board.submit_leaf("Is it raining?"),Ok(a));
+//! board.censor_leaf(a,"Too gloomy","Moderator").unwrap();
+//! board.set_duplicate_policy(DuplicatePolicy::Reject);
+//! assert_eq!(board.submit_leaf("Is it raining?"),Err(BulletinBoardError::DuplicateData(a))); // still a duplicate.
+//! ```
+
+use sha2::{Sha256, Digest};
+use serde::{Serialize,Deserialize};
+use crate::BulletinBoardError;
+use crate::backend_access::ReadAccess;
+use crate::hash::HashValue;
+use crate::hash_history::{HashInfo, HashSource, LeafHashHistory};
+
+/// What a bulletin board does when data is submitted that is the same as the data of an existing leaf.
+#[derive(Debug,Copy,Clone,Serialize,Deserialize,Eq,PartialEq,Default)]
+pub enum DuplicatePolicy {
+    /// Add a new leaf anyway. This is the default, and does not need a content index.
+    #[default]
+    Allow,
+    /// Fail with [BulletinBoardError::DuplicateData].
+    Reject,
+    /// Add nothing, and return the existing leaf, as if it had just been submitted.
+    ReturnExisting,
+}
+
+/// The key of the content index : sha256(data). Unlike a leaf hash, this does not depend on the timestamp.
+pub fn content_hash(data:&str) -> HashValue {
+    let mut hasher = Sha256::default();
+    hasher.update(data.as_bytes());
+    HashValue(<[u8; 32]>::from(hasher.finalize()))
+}
+
+/// Given the existing leaves with the same data as a submission, in the order they were recorded, decide what to do
+/// under `policy`. Returns the leaf to return instead of submitting, or None if the submission should go ahead.
+pub(crate) fn check_duplicate(policy:DuplicatePolicy,existing:&[HashValue]) -> Result<Option<HashValue>,BulletinBoardError> {
+    match (policy,existing.first()) {
+        (DuplicatePolicy::Allow,_) | (_,None) => Ok(None),
+        (DuplicatePolicy::Reject,Some(&leaf)) => Err(BulletinBoardError::DuplicateData(leaf)),
+        (DuplicatePolicy::ReturnExisting,Some(&leaf)) => Ok(Some(leaf)),
+    }
+}
+
+/// Whether `data` is the data of `leaf`, given the leaf's information. A censored leaf, or a structured leaf with
+/// redacted fields, matches if `data`, with the leaf's timestamp, hashes to the leaf's hash.
+fn leaf_has_data(info:Option<HashInfo>,leaf:HashValue,data:&str) -> bool {
+    match info {
+        Some(HashInfo{source:HashSource::Leaf(history),..}) => {
+            history.data.as_deref()==Some(data) || LeafHashHistory{ data: Some(data.to_string()), ..history }.compute_hash()==Some(leaf)
+        }
+        _ => false,
+    }
+}
+
+/// Implementation of [crate::BulletinBoard::find_leaves_by_data], shared by the sync and async boards and readers.
+pub(crate) async fn find_leaves_by_data_using(backend:&impl ReadAccess,data:&str) -> Result<Vec<HashValue>,BulletinBoardError> {
+    let mut res = vec![];
+    for leaf in backend.get_leaves_with_content_hash(content_hash(data)).await? {
+        if leaf_has_data(backend.get_hash_info(leaf).await?,leaf,data) { res.push(leaf); }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BulletinBoard, BulletinBoardBackend, BulletinBoardError};
+    use crate::backend_memory::BackendMemory;
+    use crate::content_index::{content_hash, DuplicatePolicy};
+    use crate::hash_history::{HashSource, LeafHashHistory};
+    use crate::structured_leaf::StructuredLeaf;
+
+    #[test]
+    /// Test finding several leaves with the same data, in order, including after some are censored.
+    fn test_find_same_data() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let first = board.add_leaf_at("same",1000,false);
+        let other = board.add_leaf_at("other",1001,false);
+        let second = board.add_leaf_at("same",1002,false);
+        let third = board.add_leaf_at("same",1003,false);
+        assert_eq!(board.find_leaves_by_data("same").unwrap(),vec![first,second,third]);
+        assert_eq!(board.find_leaves_by_data("other").unwrap(),vec![other]);
+        assert_eq!(board.find_leaves_by_data("Same").unwrap(),vec![]);
+        board.censor_leaf(second,"Spam","Moderator").unwrap();
+        board.censor_leaf(other,"Spam","Moderator").unwrap();
+        assert_eq!(board.find_leaves_by_data("same").unwrap(),vec![first,second,third]);
+        assert_eq!(board.find_leaves_by_data("other").unwrap(),vec![other]);
+        board.uncensor_leaf(second,"same","Not spam","Moderator").unwrap();
+        assert_eq!(board.find_leaves_by_data("same").unwrap(),vec![first,second,third]);
+    }
+
+    #[test]
+    /// Test finding structured leaves by their full data, including after redaction and censorship, but not by the submitted object.
+    fn test_find_structured_leaves() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let object = serde_json::json!({"name":"Fred","address":"123 Example Street"});
+        let leaf = board.submit_structured_leaf(object.as_object().unwrap()).unwrap();
+        let data = match board.get_hash_info(leaf).unwrap().source {
+            HashSource::Leaf(LeafHashHistory{data:Some(data),..}) => data,
+            _ => panic!("Not an uncensored leaf"),
+        };
+        assert_eq!(board.find_leaves_by_data(&data).unwrap(),vec![leaf]);
+        assert_eq!(board.find_leaves_by_data(&serde_json::to_string(&object).unwrap()).unwrap(),vec![]);
+        let plain = board.submit_leaf(&serde_json::to_string(&object).unwrap()).unwrap();
+        assert_eq!(board.find_leaves_by_data(&serde_json::to_string(&object).unwrap()).unwrap(),vec![plain]);
+        board.redact_field(leaf,"address","Privacy","Moderator").unwrap();
+        assert_eq!(board.find_leaves_by_data(&data).unwrap(),vec![leaf]);
+        let redacted = StructuredLeaf::from_leaf_data(&data).unwrap().redact("address").unwrap().to_leaf_data();
+        assert_eq!(board.find_leaves_by_data(&redacted).unwrap(),vec![]); // only indexed by the original data.
+        board.censor_leaf(leaf,"Privacy","Moderator").unwrap();
+        assert_eq!(board.find_leaves_by_data(&data).unwrap(),vec![leaf]);
+    }
+
+    #[test]
+    /// Check that censoring a leaf again after uncensoring it adds a new censorship record, whatever the duplicate policy.
+    fn test_recensor_under_duplicate_policies() {
+        for policy in [DuplicatePolicy::Allow,DuplicatePolicy::Reject,DuplicatePolicy::ReturnExisting] {
+            let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+            board.set_duplicate_policy(policy);
+            let leaf = board.submit_leaf("a").unwrap();
+            let first = board.censor_leaf(leaf,"Spam","Moderator").unwrap();
+            board.uncensor_leaf(leaf,"a","Not spam","Moderator").unwrap();
+            let second = board.censor_leaf(leaf,"Spam","Moderator").unwrap();
+            assert_ne!(first,second,"{:?}",policy);
+            assert!(matches!(board.get_hash_info(leaf).unwrap().source,HashSource::Leaf(LeafHashHistory{data:None,..})),"{:?}",policy);
+            let root = board.order_new_published_root().unwrap();
+            let records : Vec<_> = board.get_censorship_records_between(None,root).unwrap().into_iter().filter(|e|!e.record.uncensored).map(|e|e.record_leaf).collect();
+            assert_eq!(records,vec![first,second],"{:?}",policy);
+        }
+    }
+
+    #[test]
+    /// Check that an idempotency key used for a duplicate returned under [DuplicatePolicy::ReturnExisting] identifies the existing leaf,
+    /// and that a key already in use is not moved to another leaf.
+    fn test_idempotency_key_for_existing_leaf() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        board.set_duplicate_policy(DuplicatePolicy::ReturnExisting);
+        let a = board.submit_leaf("a").unwrap();
+        assert_eq!(board.submit_leaf_with_idempotency_key("a","request 1"),Ok(a));
+        assert_eq!(board.backend.get_leaf_for_idempotency_key("request 1"),Ok(Some(a)));
+        board.set_duplicate_policy(DuplicatePolicy::Allow);
+        assert_eq!(board.submit_leaf_with_idempotency_key("a","request 1"),Ok(a)); // a retry, not a new leaf.
+        assert_eq!(board.submit_leaf_with_idempotency_key("b","request 1"),Err(BulletinBoardError::IdempotencyKeyReused));
+        assert_eq!(board.get_parentless_unpublished_hash_values().unwrap(),vec![a]);
+        board.backend.store_idempotency_key("request 1",content_hash("b")).unwrap();
+        assert_eq!(board.backend.get_leaf_for_idempotency_key("request 1"),Ok(Some(a)));
+    }
+}
@@ -3,6 +3,7 @@ use crate::hash::HashValue;
 use crate::growing_forest::{GrowingForest, HashAndDepth};
 use crate::hash_history::{HashInfo, HashSource, RootHashHistory};
 use std::collections::HashMap;
+use crate::backend_access::{run_ready, ReadAccess, SyncAccess};
 
 /// Deduce the set of transactions needed to go from state 'from' to state 'to'
 /// where the states are the list of leaves or branches without parents.
@@ -76,17 +77,25 @@ use std::collections::HashMap;
 /// assert_eq!(journal[5].pending.len(),3); // publish "D", make a tree with "C", make a tree with "AB".
 /// assert_eq!(journal[6].pending.len(),1); // published root
 /// ```
-
 pub fn deduce_journal(board:&impl BulletinBoardBackend,from:&Vec<HashValue>,to:&Vec<HashValue>,include_published_roots:bool) -> Result<Vec<DatabaseTransaction>,BulletinBoardError> {
+    run_ready(deduce_journal_using(&SyncAccess(board),from,to,include_published_roots))
+}
+
+/// [deduce_journal], for any kind of backend.
+pub(crate) async fn deduce_journal_using(board:&impl ReadAccess,from:&Vec<HashValue>,to:&Vec<HashValue>,include_published_roots:bool) -> Result<Vec<DatabaseTransaction>,BulletinBoardError> {
     let mut res = vec![];
-    let from_last = GrowingForest::new(from,|h|board.left_depth(h))?.last();
-    let mut work = GrowingForest::new(to,|h|board.left_depth(h))?;
+    let mut depths : HashMap<HashValue,usize> = HashMap::default();
+    for hash in from.iter().chain(to.iter()) {
+        depths.insert(*hash,board.get_depth(*hash).await?);
+    }
+    let from_last = GrowingForest::new(from,|h|Ok(depths[&h]))?.last();
+    let mut work = GrowingForest::new(to,|h|Ok(depths[&h]))?;
     let mut current_trans : Vec<(HashValue,HashSource)> = vec![];
     let mut at_very_start : Vec<DatabaseTransaction> = vec![];
     let mut check_for_published_roots : HashMap<HashValue,Vec<DatabaseTransaction>> = if include_published_roots {
         let mut check : HashMap<HashValue,Vec<DatabaseTransaction>> = Default::default();
-        for root in board.get_all_published_roots()? {
-            let info = board.get_hash_info(root)?.unwrap();
+        for root in board.get_all_published_roots().await? {
+            let info = board.get_hash_info(root).await?.unwrap();
             match &info.source {
                 HashSource::Root(RootHashHistory{elements,..}) => {
                     if let Some(last) = elements.last() {
@@ -113,7 +122,7 @@ pub fn deduce_journal(board:&impl BulletinBoardBackend,from:&Vec<HashValue>,to:&
         work.last() != from_last // the actual condition of the while loop.
           } {
         if let Some(HashAndDepth{ hash, depth }) = work.forest.pop() {
-            match board.get_hash_info(hash)?.ok_or_else(||BulletinBoardError::BackendInconsistentError(format!("Hash {} does not have any info",hash)))?.source {
+            match board.get_hash_info(hash).await?.ok_or_else(||BulletinBoardError::BackendInconsistentError(format!("Hash {} does not have any info",hash)))?.source {
                 HashSource::Leaf(history) => { // undo the leaf. This is the start of a transaction.
                     let mut transaction = DatabaseTransaction::default();
                     transaction.pending.push((hash,HashSource::Leaf(history)));
@@ -136,9 +145,9 @@ pub fn deduce_journal(board:&impl BulletinBoardBackend,from:&Vec<HashValue>,to:&
 }
 
 /// Get the hashes for the given root, should it exist. If not, empty vec.
-fn get_hashes_for_optional_root(board:&impl BulletinBoardBackend,root:Option<HashValue>) -> Result<Vec<HashValue>,BulletinBoardError> {
+pub(crate) async fn get_hashes_for_optional_root(board:&impl ReadAccess,root:Option<HashValue>) -> Result<Vec<HashValue>,BulletinBoardError> {
     if let Some(root) = root {
-        match board.get_hash_info(root)? {
+        match board.get_hash_info(root).await? {
             Some(HashInfo{source:HashSource::Root(RootHashHistory{ elements,.. }),..}) => Ok(elements),
             _ => Err(BulletinBoardError::BackendInconsistentError(format!("{} is not a root",root)))
         }
@@ -166,7 +175,13 @@ fn get_hashes_for_optional_root(board:&impl BulletinBoardBackend,root:Option<Has
 /// assert_eq!(journal[0].pending[0].0,d); // publish D
 /// ```
 pub fn deduce_journal_last_published_root_to_present(board:&impl BulletinBoardBackend) -> Result<Vec<DatabaseTransaction>,BulletinBoardError> {
-    deduce_journal(board,&get_hashes_for_optional_root(board,board.get_most_recent_published_root()?)?,&board.get_all_leaves_and_branches_without_a_parent()?,false)
+    run_ready(deduce_journal_last_published_root_to_present_using(&SyncAccess(board)))
+}
+
+/// [deduce_journal_last_published_root_to_present], for any kind of backend.
+pub(crate) async fn deduce_journal_last_published_root_to_present_using(board:&impl ReadAccess) -> Result<Vec<DatabaseTransaction>,BulletinBoardError> {
+    let from = get_hashes_for_optional_root(board,board.get_most_recent_published_root().await?).await?;
+    deduce_journal_using(board,&from,&board.get_all_leaves_and_branches_without_a_parent().await?,false).await
 }
 
 /// A special case of [deduce_journal] that gets the journal from the last published hash to the present day.
@@ -193,10 +208,16 @@ pub fn deduce_journal_last_published_root_to_present(board:&impl BulletinBoardBa
 /// assert_eq!(journal[3].pending.len(),1); // root
 /// ```
 pub fn deduce_journal_from_prior_root_to_given_root(board:&impl BulletinBoardBackend,root:HashValue) -> Result<Vec<DatabaseTransaction>,BulletinBoardError> {
-    match board.get_hash_info(root)? {
-        Some(HashInfo{source: HashSource::Root(RootHashHistory{ elements,prior,timestamp }),..}) => {
-            let mut journal = deduce_journal(board,&get_hashes_for_optional_root(board,prior)?,&elements,false)?;
-            journal.push(DatabaseTransaction::singleton(root,HashSource::Root(RootHashHistory{ elements,prior,timestamp })));
+    run_ready(deduce_journal_from_prior_root_to_given_root_using(&SyncAccess(board),root))
+}
+
+/// [deduce_journal_from_prior_root_to_given_root], for any kind of backend.
+pub(crate) async fn deduce_journal_from_prior_root_to_given_root_using(board:&impl ReadAccess,root:HashValue) -> Result<Vec<DatabaseTransaction>,BulletinBoardError> {
+    match board.get_hash_info(root).await? {
+        Some(HashInfo{source: HashSource::Root(RootHashHistory{ elements,prior,timestamp,skips }),..}) => {
+            let from = get_hashes_for_optional_root(board,prior).await?;
+            let mut journal = deduce_journal_using(board,&from,&elements,false).await?;
+            journal.push(DatabaseTransaction::singleton(root,HashSource::Root(RootHashHistory{ elements,prior,timestamp,skips })));
             Ok(journal)
         },
         _ => Err(BulletinBoardError::BackendInconsistentError(format!("{} is not a root",root)))
@@ -0,0 +1,230 @@
+//! Notification of changes to the bulletin board, e.g. to update search indexes or send notifications.
+//!
+//! Register a [BulletinBoardListener] with [crate::BulletinBoard::add_listener]. After each change is successfully
+//! committed to the backend, each listener is called with a [BulletinBoardEvent] describing the change. Any function or closure
+//! `Fn(&BulletinBoardEvent)` can be used as a listener. Listeners are called synchronously by the writer, so should be quick.
+//! A listener can be removed again with [crate::BulletinBoard::remove_listener], using the [ListenerHandle] returned when it was added.
+//!
+//! With the `async` feature, [crate::BulletinBoard::subscribe] gives a channel from which events can be received
+//! asynchronously instead.
+//!
+//! Only changes made through the bulletin board object the listener is registered with produce events; changes
+//! made by other writers sharing the same database do not.
+
+use serde::{Serialize,Deserialize};
+use crate::DatabaseTransaction;
+use crate::hash::HashValue;
+use crate::hash_history::{HashSource, Timestamp};
+
+/// A change to the bulletin board.
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub enum BulletinBoardEvent {
+    /// A leaf was added, possibly creating some new branches.
+    /// This includes leaves containing censorship records.
+    LeafAdded {
+        leaf : HashValue,
+        timestamp : Timestamp,
+        /// The branches created by adding the leaf, lowest first.
+        branches : Vec<HashValue>,
+    },
+    /// A new root was published.
+    RootPublished {
+        root : HashValue,
+        timestamp : Timestamp,
+        /// The prior published root, if any.
+        prior : Option<HashValue>,
+        /// The elements of the new root.
+        elements : Vec<HashValue>,
+    },
+    /// A leaf was censored, or a field of a structured leaf was censored.
+    LeafCensored {
+        leaf : HashValue,
+        /// The leaf containing the [crate::censorship::CensorshipRecord].
+        record_leaf : HashValue,
+        /// If just one field of a structured leaf was censored, the name of that field.
+        field : Option<String>,
+    },
+    /// The text of a censored leaf was restored.
+    LeafUncensored {
+        leaf : HashValue,
+        /// The leaf containing the [crate::censorship::CensorshipRecord] of the reversal.
+        record_leaf : HashValue,
+    },
+}
+
+/// Something that wants to know about changes to the bulletin board.
+pub trait BulletinBoardListener : Send + Sync {
+    /// Called after a change has been committed.
+    fn on_event(&self,event:&BulletinBoardEvent);
+}
+
+impl <F:Fn(&BulletinBoardEvent)+Send+Sync> BulletinBoardListener for F {
+    fn on_event(&self, event: &BulletinBoardEvent) { self(event) }
+}
+
+/// Identifies a listener added with [crate::BulletinBoard::add_listener], so that it can be removed.
+#[derive(Debug,Clone,Copy,Eq,PartialEq,Hash)]
+pub struct ListenerHandle(u64);
+
+/// The listeners registered with a bulletin board.
+#[derive(Default)]
+pub(crate) struct EventDispatcher {
+    listeners : Vec<(ListenerHandle,Box<dyn BulletinBoardListener>)>,
+    /// The number of listeners ever added, used to make unique handles.
+    listeners_added : u64,
+    #[cfg(feature = "async")]
+    subscribers : Vec<async_std::channel::Sender<BulletinBoardEvent>>,
+}
+
+impl EventDispatcher {
+    pub(crate) fn add_listener(&mut self,listener:impl BulletinBoardListener+'static) -> ListenerHandle {
+        let handle = ListenerHandle(self.listeners_added);
+        self.listeners_added+=1;
+        self.listeners.push((handle,Box::new(listener)));
+        handle
+    }
+
+    /// Remove a listener, returning false if it was not present.
+    pub(crate) fn remove_listener(&mut self,handle:ListenerHandle) -> bool {
+        let len_before = self.listeners.len();
+        self.listeners.retain(|(h,_)|*h!=handle);
+        self.listeners.len()!=len_before
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn subscribe(&mut self) -> async_std::channel::Receiver<BulletinBoardEvent> {
+        let (sender,receiver) = async_std::channel::unbounded();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Tell everyone about an event.
+    pub(crate) fn fire(&mut self,event:BulletinBoardEvent) {
+        for (_,listener) in &self.listeners { listener.on_event(&event); }
+        #[cfg(feature = "async")]
+        self.subscribers.retain(|s|s.try_send(event.clone()).is_ok()); // forget about subscribers that have dropped their receiver.
+    }
+
+    /// Tell everyone about a transaction that was committed by submitting a leaf or publishing a root.
+    pub(crate) fn fire_transaction(&mut self,transaction:&DatabaseTransaction) {
+        match transaction.pending.first() {
+            Some((leaf,HashSource::Leaf(history))) => {
+                let branches = transaction.pending[1..].iter().map(|(hash,_)|*hash).collect();
+                self.fire(BulletinBoardEvent::LeafAdded { leaf: *leaf, timestamp: history.timestamp, branches });
+            }
+            Some((root,HashSource::Root(history))) => {
+                self.fire(BulletinBoardEvent::RootPublished { root: *root, timestamp: history.timestamp, prior: history.prior, elements: history.elements.clone() });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use crate::{BulletinBoard, BulletinBoardBackend, BulletinBoardError, DatabaseTransaction};
+    use crate::backend_memory::BackendMemory;
+    use crate::events::BulletinBoardEvent;
+    use crate::hash::HashValue;
+    use crate::hash_history::{HashInfo, HashSource};
+
+    /// A backend whose writes can be made to fail.
+    #[derive(Default)]
+    struct FailingWrites {
+        memory : BackendMemory,
+        /// All writes fail.
+        fail : bool,
+        /// Censoring and uncensoring fail.
+        fail_censorship : bool,
+    }
+
+    impl FailingWrites {
+        fn check(&self,censorship:bool) -> Result<(),BulletinBoardError> {
+            if self.fail || (censorship && self.fail_censorship) { Err(BulletinBoardError::BackendIOError("disk full".to_string())) } else { Ok(()) }
+        }
+    }
+
+    impl BulletinBoardBackend for FailingWrites {
+        fn get_all_published_roots(&self) -> Result<Vec<HashValue>, BulletinBoardError> { self.memory.get_all_published_roots() }
+        fn get_most_recent_published_root(&self) -> Result<Option<HashValue>, BulletinBoardError> { self.memory.get_most_recent_published_root() }
+        fn get_all_leaves_and_branches_without_a_parent(&self) -> Result<Vec<HashValue>, BulletinBoardError> { self.memory.get_all_leaves_and_branches_without_a_parent() }
+        fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>, BulletinBoardError> { self.memory.get_hash_info(query) }
+        fn publish(&mut self, transaction: &DatabaseTransaction) -> Result<(), BulletinBoardError> { self.check(false)?; self.memory.publish(transaction) }
+        fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(), BulletinBoardError> { self.check(true)?; self.memory.censor_leaf(leaf_to_censor) }
+        fn uncensor_leaf(&mut self, leaf_to_uncensor: HashValue, data: &str) -> Result<(), BulletinBoardError> { self.check(true)?; self.memory.uncensor_leaf(leaf_to_uncensor,data) }
+    }
+
+    /// Make a board, with a listener recording all events.
+    fn board_and_events<B:BulletinBoardBackend>(backend:B) -> (BulletinBoard<B>,Arc<Mutex<Vec<BulletinBoardEvent>>>) {
+        let mut board = BulletinBoard::new(backend).unwrap();
+        let heard = Arc::new(Mutex::new(vec![]));
+        let heard_by_listener = heard.clone();
+        board.add_listener(move |event:&BulletinBoardEvent| heard_by_listener.lock().unwrap().push(event.clone()));
+        (board,heard)
+    }
+
+    /// The event for adding a leaf, with the branches made above it.
+    fn leaf_added<B:BulletinBoardBackend>(board:&BulletinBoard<B>,leaf:HashValue,branches:usize) -> BulletinBoardEvent {
+        let info = board.get_hash_info(leaf).unwrap();
+        let HashSource::Leaf(history) = info.source else { panic!("Not a leaf") };
+        let mut ancestors = vec![];
+        let mut parent = info.parent;
+        while let Some(branch) = parent {
+            ancestors.push(branch);
+            parent = board.get_hash_info(branch).unwrap().parent;
+        }
+        ancestors.truncate(branches);
+        BulletinBoardEvent::LeafAdded { leaf, timestamp: history.timestamp, branches: ancestors }
+    }
+
+    #[test]
+    /// Test the events fired, in order, by submitting, publishing, censoring, redacting and uncensoring.
+    fn test_events_in_order() {
+        let (mut board,heard) = board_and_events(BackendMemory::default());
+        let a = board.submit_leaf("a").unwrap();
+        let b = board.submit_leaf("b").unwrap();
+        let root = board.order_new_published_root().unwrap();
+        let HashSource::Root(root_history) = board.get_hash_info(root).unwrap().source else { panic!("Not a root") };
+        let censorship = board.censor_leaf(a,"Spam","Moderator").unwrap();
+        let object = serde_json::json!({"name":"Fred","address":"123 Example Street"});
+        let structured = board.submit_structured_leaf(object.as_object().unwrap()).unwrap();
+        let redaction = board.redact_field(structured,"address","Privacy","Moderator").unwrap();
+        let uncensorship = board.uncensor_leaf(a,"a","Not spam","Appeals panel").unwrap();
+        assert_eq!(*heard.lock().unwrap(),vec![
+            leaf_added(&board,a,0),
+            leaf_added(&board,b,1),
+            BulletinBoardEvent::RootPublished { root, timestamp: root_history.timestamp, prior: None, elements: root_history.elements.clone() },
+            leaf_added(&board,censorship,0),
+            BulletinBoardEvent::LeafCensored { leaf: a, record_leaf: censorship, field: None },
+            leaf_added(&board,structured,2), // merges with the censorship record, then a and b.
+            leaf_added(&board,redaction,0),
+            BulletinBoardEvent::LeafCensored { leaf: structured, record_leaf: redaction, field: Some("address".to_string()) },
+            leaf_added(&board,uncensorship,1),
+            BulletinBoardEvent::LeafUncensored { leaf: a, record_leaf: uncensorship },
+        ]);
+    }
+
+    #[test]
+    /// Test that nothing is fired for changes that the backend fails to make, other than for a censorship record that was added.
+    fn test_no_events_on_failure() {
+        let (mut board,heard) = board_and_events(FailingWrites::default());
+        let a = board.submit_leaf("a").unwrap();
+        let censorship = board.censor_leaf(a,"Spam","Moderator").unwrap();
+        heard.lock().unwrap().clear();
+        board.backend.fail = true;
+        assert!(board.submit_leaf("b").is_err());
+        assert!(board.order_new_published_root().is_err());
+        assert!(board.censor_leaf(censorship,"Spam","Moderator").is_err()); // rejected before anything is written.
+        assert!(board.uncensor_leaf(a,"a","Not spam","Appeals panel").is_err());
+        assert!(heard.lock().unwrap().is_empty());
+        board.backend.fail = false;
+        let uncensorship = board.uncensor_leaf(a,"a","Not spam","Appeals panel").unwrap();
+        assert_eq!(*heard.lock().unwrap(),vec![leaf_added(&board,uncensorship,0),BulletinBoardEvent::LeafUncensored { leaf: a, record_leaf: uncensorship }]);
+        // the censorship record is added, and announced, but censoring the leaf fails.
+        heard.lock().unwrap().clear();
+        board.backend.fail_censorship = true;
+        let Err(BulletinBoardError::CensorshipRecordedButNotDone(record,_)) = board.censor_leaf(a,"Spam again","Moderator") else { panic!("censorship should have failed") };
+        assert_eq!(*heard.lock().unwrap(),vec![leaf_added(&board,record,2)]);
+    }
+}
@@ -0,0 +1,224 @@
+//! Portable evidence that a board operator published incompatible statements.
+//!
+//! A board publishes a single chain of roots, each referencing the one before it, and each node in its trees
+//! has at most one parent, as nodes never change parents once given one. An operator who shows different people
+//! different boards, or rewrites history, has to break one of these rules, and anyone holding both sides of the
+//! contradiction can make a [FraudProof] to show anyone else.
+//!
+//! There are two kinds of contradiction :
+//!  * [FraudProof::Fork] : two different published roots both claiming the same root was published the same number of
+//!    publications before them, most simply two roots with the same [crate::hash_history::RootHashHistory::prior]. Find them in roots you have been given,
+//!    e.g. the chains of two [crate::hash_history::AncestryProof]s, with [find_fork].
+//!  * [FraudProof::TwoParents] : two proof chains, each to a published root, in which the same node has different parents.
+//!    Find them in two proofs you have been given with [find_inconsistent_proofs].
+//!
+//! Check a fraud proof with [verify_fraud_proof]. The proof contains everything needed to recompute the hashes, but
+//! it only shows misbehaviour if the roots in it were actually published by the board, which is something you
+//! have to check separately, e.g. by comparing [FraudProof::published_roots] with roots the board signed or you were told at the time.
+//!
+//! # Example
+//!
+//! ```
+//! use merkle_tree_bulletin_board::fraud_proof::{find_fork, verify_fraud_proof};
+//! use merkle_tree_bulletin_board::hash_history::{HashInfoWithHash, HashSource, RootHashHistory};
+//!
+//! let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+//!     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+//! let first = board.order_new_published_root().unwrap();
+//! board.submit_leaf("a").unwrap();
+//! let second = board.order_new_published_root().unwrap();
+//! let honest = board.get_hash_info(second).unwrap().add_hash(second);
+//! let HashSource::Root(history) = &honest.source else { panic!("not a root") };
+//! assert_eq!(history.prior,Some(first));
+//! // the operator shows someone else a different second root, without "a".
+//! let other = RootHashHistory{ elements: vec![], ..history.clone() };
+//! let other = HashInfoWithHash{ hash: other.compute_hash(), source: HashSource::Root(other), parent: None };
+//! assert!(find_fork(&[honest.clone()],&[honest.clone()]).is_none()); // the same root does not contradict itself.
+//! let proof = find_fork(&[honest],&[other.clone()]).unwrap();
+//! assert_eq!(verify_fraud_proof(&proof),None); // valid evidence...
+//! assert_eq!(proof.published_roots(),vec![second,other.hash]); // ...if the board really published these roots.
+//! ```
+
+use std::collections::HashMap;
+use serde::{Serialize,Deserialize};
+use crate::hash::HashValue;
+use crate::hash_history::{FullProof, HashInfoWithHash, HashSource};
+use crate::verifier::{verify_branches, verify_published_root_in_proof};
+
+/// Evidence that a board published incompatible statements. See the [module documentation](self).
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub enum FraudProof {
+    /// Two different published roots that both reference the same root as the one published 2^level publications before them,
+    /// as given by [crate::hash_history::RootHashHistory::published_before]. Level 0 means they have the same prior root.
+    /// In an honest board, only one root is published at each position, so they cannot both be right.
+    Fork { level : usize, first : HashInfoWithHash, second : HashInfoWithHash },
+    /// Two proof chains, each to a published root, in which the node `child` has different parents.
+    TwoParents { child : HashValue, first : FullProof, second : FullProof },
+}
+
+impl FraudProof {
+    /// The published roots this evidence relies on. It only shows misbehaviour if the board actually published all of them.
+    pub fn published_roots(&self) -> Vec<HashValue> {
+        match self {
+            FraudProof::Fork { first, second, .. } => vec![first.hash,second.hash],
+            FraudProof::TwoParents { first, second, .. } => [first,second].iter().filter_map(|proof|proof.published_root.as_ref().map(|root|root.hash)).collect(),
+        }
+    }
+}
+
+/// The parent of `child` in a proof chain, if the chain contains it and it is not the last element.
+fn parent_in_chain(proof:&FullProof,child:HashValue) -> Option<HashValue> {
+    let position = proof.chain.iter().position(|node|node.hash==child)?;
+    proof.chain.get(position+1).map(|parent|parent.hash)
+}
+
+/// Check a proof chain is a valid path up to a published root containing its end.
+fn verify_proof_to_root(proof:&FullProof,description:&str) -> Option<String> {
+    if proof.published_root.is_none() { return Some(format!("The {} proof does not go to a published root",description)); }
+    verify_branches(proof).or_else(||verify_published_root_in_proof(proof)).map(|problem|format!("In the {} proof : {}",description,problem))
+}
+
+/// Check that a fraud proof shows that a board published incompatible statements.
+/// Returns None if the proof is valid, otherwise a description of the problem.
+///
+/// This checks the hashes, and that the statements are incompatible. You should also check that the board actually
+/// published the roots in [FraudProof::published_roots].
+pub fn verify_fraud_proof(proof:&FraudProof) -> Option<String> {
+    match proof {
+        FraudProof::Fork { level, first, second } => {
+            let mut referenced = vec![];
+            for (root,description) in [(first,"first"),(second,"second")] {
+                let HashSource::Root(history) = &root.source else { return Some(format!("The {} root is not a root",description)) };
+                if history.compute_hash()!=root.hash { return Some(format!("The {} root does not hash to the correct value",description)); }
+                let Some(before) = history.published_before(*level) else { return Some(format!("The {} root has no reference at level {}",description,level)) };
+                referenced.push(before);
+            }
+            if first.hash==second.hash { return Some("The two roots are the same".to_string()); }
+            if referenced[0]!=referenced[1] { return Some("The two roots reference different earlier roots".to_string()); }
+        }
+        FraudProof::TwoParents { child, first, second } => {
+            if let Some(problem) = verify_proof_to_root(first,"first").or_else(||verify_proof_to_root(second,"second")) { return Some(problem); }
+            let Some(first_parent) = parent_in_chain(first,*child) else { return Some(format!("The first proof does not show a parent of {}",child)) };
+            let Some(second_parent) = parent_in_chain(second,*child) else { return Some(format!("The second proof does not show a parent of {}",child)) };
+            if first_parent==second_parent { return Some(format!("Both proofs show the same parent of {}",child)); }
+        }
+    }
+    None
+}
+
+/// Look for two different roots, among the roots in `first` and `second`, that reference the same root as the one published
+/// the same number of publications before them, returning the first found as a [FraudProof::Fork].
+///
+/// Elements that are not roots, or do not hash correctly, are ignored. Typically `first` and `second` are roots obtained
+/// by different people, for instance the chains of [crate::hash_history::AncestryProof]s, but a fork within either is found too.
+pub fn find_fork(first:&[HashInfoWithHash],second:&[HashInfoWithHash]) -> Option<FraudProof> {
+    let mut seen : HashMap<(usize,HashValue),&HashInfoWithHash> = HashMap::new(); // (level,referenced root) -> root referencing it.
+    for root in first.iter().chain(second.iter()) {
+        let HashSource::Root(history) = &root.source else { continue };
+        if history.compute_hash()!=root.hash { continue; }
+        for level in 0..=history.skips.len() {
+            let Some(before) = history.published_before(level) else { continue };
+            match seen.get(&(level,before)) {
+                Some(other) if other.hash!=root.hash => return Some(FraudProof::Fork { level, first: (*other).clone(), second: root.clone() }),
+                Some(_) => {}
+                None => { seen.insert((level,before),root); }
+            }
+        }
+    }
+    None
+}
+
+/// Look for a contradiction between two proofs obtained from the same board, such as from [crate::BulletinBoard::get_proof_chain].
+/// Finds a node with different parents in the two proof chains, or a fork between the two published roots (see [find_fork]).
+///
+/// Returns None if there is no contradiction, or either proof is not a valid proof to a published root.
+pub fn find_inconsistent_proofs(first:&FullProof,second:&FullProof) -> Option<FraudProof> {
+    if verify_proof_to_root(first,"first").is_some() || verify_proof_to_root(second,"second").is_some() { return None; }
+    for node in &first.chain {
+        if let (Some(first_parent),Some(second_parent)) = (parent_in_chain(first,node.hash),parent_in_chain(second,node.hash)) {
+            if first_parent!=second_parent { return Some(FraudProof::TwoParents { child: node.hash, first: first.clone(), second: second.clone() }); }
+        }
+    }
+    find_fork(first.published_root.as_slice(),second.published_root.as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fraud_proof::{find_fork, find_inconsistent_proofs, verify_fraud_proof, FraudProof};
+    use crate::hash_history::{BranchHashHistory, FullProof, HashInfoWithHash, HashSource, LeafHashHistory, RootHashHistory};
+    use crate::BulletinBoard;
+    use crate::backend_memory::BackendMemory;
+
+    fn node(source:HashSource) -> HashInfoWithHash {
+        let hash = match &source {
+            HashSource::Leaf(history) => history.compute_hash().unwrap(),
+            HashSource::Branch(history) => history.compute_hash(),
+            HashSource::Root(history) => history.compute_hash(),
+        };
+        HashInfoWithHash{ hash, source, parent: None }
+    }
+
+    /// A proof chain from a leaf up through a branch with the given sibling, to a root with the given prior.
+    fn proof_with_sibling(leaf:&HashInfoWithHash,sibling:&str,prior:Option<HashInfoWithHash>) -> FullProof {
+        let sibling = node(HashSource::Leaf(LeafHashHistory{ timestamp: 0, data: Some(sibling.to_string()), structured: false }));
+        let branch = node(HashSource::Branch(BranchHashHistory{ left: leaf.hash, right: sibling.hash }));
+        let root = node(HashSource::Root(RootHashHistory{ timestamp: 1, prior: prior.map(|p|p.hash), elements: vec![branch.hash], skips: vec![] }));
+        FullProof{ chain: vec![leaf.clone(),branch], published_root: Some(root), leaf_index: Some(0) }
+    }
+
+    #[test]
+    /// Check that a leaf shown with two different parents, and two roots with the same prior, are found and verified.
+    fn test_equivocation_found() {
+        let leaf = node(HashSource::Leaf(LeafHashHistory{ timestamp: 0, data: Some("a".to_string()), structured: false }));
+        let genesis = node(HashSource::Root(RootHashHistory{ timestamp: 0, prior: None, elements: vec![], skips: vec![] }));
+        let first = proof_with_sibling(&leaf,"b",Some(genesis.clone()));
+        let second = proof_with_sibling(&leaf,"c",Some(genesis.clone()));
+        assert!(find_inconsistent_proofs(&first,&first).is_none());
+        let proof = find_inconsistent_proofs(&first,&second).unwrap();
+        assert!(matches!(&proof,FraudProof::TwoParents{child,..} if *child==leaf.hash));
+        assert_eq!(verify_fraud_proof(&proof),None);
+        assert_eq!(proof.published_roots().len(),2);
+        // the evidence survives serialization.
+        let proof : FraudProof = serde_json::from_str(&serde_json::to_string(&proof).unwrap()).unwrap();
+        assert_eq!(verify_fraud_proof(&proof),None);
+        // tampering is noticed.
+        let FraudProof::TwoParents{child,first:mut tampered,..} = proof else { panic!("wrong kind of proof") };
+        tampered.chain[1] = second.chain[1].clone();
+        assert!(verify_fraud_proof(&FraudProof::TwoParents{child,first:tampered.clone(),second:second.clone()}).is_some()); // root does not contain the branch.
+        tampered.published_root = second.published_root.clone();
+        assert!(verify_fraud_proof(&FraudProof::TwoParents{child,first:tampered,second:second.clone()}).is_some()); // same parent.
+        // the two published roots also fork.
+        let roots = [first.published_root.clone().unwrap(),second.published_root.clone().unwrap()];
+        let fork = find_fork(&roots[..1],&roots[1..]).unwrap();
+        assert!(matches!(fork,FraudProof::Fork{level:0,..}));
+        assert_eq!(verify_fraud_proof(&fork),None);
+        let FraudProof::Fork{level,first,..} = fork else { panic!("wrong kind of proof") };
+        assert!(verify_fraud_proof(&FraudProof::Fork{level,first:first.clone(),second:first.clone()}).is_some());
+        assert!(verify_fraud_proof(&FraudProof::Fork{level:1,first,second:roots[1].clone()}).is_some());
+    }
+
+    #[test]
+    /// Check that an honest board's proofs and roots, including skip references, give no fraud proof, and that a fork is found through skips.
+    fn test_honest_board_not_accused() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        board.set_root_skip_list(true);
+        let mut leaves = vec![];
+        let mut proofs = vec![];
+        for i in 0..10 {
+            leaves.push(board.submit_leaf(&i.to_string()).unwrap());
+            board.order_new_published_root().unwrap();
+            proofs.push(board.get_proof_chain(leaves[0]).unwrap());
+        }
+        let roots : Vec<HashInfoWithHash> = board.get_all_published_roots().unwrap().into_iter().map(|r|board.get_hash_info(r).unwrap().add_hash(r)).collect();
+        assert!(find_fork(&roots,&roots).is_none());
+        for first in &proofs {
+            for second in &proofs { assert!(find_inconsistent_proofs(first,second).is_none()); }
+        }
+        // a different last root, with a different prior, that references the same root 2 publications back.
+        let HashSource::Root(history) = &roots[9].source else { panic!("not a root") };
+        let forged = node(HashSource::Root(RootHashHistory{ prior: Some(leaves[0]), ..history.clone() }));
+        let fork = find_fork(&roots,&[forged]).unwrap();
+        assert!(matches!(fork,FraudProof::Fork{level:1,..}));
+        assert_eq!(verify_fraud_proof(&fork),None);
+    }
+}
@@ -6,6 +6,7 @@ use crate::hash::HashValue;
 use crate::hash_history::BranchHashHistory;
 use serde::{Serialize,Deserialize};
 use crate::{BulletinBoardBackend, BulletinBoardError, DatabaseTransaction};
+use crate::backend_access::{run_ready, ReadAccess, SyncAccess};
 
 #[derive(Debug,Clone,Serialize,Deserialize)]
 /// A hash and its depth
@@ -43,14 +44,14 @@ pub struct GrowingForest {
     pub(crate) forest: Vec<HashAndDepth>,
 }
 
-fn merge_hashes<B:BulletinBoardBackend>(left:HashValue,right:HashValue,backend:&B,transaction:&mut DatabaseTransaction) -> Result<HashValue,BulletinBoardError> {
+async fn merge_hashes(left:HashValue,right:HashValue,backend:&impl ReadAccess,transaction:&mut DatabaseTransaction) -> Result<HashValue,BulletinBoardError> {
     let history = BranchHashHistory{ left, right };
     let new_hash = history.compute_hash();
-    if let Some(hash_collision) = transaction.get_hash_info_completely(backend,new_hash)? {
+    if let Some(hash_collision) = transaction.get_hash_info_completely(backend,new_hash).await? {
         println!("Time to enter the lottery! You have just found a hash collision between {:?} and {:?}. More likely the program is buggy.",&hash_collision,&history);
         let history = BranchHashHistory{ right, left };
         let new_hash = history.compute_hash();
-        if let Some(hash_collision) = transaction.get_hash_info_completely(backend,new_hash)? {
+        if let Some(hash_collision) = transaction.get_hash_info_completely(backend,new_hash).await? {
             println!("Time to enter the lottery! You have just found a hash collision between {:?} and {:?} as well. I am sure the program is buggy. Giving up!",&hash_collision,&history);
             Err(BulletinBoardError::MultipleHashClashes)
         } else { // no hash collision, all is good. Should go here 99.99999999999999999999999999999..% of the remaining time. Except the first collision was probably a bug, so probably won't help.
@@ -65,10 +66,10 @@ fn merge_hashes<B:BulletinBoardBackend>(left:HashValue,right:HashValue,backend:&
 
 impl GrowingForest {
     /// Merge the last two elements of this tree.
-    fn merge_last_two(&mut self,backend:&impl BulletinBoardBackend,transaction:&mut DatabaseTransaction) -> Result<(),BulletinBoardError> {
+    async fn merge_last_two(&mut self,backend:&impl ReadAccess,transaction:&mut DatabaseTransaction) -> Result<(),BulletinBoardError> {
         let right = self.forest.pop().unwrap();
         let left = self.forest.pop().unwrap();
-        match merge_hashes(left.hash,right.hash,backend,transaction) {
+        match merge_hashes(left.hash,right.hash,backend,transaction).await {
             Ok(hash) => {
                 self.forest.push(HashAndDepth {hash,depth:left.depth+1});
                 Ok(())
@@ -82,9 +83,14 @@ impl GrowingForest {
     }
     /// Add the given hash value as a leaf to this tree collection.
     pub fn add_leaf(&mut self, hash:HashValue, backend:&impl BulletinBoardBackend, transaction:&mut DatabaseTransaction) -> Result<(),BulletinBoardError> {
+        run_ready(self.add_leaf_using(hash,&SyncAccess(backend),transaction))
+    }
+
+    /// [GrowingForest::add_leaf], for any kind of backend.
+    pub(crate) async fn add_leaf_using(&mut self, hash:HashValue, backend:&impl ReadAccess, transaction:&mut DatabaseTransaction) -> Result<(),BulletinBoardError> {
         self.forest.push(HashAndDepth { hash, depth: 0 });
         while self.forest.len()>=2 && self.forest[self.forest.len()-1].depth==self.forest[self.forest.len()-2].depth {
-            self.merge_last_two(backend,transaction)?;
+            self.merge_last_two(backend,transaction).await?;
         }
         Ok(())
     }
@@ -93,6 +99,29 @@ impl GrowingForest {
         self.forest.iter().map(|e|e.hash).collect()
     }
 
+    /// The number of leaves in all the trees in this collection.
+    pub fn leaf_count(&self) -> u64 {
+        self.forest.iter().map(|e|1u64<<e.depth).sum()
+    }
+
+    /// Find the tree containing the leaf with a given sequence number (see [crate::BulletinBoardBackend::get_leaf_by_index]).
+    /// Returns the tree, its depth, and the position of the leaf within it, or None if there are not that many leaves.
+    pub(crate) fn find_tree_containing(&self,index:u64) -> Option<(HashValue,usize,u64)> {
+        let mut index = index;
+        for tree in &self.forest {
+            let size = 1u64<<tree.depth;
+            if index<size { return Some((tree.hash,tree.depth,index)); }
+            index-=size;
+        }
+        None
+    }
+
+    /// The number of leaves in the trees before the given tree, or None if it is not one of the trees in this collection.
+    pub(crate) fn leaves_before_tree(&self,hash:HashValue) -> Option<u64> {
+        let position = self.forest.iter().position(|e|e.hash==hash)?;
+        Some(self.forest[..position].iter().map(|e|1u64<<e.depth).sum())
+    }
+
     /// Make a new growing forest from an (unordered) list of hash values and a function from hash value to depth.
     pub fn new<F:Fn(HashValue)->Result<usize,BulletinBoardError>>(hashes:&Vec<HashValue>,get_depth:F) -> Result<Self,BulletinBoardError> {
         let mut pending : Vec<HashAndDepth> = Vec::default();
@@ -52,13 +52,13 @@ impl FromStr for HashValue {
 }
 impl Display for HashValue {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}",&hex::encode(&self.0))
+        write!(f, "{}",&hex::encode(self.0))
     }
 }
 
 impl Debug for HashValue {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}",&hex::encode(&self.0))
+        write!(f, "{}",&hex::encode(self.0))
     }
 }
 
@@ -66,7 +66,7 @@ impl Debug for HashValue {
 /// Serialize an array of bytes as a string of the hexadecimal representation, as used in the "sha256sum" program.
 impl Serialize for HashValue {
     fn serialize<S>(&self, serializer: S) -> Result<<S as Serializer>::Ok, <S as Serializer>::Error> where S: Serializer {
-        serializer.serialize_str(&hex::encode(&self.0))
+        serializer.serialize_str(&hex::encode(self.0))
     }
 }
 
@@ -98,7 +98,7 @@ impl<'de> Visitor<'de> for HashValueVisitor {
 /// Parse a string of semicolon separated hash values
 pub fn parse_string_to_hash_vec(s:&str) -> Result<Vec<HashValue>,FromHashValueError> {
     let mut res = vec![];
-    if s.len()>0 {
+    if !s.is_empty() {
         for s_hash in s.split(';') {
             res.push(HashValue::from_str(s_hash)?)
         }
@@ -1,4 +1,5 @@
 use crate::hash::HashValue;
+use crate::structured_leaf::StructuredLeaf;
 use std::time::{SystemTime, SystemTimeError};
 use sha2::{Sha256, Digest};
 use serde::{Serialize,Deserialize};
@@ -11,6 +12,7 @@ pub fn timestamp_now() -> Result<Timestamp,SystemTimeError> { Ok(SystemTime::now
 
 /// Where a leaf comes from
 /// Hash = sha256(0|timestamp(bigendian 64 bits)|data)
+/// unless it is a structured leaf (see [LeafHashHistory::structured]), in which case see [crate::structured_leaf].
 /// If the data is None it means it has been censored post incorporation into the tree,
 /// and therefore it is no longer possible to compute the hash.
 #[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
@@ -19,15 +21,22 @@ pub struct LeafHashHistory {
     pub timestamp : Timestamp,
     /// the data that went into the leaf; If None then it has been censored.
     pub data : Option<String>,
+    /// Whether this is a structured leaf, made by [crate::BulletinBoard::submit_structured_leaf], whose data is
+    /// a [StructuredLeaf]. This is stored with the leaf rather than deduced from the data, so that the hash of
+    /// a plain leaf never depends on what its text looks like.
+    #[serde(default,skip_serializing_if="std::ops::Not::not")]
+    pub structured : bool,
 }
 
 impl LeafHashHistory {
     /// Hash = sha256(0|timestamp(bigendian 64 bits)|data)
-    /// Returns None if the data has been censored.
+    /// or for a structured leaf, see [StructuredLeaf::compute_hash].
+    /// Returns None if the data has been censored, or this is a structured leaf whose data is not a valid [StructuredLeaf].
     pub fn compute_hash(&self) -> Option<HashValue> {
         if let Some(data) = &self.data {
+            if self.structured { return StructuredLeaf::from_leaf_data(data).map(|s|s.compute_hash(self.timestamp)); }
             let mut hasher = Sha256::default();
-            hasher.update(&[0]);
+            hasher.update([0]);
             hasher.update(self.timestamp.to_be_bytes());
             hasher.update(data.as_bytes());
             Some(HashValue(<[u8; 32]>::from(hasher.finalize())))
@@ -39,6 +48,7 @@ impl LeafHashHistory {
 ///
 /// Every element in the right side of the tree will generally postdate every element on the left side of the tree,
 /// The exception is in the absurdly unlikely case of a hash collision, in which case the two sides will be swapped.
+/// This is what lets an [OrderingProof] show that one leaf was submitted before another.
 ///
 /// The depth of the left side will always be the same as the depth of the right side; this is a balanced tree.
 ///
@@ -52,39 +62,66 @@ pub struct BranchHashHistory {
 impl BranchHashHistory {
     pub fn compute_hash(&self) -> HashValue {
         let mut hasher = Sha256::default();
-        hasher.update(&[1]);
-        hasher.update(&self.left.0);
-        hasher.update(&self.right.0);
+        hasher.update([1]);
+        hasher.update(self.left.0);
+        hasher.update(self.right.0);
         HashValue(<[u8; 32]>::from(hasher.finalize()))
     }
 }
 
 /// Where a root comes from
 /// Hash = sha256(2|timestamp|prior if exists otherwise byte 0|elements concatenated)
+///
+/// If the root has skip references (see [RootHashHistory::skips]), a second version of the format is used, so that
+/// roots without them, including all roots of boards made before skip references existed, keep the same hash :
+/// Hash = sha256(5|timestamp|prior if exists otherwise byte 0|number of skips (1 byte)|skips concatenated|elements concatenated)
 #[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
 pub struct RootHashHistory {
     /// time that the root was published.
     pub timestamp : Timestamp,
     /// the prior published root, if any.
     pub prior : Option<HashValue>,
-    /// elements in this root
+    /// elements in this root, each the top of a tree. Every leaf in a tree was submitted before every leaf in later trees.
     pub elements : Vec<HashValue>,
+    /// Skip references to earlier published roots, used for [AncestryProof]s. `skips[i]` is the root published 2^(i+1)
+    /// publications before this one, just as `prior` is the one published 1 publication before.
+    /// Empty unless the board was told to add them with [crate::BulletinBoard::set_root_skip_list], and possibly shorter
+    /// than it could be for roots published soon after that. Never more than 255 long.
+    #[serde(default,skip_serializing_if="Vec::is_empty")]
+    pub skips : Vec<HashValue>,
 }
 
 impl RootHashHistory {
     pub fn compute_hash(&self) -> HashValue {
         let mut hasher = Sha256::default();
-        hasher.update(&[2]);
+        hasher.update([if self.skips.is_empty() { 2 } else { 5 }]);
         hasher.update(self.timestamp.to_be_bytes());
         match self.prior {
-            None => hasher.update(&[0]),
-            Some(prior) => hasher.update(&prior.0),
+            None => hasher.update([0]),
+            Some(prior) => hasher.update(prior.0),
+        }
+        if !self.skips.is_empty() {
+            hasher.update([self.skips.len() as u8]);
+            for skip in &self.skips {
+                hasher.update(skip.0);
+            }
         }
         for elem in &self.elements {
-            hasher.update(&elem.0);
+            hasher.update(elem.0);
         }
         HashValue(<[u8; 32]>::from(hasher.finalize()))
     }
+
+    /// The root published 2^level publications before this one, if this root references it.
+    /// Level 0 is the prior root, higher levels come from [RootHashHistory::skips].
+    pub fn published_before(&self,level:usize) -> Option<HashValue> {
+        if level==0 { self.prior } else { self.skips.get(level-1).cloned() }
+    }
+
+    /// Whether this root directly references `root`, as its prior or one of its skips.
+    pub fn references(&self,root:HashValue) -> bool {
+        self.prior==Some(root) || self.skips.contains(&root)
+    }
 }
 
 
@@ -135,7 +172,87 @@ pub struct FullProof {
     /// chain back to the start. Each element is the parent of the prior element.
     pub chain : Vec<HashInfoWithHash>,
     /// most recent published root, if it includes the last element of the chain. If None, then the last element of the chain has not been published yet.
-    pub published_root : Option<HashInfoWithHash>
+    pub published_root : Option<HashInfoWithHash>,
+    /// the sequence number of the leaf, if the chain starts with a leaf. See [crate::BulletinBoardBackend::get_leaf_by_index].
+    /// Its low bits are checked against [FullProof::path_position] by [crate::verifier::verify_proof].
+    #[serde(default)]
+    pub leaf_index : Option<u64>,
+}
+
+/// A proof of how many leaves a published root covers.
+///
+/// Each element of a root is a perfect binary tree with 2^depth leaves, so the number of leaves follows from the depths
+/// of the elements. The depth of each element is shown by a path down from it to a leaf. The path follows the left
+/// side of each branch where possible, but goes right where needed to reach a leaf that is not censored, as a
+/// censored leaf cannot be shown to be a leaf rather than a branch.
+///
+/// See [crate::verifier::verify_leaf_count] for how to verify the proof.
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct LeafCountProof {
+    /// the published root.
+    pub published_root : HashInfoWithHash,
+    /// for each element of the published root, in order, a path from the element down to an uncensored leaf.
+    /// Each path starts with the element, and each subsequent entry is a child of the prior entry.
+    pub paths : Vec<Vec<HashInfoWithHash>>,
+}
+
+/// A proof that one leaf was added to the board before another, made of inclusion proofs for both leaves in the same published root.
+///
+/// Earlier leaves are to the left of later leaves, both within a tree (see [BranchHashHistory]) and between the trees
+/// that are the elements of a published root, so the order follows from the positions shown by the two proof chains.
+///
+/// See [crate::verifier::verify_ordering_proof] for how to verify the proof.
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct OrderingProof {
+    /// the inclusion proof for the earlier leaf.
+    pub earlier : FullProof,
+    /// the inclusion proof for the later leaf, in the same published root.
+    pub later : FullProof,
+}
+
+/// A proof that one published root was published before (or is) another, that is, is an ancestor of it in the chain of
+/// [RootHashHistory::prior] references.
+///
+/// Following `prior` from the later root takes one step per publication in between. Roots with [RootHashHistory::skips]
+/// also reference roots 2, 4, 8, ... publications back, so when the roots in between have them the proof needs only
+/// about log2(number of publications in between) steps.
+///
+/// See [crate::verifier::verify_ancestry_proof] for how to verify the proof.
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct AncestryProof {
+    /// the chain of roots, starting with the later root and ending with the earlier root.
+    /// Each root references the next, as its prior or one of its skips.
+    pub chain : Vec<HashInfoWithHash>,
+}
+
+impl FullProof {
+    /// The position of the first element of the chain in the published root : the index of the root element at the end of
+    /// the chain, and the [FullProof::path_position] within it. Positions compare in the order leaves were added.
+    ///
+    /// Returns None if there is no published root, the root does not contain the end of the chain, or the chain is not a path up the tree.
+    pub fn position_in_published_root(&self) -> Option<(usize,u64)> {
+        let HashSource::Root(history) = &self.published_root.as_ref()?.source else { return None };
+        let top = self.chain.last()?.hash;
+        let element = history.elements.iter().position(|e|*e==top)?;
+        Some((element,self.path_position()?))
+    }
+
+    /// The position of the first element of the chain among the leaves of the tree at the end of the chain, counting from 0
+    /// on the left, derived from which side of each branch the chain goes up through. As earlier leaves are on the left
+    /// (see [BranchHashHistory]), a leaf's sequence number is this plus the number of leaves in earlier trees.
+    ///
+    /// Returns None if an element after the first is not a branch referencing the prior element.
+    pub fn path_position(&self) -> Option<u64> {
+        let mut position = 0u64;
+        for i in 1..self.chain.len() {
+            match &self.chain[i].source {
+                HashSource::Branch(history) if history.left==self.chain[i-1].hash => {}
+                HashSource::Branch(history) if history.right==self.chain[i-1].hash => position |= 1u64.checked_shl((i-1) as u32)?,
+                _ => return None,
+            }
+        }
+        Some(position)
+    }
 }
 
 
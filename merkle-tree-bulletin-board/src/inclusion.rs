@@ -0,0 +1,118 @@
+//! Waiting for a leaf to be included in a published root.
+//!
+//! Rather than polling [crate::BulletinBoard::get_proof_chain] until its `published_root` is not None, a client
+//! can wait until a root including the leaf is published, getting the proof chain as soon as it is.
+//!
+//! For a [crate::BulletinBoard], get a [PublicationWatcher] from [crate::BulletinBoard::publication_watcher], which
+//! is woken each time the board publishes a root, and wait with [PublicationWatcher::wait_for_inclusion] or
+//! [crate::shared_backend::BulletinBoardReader::wait_for_inclusion]. Waiting is done without holding any lock on the board,
+//! so the board can carry on publishing.
+//!
+//! For an [crate::async_board::AsyncBulletinBoard], subscribe to its events and use [wait_for_inclusion_async]
+//! or [crate::async_board::AsyncBulletinBoardReader::wait_for_inclusion].
+//!
+//! Only roots published through the same bulletin board object wake the waiter; if another writer sharing the
+//! database publishes the including root, the wait will continue until the next local publication or the timeout.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use crate::BulletinBoardError;
+use crate::hash::HashValue;
+use crate::hash_history::FullProof;
+#[cfg(feature = "async")]
+use crate::events::BulletinBoardEvent;
+#[cfg(feature = "async")]
+use std::future::Future;
+
+/// Woken each time a bulletin board publishes a root. Cheap to clone; all clones watch the same board.
+#[derive(Clone,Default)]
+pub struct PublicationWatcher {
+    /// The number of roots published since the watcher was made, and a condition variable notified when it changes.
+    state : Arc<(Mutex<u64>,Condvar)>,
+}
+
+impl PublicationWatcher {
+    /// Called by the board after it publishes a root.
+    pub(crate) fn published(&self) {
+        if let Ok(mut guard) = self.state.0.lock() {
+            *guard+=1;
+            self.state.1.notify_all();
+        }
+    }
+
+    /// The number of roots published since the watcher was made.
+    pub fn publications(&self) -> u64 {
+        self.state.0.lock().map(|guard|*guard).unwrap_or(0)
+    }
+
+    /// Wait until more than `seen` roots have been published, returning false if the deadline passed first.
+    pub fn wait_for_publication_after(&self,seen:u64,deadline:Instant) -> bool {
+        let Ok(mut guard) = self.state.0.lock() else { return false };
+        while *guard<=seen {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() { return false; }
+            guard = match self.state.1.wait_timeout(guard,remaining) { Ok((guard,_)) => guard, Err(_) => return false };
+        }
+        true
+    }
+
+    /// Wait until `leaf` is included in a published root, returning its proof chain, or fail with
+    /// [BulletinBoardError::InclusionTimeout] if that has not happened within `timeout`.
+    /// Returns immediately if it is already included.
+    ///
+    /// `get_proof_chain` gets the current proof chain for a hash, e.g. from a [crate::shared_backend::BulletinBoardReader],
+    /// or by briefly locking a shared board. It is called once initially and once after each publication.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::BulletinBoard;
+    /// use merkle_tree_bulletin_board::backend_memory::BackendMemory;
+    /// use std::sync::{Arc, Mutex};
+    /// use std::time::Duration;
+    ///
+    /// let board = Arc::new(Mutex::new(BulletinBoard::new(BackendMemory::default()).unwrap()));
+    /// let watcher = board.lock().unwrap().publication_watcher();
+    /// let leaf = board.lock().unwrap().submit_leaf("a").unwrap();
+    /// let get_proof_chain = |hash| board.lock().unwrap().get_proof_chain(hash);
+    /// assert!(watcher.wait_for_inclusion(leaf,Duration::from_millis(10),get_proof_chain).is_err()); // not published yet.
+    /// let publisher = {
+    ///     let board = board.clone();
+    ///     std::thread::spawn(move || {
+    ///         std::thread::sleep(Duration::from_millis(100));
+    ///         board.lock().unwrap().order_new_published_root().unwrap()
+    ///     })
+    /// };
+    /// let proof = watcher.wait_for_inclusion(leaf,Duration::from_secs(10),get_proof_chain).unwrap();
+    /// assert_eq!(proof.published_root.unwrap().hash,publisher.join().unwrap());
+    /// ```
+    pub fn wait_for_inclusion(&self,leaf:HashValue,timeout:Duration,mut get_proof_chain:impl FnMut(HashValue)->Result<FullProof,BulletinBoardError>) -> Result<FullProof,BulletinBoardError> {
+        let deadline = Instant::now()+timeout;
+        loop {
+            let seen = self.publications(); // read before getting the proof so that a publication in between is not missed.
+            let proof = get_proof_chain(leaf)?;
+            if proof.published_root.is_some() { return Ok(proof); }
+            if !self.wait_for_publication_after(seen,deadline) { return Err(BulletinBoardError::InclusionTimeout); }
+        }
+    }
+}
+
+/// The async equivalent of [PublicationWatcher::wait_for_inclusion]. `events` should be obtained from
+/// [crate::async_board::AsyncBulletinBoard::subscribe] before calling this, and is used to be told of new roots.
+#[cfg(feature = "async")]
+pub async fn wait_for_inclusion_async<F,Fut>(events:&async_std::channel::Receiver<BulletinBoardEvent>,leaf:HashValue,timeout:Duration,mut get_proof_chain:F) -> Result<FullProof,BulletinBoardError>
+    where F:FnMut(HashValue)->Fut, Fut:Future<Output=Result<FullProof,BulletinBoardError>> {
+    let deadline = Instant::now()+timeout;
+    loop {
+        let proof = get_proof_chain(leaf).await?;
+        if proof.published_root.is_some() { return Ok(proof); }
+        loop { // wait for a root to be published.
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match async_std::future::timeout(remaining,events.recv()).await {
+                Ok(Ok(BulletinBoardEvent::RootPublished{..})) => break,
+                Ok(Ok(_)) => {}
+                Ok(Err(_)) | Err(_) => return Err(BulletinBoardError::InclusionTimeout), // board has gone away, or timed out.
+            }
+        }
+    }
+}
@@ -58,17 +58,44 @@ pub mod growing_forest;
 pub mod backend_memory;
 pub mod backend_flatfile;
 pub mod backend_journal;
+pub mod backend_cache;
+pub mod shared_backend;
 pub mod deduce_journal;
 pub mod verifier;
+pub mod censorship;
+mod backend_access;
+pub mod retention;
+pub mod structured_leaf;
+pub mod validation;
+pub mod events;
+pub mod scheduler;
+pub mod receipt;
+pub mod inclusion;
+pub mod content_index;
+pub mod fraud_proof;
+#[cfg(feature = "async")]
+pub mod async_board;
+#[cfg(feature = "async")]
+pub mod async_backend_blocking;
+#[cfg(feature = "async")]
+pub mod async_backend_journal;
 
 use crate::growing_forest::GrowingForest;
+use crate::backend_access::{run_ready, ReadAccess, SyncAccess, WriteAccess};
 use crate::hash::{FromHashValueError, HashValue};
-use crate::hash_history::{HashInfo, FullProof, HashSource, BranchHashHistory, RootHashHistory, LeafHashHistory, timestamp_now, HashInfoWithHash, Timestamp};
+use crate::hash_history::{HashInfo, FullProof, LeafCountProof, OrderingProof, AncestryProof, HashSource, BranchHashHistory, RootHashHistory, LeafHashHistory, timestamp_now, HashInfoWithHash, Timestamp};
 use std::time::Duration;
 use std::collections::HashSet;
 use std::iter::FromIterator;
 use std::num::ParseIntError;
 use serde::{Serialize,Deserialize};
+use crate::censorship::{CENSORSHIP_RECORD_PREFIX, CensorshipEvent, CensorshipRecord, get_censorship_records_between};
+use crate::structured_leaf::{STRUCTURED_LEAF_PREFIX, StructuredLeaf};
+use crate::validation::{SubmissionValidator, validate_submission};
+use crate::events::{BulletinBoardEvent, BulletinBoardListener, EventDispatcher, ListenerHandle};
+use crate::inclusion::PublicationWatcher;
+use crate::content_index::{check_duplicate, content_hash, find_leaves_by_data_using, DuplicatePolicy};
+use crate::receipt::{get_receipt_evidence_using, ReceiptEvidence, ReceiptSigner, SignedReceipt, SubmissionReceipt};
 
 /// This is the main API to the bulletin board library. This represents an entire bulletin board.
 /// You provide a backend of type [BulletinBoardBackend] (typically an indexed database),
@@ -82,6 +109,9 @@ use serde::{Serialize,Deserialize};
 /// want to make a separate backend with a separate database connection so [deduce_journal::deduce_journal]
 /// can run in parallel.
 ///
+/// The read functions all take `&self`. To serve reads from many threads at once while a single writer
+/// adds entries, create the board with [BulletinBoard::new_shared] and get cloneable read handles with [BulletinBoard::reader].
+///
 /// There are two simple provided backends for testing and prototyping,
 /// [backend_memory::BackendMemory] and [backend_flatfile::BackendFlatfile].
 /// In production you will probably want to use some database; this is somewhat database
@@ -94,7 +124,7 @@ use serde::{Serialize,Deserialize};
 /// Each API call is exposed as a REST call with relative URL
 /// the function name and the the hash query, if any, as a GET argument something like  `get_hash_info?hash=a425...56`.
 /// All results are returned as JSON encodings of the actual results. The leaf is submitted as a POST with body encoded JSON object containing a single field name `data`,
-/// and censoring is similarly a POST with body encoded JSON object with a single field name `leaf_to_censor`.
+/// and censoring is similarly a POST with body encoded JSON object with fields `leaf_to_censor`, `reason` and `authority`.
 ///
 ///
 /// The Merkle trees are grown as described in [GrowingForest]. Each published root consists of a hash of a small O(log leafs) number
@@ -128,7 +158,7 @@ use serde::{Serialize,Deserialize};
 /// // utility function to check that something is indeed a leaf with the expected data.
 /// fn assert_is_leaf(source:HashSource,expected_data:&str) {
 ///   match source {
-///     HashSource::Leaf(LeafHashHistory{data:Some(d),timestamp:_}) => assert_eq!(d,expected_data),
+///     HashSource::Leaf(LeafHashHistory{data:Some(d),..}) => assert_eq!(d,expected_data),
 ///     _ => panic!("Not a leaf"),
 ///   }
 /// }
@@ -164,7 +194,7 @@ use serde::{Serialize,Deserialize};
 /// // now publish! This will publish branch_ab and hash_c.
 /// let published1 : HashValue = board.order_new_published_root().unwrap();
 /// match board.get_hash_info(published1).unwrap().source {
-///     HashSource::Root(RootHashHistory{timestamp:_,elements:e,prior:None}) =>
+///     HashSource::Root(RootHashHistory{timestamp:_,elements:e,prior:None,..}) =>
 ///        assert_eq!(e,vec![branch_ab,hash_c]),
 ///     _ => panic!("Should be a root"),
 /// }
@@ -193,7 +223,7 @@ use serde::{Serialize,Deserialize};
 /// // including things from before the last publication.
 /// let published2 = board.order_new_published_root().unwrap();
 /// match board.get_hash_info(published2).unwrap().source {
-///     HashSource::Root(RootHashHistory{timestamp:_,elements:e,prior:Some(prior)}) => {
+///     HashSource::Root(RootHashHistory{timestamp:_,elements:e,prior:Some(prior),..}) => {
 ///         assert_eq!(e,vec![branch_abcd]);
 ///         assert_eq!(prior,published1);
 ///     }
@@ -207,8 +237,7 @@ use serde::{Serialize,Deserialize};
 ///
 pub struct BulletinBoard<B:BulletinBoardBackend> {
     pub backend : B,
-    /// None if there is an error, otherwise the currently growing forest.
-    current_forest: Option<GrowingForest>,
+    state : BoardState,
 }
 
 /// Possible things that could go wrong during a Bulletin Board operation.
@@ -240,6 +269,58 @@ pub enum BulletinBoardError {
     BackendParsingError(String),
     #[error("system time clock is not available")]
     ClockError,
+    #[error("Another writer modified the bulletin board at the same time")]
+    ConcurrentModification,
+    #[error("Submitted data may not start with the reserved prefix {0}")]
+    DataUsesReservedPrefix(String),
+    #[error("Censorship records can not be censored, as they are the audit trail of censorship")]
+    CanNotCensorCensorshipRecord,
+    #[error("The leaf has already been censored")]
+    LeafAlreadyCensored,
+    #[error("The censorship record {0} was added to the board, but the backend failed to censor the leaf : {1}")]
+    CensorshipRecordedButNotDone(HashValue,String),
+    #[error("The leaf is not censored")]
+    LeafIsNotCensored,
+    #[error("The provided data, with the leaf's timestamp, does not hash to the leaf's hash")]
+    UncensoredDataDoesNotMatchHash,
+    #[error("The bulletin board backend does not support uncensoring leaves")]
+    UncensoringNotSupported,
+    #[error("Invalid structured leaf : {0}")]
+    InvalidStructuredLeaf(String),
+    #[error("No random number generator is available")]
+    RandomNumberGeneratorUnavailable,
+    #[error("The leaf is not an uncensored structured leaf")]
+    NotAStructuredLeaf,
+    #[error("The structured leaf has no visible field {0}")]
+    NoSuchVisibleField(String),
+    #[error("The submission was rejected : {0}")]
+    Rejected(String),
+    #[error("The hash is not a leaf")]
+    NotALeaf,
+    #[error("This bulletin board does not give receipts")]
+    ReceiptsNotEnabled,
+    #[error("Timed out waiting for the leaf to be included in a published root")]
+    InclusionTimeout,
+    #[error("The bulletin board backend does not support idempotency keys")]
+    IdempotencyKeysNotSupported,
+    #[error("The idempotency key was already used to submit different data")]
+    IdempotencyKeyReused,
+    #[error("Idempotency keys must be between 1 and {} bytes long",MAX_IDEMPOTENCY_KEY_LENGTH)]
+    InvalidIdempotencyKey,
+    #[error("The bulletin board backend does not have a content index")]
+    ContentIndexNotSupported,
+    #[error("The same data has already been submitted to the bulletin board as leaf {0}")]
+    DuplicateData(HashValue),
+    #[error("Every leaf under {0} is censored, so its depth cannot be proven")]
+    AllLeavesCensored(HashValue),
+    #[error("{0} is not yet included in a published root")]
+    NotYetPublished(HashValue),
+    #[error("The leaves are not in the order claimed")]
+    LeavesNotInOrder,
+    #[error("The proofs are for different published roots, probably because a root was published between getting them")]
+    ProofsForDifferentRoots,
+    #[error("The published roots are not in the order claimed")]
+    RootsNotInOrder,
 }
 
 
@@ -263,9 +344,12 @@ impl From<ParseIntError> for BulletinBoardError {
 /// Adding one element to a set to be committed may result in a variety of elements being produced.
 /// A database may have the ability to do transactions, in which case this can be made safer by committing
 /// all the modifications needed by a single API call so that the database doesn't have dangling elements.
-#[derive(Default)]
+#[derive(Default,Clone)]
 pub struct DatabaseTransaction {
     pub pending : Vec<(HashValue,HashSource)>,
+    /// A client supplied key identifying the submission of the leaf in this transaction, which backends supporting
+    /// idempotency keys should store with the leaf. See [BulletinBoard::submit_leaf_with_idempotency_key].
+    pub idempotency_key : Option<String>,
 }
 
 impl DatabaseTransaction {
@@ -278,14 +362,25 @@ impl DatabaseTransaction {
 
     fn get_hash_info(&self,query:HashValue) -> Option<HashSource> { self.pending.iter().find(|(hash,_)| *hash == query).map(|(_,source)|source.clone()) }
     /// check for a hash collision by looking up both this and the database backend.
-    fn get_hash_info_completely(&self,backend:&impl BulletinBoardBackend,query:HashValue) -> Result<Option<HashSource>,BulletinBoardError> {
-        if let Some(info) = backend.get_hash_info(query)? { Ok(Some(info.source)) }
+    async fn get_hash_info_completely(&self,backend:&impl ReadAccess,query:HashValue) -> Result<Option<HashSource>,BulletinBoardError> {
+        if let Some(info) = backend.get_hash_info(query).await? { Ok(Some(info.source)) }
         else { Ok(self.get_hash_info(query)) }
     }
 
+    /// Whether this transaction publishes a new root, which is always the last thing in such a transaction.
+    pub(crate) fn publishes_root(&self) -> bool { matches!(self.pending.last(),Some((_,HashSource::Root(_)))) }
+
     /// make a transaction containing a single entry.
     pub fn singleton(hash:HashValue,source:HashSource) -> DatabaseTransaction {
-        DatabaseTransaction{ pending:vec![(hash,source)]}
+        DatabaseTransaction{ pending:vec![(hash,source)], idempotency_key:None }
+    }
+
+    /// The idempotency key, if any, and the leaf it identifies, which is the first entry.
+    pub fn idempotency_key_and_leaf(&self) -> Option<(&str,HashValue)> {
+        match (&self.idempotency_key,self.pending.first()) {
+            (Some(key),Some((leaf,HashSource::Leaf(_)))) => Some((key.as_str(),*leaf)),
+            _ => None,
+        }
     }
 }
 
@@ -306,30 +401,154 @@ pub trait BulletinBoardBackend {
     /// Store a transaction in the database.
     fn publish(&mut self,transaction:&DatabaseTransaction) -> Result<(),BulletinBoardError>;
 
+    /// Store a transaction in the database, but only if no other writer has changed the board since
+    /// the transaction was computed. Returns [BulletinBoardError::ConcurrentModification] otherwise,
+    /// in which case nothing is stored.
+    ///
+    /// The transaction was computed assuming that the leaves and branches without a parent were
+    /// exactly `expected_parentless`, and, if it contains a root, that the most recent published root
+    /// is that root's prior. See [BulletinBoardBackend::check_unchanged].
+    ///
+    /// This is what allows multiple [BulletinBoard] instances, e.g. in different processes, to safely share one database.
+    /// The default implementation is only safe if nothing else can write to the backend between the check and the
+    /// publication; a backend that can be shared should override it to do the check and publication
+    /// atomically, e.g. inside a database lock.
+    fn publish_if_unchanged(&mut self,transaction:&DatabaseTransaction,expected_parentless:&[HashValue]) -> Result<(),BulletinBoardError> {
+        self.check_unchanged(transaction,expected_parentless)?;
+        self.publish(transaction)
+    }
+
+    /// Check that the state of the backend is the state a transaction was computed against, returning
+    /// [BulletinBoardError::ConcurrentModification] if not. That is,
+    ///  * The set of leaves and branches without a parent is `expected_parentless` (order does not matter).
+    ///  * Any root in the transaction has the most recently published root as its prior.
+    fn check_unchanged(&self,transaction:&DatabaseTransaction,expected_parentless:&[HashValue]) -> Result<(),BulletinBoardError> {
+        run_ready(backend_access::check_unchanged(&SyncAccess(self),transaction,expected_parentless))
+    }
+
+    /// Get the leaf that was submitted with a given idempotency key, if any. See [BulletinBoard::submit_leaf_with_idempotency_key].
+    ///
+    /// Backends that support idempotency keys should override this, and store [DatabaseTransaction::idempotency_key_and_leaf]
+    /// when publishing a transaction, ideally atomically with the leaf. The default implementation
+    /// returns [BulletinBoardError::IdempotencyKeysNotSupported].
+    fn get_leaf_for_idempotency_key(&self,_key:&str) -> Result<Option<HashValue>,BulletinBoardError> {
+        Err(BulletinBoardError::IdempotencyKeysNotSupported)
+    }
+
+    /// Record that an idempotency key identifies a leaf already in the board, e.g. when [DuplicatePolicy::ReturnExisting]
+    /// returns an existing leaf instead of adding a new one, so that a retry with the key gets the same leaf.
+    /// If the key is already recorded, the existing entry is kept.
+    ///
+    /// Backends that support idempotency keys should override this. The default implementation
+    /// returns [BulletinBoardError::IdempotencyKeysNotSupported].
+    fn store_idempotency_key(&mut self,_key:&str,_leaf:HashValue) -> Result<(),BulletinBoardError> {
+        Err(BulletinBoardError::IdempotencyKeysNotSupported)
+    }
+
+    /// Get the leaves whose data has the given [content_index::content_hash], in the order they were recorded, for the content index.
+    /// Leaves stay in the content index after being censored. See [content_index].
+    ///
+    /// Backends that have a content index should override this, and index each leaf with data when it is published.
+    /// The default implementation returns [BulletinBoardError::ContentIndexNotSupported].
+    fn get_leaves_with_content_hash(&self,_content_hash:HashValue) -> Result<Vec<HashValue>,BulletinBoardError> {
+        Err(BulletinBoardError::ContentIndexNotSupported)
+    }
+
+    /// Get the number of leaves in the board.
+    ///
+    /// The default implementation adds up the sizes of the trees in [BulletinBoardBackend::compute_current_forest].
+    fn get_leaf_count(&self) -> Result<u64,BulletinBoardError> {
+        Ok(self.compute_current_forest()?.leaf_count())
+    }
+
+    /// Get the leaf with a given sequence number, if there is one. Leaves are numbered from 0 in the order
+    /// they were committed, which is also their order from left to right in the trees (see [BranchHashHistory]).
+    ///
+    /// The default implementation is [BulletinBoardBackend::leaf_by_index_from_tree]; backends where each lookup is
+    /// expensive may wish to store the sequence number of each leaf when it is published, and answer this with a single lookup.
+    fn get_leaf_by_index(&self,index:u64) -> Result<Option<HashValue>,BulletinBoardError> {
+        self.leaf_by_index_from_tree(index)
+    }
+
+    /// Get the sequence number of a leaf, or None if the hash is not a leaf. See [BulletinBoardBackend::get_leaf_by_index].
+    ///
+    /// The default implementation is [BulletinBoardBackend::leaf_index_from_tree].
+    fn get_leaf_index(&self,leaf:HashValue) -> Result<Option<u64>,BulletinBoardError> {
+        self.leaf_index_from_tree(leaf)
+    }
+
+    /// Get up to `count` leaves, in order, starting with the one with sequence number `start`.
+    ///
+    /// The default implementation calls [BulletinBoardBackend::get_leaf_by_index] for each.
+    fn list_leaves(&self,start:u64,count:u64) -> Result<Vec<HashValue>,BulletinBoardError> {
+        run_ready(backend_access::list_leaves(&SyncAccess(self),start,count))
+    }
+
+    /// Get the leaves with timestamp `t0<=timestamp<t1`, ordered by timestamp, with leaves with the same timestamp in sequence order.
+    ///
+    /// The default implementation walks down every tree, looking at every leaf and branch once, in sequence order, which is slow for a large board.
+    /// Backends should override this with an index on the leaf timestamps. (Leaf timestamps are usually, but not
+    /// necessarily, in sequence order, as the clock can go backwards, so a binary search over sequence numbers is not safe.)
+    fn list_leaves_between(&self,t0:Timestamp,t1:Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> {
+        run_ready(backend_access::list_leaves_between(&SyncAccess(self),t0,t1))
+    }
+
+    /// Get the published root that was current at time `t`, that is, the one with the latest timestamp `<=t`, or
+    /// the most recently published of these if several have that timestamp. None if no root had been published by then.
+    ///
+    /// The default implementation looks at every published root. Backends should override this with an index on the root timestamps.
+    fn get_published_root_at(&self,t:Timestamp) -> Result<Option<HashValue>,BulletinBoardError> {
+        run_ready(backend_access::get_published_root_at(&SyncAccess(self),t))
+    }
+
+    /// Find the leaf with a given sequence number by walking down the tree containing it.
+    /// This computes the current forest, and then takes O(depth) calls to get_hash_info.
+    fn leaf_by_index_from_tree(&self,index:u64) -> Result<Option<HashValue>,BulletinBoardError> {
+        run_ready(backend_access::leaf_by_index_from_tree(&SyncAccess(self),index))
+    }
+
+    /// Find the sequence number of a leaf by walking up to the top of the tree containing it, noting which side
+    /// of each branch it is on, and adding the number of leaves in earlier trees.
+    /// This takes O(depth) calls to get_hash_info, and then computes the current forest.
+    fn leaf_index_from_tree(&self,leaf:HashValue) -> Result<Option<u64>,BulletinBoardError> {
+        run_ready(backend_access::leaf_index_from_tree(&SyncAccess(self),leaf))
+    }
+
     /// Remove the text associated with a leaf.
     fn censor_leaf(&mut self,leaf_to_censor:HashValue) -> Result<(),BulletinBoardError>;
 
+    /// Restore the text associated with a previously censored leaf.
+    /// [BulletinBoard::uncensor_leaf] checks that the data matches the leaf's hash before calling this.
+    /// The default implementation fails with [BulletinBoardError::UncensoringNotSupported].
+    ///
+    /// This is also used by [BulletinBoard::redact_field] to replace the text of a structured leaf with a version
+    /// with a field redacted, which has the same hash.
+    fn uncensor_leaf(&mut self,_leaf_to_uncensor:HashValue,_data:&str) -> Result<(),BulletinBoardError> {
+        Err(BulletinBoardError::UncensoringNotSupported)
+    }
+
     /// Get the depth of a subtree rooted at a given leaf or branch node) by following elements down the left side of each branch.
     /// A leaf node has depth 0.
     /// A branch node has depth 1 or more.
     ///
     /// The default implementation is to repeatedly call get_hash_info depth times; this is usually adequate as this is only used during startup.
     fn left_depth(&self,hash:HashValue) -> Result<usize,BulletinBoardError> {
-        let mut res = 0;
-        let mut hash = hash;
-        loop {
-            match self.get_hash_info(hash)? {
-                Some(HashInfo{source:HashSource::Branch(history),..}) => {
-                    res+=1;
-                    hash = history.left;
-                }
-                _ => break
-            }
-        }
-        Ok(res)
+        run_ready(backend_access::left_depth(&SyncAccess(self),hash))
     }
 
 
+    /// Get the depth of a subtree rooted at a given leaf or branch node.
+    /// A leaf node has depth 0.
+    /// A branch node has depth 1 or more.
+    ///
+    /// This is used when reconstructing the current forest, which happens on startup and after errors.
+    /// The default implementation is [BulletinBoardBackend::left_depth], which takes O(depth) calls to get_hash_info;
+    /// backends where each lookup is expensive (e.g. a remote database) may wish to store the depth of each
+    /// branch when it is published, and answer this with a single lookup.
+    fn get_depth(&self,hash:HashValue) -> Result<usize,BulletinBoardError> {
+        self.left_depth(hash)
+    }
+
     /// Deduce the current forest structure.
     /// * First find leaf or branch elements that do not have a parent. These are the trees that are in the forest
     /// * Find the depth of each of these elements.
@@ -337,20 +556,68 @@ pub trait BulletinBoardBackend {
     ///
     /// The default implementation is usually adequate as it is only used during startup.
     fn compute_current_forest(&self) -> Result<GrowingForest,BulletinBoardError> {
-        GrowingForest::new(&self.get_all_leaves_and_branches_without_a_parent()?,|h|self.left_depth(h))
+        run_ready(backend_access::compute_current_forest(&SyncAccess(self)))
     }
 
 }
 
+/// How many times an operation will be retried if it fails because another writer changed the board at the same time.
+const MAX_CONCURRENT_MODIFICATION_RETRIES : usize = 10;
+
+/// The longest idempotency key allowed, in bytes. See [BulletinBoard::submit_leaf_with_idempotency_key].
+pub const MAX_IDEMPOTENCY_KEY_LENGTH : usize = 255;
+
+/// Check an idempotency key is of an acceptable length.
+fn check_idempotency_key(key:&str) -> Result<(),BulletinBoardError> {
+    if key.is_empty() || key.len()>MAX_IDEMPOTENCY_KEY_LENGTH { Err(BulletinBoardError::InvalidIdempotencyKey) } else { Ok(()) }
+}
+
+/// Given the information about the leaf previously submitted with an idempotency key, decide whether a resubmission of
+/// `data` with the same key is a retry of that submission, returning the leaf if so, or
+/// [BulletinBoardError::IdempotencyKeyReused] if the data is different. Returns None if the leaf does not exist, e.g. because
+/// the key was stored but the leaf was not.
+///
+/// The comparison is by hash rather than data, so that it works even if the leaf has since been censored or redacted.
+fn check_idempotent_resubmission(info:Option<HashInfo>,leaf:HashValue,data:&str) -> Result<Option<HashValue>,BulletinBoardError> {
+    match info {
+        None => Ok(None),
+        Some(HashInfo{source:HashSource::Leaf(history),..}) => {
+            let resubmitted = LeafHashHistory{ timestamp: history.timestamp, data: Some(data.to_string()), structured: false };
+            if resubmitted.compute_hash()==Some(leaf) { Ok(Some(leaf)) } else { Err(BulletinBoardError::IdempotencyKeyReused) }
+        }
+        Some(_) => Err(BulletinBoardError::BackendInconsistentError(format!("Idempotency key refers to {} which is not a leaf",leaf))),
+    }
+}
+
 fn bb_timestamp_now() -> Result<Timestamp, BulletinBoardError> {
     timestamp_now().map_err(|_|BulletinBoardError::ClockError)
 }
 
-impl <B:BulletinBoardBackend> BulletinBoard<B> {
+/// The state of a bulletin board other than its backend, shared by [BulletinBoard] and (with the `async` feature)
+/// [async_board::AsyncBulletinBoard]. The logic of both is here, using the backend through [ReadAccess] and [WriteAccess].
+#[derive(Default)]
+struct BoardState {
+    /// None if there is an error, otherwise the currently growing forest.
+    current_forest: Option<GrowingForest>,
+    /// Checks done on each submission. See [validation].
+    validators : Vec<Box<dyn SubmissionValidator>>,
+    /// Told about each change. See [events].
+    events : EventDispatcher,
+    /// Signs receipts for submissions, if enabled. See [receipt].
+    receipt_signer : Option<ReceiptSigner>,
+    /// Woken when a root is published. See [inclusion].
+    publications : PublicationWatcher,
+    /// What to do with a submission whose data is already in the board. See [content_index].
+    duplicate_policy : DuplicatePolicy,
+    /// Whether to add skip references to new published roots. See [BulletinBoard::set_root_skip_list].
+    root_skip_list : bool,
+}
+
+impl BoardState {
 
     /// called when the current_forest field is corrupt. Make it valid, if possible.
-    fn reload_current_forest(&mut self) -> Result<(),BulletinBoardError> {
-        match self.backend.compute_current_forest() {
+    async fn reload_current_forest(&mut self,backend:&impl ReadAccess) -> Result<(),BulletinBoardError> {
+        match backend.compute_current_forest().await {
             Ok(f) => {
                 self.current_forest = Some(f);
                 Ok(())
@@ -362,34 +629,458 @@ impl <B:BulletinBoardBackend> BulletinBoard<B> {
         }
     }
 
+    /// Get a valid forest reference, or an error.
+    fn forest_or_err(&self) -> Result<&GrowingForest,BulletinBoardError> {
+        self.current_forest.as_ref().ok_or(BulletinBoardError::CouldNotInitializeFromDatabase)
+    }
 
     /// Helper used in submit_leaf to wrap errors so that it is easy to reload the current forest if a recoverable error (e.g. resubmitted data) occurs during this step.
-    fn submit_leaf_work(&mut self,data:String) -> Result<HashValue,BulletinBoardError> {
-        let history = LeafHashHistory{ timestamp: bb_timestamp_now()?, data: Some(data) };
-        let new_hash = history.compute_hash().unwrap();
-        match self.backend.get_hash_info(new_hash)? {
-            Some(HashInfo{source:HashSource::Leaf(other_history), .. }) if other_history==history => {
-                Err(BulletinBoardError::IdenticalDataAlreadySubmitted)
+    /// See [BoardState::submit_leaf_unchecked] for `kind`.
+    async fn submit_leaf_work(&mut self,backend:&mut impl WriteAccess,data:&str,idempotency_key:Option<&str>,kind:LeafKind) -> Result<HashValue,BulletinBoardError> {
+        if self.duplicate_policy!=DuplicatePolicy::Allow && kind!=LeafKind::BoardGenerated { // checked here so that it is rechecked after a concurrent modification.
+            if let Some(existing) = check_duplicate(self.duplicate_policy,&backend.get_leaves_with_content_hash(content_hash(data)).await?)? {
+                if let Some(key) = idempotency_key { backend.store_idempotency_key(key,existing).await?; }
+                return Ok(existing);
             }
-            Some(hash_collision) => { // The below case is absurdly unlikely to happen.
-                eprintln!("Time to enter the lottery! Actually you have probably won without entering. You have just done a submission and found a hash collision between {:?} and {:?}",&hash_collision,&history);
-                std::thread::sleep(Duration::from_secs(1)); // work around - wait a second and retry, with a new timestamp.
-                self.submit_leaf_work(history.data.unwrap())
+        }
+        loop {
+            let history = LeafHashHistory{ timestamp: bb_timestamp_now()?, data: Some(data.to_string()), structured: kind==LeafKind::Structured };
+            let new_hash = history.compute_hash().unwrap();
+            match backend.get_hash_info(new_hash).await? {
+                Some(HashInfo{source:HashSource::Leaf(other_history), .. }) if other_history==history && kind!=LeafKind::BoardGenerated => {
+                    return Err(BulletinBoardError::IdenticalDataAlreadySubmitted)
+                }
+                Some(HashInfo{source:HashSource::Leaf(other_history), .. }) if other_history==history => { // e.g. the same censorship twice in a second.
+                    backend.sleep(Duration::from_secs(1)).await; // wait a second and retry, with a new timestamp.
+                }
+                Some(hash_collision) => { // The below case is absurdly unlikely to happen.
+                    eprintln!("Time to enter the lottery! Actually you have probably won without entering. You have just done a submission and found a hash collision between {:?} and {:?}",&hash_collision,&history);
+                    backend.sleep(Duration::from_secs(1)).await; // work around - wait a second and retry, with a new timestamp.
+                }
+                None =>  { // no hash collision, all is good. Should go here 99.99999999999999999999999999999..% of the time.
+                    let mut transaction = DatabaseTransaction{ idempotency_key: idempotency_key.map(|k|k.to_string()), ..Default::default() };
+                    transaction.add_leaf_hash(new_hash,history);
+                    let forest = self.current_forest.as_mut().ok_or(BulletinBoardError::CouldNotInitializeFromDatabase)?;
+                    let expected_parentless = forest.get_subtrees();
+                    forest.add_leaf_using(new_hash, backend, &mut transaction).await?;
+                    backend.publish_if_unchanged(&transaction,&expected_parentless).await?;
+                    self.events.fire_transaction(&transaction);
+                    return Ok(new_hash)
+                }
             }
-            _ =>  { // no hash collision, all is good. Should go here 99.99999999999999999999999999999..% of the time.
-                let mut transaction = DatabaseTransaction::default();
-                transaction.add_leaf_hash(new_hash,history);
-                self.current_forest.as_mut().ok_or_else(||BulletinBoardError::CouldNotInitializeFromDatabase)?.add_leaf(new_hash, &self.backend, &mut transaction)?;
-                self.backend.publish(&transaction)?;
-                Ok(new_hash)
+        }
+    }
+
+    /// See [BulletinBoard::submit_leaf].
+    async fn submit_leaf(&mut self,backend:&mut impl WriteAccess,data:&str) -> Result<HashValue,BulletinBoardError> {
+        check_not_reserved(data)?;
+        validate_submission(&self.validators,data)?;
+        self.submit_leaf_unchecked(backend,data,None,LeafKind::Plain).await
+    }
+
+    /// See [BulletinBoard::submit_leaf_with_idempotency_key].
+    async fn submit_leaf_with_idempotency_key(&mut self,backend:&mut impl WriteAccess,data:&str,idempotency_key:&str) -> Result<HashValue,BulletinBoardError> {
+        check_not_reserved(data)?;
+        check_idempotency_key(idempotency_key)?;
+        validate_submission(&self.validators,data)?;
+        if let Some(leaf) = backend.get_leaf_for_idempotency_key(idempotency_key).await? {
+            if let Some(leaf) = check_idempotent_resubmission(backend.get_hash_info(leaf).await?,leaf,data)? { return Ok(leaf); }
+        }
+        self.submit_leaf_unchecked(backend,data,Some(idempotency_key),LeafKind::Plain).await
+    }
+
+    /// See [BulletinBoard::submit_structured_leaf].
+    async fn submit_structured_leaf(&mut self,backend:&mut impl WriteAccess,object:&serde_json::Map<String,serde_json::Value>) -> Result<HashValue,BulletinBoardError> {
+        validate_submission(&self.validators,&serde_json::to_string(object).map_err(|e|BulletinBoardError::InvalidStructuredLeaf(e.to_string()))?)?;
+        self.submit_leaf_unchecked(backend,&StructuredLeaf::new(object)?.to_leaf_data(),None,LeafKind::Structured).await
+    }
+
+    /// See [BulletinBoard::submit_leaf_with_receipt].
+    async fn submit_leaf_with_receipt(&mut self,backend:&mut impl WriteAccess,data:&str) -> Result<SignedReceipt,BulletinBoardError> {
+        if self.receipt_signer.is_none() { return Err(BulletinBoardError::ReceiptsNotEnabled); }
+        let leaf = self.submit_leaf(backend,data).await?;
+        self.get_receipt(&*backend,leaf).await
+    }
+
+    /// See [BulletinBoard::get_receipt].
+    async fn get_receipt(&self,backend:&impl ReadAccess,leaf:HashValue) -> Result<SignedReceipt,BulletinBoardError> {
+        let signer = self.receipt_signer.as_ref().ok_or(BulletinBoardError::ReceiptsNotEnabled)?;
+        signer.sign_leaf(leaf,backend.get_hash_info(leaf).await?)
+    }
+
+    /// Like submit_leaf, but without checking that the data does not use a reserved prefix, and with an optional idempotency key.
+    ///
+    /// Leaves the board adds itself ([LeafKind::BoardGenerated]), such as censorship records, are not subject to the
+    /// duplicate policy, and identical data in the same second is delayed to the next second rather than rejected, as
+    /// censoring the same leaf again for the same reason gives the same record, and each censorship must be recorded.
+    async fn submit_leaf_unchecked(&mut self,backend:&mut impl WriteAccess,data:&str,idempotency_key:Option<&str>,kind:LeafKind) -> Result<HashValue,BulletinBoardError> {
+        let mut retries = 0;
+        loop {
+            let res = self.submit_leaf_work(backend,data,idempotency_key,kind).await;
+            if res.is_err() { self.reload_current_forest(backend).await? }
+            match res {
+                Err(BulletinBoardError::ConcurrentModification) if retries<MAX_CONCURRENT_MODIFICATION_RETRIES => retries+=1,
+                _ => return res,
             }
         }
     }
 
+    /// See [BulletinBoard::censor_leaf].
+    async fn censor_leaf(&mut self,backend:&mut impl WriteAccess,leaf_to_censor:HashValue,reason:&str,authority:&str) -> Result<HashValue,BulletinBoardError> {
+        check_censorable(backend.get_hash_info(leaf_to_censor).await?)?;
+        let record = CensorshipRecord{ censored_leaf: leaf_to_censor, reason: reason.to_string(), authority: authority.to_string(), field: None, uncensored: false };
+        let record_leaf = self.submit_leaf_unchecked(backend,&record.to_leaf_data(),None,LeafKind::BoardGenerated).await?;
+        backend.censor_leaf(leaf_to_censor).await.map_err(|e|BulletinBoardError::CensorshipRecordedButNotDone(record_leaf,e.to_string()))?;
+        self.events.fire(BulletinBoardEvent::LeafCensored { leaf: leaf_to_censor, record_leaf, field: None });
+        Ok(record_leaf)
+    }
+
+    /// See [BulletinBoard::redact_field].
+    async fn redact_field(&mut self,backend:&mut impl WriteAccess,leaf:HashValue,field:&str,reason:&str,authority:&str) -> Result<HashValue,BulletinBoardError> {
+        let redacted = redacted_leaf_data(backend.get_hash_info(leaf).await?,field)?;
+        let record = CensorshipRecord{ censored_leaf: leaf, reason: reason.to_string(), authority: authority.to_string(), field: Some(field.to_string()), uncensored: false };
+        let record_leaf = self.submit_leaf_unchecked(backend,&record.to_leaf_data(),None,LeafKind::BoardGenerated).await?;
+        backend.uncensor_leaf(leaf,&redacted).await.map_err(|e|BulletinBoardError::CensorshipRecordedButNotDone(record_leaf,e.to_string()))?;
+        self.events.fire(BulletinBoardEvent::LeafCensored { leaf, record_leaf, field: Some(field.to_string()) });
+        Ok(record_leaf)
+    }
+
+    /// See [BulletinBoard::uncensor_leaf].
+    async fn uncensor_leaf(&mut self,backend:&mut impl WriteAccess,leaf_to_uncensor:HashValue,data:&str,reason:&str,authority:&str) -> Result<HashValue,BulletinBoardError> {
+        check_uncensor(backend.get_hash_info(leaf_to_uncensor).await?,leaf_to_uncensor,data)?;
+        let record = CensorshipRecord{ censored_leaf: leaf_to_uncensor, reason: reason.to_string(), authority: authority.to_string(), field: None, uncensored: true };
+        let record_leaf = self.submit_leaf_unchecked(backend,&record.to_leaf_data(),None,LeafKind::BoardGenerated).await?;
+        backend.uncensor_leaf(leaf_to_uncensor,data).await.map_err(|e|BulletinBoardError::CensorshipRecordedButNotDone(record_leaf,e.to_string()))?;
+        self.events.fire(BulletinBoardEvent::LeafUncensored { leaf: leaf_to_uncensor, record_leaf });
+        Ok(record_leaf)
+    }
+
+    /// See [BulletinBoard::get_parentless_unpublished_hash_values].
+    async fn get_parentless_unpublished_hash_values(&self,backend:&impl ReadAccess) -> Result<Vec<HashValue>,BulletinBoardError> {
+        remove_published(backend,self.forest_or_err()?.get_subtrees()).await
+    }
+
+    /// See [BulletinBoard::order_new_published_root].
+    async fn order_new_published_root(&mut self,backend:&mut impl WriteAccess) -> Result<HashValue,BulletinBoardError> {
+        let mut retries = 0;
+        loop {
+            match self.order_new_published_root_work(backend).await {
+                Err(BulletinBoardError::ConcurrentModification) if retries<MAX_CONCURRENT_MODIFICATION_RETRIES => {
+                    self.reload_current_forest(backend).await?;
+                    retries+=1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// See [BulletinBoard::order_new_published_root_if_changed].
+    async fn order_new_published_root_if_changed(&mut self,backend:&mut impl WriteAccess) -> Result<Option<HashValue>,BulletinBoardError> {
+        if self.get_parentless_unpublished_hash_values(&*backend).await?.is_empty() { Ok(None) } else { self.order_new_published_root(backend).await.map(Some) }
+    }
+
+    /// Helper used in order_new_published_root to make it easy to retry if another writer got in first.
+    async fn order_new_published_root_work(&mut self,backend:&mut impl WriteAccess) -> Result<HashValue,BulletinBoardError> {
+        loop {
+            let prior = backend.get_most_recent_published_root().await?;
+            let skips = if self.root_skip_list { compute_root_skips(backend,prior).await? } else { vec![] };
+            let history = RootHashHistory { timestamp: bb_timestamp_now()?, elements: self.forest_or_err()?.get_subtrees(), prior, skips };
+            let new_hash = history.compute_hash();
+            match backend.get_hash_info(new_hash).await? {
+                Some(HashInfo{source:HashSource::Root(other_history), .. }) if other_history==history => {
+                    return Err(BulletinBoardError::PublishingNewRootInstantlyAfterLastRoot)
+                }
+                Some(hash_collision) => {
+                    println!("Time to enter the lottery! Actually you have probably won without entering. You have just done a submission and found a hash collision between {:?} and {:?}",&hash_collision,&history);
+                    backend.sleep(Duration::from_secs(1)).await; // work around - wait a second and retry, with a new timestamp.
+                }
+                None =>  { // no hash collision, all is good. Should go here 99.99999999999999999999999999999..% of the time.
+                    let expected_parentless = history.elements.clone();
+                    let mut transaction = DatabaseTransaction::default();
+                    transaction.add_root_hash(new_hash,history);
+                    backend.publish_if_unchanged(&transaction,&expected_parentless).await?;
+                    self.events.fire_transaction(&transaction);
+                    self.publications.published();
+                    return Ok(new_hash)
+                }
+            }
+        }
+    }
+}
+
+/// Check that a node, with information `info`, can be censored, that is, is a leaf that is not already censored
+/// and is not a censorship record. Used by [BulletinBoard::censor_leaf].
+fn check_censorable(info:Option<HashInfo>) -> Result<(),BulletinBoardError> {
+    match info {
+        None => Err(BulletinBoardError::NoSuchHash),
+        Some(HashInfo{source:HashSource::Leaf(LeafHashHistory{data:None,..}),..}) => Err(BulletinBoardError::LeafAlreadyCensored),
+        Some(HashInfo{source:HashSource::Leaf(LeafHashHistory{data:Some(data),..}),..}) if CensorshipRecord::from_leaf_data(&data).is_some() => Err(BulletinBoardError::CanNotCensorCensorshipRecord),
+        Some(HashInfo{source:HashSource::Leaf(_),..}) => Ok(()),
+        Some(_) => Err(BulletinBoardError::CanOnlyCensorLeaves),
+    }
+}
+
+/// Implementation of [BulletinBoard::get_parentless_unpublished_hash_values] given the current subtrees.
+/// Shared with [shared_backend::BulletinBoardReader].
+async fn remove_published(backend:&impl ReadAccess,mut currently_used:Vec<HashValue>) -> Result<Vec<HashValue>,BulletinBoardError> {
+    if let Some(published_root) = backend.get_most_recent_published_root().await? {
+        if let Some(HashInfo{source:HashSource::Root(history),..}) = backend.get_hash_info(published_root).await? {
+            currently_used.retain(|h|!history.elements.contains(h)) // remove already published elements.
+        } else { return Err(BulletinBoardError::PublishedRootHasNoInfo) }
+    }
+    Ok(currently_used)
+}
+
+/// The kind of leaf being added by [BoardState::submit_leaf_unchecked].
+#[derive(Debug,Copy,Clone,Eq,PartialEq)]
+enum LeafKind {
+    /// Text submitted with [BulletinBoard::submit_leaf] or [BulletinBoard::submit_leaf_with_idempotency_key].
+    Plain,
+    /// A structured leaf, submitted with [BulletinBoard::submit_structured_leaf], and marked as such.
+    Structured,
+    /// A leaf the board adds itself, such as a censorship record.
+    BoardGenerated,
+}
+
+/// Reject data for a leaf that starts with a prefix reserved for data generated by the bulletin board.
+/// Used by [BulletinBoard::submit_leaf].
+fn check_not_reserved(data:&str) -> Result<(),BulletinBoardError> {
+    for prefix in [CENSORSHIP_RECORD_PREFIX,STRUCTURED_LEAF_PREFIX] {
+        if data.starts_with(prefix) { return Err(BulletinBoardError::DataUsesReservedPrefix(prefix.to_string())); }
+    }
+    Ok(())
+}
+
+/// Check that a leaf, with information `info`, is censored (or is a structured leaf with redacted fields)
+/// and that `data` is the text that was censored. Used by [BulletinBoard::uncensor_leaf].
+fn check_uncensor(info:Option<HashInfo>,leaf_to_uncensor:HashValue,data:&str) -> Result<(),BulletinBoardError> {
+    match info {
+        None => Err(BulletinBoardError::NoSuchHash),
+        Some(HashInfo{source:HashSource::Leaf(LeafHashHistory{timestamp,data:existing,structured}),..}) => {
+            if let Some(existing) = existing {
+                if !(structured && StructuredLeaf::from_leaf_data(&existing).map(|s|s.has_redactions()).unwrap_or(false)) { return Err(BulletinBoardError::LeafIsNotCensored); }
+            }
+            let history = LeafHashHistory{ timestamp, data: Some(data.to_string()), structured };
+            if history.compute_hash()==Some(leaf_to_uncensor) { Ok(()) } else { Err(BulletinBoardError::UncensoredDataDoesNotMatchHash) }
+        }
+        Some(_) => Err(BulletinBoardError::CanOnlyCensorLeaves),
+    }
+}
+
+/// Get the data for a structured leaf, with information `info`, with the named field redacted.
+/// Used by [BulletinBoard::redact_field].
+fn redacted_leaf_data(info:Option<HashInfo>,field:&str) -> Result<String,BulletinBoardError> {
+    match info {
+        None => Err(BulletinBoardError::NoSuchHash),
+        Some(HashInfo{source:HashSource::Leaf(LeafHashHistory{data:Some(data),structured:true,..}),..}) => {
+            let structured = StructuredLeaf::from_leaf_data(&data).ok_or(BulletinBoardError::NotAStructuredLeaf)?;
+            Ok(structured.redact(field)?.to_leaf_data())
+        }
+        Some(HashInfo{source:HashSource::Leaf(_),..}) => Err(BulletinBoardError::NotAStructuredLeaf),
+        Some(_) => Err(BulletinBoardError::CanOnlyCensorLeaves),
+    }
+}
+
+/// Implementation of [BulletinBoard::get_proof_chain]. Shared with [shared_backend::BulletinBoardReader].
+async fn get_proof_chain(backend:&impl ReadAccess,query:HashValue) -> Result<FullProof,BulletinBoardError> {
+    get_proof_chain_to_root(backend,query,backend.get_most_recent_published_root().await.ok().flatten()).await
+}
+
+/// Get the proof chain for `query` up to the published root `root`, rather than the most recent one.
+/// If `root` is None or does not include `query`, the chain goes up to the top of the tree, and `published_root` is None.
+async fn get_proof_chain_to_root(backend:&impl ReadAccess,query:HashValue,root:Option<HashValue>) -> Result<FullProof,BulletinBoardError> {
+    let mut chain = vec![];
+    let mut node = query;
+    let mut published_root : Option<HashInfoWithHash> =  {
+        if let Some(published_root_hash) = root {
+            if let Ok(Some(node_info)) = backend.get_hash_info(published_root_hash).await {
+                Some(node_info.add_hash(published_root_hash))
+            } else { return Err(BulletinBoardError::ProofChainCorruptMissingPublishedNode(published_root_hash)); } // There is a break in the logic!!!
+        } else {None }
+    };
+    let published: HashSet<HashValue> = {
+        if let Some(info) = &published_root {
+            if let HashSource::Root(history) = &info.source {
+                HashSet::from_iter(history.elements.iter().cloned())
+            } else { return Err(BulletinBoardError::PublishedRootIsNotARoot(node)); } // There is a break in the logic!!!
+        } else { HashSet::default() }
+    };
+    loop {
+        if let Ok(Some(node_info)) = backend.get_hash_info(node).await {
+            chain.push(node_info.add_hash(node));
+            if published.contains(&node) { break; }
+            match node_info.parent {
+                Some(parent) => node=parent,
+                None => {
+                    published_root=None; // got to the end of the line without finding something in the published root.
+                    break
+                },
+            }
+        } else {
+            return Err(if query==node { BulletinBoardError::NoSuchHash } else { BulletinBoardError::ProofChainCorruptMissingPublishedNode(node)});
+        } // There is a break in the logic!!!
+    }
+    let leaf_index = match chain.first() {
+        Some(HashInfoWithHash{source:HashSource::Leaf(_),..}) => backend.get_leaf_index(query).await?,
+        _ => None,
+    };
+    Ok(FullProof{ chain, published_root, leaf_index })
+}
+
+/// Find a path from `node` down to an uncensored leaf, going left where possible, for a [LeafCountProof].
+/// Returns None if all the leaves under `node` are censored.
+async fn path_to_uncensored_leaf(backend:&impl ReadAccess,node:HashValue) -> Result<Option<Vec<HashInfoWithHash>>,BulletinBoardError> {
+    let mut path : Vec<HashInfoWithHash> = vec![];
+    let mut pending = vec![(0,node)]; // (length of the path above the node, node), depth first, left first.
+    while let Some((above,hash)) = pending.pop() {
+        path.truncate(above);
+        let info = backend.get_hash_info(hash).await?.ok_or(BulletinBoardError::ProofChainCorruptMissingPublishedNode(hash))?;
+        match &info.source {
+            HashSource::Leaf(history) => if history.data.is_some() {
+                path.push(info.add_hash(hash));
+                return Ok(Some(path));
+            }
+            HashSource::Branch(history) => {
+                pending.push((above+1,history.right));
+                pending.push((above+1,history.left));
+                path.push(info.add_hash(hash));
+            }
+            HashSource::Root(_) => return Err(BulletinBoardError::BackendInconsistentError(format!("Root {} is referenced as part of a tree",hash))),
+        }
+    }
+    Ok(None)
+}
+
+/// Get a proof of the number of leaves covered by a published root. See [BulletinBoard::get_leaf_count_proof].
+pub(crate) async fn get_leaf_count_proof(backend:&impl ReadAccess,published_root:HashValue) -> Result<LeafCountProof,BulletinBoardError> {
+    let info = backend.get_hash_info(published_root).await?.ok_or(BulletinBoardError::NoSuchHash)?;
+    let HashSource::Root(history) = &info.source else { return Err(BulletinBoardError::PublishedRootIsNotARoot(published_root)) };
+    let mut paths = vec![];
+    for element in &history.elements {
+        paths.push(path_to_uncensored_leaf(backend,*element).await?.ok_or(BulletinBoardError::AllLeavesCensored(*element))?);
+    }
+    Ok(LeafCountProof{ published_root: info.add_hash(published_root), paths })
+}
+
+/// Check that two proof chains for leaves are in the same published root, with the earlier one first, and make an [OrderingProof].
+/// See [BulletinBoard::get_ordering_proof].
+pub(crate) fn make_ordering_proof(earlier:FullProof,later:FullProof) -> Result<OrderingProof,BulletinBoardError> {
+    for proof in [&earlier,&later] {
+        if !matches!(proof.chain.first(),Some(HashInfoWithHash{source:HashSource::Leaf(_),..})) { return Err(BulletinBoardError::NotALeaf); }
+        if proof.published_root.is_none() { return Err(BulletinBoardError::NotYetPublished(proof.chain[0].hash)); }
+    }
+    if earlier.published_root.as_ref().map(|r|r.hash)!=later.published_root.as_ref().map(|r|r.hash) { return Err(BulletinBoardError::ProofsForDifferentRoots); } // e.g. a root was published between getting the proofs.
+    match (earlier.position_in_published_root(),later.position_in_published_root()) {
+        (Some(earlier_position),Some(later_position)) if earlier_position<later_position => Ok(OrderingProof{ earlier, later }),
+        (Some(_),Some(_)) => Err(BulletinBoardError::LeavesNotInOrder),
+        _ => Err(BulletinBoardError::BackendInconsistentError("A proof chain is not a path up the tree to the published root".to_string())),
+    }
+}
+
+/// Get a proof that the leaf `earlier` was added before the leaf `later`. See [BulletinBoard::get_ordering_proof].
+pub(crate) async fn get_ordering_proof(backend:&impl ReadAccess,earlier:HashValue,later:HashValue) -> Result<OrderingProof,BulletinBoardError> {
+    make_ordering_proof(get_proof_chain(backend,earlier).await?,get_proof_chain(backend,later).await?)
+}
+
+/// Find the first published root that includes `node`, or None if it is not yet published.
+///
+/// A published root includes a node if one of its elements is the node or an ancestor of it. Once
+/// a node is included in a published root, it is included in all subsequent published roots, so this
+/// is found by a binary search over the published roots.
+///
+/// See also [BulletinBoard::first_including_root], which also gives a proof of inclusion in that root.
+pub(crate) async fn first_including_root(backend:&impl ReadAccess,node:HashValue) -> Result<Option<HashValue>,BulletinBoardError> {
+    let mut ancestors : HashSet<HashValue> = HashSet::default();
+    let mut current = Some(node);
+    while let Some(hash) = current {
+        ancestors.insert(hash);
+        current = backend.get_hash_info(hash).await?.ok_or(BulletinBoardError::NoSuchHash)?.parent;
+    }
+    let roots = backend.get_all_published_roots().await?;
+    let (mut start,mut end) = (0,roots.len()); // roots before start do not include node, roots from end do.
+    while start<end {
+        let mid = (start+end)/2;
+        let includes = match backend.get_hash_info(roots[mid]).await? {
+            Some(HashInfo{source:HashSource::Root(history),..}) => history.elements.iter().any(|h|ancestors.contains(h)),
+            _ => return Err(BulletinBoardError::PublishedRootIsNotARoot(roots[mid])),
+        };
+        if includes { end=mid } else { start=mid+1 }
+    }
+    Ok(roots.get(start).cloned())
+}
+
+/// Get the history of a published root.
+async fn get_root_history(backend:&impl ReadAccess,root:HashValue) -> Result<RootHashHistory,BulletinBoardError> {
+    match backend.get_hash_info(root).await? {
+        Some(HashInfo{source:HashSource::Root(history),..}) => Ok(history),
+        Some(_) => Err(BulletinBoardError::PublishedRootIsNotARoot(root)),
+        None => Err(BulletinBoardError::NoSuchHash),
+    }
+}
+
+/// The [RootHashHistory::skips] for a new root whose prior is `prior`. The root 2^(level+1) publications back is the
+/// root 2^level publications back from the root 2^level publications back, so each is found from the previous one.
+/// Stops at the first level that the earlier root does not have, e.g. because it was published before skips were enabled.
+async fn compute_root_skips(backend:&impl ReadAccess,prior:Option<HashValue>) -> Result<Vec<HashValue>,BulletinBoardError> {
+    let mut skips = vec![];
+    let Some(mut back) = prior else { return Ok(skips) }; // the root 2^skips.len() publications back.
+    while let Some(further_back) = get_root_history(backend,back).await?.published_before(skips.len()) {
+        skips.push(further_back);
+        back = further_back;
+    }
+    Ok(skips)
+}
+
+/// Given the positions in the list of published roots of two roots, choose the step to take back from the later one,
+/// given its history, to get closer to the earlier one. Returns the level (see [RootHashHistory::published_before]) and the root.
+/// Takes the largest step available that does not go past the earlier root.
+fn ancestry_step(history:&RootHashHistory,later_position:usize,earlier_position:usize) -> Option<(usize,HashValue)> {
+    let distance = later_position.checked_sub(earlier_position)?;
+    if distance==0 { return None; }
+    let max_level = distance.ilog2() as usize;
+    (0..=max_level).rev().find_map(|level|history.published_before(level).map(|root|(level,root)))
+}
+
+/// Implementation of [BulletinBoard::get_ancestry_proof].
+pub(crate) async fn get_ancestry_proof(backend:&impl ReadAccess,earlier:HashValue,later:HashValue) -> Result<AncestryProof,BulletinBoardError> {
+    let roots = backend.get_all_published_roots().await?;
+    let position = |root:HashValue| roots.iter().position(|r|*r==root).ok_or(BulletinBoardError::PublishedRootIsNotARoot(root));
+    let (earlier_position,mut later_position) = (position(earlier)?,position(later)?);
+    if earlier_position>later_position { return Err(BulletinBoardError::RootsNotInOrder); }
+    let mut current = later;
+    let mut history = get_root_history(backend,current).await?;
+    let mut chain = vec![];
+    while let Some((level,next)) = ancestry_step(&history,later_position,earlier_position) {
+        later_position-=1<<level;
+        if roots[later_position]!=next { return Err(BulletinBoardError::BackendInconsistentError(format!("Root {} references {} as {} publications before, but it is not",current,next,1usize<<level))); }
+        chain.push(HashInfo{ source: HashSource::Root(history), parent: None }.add_hash(current));
+        current = next;
+        history = get_root_history(backend,current).await?;
+    }
+    chain.push(HashInfo{ source: HashSource::Root(history), parent: None }.add_hash(current));
+    Ok(AncestryProof{ chain })
+}
+
+/// Implementation of [BulletinBoard::first_including_root].
+pub(crate) async fn first_including_root_with_proof(backend:&impl ReadAccess,query:HashValue) -> Result<Option<FullProof>,BulletinBoardError> {
+    match first_including_root(backend,query).await? {
+        Some(root) => Ok(Some(get_proof_chain_to_root(backend,query,Some(root)).await?)),
+        None => Ok(None),
+    }
+}
+
+impl <B:BulletinBoardBackend> BulletinBoard<B> {
 
     /// Submit some data to be included in the bulletin board, and get back a HashValue that the
     /// board commits to having in the history.
     /// Note that if the same data is submitted twice in the same second it will return an error (as this probably is)
+    /// To treat the same data submitted at any time as a duplicate, see [BulletinBoard::set_duplicate_policy].
+    ///
+    /// Data starting with [censorship::CENSORSHIP_RECORD_PREFIX] is rejected, as that is used for records of censorship.
+    /// Likewise data starting with [structured_leaf::STRUCTURED_LEAF_PREFIX] is rejected; use [BulletinBoard::submit_structured_leaf].
+    ///
+    /// If another writer sharing the same backend added something at the same time, the current state is reloaded
+    /// from the backend and the submission retried.
     ///
     /// # Example
     ///
@@ -400,23 +1091,208 @@ impl <B:BulletinBoardBackend> BulletinBoard<B> {
     /// // the board now has one leaf!
     ///```
     pub fn submit_leaf(&mut self,data:&str) -> Result<HashValue,BulletinBoardError> {
-        let res = self.submit_leaf_work(data.to_string());
-        if res.is_err() { self.reload_current_forest()? }
-        res
+        run_ready(self.state.submit_leaf(&mut SyncAccess(&mut self.backend),data))
+    }
+
+    /// Like [BulletinBoard::submit_leaf], but with a client supplied idempotency key, e.g. a random request ID, which
+    /// is stored with the leaf. If a leaf was already submitted with the same key and the same data, its hash is returned
+    /// and nothing new is added, so a client that is unsure whether a submission succeeded can safely retry it.
+    /// If the key was already used with different data, this fails with [BulletinBoardError::IdempotencyKeyReused].
+    /// If the [DuplicatePolicy] returns an existing leaf instead of adding one, the key is recorded for the existing leaf.
+    ///
+    /// Keys must be between 1 and [MAX_IDEMPOTENCY_KEY_LENGTH] bytes long. Not all backends support
+    /// idempotency keys; see [BulletinBoardBackend::get_leaf_for_idempotency_key].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::BulletinBoardError;
+    ///
+    /// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+    ///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+    /// let hash = board.submit_leaf_with_idempotency_key("a","request 1").unwrap();
+    /// assert_eq!(board.submit_leaf_with_idempotency_key("a","request 1"),Ok(hash)); // a retry
+    /// assert_eq!(board.submit_leaf_with_idempotency_key("b","request 1"),Err(BulletinBoardError::IdempotencyKeyReused));
+    /// assert_eq!(board.submit_leaf_with_idempotency_key("b",&"k".repeat(256)),Err(BulletinBoardError::InvalidIdempotencyKey));
+    /// assert_eq!(board.get_parentless_unpublished_hash_values().unwrap(),vec![hash]);
+    /// ```
+    pub fn submit_leaf_with_idempotency_key(&mut self,data:&str,idempotency_key:&str) -> Result<HashValue,BulletinBoardError> {
+        run_ready(self.state.submit_leaf_with_idempotency_key(&mut SyncAccess(&mut self.backend),data,idempotency_key))
+    }
+
+    /// Set what to do with subsequent submissions whose data is the same as an existing leaf's. The default is
+    /// [DuplicatePolicy::Allow]; other policies need a backend with a content index. See [content_index].
+    pub fn set_duplicate_policy(&mut self,policy:DuplicatePolicy) {
+        self.state.duplicate_policy = policy;
+    }
+
+    /// Set whether subsequently published roots include [RootHashHistory::skips], references to the roots 2, 4, 8, ...
+    /// publications earlier, which make [BulletinBoard::get_ancestry_proof] logarithmic rather than linear in size.
+    /// The default is not to, as roots with skips use a different hash format that older verifiers do not understand.
+    pub fn set_root_skip_list(&mut self,enabled:bool) {
+        self.state.root_skip_list = enabled;
+    }
+
+    /// Add a check that will be done on all subsequent submissions. See [validation].
+    pub fn add_validator(&mut self,validator:impl SubmissionValidator+'static) {
+        self.state.validators.push(Box::new(validator));
+    }
+
+    /// Add a listener that will be told about all subsequent changes made through this object. See [events].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::events::BulletinBoardEvent;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+    ///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+    /// let heard = Arc::new(Mutex::new(vec![]));
+    /// let heard_by_listener = heard.clone();
+    /// board.add_listener(move |event:&BulletinBoardEvent| heard_by_listener.lock().unwrap().push(event.clone()));
+    /// let a = board.submit_leaf("a").unwrap();
+    /// let b = board.submit_leaf("b").unwrap();
+    /// let root = board.order_new_published_root().unwrap();
+    /// let record_leaf = board.censor_leaf(a,"reason","authority").unwrap();
+    /// let heard = heard.lock().unwrap();
+    /// assert_eq!(heard.len(),5);
+    /// assert!(matches!(&heard[0],BulletinBoardEvent::LeafAdded{leaf,branches,..} if *leaf==a && branches.is_empty()));
+    /// assert!(matches!(&heard[1],BulletinBoardEvent::LeafAdded{leaf,branches,..} if *leaf==b && branches.len()==1));
+    /// assert!(matches!(&heard[2],BulletinBoardEvent::RootPublished{root:r,prior:None,elements,..} if *r==root && elements.len()==1));
+    /// assert!(matches!(&heard[3],BulletinBoardEvent::LeafAdded{leaf,..} if *leaf==record_leaf));
+    /// assert_eq!(heard[4],BulletinBoardEvent::LeafCensored{leaf:a,record_leaf,field:None});
+    /// ```
+    pub fn add_listener(&mut self,listener:impl BulletinBoardListener+'static) -> ListenerHandle {
+        self.state.events.add_listener(listener)
+    }
+
+    /// Remove a listener added with [BulletinBoard::add_listener], so that it is told about no more changes and is dropped.
+    /// Returns false if it had already been removed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::events::BulletinBoardEvent;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+    ///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+    /// let heard = Arc::new(Mutex::new(0));
+    /// let heard_by_listener = heard.clone();
+    /// let handle = board.add_listener(move |_:&BulletinBoardEvent| *heard_by_listener.lock().unwrap()+=1);
+    /// board.submit_leaf("a").unwrap();
+    /// assert!(board.remove_listener(handle));
+    /// board.submit_leaf("b").unwrap();
+    /// assert_eq!(*heard.lock().unwrap(),1);
+    /// assert_eq!(Arc::strong_count(&heard),1); // the listener has been dropped.
+    /// assert!(!board.remove_listener(handle));
+    /// ```
+    pub fn remove_listener(&mut self,handle:ListenerHandle) -> bool {
+        self.state.events.remove_listener(handle)
+    }
+
+    /// Get a channel on which all subsequent changes made through this object will be sent. See [events].
+    /// If the receiver is dropped, no more events will be sent to it.
+    #[cfg(feature = "async")]
+    pub fn subscribe(&mut self) -> async_std::channel::Receiver<BulletinBoardEvent> {
+        self.state.events.subscribe()
+    }
+
+    /// Get a watcher that is woken each time this object publishes a root, for waiting until a leaf
+    /// is included in a published root without holding a lock on the board. See [inclusion].
+    pub fn publication_watcher(&self) -> PublicationWatcher {
+        self.state.publications.clone()
+    }
+
+    /// Submit a JSON object to be included in the bulletin board as a structured leaf, whose fields can
+    /// be censored individually with [BulletinBoard::redact_field]. See [structured_leaf].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::structured_leaf::StructuredLeaf;
+    /// use merkle_tree_bulletin_board::hash_history::{HashSource, LeafHashHistory};
+    /// use merkle_tree_bulletin_board::verifier::verify_proof;
+    ///
+    /// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+    ///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+    /// let object = serde_json::json!({"question":"Why?","asked_by":"Fred"});
+    /// let hash = board.submit_structured_leaf(object.as_object().unwrap()).unwrap();
+    /// let root = board.order_new_published_root().unwrap();
+    /// let original = match board.get_hash_info(hash).unwrap().source {
+    ///     HashSource::Leaf(LeafHashHistory{data:Some(data),..}) => data,
+    ///     _ => panic!("Not a leaf"),
+    /// };
+    /// board.redact_field(hash,"asked_by","Privacy","Fred").unwrap();
+    /// let proof = board.get_proof_chain(hash).unwrap();
+    /// let redacted = match &proof.chain[0].source {
+    ///     HashSource::Leaf(LeafHashHistory{data:Some(data),..}) => StructuredLeaf::from_leaf_data(data).unwrap(),
+    ///     _ => panic!("Not a leaf"),
+    /// };
+    /// assert_eq!(redacted.visible_value("question"),Some("\"Why?\""));
+    /// assert_eq!(redacted.visible_value("asked_by"),None);
+    /// // Fred can still check that his original submission is in the board.
+    /// assert_eq!(verify_proof(&original,root,&proof),None);
+    /// // and the redaction can be undone, given the original.
+    /// board.uncensor_leaf(hash,&original,"Consent given","Fred").unwrap();
+    /// ```
+    pub fn submit_structured_leaf(&mut self,object:&serde_json::Map<String,serde_json::Value>) -> Result<HashValue,BulletinBoardError> {
+        run_ready(self.state.submit_structured_leaf(&mut SyncAccess(&mut self.backend),object))
+    }
+
+    /// Sign receipts for submissions with the given signer, promising inclusion in a published root within
+    /// the signer's maximum inclusion delay. See [receipt].
+    pub fn set_receipt_signer(&mut self,signer:ReceiptSigner) {
+        self.state.receipt_signer = Some(signer);
+    }
+
+    /// Like [BulletinBoard::submit_leaf], but returns a signed receipt for the leaf. See [receipt].
+    /// Fails with [BulletinBoardError::ReceiptsNotEnabled] if there is no receipt signer, in which case nothing is submitted.
+    pub fn submit_leaf_with_receipt(&mut self,data:&str) -> Result<SignedReceipt,BulletinBoardError> {
+        run_ready(self.state.submit_leaf_with_receipt(&mut SyncAccess(&mut self.backend),data))
+    }
+
+    /// Get a signed receipt for an existing leaf, e.g. a structured leaf or one whose receipt was lost.
+    /// The deadline is computed from the leaf's timestamp, so this gives the same receipt each time.
+    pub fn get_receipt(&self,leaf:HashValue) -> Result<SignedReceipt,BulletinBoardError> {
+        run_ready(self.state.get_receipt(&SyncAccess(&self.backend),leaf))
+    }
+
+    /// Get the evidence needed to check whether a receipt was honoured, with [receipt::check_receipt].
+    /// This does not need a receipt signer.
+    pub fn get_receipt_evidence(&self,receipt:&SubmissionReceipt) -> Result<ReceiptEvidence,BulletinBoardError> {
+        run_ready(get_receipt_evidence_using(&SyncAccess(&self.backend),receipt))
+    }
+
+    /// Find the leaves whose data is `data`, in the order they were added, for someone who has their original text
+    /// but not the leaf hash or timestamp. A censored leaf is found if `data` hashes to it, with its timestamp.
+    ///
+    /// This uses the backend's content index; see [content_index].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+    ///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+    /// let a = board.submit_leaf("a").unwrap();
+    /// board.submit_leaf("b").unwrap();
+    /// assert_eq!(board.find_leaves_by_data("a").unwrap(),vec![a]);
+    /// assert_eq!(board.find_leaves_by_data("c").unwrap(),vec![]);
+    /// board.censor_leaf(a,"Spam","Moderator").unwrap();
+    /// assert_eq!(board.find_leaves_by_data("a").unwrap(),vec![a]);
+    /// ```
+    pub fn find_leaves_by_data(&self,data:&str) -> Result<Vec<HashValue>,BulletinBoardError> {
+        run_ready(find_leaves_by_data_using(&SyncAccess(&self.backend),data))
     }
 
     /// Create a new bulletin board from a backend.
     pub fn new(backend:B) -> Result<Self,BulletinBoardError> {
-        let mut res = BulletinBoard { backend, current_forest : None };
-        res.reload_current_forest()?;
+        let mut res = BulletinBoard { backend, state : BoardState::default() };
+        run_ready(res.state.reload_current_forest(&SyncAccess(&res.backend)))?;
         Ok(res)
     }
 
-    /// Get a valid forest reference, or an error.
-    fn forest_or_err(&self) -> Result<&GrowingForest,BulletinBoardError> {
-        self.current_forest.as_ref().ok_or_else(||BulletinBoardError::CouldNotInitializeFromDatabase)
-    }
-
     /// Get the current published head that everyone knows. Everyone who is paying attention, that is. And who can remember 256 bits of gibberish.
     ///
     /// # Example
@@ -475,37 +1351,36 @@ impl <B:BulletinBoardBackend> BulletinBoard<B> {
     ///   // will be the tree formed from "A" and "B", not "B" itself.
     ///```
     pub fn get_parentless_unpublished_hash_values(&self) -> Result<Vec<HashValue>,BulletinBoardError> {
-        let mut currently_used : Vec<HashValue> = self.forest_or_err()?.get_subtrees();
-        if let Some(published_root) = self.backend.get_most_recent_published_root()? {
-            if let Some(HashInfo{source:HashSource::Root(history),..}) = self.backend.get_hash_info(published_root)? {
-                currently_used.retain(|h|!history.elements.contains(h)) // remove already published elements.
-            } else { return Err(BulletinBoardError::PublishedRootHasNoInfo) }
-        }
-        Ok(currently_used)
+        run_ready(self.state.get_parentless_unpublished_hash_values(&SyncAccess(&self.backend)))
     }
 
     /// Request a new published root. This will contain a reference to each tree in
     /// the current forest. That is, each leaf or branch node that doesn't have a parent.
     /// This will return an error if called twice in rapid succession (same timestamp) with nothing added in the meantime, as it would otherwise produce the same hash, and is almost certainly not what was intended anyway.
+    ///
+    /// If another writer sharing the same backend changed the board at the same time, the current state is reloaded
+    /// from the backend and the publication retried.
     pub fn order_new_published_root(&mut self) -> Result<HashValue,BulletinBoardError> {
-        let history = RootHashHistory { timestamp: bb_timestamp_now()?, elements: self.forest_or_err()?.get_subtrees(), prior : self.get_most_recent_published_root()? };
-        let new_hash = history.compute_hash();
-        match self.backend.get_hash_info(new_hash)? {
-            Some(HashInfo{source:HashSource::Root(other_history), .. }) if other_history==history => {
-                Err(BulletinBoardError::PublishingNewRootInstantlyAfterLastRoot)
-            }
-            Some(hash_collision) => {
-                println!("Time to enter the lottery! Actually you have probably won without entering. You have just done a submission and found a hash collision between {:?} and {:?}",&hash_collision,&history);
-                std::thread::sleep(Duration::from_secs(1)); // work around - wait a second and retry, with a new timestamp.
-                self.order_new_published_root()
-            }
-            _ =>  { // no hash collision, all is good. Should go here 99.99999999999999999999999999999..% of the time.
-                let mut transaction = DatabaseTransaction::default();
-                transaction.add_root_hash(new_hash,history);
-                self.backend.publish(&transaction)?;
-                Ok(new_hash)
-            }
-        }
+        run_ready(self.state.order_new_published_root(&mut SyncAccess(&mut self.backend)))
+    }
+
+    /// Like [BulletinBoard::order_new_published_root], except if nothing has been added since the last
+    /// published root, nothing is done and None is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+    ///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+    /// assert_eq!(board.order_new_published_root_if_changed().unwrap(),None);
+    /// board.submit_leaf("a").unwrap();
+    /// let root = board.order_new_published_root_if_changed().unwrap();
+    /// assert!(root.is_some());
+    /// assert_eq!(board.order_new_published_root_if_changed().unwrap(),None);
+    /// assert_eq!(board.get_all_published_roots().unwrap(),vec![root.unwrap()]);
+    /// ```
+    pub fn order_new_published_root_if_changed(&mut self) -> Result<Option<HashValue>,BulletinBoardError> {
+        run_ready(self.state.order_new_published_root_if_changed(&mut SyncAccess(&mut self.backend)))
     }
 
     /// Get information about a HashValue, assuming it exists.
@@ -522,12 +1397,12 @@ impl <B:BulletinBoardBackend> BulletinBoard<B> {
     /// let info = board.get_hash_info(hash).unwrap();
     /// assert_eq!(info.parent,None);
     /// match info.source {
-    ///         HashSource::Leaf(LeafHashHistory{data:Some(d),timestamp:_}) => assert_eq!(d,"A"),
+    ///         HashSource::Leaf(LeafHashHistory{data:Some(d),..}) => assert_eq!(d,"A"),
     ///         _ => panic!("Not a leaf"),
     /// }
     /// ```
     pub fn get_hash_info(&self, query:HashValue) -> Result<HashInfo,BulletinBoardError> {
-        self.backend.get_hash_info(query)?.ok_or_else(||BulletinBoardError::NoSuchHash)
+        self.backend.get_hash_info(query)?.ok_or(BulletinBoardError::NoSuchHash)
     }
 
 
@@ -564,40 +1439,170 @@ impl <B:BulletinBoardBackend> BulletinBoard<B> {
     /// assert_eq!(proof.chain[1].source,
     ///     HashSource::Branch(BranchHashHistory{left: hash_a,right: hash_b}));
     /// assert_eq!(verify_proof("a",root,&proof),None); // A thorough check.
+    /// assert_eq!(proof.leaf_index,Some(0)); // the first leaf.
+    /// assert_eq!(board.get_proof_chain(hash_b).unwrap().path_position(),Some(1)); // the right side of branch.
     /// ```
-   pub fn get_proof_chain(&self,query:HashValue) -> Result<FullProof,BulletinBoardError> {
-        let mut chain = vec![];
-        let mut node = query;
-        let mut published_root : Option<HashInfoWithHash> =  {
-            if let Ok(Some(published_root_hash)) = self.get_most_recent_published_root() {
-                if let Ok(node_info) = self.get_hash_info(published_root_hash) {
-                    Some(node_info.add_hash(published_root_hash))
-                } else { return Err(BulletinBoardError::ProofChainCorruptMissingPublishedNode(published_root_hash)); } // There is a break in the logic!!!
-            } else {None }
-        };
-        let published: HashSet<HashValue> = {
-            if let Some(info) = &published_root {
-                if let HashSource::Root(history) = &info.source {
-                    HashSet::from_iter(history.elements.iter().cloned())
-                } else { return Err(BulletinBoardError::PublishedRootIsNotARoot(node)); } // There is a break in the logic!!!
-            } else { HashSet::default() }
-        };
-        loop {
-            if let Ok(node_info) = self.get_hash_info(node) {
-                chain.push(node_info.add_hash(node));
-                if published.contains(&node) { break; }
-                match node_info.parent {
-                    Some(parent) => node=parent,
-                    None => {
-                        published_root=None; // got to the end of the line without finding something in the published root.
-                        break
-                    },
-                }
-            } else {
-                return Err(if query==node { BulletinBoardError::NoSuchHash } else { BulletinBoardError::ProofChainCorruptMissingPublishedNode(node)});
-            } // There is a break in the logic!!!
-        }
-        Ok(FullProof{ chain, published_root })
+    pub fn get_proof_chain(&self,query:HashValue) -> Result<FullProof,BulletinBoardError> {
+        run_ready(get_proof_chain(&SyncAccess(&self.backend),query))
+    }
+
+    /// Find when a leaf (or other node) was first publicly committed to : the first published root that includes it,
+    /// with a proof chain up to that root rather than the most recent one. The root, including its timestamp,
+    /// is the proof's `published_root`. Returns None if no published root includes it yet.
+    ///
+    /// As each published root includes everything in the prior one, this can be used as evidence that the
+    /// leaf existed by the time of that root, which is verified with [verifier::verify_proof] in the usual way.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::verifier::verify_proof;
+    ///
+    /// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+    ///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+    /// let hash_a = board.submit_leaf("a").unwrap();
+    /// assert!(board.first_including_root(hash_a).unwrap().is_none());
+    /// let first_root = board.order_new_published_root().unwrap();
+    /// board.submit_leaf("b").unwrap();
+    /// let second_root = board.order_new_published_root().unwrap();
+    /// let proof = board.first_including_root(hash_a).unwrap().unwrap();
+    /// assert_eq!(proof.published_root.as_ref().unwrap().hash,first_root);
+    /// assert_eq!(proof.chain.len(),1); // "a" was an element of the first root by itself.
+    /// assert_eq!(verify_proof("a",first_root,&proof),None);
+    /// assert_eq!(board.get_proof_chain(hash_a).unwrap().published_root.unwrap().hash,second_root);
+    /// ```
+    pub fn first_including_root(&self,query:HashValue) -> Result<Option<FullProof>,BulletinBoardError> {
+        run_ready(first_including_root_with_proof(&SyncAccess(&self.backend),query))
+    }
+
+    /// Get a proof that the leaf `earlier` was added before the leaf `later`, to be checked with [verifier::verify_ordering_proof].
+    /// Both leaves must be included in the most recent published root. Fails with [BulletinBoardError::LeavesNotInOrder]
+    /// if `later` was actually added first, or is the same leaf as `earlier`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::BulletinBoardError;
+    /// use merkle_tree_bulletin_board::verifier::verify_ordering_proof;
+    ///
+    /// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+    ///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+    /// let leaves : Vec<_> = ["a","b","c","d","e"].into_iter().map(|data|board.submit_leaf(data).unwrap()).collect();
+    /// assert_eq!(board.get_ordering_proof(leaves[1],leaves[2]).err(),Some(BulletinBoardError::NotYetPublished(leaves[1])));
+    /// let root = board.order_new_published_root().unwrap();
+    /// let proof = board.get_ordering_proof(leaves[1],leaves[4]).unwrap();
+    /// assert_eq!(verify_ordering_proof(root,leaves[1],leaves[4],&proof),None);
+    /// assert!(verify_ordering_proof(root,leaves[4],leaves[1],&proof).is_some());
+    /// assert_eq!(board.get_ordering_proof(leaves[3],leaves[2]).err(),Some(BulletinBoardError::LeavesNotInOrder));
+    /// ```
+    pub fn get_ordering_proof(&self,earlier:HashValue,later:HashValue) -> Result<OrderingProof,BulletinBoardError> {
+        run_ready(get_ordering_proof(&SyncAccess(&self.backend),earlier,later))
+    }
+
+    /// Get a proof that the published root `earlier` was published before, or is, the published root `later`, to be
+    /// checked with [verifier::verify_ancestry_proof]. Fails with [BulletinBoardError::RootsNotInOrder] if `later` was
+    /// actually published first.
+    ///
+    /// This follows prior references from `later`, which takes one step per publication in between, except where
+    /// roots have skip references (see [BulletinBoard::set_root_skip_list]), in which case it takes a logarithmic number of steps.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::BulletinBoardError;
+    /// use merkle_tree_bulletin_board::verifier::verify_ancestry_proof;
+    ///
+    /// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+    ///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+    /// board.set_root_skip_list(true);
+    /// let mut roots = vec![];
+    /// for i in 0..20 {
+    ///     board.submit_leaf(&i.to_string()).unwrap();
+    ///     roots.push(board.order_new_published_root().unwrap());
+    /// }
+    /// let proof = board.get_ancestry_proof(roots[2],roots[19]).unwrap();
+    /// assert_eq!(proof.chain.len(),3); // 19 -> 3 (16 back) -> 2 (1 back).
+    /// assert_eq!(verify_ancestry_proof(roots[2],roots[19],&proof),None);
+    /// assert!(verify_ancestry_proof(roots[1],roots[19],&proof).is_some());
+    /// assert_eq!(board.get_ancestry_proof(roots[19],roots[2]).err(),Some(BulletinBoardError::RootsNotInOrder));
+    /// ```
+    pub fn get_ancestry_proof(&self,earlier:HashValue,later:HashValue) -> Result<AncestryProof,BulletinBoardError> {
+        run_ready(get_ancestry_proof(&SyncAccess(&self.backend),earlier,later))
+    }
+
+    /// Get a proof of how many leaves a published root covers, to be checked with [verifier::verify_leaf_count].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::verifier::verify_leaf_count;
+    ///
+    /// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+    ///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+    /// for data in ["a","b","c","d","e","f"] { board.submit_leaf(data).unwrap(); }
+    /// let root = board.order_new_published_root().unwrap();
+    /// let proof = board.get_leaf_count_proof(root).unwrap();
+    /// assert_eq!(proof.paths.len(),2); // trees of 4 and 2 leaves.
+    /// assert_eq!(verify_leaf_count(root,&proof),Ok(6));
+    /// ```
+    pub fn get_leaf_count_proof(&self,published_root:HashValue) -> Result<LeafCountProof,BulletinBoardError> {
+        run_ready(get_leaf_count_proof(&SyncAccess(&self.backend),published_root))
+    }
+
+    /// Get the number of leaves in the board, including censored leaves and censorship records.
+    pub fn get_leaf_count(&self) -> Result<u64,BulletinBoardError> {
+        self.backend.get_leaf_count()
+    }
+
+    /// Get the leaf with a given sequence number, if there is one. Leaves are numbered from 0 in the order they were added.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+    ///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+    /// let leaves : Vec<_> = ["a","b","c"].into_iter().map(|data|board.submit_leaf(data).unwrap()).collect();
+    /// assert_eq!(board.get_leaf_count().unwrap(),3);
+    /// assert_eq!(board.get_leaf_by_index(1).unwrap(),Some(leaves[1]));
+    /// assert_eq!(board.get_leaf_by_index(3).unwrap(),None);
+    /// assert_eq!(board.list_leaves(1,10).unwrap(),leaves[1..]);
+    /// ```
+    pub fn get_leaf_by_index(&self,index:u64) -> Result<Option<HashValue>,BulletinBoardError> {
+        self.backend.get_leaf_by_index(index)
+    }
+
+    /// Get up to `count` leaves, in the order they were added, starting with the one with sequence number `start`.
+    /// See [BulletinBoard::get_leaf_by_index].
+    pub fn list_leaves(&self,start:u64,count:u64) -> Result<Vec<HashValue>,BulletinBoardError> {
+        self.backend.list_leaves(start,count)
+    }
+
+    /// Get the leaves submitted at or after time `t0` and before time `t1` (times in seconds since the epoch),
+    /// ordered by timestamp. Censored leaves are included.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::hash_history::{HashInfo, HashSource, RootHashHistory, LeafHashHistory};
+    /// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+    ///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+    /// let a = board.submit_leaf("a").unwrap();
+    /// let HashInfo{source:HashSource::Leaf(LeafHashHistory{timestamp,..}),..} = board.get_hash_info(a).unwrap() else { panic!() };
+    /// assert!(board.list_leaves_between(timestamp,timestamp+1).unwrap().contains(&a));
+    /// assert!(board.list_leaves_between(0,timestamp).unwrap().is_empty());
+    /// let root = board.order_new_published_root().unwrap();
+    /// let HashInfo{source:HashSource::Root(RootHashHistory{timestamp,..}),..} = board.get_hash_info(root).unwrap() else { panic!() };
+    /// assert_eq!(board.get_published_root_at(timestamp).unwrap(),Some(root));
+    /// assert_eq!(board.get_published_root_at(timestamp-1).unwrap(),None);
+    /// ```
+    pub fn list_leaves_between(&self,t0:Timestamp,t1:Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> {
+        self.backend.list_leaves_between(t0,t1)
+    }
+
+    /// Get the published root that was current at time `t` (in seconds since the epoch), that is, the most recent one
+    /// published at or before `t`, or None if none had been published by then. See [BulletinBoard::list_leaves_between] for an example.
+    pub fn get_published_root_at(&self,t:Timestamp) -> Result<Option<HashValue>,BulletinBoardError> {
+        self.backend.get_published_root_at(t)
     }
 
     /// Censor a leaf!
@@ -620,6 +1625,26 @@ impl <B:BulletinBoardBackend> BulletinBoard<B> {
     /// post published root, as that would invalidate the parent branch. The timestamp cannot
     /// however be verified unless you happen to know the uncensored text.
     ///
+    /// Before the leaf is censored, a [censorship::CensorshipRecord] containing the leaf's hash, the reason (or legal
+    /// basis) for the censorship, and the authority that ordered it, is added to the board as a new leaf, whose hash is returned.
+    /// The next published root will thus commit to the fact that the censorship happened. These records
+    /// can be listed with [BulletinBoard::get_censorship_records_between]. Censorship records can not themselves
+    /// be censored, failing with [BulletinBoardError::CanNotCensorCensorshipRecord], and a leaf already censored
+    /// fails with [BulletinBoardError::LeafAlreadyCensored] rather than adding a second record.
+    ///
+    /// How promptly the censored text is removed from storage depends on the backend. In particular
+    /// [backend_flatfile::BackendFlatfile] records censorship in a small tombstone file, and only removes the text from its main file
+    /// when [backend_flatfile::BackendFlatfile::compact] is called, which it does itself when the next root is published;
+    /// call it directly (via [BulletinBoard::backend]) to remove the text immediately. Likewise [backend_journal::BackendJournal]
+    /// rewrites the affected journal file when the next root is published.
+    ///
+    /// If the record is added but the backend then fails to censor the leaf, e.g. due to an IO error, the board is committed
+    /// to a censorship that did not happen, and this fails with [BulletinBoardError::CensorshipRecordedButNotDone] naming
+    /// the record. Calling this again will add a second record, and try the censorship again.
+    ///
+    /// Prior to the censorship records, this took just the leaf and returned `()`. Callers of the old form should supply
+    /// a reason and authority, and can ignore the returned hash.
+    ///
     /// # Example
     ///
     /// ```
@@ -632,20 +1657,443 @@ impl <B:BulletinBoardBackend> BulletinBoard<B> {
     /// let info = board.get_hash_info(hash).unwrap();
     /// assert_eq!(info.parent,None);
     /// match info.source {
-    ///         HashSource::Leaf(LeafHashHistory{data:Some(d),timestamp:_}) => assert_eq!(d,"A"),
+    ///         HashSource::Leaf(LeafHashHistory{data:Some(d),..}) => assert_eq!(d,"A"),
     ///         _ => panic!("Not an uncensored leaf"),
     /// }
     ///
-    /// board.censor_leaf(hash).unwrap();
-    /// // get censored leaf. Identical except data is missing.
+    /// let record = board.censor_leaf(hash,"Defamation","Court order 1234").unwrap();
+    /// // get censored leaf. Identical except data is missing. It now has a parent as the record was added after it.
     /// let info = board.get_hash_info(hash).unwrap();
-    /// assert_eq!(info.parent,None);
+    /// assert!(info.parent.is_some());
     /// match info.source {
-    ///         HashSource::Leaf(LeafHashHistory{data:None,timestamp:_}) => {}
+    ///         HashSource::Leaf(LeafHashHistory{data:None,..}) => {}
     ///         _ => panic!("Not a censored leaf"),
     /// }
+    /// // the record of the censorship is in the board.
+    /// let root = board.order_new_published_root().unwrap();
+    /// let events = board.get_censorship_records_between(None,root).unwrap();
+    /// assert_eq!(events.len(),1);
+    /// assert_eq!(events[0].record_leaf,record);
+    /// assert_eq!(events[0].record.censored_leaf,hash);
+    /// assert_eq!(events[0].record.reason,"Defamation");
+    /// assert_eq!(events[0].record.authority,"Court order 1234");
     /// ```
-    pub fn censor_leaf(&mut self,leaf_to_censor:HashValue) -> Result<(),BulletinBoardError> {
-        self.backend.censor_leaf(leaf_to_censor)
+    pub fn censor_leaf(&mut self,leaf_to_censor:HashValue,reason:&str,authority:&str) -> Result<HashValue,BulletinBoardError> {
+        run_ready(self.state.censor_leaf(&mut SyncAccess(&mut self.backend),leaf_to_censor,reason,authority))
+    }
+
+    /// Censor one field of a structured leaf (see [BulletinBoard::submit_structured_leaf]), leaving the other fields
+    /// visible. Like [BulletinBoard::censor_leaf], a record of the censorship is added to the board, and its hash returned.
+    ///
+    /// The backend stores the redacted text with [BulletinBoardBackend::uncensor_leaf], so this fails with
+    /// [BulletinBoardError::UncensoringNotSupported] (wrapped in [BulletinBoardError::CensorshipRecordedButNotDone])
+    /// for backends that don't support that.
+    pub fn redact_field(&mut self,leaf:HashValue,field:&str,reason:&str,authority:&str) -> Result<HashValue,BulletinBoardError> {
+        run_ready(self.state.redact_field(&mut SyncAccess(&mut self.backend),leaf,field,reason,authority))
+    }
+
+    /// Reverse a censorship, e.g. if a takedown is overturned, by supplying the original text of the leaf.
+    /// This also restores all redacted fields of a structured leaf.
+    ///
+    /// The text is checked by hashing it with the leaf's timestamp; it is only restored if this gives the leaf's hash.
+    ///
+    /// As with [BulletinBoard::censor_leaf], a [censorship::CensorshipRecord] (with `uncensored` set) containing the reason
+    /// and the authority that ordered the reversal is first added to the board as a new leaf, whose hash is returned.
+    /// Backends that can't restore data fail with [BulletinBoardError::UncensoringNotSupported], wrapped in
+    /// [BulletinBoardError::CensorshipRecordedButNotDone].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::hash_history::{HashSource, LeafHashHistory};
+    /// use merkle_tree_bulletin_board::BulletinBoardError;
+    ///
+    /// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+    ///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+    /// let hash = board.submit_leaf("A").unwrap();
+    /// board.censor_leaf(hash,"Defamation","Court order 1234").unwrap();
+    /// assert_eq!(board.uncensor_leaf(hash,"B","Appeal","Court order 1235"),Err(BulletinBoardError::UncensoredDataDoesNotMatchHash));
+    /// board.uncensor_leaf(hash,"A","Appeal","Court order 1235").unwrap();
+    /// match board.get_hash_info(hash).unwrap().source {
+    ///         HashSource::Leaf(LeafHashHistory{data:Some(d),..}) => assert_eq!(d,"A"),
+    ///         _ => panic!("Not an uncensored leaf"),
+    /// }
+    /// ```
+    pub fn uncensor_leaf(&mut self,leaf_to_uncensor:HashValue,data:&str,reason:&str,authority:&str) -> Result<HashValue,BulletinBoardError> {
+        run_ready(self.state.uncensor_leaf(&mut SyncAccess(&mut self.backend),leaf_to_uncensor,data,reason,authority))
+    }
+
+    /// Get all the records of censorship added after the published root `from` (or the beginning of time if None),
+    /// up to the published root `to`. See [censorship::get_censorship_records_between].
+    pub fn get_censorship_records_between(&self,from:Option<HashValue>,to:HashValue) -> Result<Vec<CensorshipEvent>,BulletinBoardError> {
+        get_censorship_records_between(&self.backend,from,to)
+    }
+}
+
+#[cfg(test)]
+impl <B:BulletinBoardBackend> BulletinBoard<B> {
+    /// Add a leaf with a given timestamp, which may be long ago, for testing things that depend on the age of leaves.
+    /// `structured` is true for a structured leaf, see [BulletinBoard::submit_structured_leaf].
+    pub(crate) fn add_leaf_at(&mut self,data:&str,timestamp:Timestamp,structured:bool) -> HashValue {
+        let history = LeafHashHistory{ timestamp, data: Some(data.to_string()), structured };
+        let hash = history.compute_hash().unwrap();
+        let mut transaction = DatabaseTransaction::default();
+        transaction.add_leaf_hash(hash,history);
+        self.state.current_forest.as_mut().unwrap().add_leaf(hash,&self.backend,&mut transaction).unwrap();
+        self.backend.publish(&transaction).unwrap();
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::backend_memory::BackendMemory;
+    use crate::{BulletinBoard, BulletinBoardBackend, BulletinBoardError, DatabaseTransaction, make_ordering_proof};
+    use crate::hash::HashValue;
+    use crate::hash_history::{HashInfo, HashSource, BranchHashHistory, LeafHashHistory, OrderingProof};
+    use crate::censorship::{CENSORSHIP_RECORD_PREFIX, CensorshipRecord};
+    use crate::structured_leaf::{STRUCTURED_LEAF_PREFIX, StructuredLeaf};
+    use crate::verifier::{verify_ancestry_proof, verify_leaf_count, verify_ordering_proof, verify_proof};
+
+    /// A backend that can be shared between two bulletin boards, to simulate multiple processes using the same database.
+    /// Uses the default publish_if_unchanged.
+    #[derive(Clone,Default)]
+    struct SharedMemory(Rc<RefCell<BackendMemory>>);
+
+    impl BulletinBoardBackend for SharedMemory {
+        fn get_all_published_roots(&self) -> Result<Vec<HashValue>, BulletinBoardError> { self.0.borrow().get_all_published_roots() }
+        fn get_most_recent_published_root(&self) -> Result<Option<HashValue>, BulletinBoardError> { self.0.borrow().get_most_recent_published_root() }
+        fn get_all_leaves_and_branches_without_a_parent(&self) -> Result<Vec<HashValue>, BulletinBoardError> { self.0.borrow().get_all_leaves_and_branches_without_a_parent() }
+        fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>, BulletinBoardError> { self.0.borrow().get_hash_info(query) }
+        fn publish(&mut self, transaction: &DatabaseTransaction) -> Result<(), BulletinBoardError> { self.0.borrow_mut().publish(transaction) }
+        fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(), BulletinBoardError> { self.0.borrow_mut().censor_leaf(leaf_to_censor) }
+        fn uncensor_leaf(&mut self, leaf_to_uncensor: HashValue, data: &str) -> Result<(), BulletinBoardError> { self.0.borrow_mut().uncensor_leaf(leaf_to_uncensor,data) }
+    }
+
+    #[test]
+    /// Test that two boards writing to the same backend build a single consistent tree.
+    fn test_two_writers() {
+        let backend = SharedMemory::default();
+        let mut board1 = BulletinBoard::new(backend.clone()).unwrap();
+        let mut board2 = BulletinBoard::new(backend.clone()).unwrap();
+        let hash_a = board1.submit_leaf("a").unwrap();
+        let hash_b = board2.submit_leaf("b").unwrap(); // board2 does not know about "a" until it tries to write.
+        let branch_ab = board2.get_hash_info(hash_a).unwrap().parent.unwrap();
+        assert_eq!(board2.get_hash_info(branch_ab).unwrap().source,HashSource::Branch(BranchHashHistory{left:hash_a,right:hash_b}));
+        assert_eq!(backend.get_all_leaves_and_branches_without_a_parent().unwrap(),vec![branch_ab]);
+        let root1 = board2.order_new_published_root().unwrap();
+        board1.submit_leaf("c").unwrap(); // board1 does not know about "b".
+        let root2 = board1.order_new_published_root().unwrap();
+        match board1.get_hash_info(root2).unwrap().source {
+            HashSource::Root(history) => {
+                assert_eq!(history.prior,Some(root1));
+                assert_eq!(history.elements.len(),2);
+                assert_eq!(history.elements[0],branch_ab);
+            }
+            _ => panic!("Not a root"),
+        }
+        // board2 does not know about "c" or root2
+        let root3 = board2.order_new_published_root().unwrap();
+        match board2.get_hash_info(root3).unwrap().source {
+            HashSource::Root(history) => assert_eq!(history.prior,Some(root2)),
+            _ => panic!("Not a root"),
+        }
+    }
+
+    #[test]
+    /// Test that stored leaf sequence numbers agree with those derived from the trees, and with proofs.
+    fn test_leaf_sequence_numbers() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let mut leaves = vec![];
+        for i in 0..13 {
+            leaves.push(board.submit_leaf(&i.to_string()).unwrap());
+            if i%5==2 { board.order_new_published_root().unwrap(); }
+        }
+        let backend = &board.backend;
+        assert_eq!(backend.get_leaf_count().unwrap(),13);
+        assert_eq!(backend.compute_current_forest().unwrap().leaf_count(),13);
+        for (index,leaf) in leaves.iter().enumerate() {
+            let index = index as u64;
+            assert_eq!(backend.get_leaf_by_index(index).unwrap(),Some(*leaf));
+            assert_eq!(backend.leaf_by_index_from_tree(index).unwrap(),Some(*leaf));
+            assert_eq!(backend.get_leaf_index(*leaf).unwrap(),Some(index));
+            assert_eq!(backend.leaf_index_from_tree(*leaf).unwrap(),Some(index));
+            let proof = board.get_proof_chain(*leaf).unwrap();
+            assert_eq!(proof.leaf_index,Some(index));
+            assert_eq!(verify_proof(&index.to_string(),proof.published_root.as_ref().unwrap().hash,&proof),None);
+        }
+        assert_eq!(backend.leaf_by_index_from_tree(13).unwrap(),None);
+        let branch = board.get_hash_info(leaves[0]).unwrap().parent.unwrap();
+        assert_eq!(backend.leaf_index_from_tree(branch).unwrap(),None);
+        let mut proof = board.get_proof_chain(leaves[5]).unwrap();
+        proof.leaf_index = Some(4);
+        assert!(verify_proof("5",proof.published_root.as_ref().unwrap().hash,&proof).is_some());
+    }
+
+    #[test]
+    /// Test that the timestamp indexes in BackendMemory agree with the default implementations that look at everything.
+    fn test_time_range_queries() {
+        let backend = SharedMemory::default();
+        let mut board = BulletinBoard::new(backend.clone()).unwrap();
+        let mut leaves = vec![];
+        for i in 0..6 {
+            if i==3 { std::thread::sleep(std::time::Duration::from_secs(1)); }
+            leaves.push(board.submit_leaf(&i.to_string()).unwrap());
+            if i%2==1 { board.order_new_published_root().unwrap(); }
+        }
+        let timestamp = |leaf| match backend.get_hash_info(leaf).unwrap() { Some(HashInfo{source:HashSource::Leaf(history),..}) => history.timestamp, _ => panic!("Not a leaf") };
+        let (first,last) = (timestamp(leaves[0]),timestamp(leaves[5]));
+        assert!(last>first);
+        assert_eq!(backend.list_leaves_between(first,last+1).unwrap(),leaves);
+        let memory = backend.0.borrow();
+        for t in first-1..=last+1 {
+            assert_eq!(memory.list_leaves_between(t,t+1).unwrap(),backend.list_leaves_between(t,t+1).unwrap());
+            assert_eq!(memory.list_leaves_between(0,t).unwrap(),backend.list_leaves_between(0,t).unwrap());
+            assert_eq!(memory.get_published_root_at(t).unwrap(),backend.get_published_root_at(t).unwrap());
+        }
+        assert_eq!(memory.get_published_root_at(first-1).unwrap(),None);
+        assert_eq!(memory.get_published_root_at(last+1).unwrap(),backend.get_most_recent_published_root().unwrap());
+        assert!(memory.list_leaves_between(last,first).unwrap().is_empty());
+    }
+
+    #[test]
+    /// Test ancestry proofs between all pairs of roots, when root skip references are turned on part way through.
+    fn test_ancestry_proofs() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let mut roots = vec![];
+        for i in 0..40 {
+            if i==5 { board.set_root_skip_list(true); }
+            board.submit_leaf(&i.to_string()).unwrap();
+            roots.push(board.order_new_published_root().unwrap());
+        }
+        for (i,root) in roots.iter().enumerate() {
+            match board.get_hash_info(*root).unwrap().source {
+                HashSource::Root(history) => {
+                    assert_eq!(history.skips.is_empty(),i<5,"root {}",i);
+                    for (level,skip) in history.skips.iter().enumerate() { assert_eq!(*skip,roots[i-(2<<level)]); }
+                }
+                _ => panic!("Not a root"),
+            }
+        }
+        for earlier in 0..roots.len() {
+            for later in earlier..roots.len() {
+                let proof = board.get_ancestry_proof(roots[earlier],roots[later]).unwrap();
+                assert_eq!(verify_ancestry_proof(roots[earlier],roots[later],&proof),None);
+                if earlier>=5 { assert!(proof.chain.len()<=2*(later-earlier).max(1).ilog2() as usize+2,"{} to {} took {}",earlier,later,proof.chain.len()); }
+                if earlier<later {
+                    assert_eq!(board.get_ancestry_proof(roots[later],roots[earlier]).err(),Some(BulletinBoardError::RootsNotInOrder));
+                    assert!(verify_ancestry_proof(roots[later],roots[earlier],&proof).is_some());
+                }
+            }
+        }
+        let mut proof = board.get_ancestry_proof(roots[10],roots[39]).unwrap();
+        proof.chain.remove(1);
+        assert!(verify_ancestry_proof(roots[10],roots[39],&proof).is_some());
+    }
+
+    #[test]
+    /// Test that leaf count proofs go around censored leaves, and that shortened paths are rejected.
+    fn test_leaf_count_proof() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let a = board.submit_leaf("a").unwrap();
+        for data in ["b","c","d","e"] { board.submit_leaf(data).unwrap(); }
+        board.censor_leaf(a,"Spam","Moderator").unwrap(); // adds a sixth leaf, the censorship record.
+        let root = board.order_new_published_root().unwrap();
+        let proof = board.get_leaf_count_proof(root).unwrap();
+        assert_eq!(verify_leaf_count(root,&proof),Ok(6));
+        assert_eq!(proof.paths[0].len(),3);
+        assert!(matches!(&proof.paths[0][2].source,HashSource::Leaf(LeafHashHistory{data:Some(data),..}) if data=="b")); // went right to avoid "a".
+        let mut shortened = proof.clone();
+        shortened.paths[0].remove(1);
+        assert!(verify_leaf_count(root,&shortened).is_err());
+        let mut swapped = proof.clone();
+        swapped.paths.swap(0,1);
+        assert!(verify_leaf_count(root,&swapped).is_err());
+        assert_eq!(board.get_leaf_count_proof(a).err(),Some(BulletinBoardError::PublishedRootIsNotARoot(a)));
+    }
+
+    #[test]
+    /// Test that censorship records can't be forged by ordinary submissions, and are listed between the right roots.
+    fn test_censorship_records() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let hash_a = board.submit_leaf("a").unwrap();
+        let forged = CensorshipRecord{ censored_leaf: hash_a, reason: "forged".to_string(), authority: "nobody".to_string(), field: None, uncensored: false };
+        assert_eq!(board.submit_leaf(&forged.to_leaf_data()),Err(BulletinBoardError::DataUsesReservedPrefix(CENSORSHIP_RECORD_PREFIX.to_string())));
+        let root1 = board.order_new_published_root().unwrap();
+        let record = board.censor_leaf(hash_a,"reason","authority").unwrap();
+        assert_eq!(board.censor_leaf(record,"reason","authority"),Err(BulletinBoardError::CanNotCensorCensorshipRecord)); // the audit trail can't be erased.
+        let root2 = board.order_new_published_root().unwrap();
+        assert!(board.get_censorship_records_between(None,root1).unwrap().is_empty());
+        let events = board.get_censorship_records_between(Some(root1),root2).unwrap();
+        assert_eq!(events.len(),1);
+        assert_eq!(events[0].record_leaf,record);
+        assert_eq!(events[0].record.censored_leaf,hash_a);
+        assert_eq!(board.get_censorship_records_between(None,root2).unwrap(),events);
+    }
+
+    /// A backend that fails to censor leaves, as if it had an IO error.
+    #[derive(Default)]
+    struct CensorshipFails(BackendMemory);
+
+    impl BulletinBoardBackend for CensorshipFails {
+        fn get_all_published_roots(&self) -> Result<Vec<HashValue>, BulletinBoardError> { self.0.get_all_published_roots() }
+        fn get_most_recent_published_root(&self) -> Result<Option<HashValue>, BulletinBoardError> { self.0.get_most_recent_published_root() }
+        fn get_all_leaves_and_branches_without_a_parent(&self) -> Result<Vec<HashValue>, BulletinBoardError> { self.0.get_all_leaves_and_branches_without_a_parent() }
+        fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>, BulletinBoardError> { self.0.get_hash_info(query) }
+        fn publish(&mut self, transaction: &DatabaseTransaction) -> Result<(), BulletinBoardError> { self.0.publish(transaction) }
+        fn censor_leaf(&mut self, _leaf_to_censor: HashValue) -> Result<(), BulletinBoardError> { Err(BulletinBoardError::BackendIOError("disk full".to_string())) }
+    }
+
+    #[test]
+    /// Test that censorship records and censored leaves can not be censored, and that a failed censorship names the record already added.
+    fn test_censorship_failures() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let leaf = board.submit_leaf("a").unwrap();
+        let record = board.censor_leaf(leaf,"Spam","Moderator").unwrap();
+        assert_eq!(board.censor_leaf(leaf,"Spam","Moderator"),Err(BulletinBoardError::LeafAlreadyCensored));
+        assert_eq!(board.censor_leaf(record,"Embarrassing","Moderator"),Err(BulletinBoardError::CanNotCensorCensorshipRecord));
+        assert!(matches!(board.get_hash_info(record).unwrap().source,HashSource::Leaf(LeafHashHistory{data:Some(_),..})));
+        assert_eq!(board.get_parentless_unpublished_hash_values().unwrap().len(),1); // no extra records were added by the failures.
+        let mut board = BulletinBoard::new(CensorshipFails::default()).unwrap();
+        let leaf = board.submit_leaf("a").unwrap();
+        let Err(BulletinBoardError::CensorshipRecordedButNotDone(record,_)) = board.censor_leaf(leaf,"Spam","Moderator") else { panic!("censorship should have failed") };
+        let root = board.order_new_published_root().unwrap();
+        assert_eq!(board.get_censorship_records_between(None,root).unwrap()[0].record_leaf,record);
+        assert!(matches!(board.get_hash_info(leaf).unwrap().source,HashSource::Leaf(LeafHashHistory{data:Some(_),..})));
+    }
+
+    #[test]
+    /// Test that uncensoring is recorded like censorship, and that backends fail to uncensor by default.
+    fn test_uncensor_records() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let leaf = board.submit_leaf("a").unwrap();
+        board.censor_leaf(leaf,"Spam","Moderator").unwrap();
+        let record = board.uncensor_leaf(leaf,"a","Not spam","Appeals panel").unwrap();
+        let root = board.order_new_published_root().unwrap();
+        let events = board.get_censorship_records_between(None,root).unwrap();
+        assert_eq!(events.len(),2);
+        assert_eq!(events[1].record_leaf,record);
+        assert_eq!(events[1].record,CensorshipRecord{ censored_leaf: leaf, reason: "Not spam".to_string(), authority: "Appeals panel".to_string(), field: None, uncensored: true });
+        assert!(!events[0].record.uncensored);
+        assert_eq!(CensorshipFails::default().uncensor_leaf(leaf,"a"),Err(BulletinBoardError::UncensoringNotSupported));
+    }
+
+    #[test]
+    /// Test redacting fields of structured leaves, and that plain submissions can't pretend to be structured.
+    fn test_structured_leaf_redaction() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let object = serde_json::json!({"a":1,"b":"two","c":[3]});
+        let hash = board.submit_structured_leaf(object.as_object().unwrap()).unwrap();
+        let (timestamp,original) = match board.get_hash_info(hash).unwrap().source {
+            HashSource::Leaf(LeafHashHistory{timestamp,data:Some(data),structured:true}) => (timestamp,data),
+            _ => panic!("Not a leaf"),
+        };
+        assert_eq!(board.submit_leaf(&original),Err(BulletinBoardError::DataUsesReservedPrefix(STRUCTURED_LEAF_PREFIX.to_string())));
+        assert_eq!(board.uncensor_leaf(hash,&original,"reason","authority"),Err(BulletinBoardError::LeafIsNotCensored));
+        board.redact_field(hash,"b","reason","authority").unwrap();
+        assert_eq!(board.redact_field(hash,"b","reason","authority"),Err(BulletinBoardError::NoSuchVisibleField("b".to_string())));
+        board.redact_field(hash,"c","reason","authority").unwrap();
+        let root = board.order_new_published_root().unwrap();
+        let proof = board.get_proof_chain(hash).unwrap();
+        let redacted = match &proof.chain[0].source {
+            HashSource::Leaf(LeafHashHistory{data:Some(data),..}) => StructuredLeaf::from_leaf_data(data).unwrap(),
+            _ => panic!("Not a leaf"),
+        };
+        assert_eq!(redacted.visible_value("a"),Some("1"));
+        assert_eq!(redacted.visible_value("b"),None);
+        assert_eq!(verify_proof(&original,root,&proof),None);
+        assert_eq!(verify_proof(&redacted.to_leaf_data(),root,&proof),None);
+        assert!(verify_proof(&StructuredLeaf::new(object.as_object().unwrap()).unwrap().to_leaf_data(),root,&proof).is_some()); // different salts.
+        let events = board.get_censorship_records_between(None,root).unwrap();
+        assert_eq!(events.iter().map(|e|e.record.field.clone()).collect::<Vec<_>>(),vec![Some("b".to_string()),Some("c".to_string())]);
+        assert_eq!(board.redact_field(events[0].record_leaf,"b","reason","authority"),Err(BulletinBoardError::NotAStructuredLeaf));
+        board.uncensor_leaf(hash,&original,"reason","authority").unwrap();
+        assert_eq!(board.get_hash_info(hash).unwrap().source,HashSource::Leaf(LeafHashHistory{timestamp,data:Some(original),structured:true}));
+    }
+
+    #[test]
+    /// Test that a plain leaf whose text starts with the structured leaf prefix, e.g. from before structured leaves existed,
+    /// is still hashed and treated as a plain leaf.
+    fn test_plain_leaf_with_structured_prefix() {
+        let object = serde_json::json!({"a":1});
+        let data = StructuredLeaf::new(object.as_object().unwrap()).unwrap().to_leaf_data();
+        let history = LeafHashHistory{ timestamp: 1000, data: Some(data.clone()), structured: false };
+        let leaf = history.compute_hash().unwrap();
+        assert_ne!(Some(leaf),LeafHashHistory{ structured: true, ..history.clone() }.compute_hash());
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        assert_eq!(board.add_leaf_at(&data,1000,false),leaf);
+        let root = board.order_new_published_root().unwrap();
+        assert_eq!(verify_proof(&data,root,&board.get_proof_chain(leaf).unwrap()),None);
+        assert_eq!(board.redact_field(leaf,"a","reason","authority"),Err(BulletinBoardError::NotAStructuredLeaf));
+        assert_eq!(board.get_hash_info(leaf).unwrap().source,HashSource::Leaf(history)); // nothing was recorded.
+        board.censor_leaf(leaf,"reason","authority").unwrap();
+        let root = board.order_new_published_root().unwrap();
+        assert_eq!(verify_proof(&data,root,&board.get_proof_chain(leaf).unwrap()),None);
+        board.uncensor_leaf(leaf,&data,"reason","authority").unwrap();
+    }
+
+    #[test]
+    /// Test making and verifying ordering proofs, including the ways they can fail.
+    fn test_ordering_proofs() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let leaves : Vec<HashValue> = ["a","b","c","d","e"].into_iter().map(|data|board.submit_leaf(data).unwrap()).collect();
+        let root = board.order_new_published_root().unwrap();
+        // leaves 0-3 are in one tree, leaf 4 is its own element of the published root.
+        assert_eq!(board.get_ordering_proof(leaves[2],leaves[2]).err(),Some(BulletinBoardError::LeavesNotInOrder));
+        assert_eq!(board.get_ordering_proof(leaves[2],leaves[1]).err(),Some(BulletinBoardError::LeavesNotInOrder));
+        assert_eq!(board.get_ordering_proof(leaves[4],leaves[0]).err(),Some(BulletinBoardError::LeavesNotInOrder));
+        let same_tree = board.get_ordering_proof(leaves[1],leaves[2]).unwrap();
+        assert_eq!(verify_ordering_proof(root,leaves[1],leaves[2],&same_tree),None);
+        let different_trees = board.get_ordering_proof(leaves[0],leaves[4]).unwrap();
+        assert_eq!(verify_ordering_proof(root,leaves[0],leaves[4],&different_trees),None);
+        // tampering
+        assert!(verify_ordering_proof(root,leaves[2],leaves[1],&same_tree).is_some());
+        let swapped = OrderingProof{ earlier: same_tree.later.clone(), later: same_tree.earlier.clone() };
+        assert!(verify_ordering_proof(root,leaves[2],leaves[1],&swapped).is_some());
+        let mut flipped = same_tree.clone(); // claim the later leaf is on the left of its parent.
+        if let HashSource::Branch(history) = &mut flipped.later.chain[1].source { std::mem::swap(&mut history.left,&mut history.right); } else { panic!("Expecting a branch"); }
+        assert!(verify_ordering_proof(root,leaves[1],leaves[2],&flipped).is_some());
+        let mut wrong_root = different_trees.clone();
+        wrong_root.later.published_root = None;
+        assert!(verify_ordering_proof(root,leaves[0],leaves[4],&wrong_root).is_some());
+        // proofs for different published roots.
+        let earlier = board.get_proof_chain(leaves[0]).unwrap();
+        board.submit_leaf("f").unwrap();
+        board.order_new_published_root().unwrap();
+        let later = board.get_proof_chain(leaves[4]).unwrap();
+        assert_eq!(make_ordering_proof(earlier,later).err(),Some(BulletinBoardError::ProofsForDifferentRoots));
+    }
+
+    #[test]
+    /// Test finding the first published root including a leaf or branch, with a proof chain to it.
+    fn test_first_including_root() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let mut roots = vec![];
+        let a = board.submit_leaf("a").unwrap();
+        roots.push(board.order_new_published_root().unwrap());
+        let b = board.submit_leaf("b").unwrap();
+        roots.push(board.order_new_published_root().unwrap());
+        let c = board.submit_leaf("c").unwrap();
+        roots.push(board.order_new_published_root().unwrap());
+        board.submit_leaf("d").unwrap();
+        let e = board.submit_leaf("e").unwrap();
+        roots.push(board.order_new_published_root().unwrap());
+        let f = board.submit_leaf("f").unwrap();
+        for (data,leaf,root) in [("a",a,roots[0]),("b",b,roots[1]),("c",c,roots[2]),("e",e,roots[3])] {
+            let proof = board.first_including_root(leaf).unwrap().unwrap();
+            assert_eq!(proof.published_root.as_ref().unwrap().hash,root,"{}",data);
+            assert_eq!(verify_proof(data,root,&proof),None,"{}",data);
+        }
+        assert!(board.first_including_root(f).unwrap().is_none());
+        let branch_ab = board.get_hash_info(a).unwrap().parent.unwrap();
+        let branch_abcd = board.get_hash_info(branch_ab).unwrap().parent.unwrap();
+        assert_eq!(board.first_including_root(branch_ab).unwrap().unwrap().published_root.unwrap().hash,roots[1]);
+        let proof = board.first_including_root(branch_abcd).unwrap().unwrap();
+        assert_eq!(proof.chain[0].hash,branch_abcd);
+        assert_eq!(proof.published_root.unwrap().hash,roots[3]);
+        assert!(board.first_including_root(roots[1]).unwrap().is_none()); // a published root is not an element of any root.
+        assert_eq!(board.first_including_root(HashValue([0;32])).err(),Some(BulletinBoardError::NoSuchHash));
     }
 }
@@ -0,0 +1,352 @@
+//! Signed receipts for submissions, promising inclusion in a published root by a deadline.
+//!
+//! The hash returned by [crate::BulletinBoard::submit_leaf] is only an unsigned promise. If the board has a
+//! [ReceiptSigner] (see [crate::BulletinBoard::set_receipt_signer]), it can instead give a [SignedReceipt] for a leaf,
+//! containing the leaf hash, the leaf's timestamp, and a deadline by which the leaf will be included in a published root.
+//! The receipt is signed with ed25519; the message signed is
+//!
+//! [RECEIPT_DOMAIN]|leaf hash|timestamp(bigendian 64 bits)|deadline(bigendian 64 bits)
+//!
+//! The board operator must publish roots often enough to keep this promise, e.g. with a
+//! [crate::scheduler::PublicationSchedule] whose interval is comfortably less than the maximum inclusion delay.
+//!
+//! Later, anyone holding a receipt can get a [ReceiptEvidence] from the board, and check it with [check_receipt].
+//! This either confirms a root published by the deadline includes the leaf, or produces a [MisbehaviourProof]
+//! that can be given to anyone who knows the board's public key and published roots, and checked
+//! with [verify_misbehaviour_proof].
+//!
+//! A leaf is included in a root if one of the root's elements is the leaf or one of its ancestors. As nodes in the tree
+//! never change parents, a proof chain from the leaf to any later root contains every ancestor the leaf has ever had.
+//! So showing that the last root published by the deadline contains none of them shows that root does not include the leaf.
+//!
+//! If the board never includes the leaf in any root, it cannot produce a proof chain to a root, so no portable
+//! misbehaviour proof can be made. The signed receipt, and the board's inability to give an inclusion proof, are then the evidence.
+
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Serialize,Deserialize};
+use crate::{get_proof_chain, BulletinBoardError};
+use crate::backend_access::ReadAccess;
+use crate::hash::HashValue;
+use crate::hash_history::{FullProof, HashInfo, HashInfoWithHash, HashSource, RootHashHistory, Timestamp};
+use crate::verifier::{verify_branches, verify_published_root_in_proof};
+
+/// The start of the message signed for a receipt, so that the signature can't be confused with anything else signed by the same key.
+pub const RECEIPT_DOMAIN : &[u8] = b"bulletin-board-receipt:";
+
+/// Implement hex string display and (de)serialization for a wrapper around a fixed length array of bytes.
+macro_rules! hex_bytes {
+    ($name:ident,$len:expr) => {
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { write!(f, "{}",&hex::encode(self.0)) }
+        }
+        impl Debug for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { write!(f, "{}",&hex::encode(self.0)) }
+        }
+        impl TryFrom<String> for $name {
+            type Error = String;
+            fn try_from(value: String) -> Result<Self, Self::Error> {
+                let mut res = [0;$len];
+                hex::decode_to_slice(&value,&mut res).map_err(|_|format!("Expecting {} hexadecimal characters",2*$len))?;
+                Ok($name(res))
+            }
+        }
+        impl From<$name> for String {
+            fn from(value: $name) -> Self { value.to_string() }
+        }
+    };
+}
+
+/// An ed25519 public key used to check receipts, serialized as a hex string.
+#[derive(Clone,Copy,Eq,PartialEq,Serialize,Deserialize)]
+#[serde(try_from="String",into="String")]
+pub struct ReceiptPublicKey(pub [u8;32]);
+hex_bytes!(ReceiptPublicKey,32);
+
+/// An ed25519 signature on a receipt, serialized as a hex string.
+#[derive(Clone,Copy,Eq,PartialEq,Serialize,Deserialize)]
+#[serde(try_from="String",into="String")]
+pub struct ReceiptSignature(pub [u8;64]);
+hex_bytes!(ReceiptSignature,64);
+
+/// What the board promises about a leaf.
+#[derive(Debug,Clone,Copy,Serialize,Deserialize,Eq,PartialEq)]
+pub struct SubmissionReceipt {
+    /// The hash of the leaf.
+    pub leaf : HashValue,
+    /// The timestamp of the leaf.
+    pub timestamp : Timestamp,
+    /// The leaf will be included in a root published at or before this time.
+    pub deadline : Timestamp,
+}
+
+impl SubmissionReceipt {
+    /// The message that is signed, [RECEIPT_DOMAIN]|leaf|timestamp|deadline.
+    pub fn signed_message(&self) -> Vec<u8> {
+        let mut res = RECEIPT_DOMAIN.to_vec();
+        res.extend_from_slice(&self.leaf.0);
+        res.extend_from_slice(&self.timestamp.to_be_bytes());
+        res.extend_from_slice(&self.deadline.to_be_bytes());
+        res
+    }
+}
+
+/// A [SubmissionReceipt] signed by the board.
+#[derive(Debug,Clone,Copy,Serialize,Deserialize,Eq,PartialEq)]
+pub struct SignedReceipt {
+    pub receipt : SubmissionReceipt,
+    pub signature : ReceiptSignature,
+}
+
+impl SignedReceipt {
+    /// Check the signature. Returns None if OK, otherwise a description of the problem.
+    pub fn verify_signature(&self,public_key:&ReceiptPublicKey) -> Option<String> {
+        let key = match VerifyingKey::from_bytes(&public_key.0) {
+            Ok(key) => key,
+            Err(_) => return Some("The public key is not a valid ed25519 key".to_string()),
+        };
+        match key.verify(&self.receipt.signed_message(),&Signature::from_bytes(&self.signature.0)) {
+            Ok(()) => None,
+            Err(_) => Some("The receipt signature is not valid".to_string()),
+        }
+    }
+}
+
+/// The key used by a board to sign receipts, and the promised maximum delay between a leaf's timestamp and
+/// its inclusion in a published root.
+pub struct ReceiptSigner {
+    key : SigningKey,
+    /// The deadline for a leaf is its timestamp plus this many seconds.
+    pub max_inclusion_delay : Timestamp,
+}
+
+impl ReceiptSigner {
+    /// Make a signer from an ed25519 secret key.
+    pub fn new(secret_key:[u8;32],max_inclusion_delay:Timestamp) -> Self {
+        ReceiptSigner{ key: SigningKey::from_bytes(&secret_key), max_inclusion_delay }
+    }
+
+    /// Make a signer with a new random secret key. Save [ReceiptSigner::secret_key] to keep using the same key.
+    pub fn generate(max_inclusion_delay:Timestamp) -> Result<Self,BulletinBoardError> {
+        let mut secret_key = [0u8;32];
+        getrandom::getrandom(&mut secret_key).map_err(|_|BulletinBoardError::RandomNumberGeneratorUnavailable)?;
+        Ok(Self::new(secret_key,max_inclusion_delay))
+    }
+
+    /// The secret key. Keep this secret.
+    pub fn secret_key(&self) -> [u8;32] { self.key.to_bytes() }
+
+    /// The public key, which should be published so that people can check receipts.
+    pub fn public_key(&self) -> ReceiptPublicKey { ReceiptPublicKey(self.key.verifying_key().to_bytes()) }
+
+    /// Sign a receipt for a leaf with the given timestamp. Signatures are deterministic, so
+    /// signing the same leaf again gives the same receipt.
+    pub fn sign(&self,leaf:HashValue,timestamp:Timestamp) -> SignedReceipt {
+        let receipt = SubmissionReceipt{ leaf, timestamp, deadline: timestamp.saturating_add(self.max_inclusion_delay) };
+        SignedReceipt{ receipt, signature: ReceiptSignature(self.key.sign(&receipt.signed_message()).to_bytes()) }
+    }
+
+    /// Sign a receipt for a leaf, given the leaf's information.
+    pub(crate) fn sign_leaf(&self,leaf:HashValue,info:Option<HashInfo>) -> Result<SignedReceipt,BulletinBoardError> {
+        match info {
+            Some(HashInfo{source:HashSource::Leaf(history),..}) => Ok(self.sign(leaf,history.timestamp)),
+            Some(_) => Err(BulletinBoardError::NotALeaf),
+            None => Err(BulletinBoardError::NoSuchHash),
+        }
+    }
+}
+
+/// What the board says about whether a receipt was honoured. See [check_receipt].
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct ReceiptEvidence {
+    /// A proof chain for the leaf, to the most recently published root.
+    pub proof : FullProof,
+    /// The last root published at or before the receipt's deadline, if any.
+    pub last_root_by_deadline : Option<HashInfoWithHash>,
+    /// The first root published after the receipt's deadline, if any.
+    pub first_root_after_deadline : Option<HashInfoWithHash>,
+}
+
+/// Portable proof that a board did not include a leaf in a published root by the deadline it signed.
+/// Check with [verify_misbehaviour_proof].
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct MisbehaviourProof {
+    pub receipt : SignedReceipt,
+    /// The key the board signs receipts with.
+    pub public_key : ReceiptPublicKey,
+    pub evidence : ReceiptEvidence,
+}
+
+/// The result of [check_receipt].
+#[derive(Debug,Clone)]
+pub enum ReceiptCheck {
+    /// The given root, published at or before the deadline, includes the leaf.
+    Honoured { root : HashValue },
+    /// The leaf is not yet shown to be included by the deadline, but nor can it be proven that it wasn't,
+    /// e.g. because the deadline has not passed, or no root has been published since.
+    Undetermined,
+    /// The board broke its promise, and here is the proof.
+    Broken(Box<MisbehaviourProof>),
+}
+
+/// Check a root in the evidence is validly hashed, returning its history.
+fn root_history<'a>(info:&'a HashInfoWithHash,description:&str) -> Result<&'a RootHashHistory,String> {
+    match &info.source {
+        HashSource::Root(history) if history.compute_hash()==info.hash => Ok(history),
+        HashSource::Root(_) => Err(format!("The {} does not hash to the correct value",description)),
+        _ => Err(format!("The {} is not a root",description)),
+    }
+}
+
+/// Check whether a board honoured a receipt, given evidence obtained from the board (see [crate::BulletinBoard::get_receipt_evidence]).
+/// Returns an error if the receipt's signature is invalid or the evidence is inconsistent.
+///
+/// This checks that the evidence is internally consistent, but you should also check that the public key is the board's,
+/// and that the roots in the evidence are ones the board has actually published, e.g. by comparing with
+/// roots you were told at the time.
+///
+/// # Example
+///
+/// ```
+/// use merkle_tree_bulletin_board::receipt::{check_receipt, verify_misbehaviour_proof, ReceiptCheck, ReceiptSigner};
+///
+/// use merkle_tree_bulletin_board::{BulletinBoard, BulletinBoardBackend, DatabaseTransaction};
+/// use merkle_tree_bulletin_board::backend_memory::BackendMemory;
+/// use merkle_tree_bulletin_board::hash_history::{LeafHashHistory, timestamp_now};
+///
+/// // a board holding a leaf submitted an hour ago, that has not yet been published.
+/// let history = LeafHashHistory{ timestamp: timestamp_now().unwrap()-60*60, data: Some("a".to_string()), structured: false };
+/// let leaf = history.compute_hash().unwrap();
+/// let mut transaction = DatabaseTransaction::default();
+/// transaction.add_leaf_hash(leaf,history);
+/// let mut backend = BackendMemory::default();
+/// backend.publish(&transaction).unwrap();
+/// let mut board = BulletinBoard::new(backend).unwrap();
+/// let signer = ReceiptSigner::generate(60).unwrap(); // promises publication within a minute.
+/// let public_key = signer.public_key();
+/// board.set_receipt_signer(signer);
+/// let receipt = board.get_receipt(leaf).unwrap();
+/// assert_eq!(receipt.verify_signature(&public_key),None);
+/// let evidence = board.get_receipt_evidence(&receipt.receipt).unwrap();
+/// assert!(matches!(check_receipt(&receipt,&public_key,&evidence),Ok(ReceiptCheck::Undetermined)));
+/// let root = board.order_new_published_root().unwrap(); // too late!
+/// let evidence = board.get_receipt_evidence(&receipt.receipt).unwrap();
+/// match check_receipt(&receipt,&public_key,&evidence) {
+///     Ok(ReceiptCheck::Broken(proof)) => {
+///         assert_eq!(proof.evidence.first_root_after_deadline.as_ref().unwrap().hash,root);
+///         assert_eq!(verify_misbehaviour_proof(&proof),None); // it is a valid proof of misbehaviour.
+///     }
+///     _ => panic!("Receipt should not have been honoured"),
+/// }
+/// ```
+pub fn check_receipt(receipt:&SignedReceipt,public_key:&ReceiptPublicKey,evidence:&ReceiptEvidence) -> Result<ReceiptCheck,String> {
+    if let Some(problem) = receipt.verify_signature(public_key) { return Err(problem); }
+    let deadline = receipt.receipt.deadline;
+    // check the proof chain is a valid chain of ancestors of the leaf.
+    let proof = &evidence.proof;
+    match proof.chain.first() {
+        Some(HashInfoWithHash{hash,source:HashSource::Leaf(history),..}) if *hash==receipt.receipt.leaf => {
+            if history.timestamp!=receipt.receipt.timestamp { return Err("The leaf in the proof chain has a different timestamp to the receipt".to_string()); }
+        }
+        _ => return Err("The proof chain does not start with the leaf in the receipt".to_string()),
+    }
+    if let Some(problem) = verify_branches(proof).or_else(||verify_published_root_in_proof(proof)) { return Err(problem); }
+    // check the roots on either side of the deadline.
+    let last_root_by_deadline = match &evidence.last_root_by_deadline {
+        Some(info) => {
+            let history = root_history(info,"last root published by the deadline")?;
+            if history.timestamp>deadline { return Err("The last root published by the deadline was published after the deadline".to_string()); }
+            Some(history)
+        }
+        None => None,
+    };
+    if let Some(info) = &evidence.first_root_after_deadline {
+        let history = root_history(info,"first root published after the deadline")?;
+        if history.timestamp<=deadline { return Err("The first root published after the deadline was published before the deadline".to_string()); }
+        if history.prior!=evidence.last_root_by_deadline.as_ref().map(|r|r.hash) { return Err("The first root published after the deadline does not follow the last root published by the deadline".to_string()); }
+    }
+    // see if a root by the deadline includes the leaf.
+    let proof_root = proof.published_root.as_ref().map(|info|root_history(info,"root in the proof")).transpose()?;
+    if let Some(history) = proof_root {
+        if history.timestamp<=deadline { return Ok(ReceiptCheck::Honoured { root: proof.published_root.as_ref().unwrap().hash }); }
+    }
+    if let (Some(info),Some(history)) = (&evidence.last_root_by_deadline,last_root_by_deadline) {
+        if proof.chain.iter().any(|node|history.elements.contains(&node.hash)) { return Ok(ReceiptCheck::Honoured { root: info.hash }); }
+    }
+    // not included by the deadline. That can be proven if there is a root after the deadline, and either
+    // no root before it, or a proof chain to a root after the deadline so the chain contains all ancestors the leaf had by then.
+    if evidence.first_root_after_deadline.is_some() && (last_root_by_deadline.is_none() || proof_root.is_some()) {
+        Ok(ReceiptCheck::Broken(Box::new(MisbehaviourProof{ receipt: *receipt, public_key: *public_key, evidence: evidence.clone() })))
+    } else { Ok(ReceiptCheck::Undetermined) }
+}
+
+/// Check a proof that a board did not include a leaf by the deadline in a receipt it signed.
+/// Returns None if the proof is valid, otherwise a description of the problem.
+///
+/// As for [check_receipt], you should also check that the public key is the board's and the roots in the evidence
+/// were actually published by the board.
+pub fn verify_misbehaviour_proof(proof:&MisbehaviourProof) -> Option<String> {
+    match check_receipt(&proof.receipt,&proof.public_key,&proof.evidence) {
+        Ok(ReceiptCheck::Broken(_)) => None,
+        Ok(ReceiptCheck::Honoured{root}) => Some(format!("The receipt was honoured by root {}",root)),
+        Ok(ReceiptCheck::Undetermined) => Some("The evidence does not show that the receipt was not honoured".to_string()),
+        Err(problem) => Some(problem),
+    }
+}
+
+/// Get the timestamp of a published root.
+fn root_timestamp(info:Option<HashInfo>,root:HashValue) -> Result<Timestamp,BulletinBoardError> {
+    match info {
+        Some(HashInfo{source:HashSource::Root(history),..}) => Ok(history.timestamp),
+        _ => Err(BulletinBoardError::PublishedRootIsNotARoot(root)),
+    }
+}
+
+/// Implementation of [crate::BulletinBoard::get_receipt_evidence], shared by the sync and async boards and readers.
+pub(crate) async fn get_receipt_evidence_using(backend:&impl ReadAccess,receipt:&SubmissionReceipt) -> Result<ReceiptEvidence,BulletinBoardError> {
+    let proof = get_proof_chain(backend,receipt.leaf).await?;
+    let roots = backend.get_all_published_roots().await?;
+    let (mut start,mut end) = (0,roots.len()); // roots before start were published by the deadline, roots from end after it.
+    while start<end {
+        let mid = (start+end)/2;
+        if root_timestamp(backend.get_hash_info(roots[mid]).await?,roots[mid])?<=receipt.deadline { start=mid+1 } else { end=mid }
+    }
+    let last_root_by_deadline = if start>0 { Some(root_info(backend,roots[start-1]).await?) } else { None };
+    let first_root_after_deadline = if start<roots.len() { Some(root_info(backend,roots[start]).await?) } else { None };
+    Ok(ReceiptEvidence{ proof, last_root_by_deadline, first_root_after_deadline })
+}
+
+/// Get the information about a published root.
+async fn root_info(backend:&impl ReadAccess,root:HashValue) -> Result<HashInfoWithHash,BulletinBoardError> {
+    Ok(backend.get_hash_info(root).await?.ok_or(BulletinBoardError::PublishedRootHasNoInfo)?.add_hash(root))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BulletinBoard;
+    use crate::backend_memory::BackendMemory;
+    use crate::receipt::{check_receipt, ReceiptCheck, ReceiptSigner};
+
+    #[test]
+    /// Check a receipt honoured by a timely root, and that tampering with the receipt is noticed.
+    fn test_receipt_honoured() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let signer = ReceiptSigner::new([7;32],100);
+        let public_key = signer.public_key();
+        board.set_receipt_signer(signer);
+        let receipt = board.submit_leaf_with_receipt("a").unwrap();
+        assert_eq!(receipt.receipt.deadline,receipt.receipt.timestamp+100);
+        assert_eq!(board.get_receipt(receipt.receipt.leaf).unwrap(),receipt);
+        board.submit_leaf("b").unwrap();
+        let root = board.order_new_published_root().unwrap();
+        board.submit_leaf("c").unwrap();
+        board.order_new_published_root().unwrap();
+        let evidence = board.get_receipt_evidence(&receipt.receipt).unwrap();
+        assert!(matches!(check_receipt(&receipt,&public_key,&evidence),Ok(ReceiptCheck::Honoured{root:r}) if r!=root)); // the most recent root is also by the deadline.
+        let mut tampered = receipt;
+        tampered.receipt.deadline+=1;
+        assert!(check_receipt(&tampered,&public_key,&evidence).is_err());
+        assert!(check_receipt(&receipt,&ReceiptSigner::new([8;32],100).public_key(),&evidence).is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! Automatic censorship of leaves older than a retention period.
+//!
+//! Privacy law may require personal data to be removed once it is older than some age. A [RetentionPolicy]
+//! describes that age, and [crate::BulletinBoard::apply_retention_policy] censors all leaves older than it through
+//! the normal [crate::BulletinBoard::censor_leaf] path, so each redaction has a [crate::censorship::CensorshipRecord]
+//! committed to in the board, with reason [RETENTION_REASON]. The tree structure, and therefore all proofs, stay intact.
+//!
+//! Leaves on legal hold are exempt. Censorship records themselves are also exempt, as they are the audit trail.
+//!
+//! This is expected to be run periodically, e.g. daily. Each run returns a [RetentionReport] listing what was done.
+
+use std::collections::HashSet;
+use serde::{Serialize,Deserialize};
+use crate::{bb_timestamp_now, BoardState, BulletinBoard, BulletinBoardBackend, BulletinBoardError};
+use crate::backend_access::{run_ready, SyncAccess, WriteAccess};
+#[cfg(feature = "async")]
+use crate::backend_access::AsyncAccess;
+use crate::censorship::CensorshipRecord;
+#[cfg(feature = "async")]
+use crate::async_board::{AsyncBulletinBoard, AsyncBulletinBoardBackend};
+use crate::hash::HashValue;
+use crate::hash_history::{HashInfo, HashSource, LeafHashHistory, Timestamp};
+
+/// The reason given in the censorship record for a leaf censored because its retention period expired.
+pub const RETENTION_REASON : &str = "retention period expired";
+
+/// Which leaves should be censored for being too old.
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub struct RetentionPolicy {
+    /// Leaves with a timestamp more than this many seconds in the past will be censored.
+    pub max_age : Timestamp,
+    /// The authority recorded in the censorship record, e.g. the regulation requiring it.
+    pub authority : String,
+    /// Leaves that must not be censored, even when older than max_age.
+    pub legal_holds : HashSet<HashValue>,
+}
+
+/// One leaf censored by a [RetentionPolicy].
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub struct RetentionRedaction {
+    /// The leaf that was censored.
+    pub leaf : HashValue,
+    /// The timestamp of the leaf that was censored.
+    pub leaf_timestamp : Timestamp,
+    /// The leaf containing the censorship record.
+    pub record_leaf : HashValue,
+}
+
+/// What was done by one application of a [RetentionPolicy].
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub struct RetentionReport {
+    /// When the policy was applied.
+    pub run_at : Timestamp,
+    /// Leaves with a timestamp before this were past their retention period.
+    pub cutoff : Timestamp,
+    /// The leaves censored, oldest first.
+    pub redactions : Vec<RetentionRedaction>,
+    /// Leaves past their retention period that were not censored because they are on legal hold.
+    pub held : Vec<HashValue>,
+}
+
+/// Given a leaf older than the cutoff, from [BulletinBoardBackend::list_leaves_between], note it as to be censored,
+/// as (leaf, timestamp), or held, unless it is already censored or is a censorship record.
+fn note_expired_leaf(policy:&RetentionPolicy,leaf:HashValue,info:Option<HashInfo>,expired:&mut Vec<(HashValue,Timestamp)>,held:&mut Vec<HashValue>) {
+    if let Some(HashInfo{source:HashSource::Leaf(LeafHashHistory{timestamp,data:Some(data),..}),..}) = info {
+        if CensorshipRecord::from_leaf_data(&data).is_none() {
+            if policy.legal_holds.contains(&leaf) { held.push(leaf) } else { expired.push((leaf,timestamp)) }
+        }
+    }
+}
+
+impl BoardState {
+    /// See [BulletinBoard::apply_retention_policy_at].
+    pub(crate) async fn apply_retention_policy_at(&mut self,backend:&mut impl WriteAccess,policy:&RetentionPolicy,now:Timestamp) -> Result<RetentionReport,BulletinBoardError> {
+        let cutoff = now.saturating_sub(policy.max_age);
+        let (mut expired,mut held) = (vec![],vec![]);
+        for leaf in backend.list_leaves_between(0,cutoff).await? {
+            note_expired_leaf(policy,leaf,backend.get_hash_info(leaf).await?,&mut expired,&mut held);
+        }
+        let mut redactions = vec![];
+        for (leaf,leaf_timestamp) in expired {
+            let record_leaf = self.censor_leaf(backend,leaf,RETENTION_REASON,&policy.authority).await?;
+            redactions.push(RetentionRedaction{ leaf, leaf_timestamp, record_leaf });
+        }
+        Ok(RetentionReport{ run_at: now, cutoff, redactions, held })
+    }
+}
+
+impl <B:BulletinBoardBackend> BulletinBoard<B> {
+    /// Censor all leaves older than the policy's retention period, other than those on legal hold
+    /// and censorship records. See [crate::retention].
+    ///
+    /// This looks at every leaf older than the cutoff, found with [crate::BulletinBoardBackend::list_leaves_between].
+    pub fn apply_retention_policy(&mut self,policy:&RetentionPolicy) -> Result<RetentionReport,BulletinBoardError> {
+        self.apply_retention_policy_at(policy,bb_timestamp_now()?)
+    }
+
+    /// Like [BulletinBoard::apply_retention_policy], but as if run at time `now` rather than the current time.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::retention::{RetentionPolicy, RETENTION_REASON};
+    /// use merkle_tree_bulletin_board::hash_history::{HashSource, LeafHashHistory, timestamp_now};
+    ///
+    /// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+    ///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+    /// let a = board.submit_leaf("A").unwrap();
+    /// let b = board.submit_leaf("B").unwrap();
+    /// let a_day_later = timestamp_now().unwrap()+24*60*60;
+    /// let policy = RetentionPolicy{ max_age: 60*60, authority: "Privacy Act".to_string(), legal_holds: [b].into_iter().collect() };
+    /// let report = board.apply_retention_policy_at(&policy,a_day_later).unwrap();
+    /// assert_eq!(report.redactions.len(),1);
+    /// assert_eq!(report.redactions[0].leaf,a);
+    /// assert_eq!(report.held,vec![b]);
+    /// match board.get_hash_info(a).unwrap().source {
+    ///     HashSource::Leaf(LeafHashHistory{data:None,..}) => {}
+    ///     _ => panic!("Not a censored leaf"),
+    /// }
+    /// let root = board.order_new_published_root().unwrap();
+    /// let events = board.get_censorship_records_between(None,root).unwrap();
+    /// assert_eq!(events[0].record.reason,RETENTION_REASON);
+    /// // Running again does nothing, as the leaf is already censored, and the censorship record is exempt.
+    /// assert!(board.apply_retention_policy_at(&policy,a_day_later).unwrap().redactions.is_empty());
+    /// ```
+    pub fn apply_retention_policy_at(&mut self,policy:&RetentionPolicy,now:Timestamp) -> Result<RetentionReport,BulletinBoardError> {
+        run_ready(self.state.apply_retention_policy_at(&mut SyncAccess(&mut self.backend),policy,now))
+    }
+}
+
+#[cfg(feature = "async")]
+impl <B:AsyncBulletinBoardBackend> AsyncBulletinBoard<B> {
+    /// The async equivalent of [BulletinBoard::apply_retention_policy].
+    pub async fn apply_retention_policy(&mut self,policy:&RetentionPolicy) -> Result<RetentionReport,BulletinBoardError> {
+        self.apply_retention_policy_at(policy,bb_timestamp_now()?).await
+    }
+
+    /// The async equivalent of [BulletinBoard::apply_retention_policy_at].
+    pub async fn apply_retention_policy_at(&mut self,policy:&RetentionPolicy,now:Timestamp) -> Result<RetentionReport,BulletinBoardError> {
+        self.state.apply_retention_policy_at(&mut AsyncAccess(&mut self.backend),policy,now).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BulletinBoard, BulletinBoardBackend};
+    use crate::backend_memory::BackendMemory;
+    use crate::censorship::CensorshipRecord;
+    use crate::hash::HashValue;
+    use crate::hash_history::HashSource;
+    use crate::retention::{RetentionPolicy, RETENTION_REASON};
+    use crate::structured_leaf::StructuredLeaf;
+
+    fn data(board:&BulletinBoard<BackendMemory>,leaf:HashValue) -> Option<String> {
+        match board.get_hash_info(leaf).unwrap().source {
+            HashSource::Leaf(history) => history.data,
+            _ => panic!("Not a leaf"),
+        }
+    }
+
+    fn policy(legal_holds:&[HashValue]) -> RetentionPolicy {
+        RetentionPolicy{ max_age: 3600, authority: "Privacy Act".to_string(), legal_holds: legal_holds.iter().cloned().collect() }
+    }
+
+    #[test]
+    /// Test that only leaves older than the cutoff, and not on legal hold, are censored, oldest first.
+    fn test_cutoff_and_legal_holds() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let old = board.add_leaf_at("old",1000,false);
+        let held = board.add_leaf_at("held",2000,false);
+        let older_still = board.add_leaf_at("older still",500,false);
+        let recent = board.submit_leaf("recent").unwrap();
+        let report = board.apply_retention_policy(&policy(&[held])).unwrap();
+        assert_eq!(report.cutoff,report.run_at-3600);
+        assert_eq!(report.redactions.iter().map(|r|(r.leaf,r.leaf_timestamp)).collect::<Vec<_>>(),vec![(older_still,500),(old,1000)]);
+        assert_eq!(report.held,vec![held]);
+        assert_eq!(data(&board,old),None);
+        assert_eq!(data(&board,older_still),None);
+        assert_eq!(data(&board,held),Some("held".to_string()));
+        assert_eq!(data(&board,recent),Some("recent".to_string()));
+        for redaction in &report.redactions {
+            let record = CensorshipRecord::from_leaf_data(&data(&board,redaction.record_leaf).unwrap()).unwrap();
+            assert_eq!(record,CensorshipRecord{ censored_leaf: redaction.leaf, reason: RETENTION_REASON.to_string(), authority: "Privacy Act".to_string(), field: None, uncensored: false });
+        }
+        // the records are recent, so are not expired, and the held leaf is still held.
+        let report = board.apply_retention_policy(&policy(&[held])).unwrap();
+        assert!(report.redactions.is_empty());
+        assert_eq!(report.held,vec![held]);
+        // releasing the hold lets it be censored.
+        assert_eq!(board.apply_retention_policy(&policy(&[])).unwrap().redactions.iter().map(|r|r.leaf).collect::<Vec<_>>(),vec![held]);
+    }
+
+    #[test]
+    /// Test that already censored leaves are not censored again or reported as held, and old censorship records are exempt.
+    fn test_censored_leaves_and_censorship_records() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let censored = board.add_leaf_at("censored",1000,false);
+        let censored_and_held = board.add_leaf_at("censored and held",1000,false);
+        let record = CensorshipRecord{ censored_leaf: censored, reason: "Spam".to_string(), authority: "Moderator".to_string(), field: None, uncensored: false };
+        let old_record = board.add_leaf_at(&record.to_leaf_data(),1001,false);
+        board.backend.censor_leaf(censored).unwrap();
+        board.censor_leaf(censored_and_held,"Spam","Moderator").unwrap();
+        let report = board.apply_retention_policy(&policy(&[censored_and_held])).unwrap();
+        assert!(report.redactions.is_empty());
+        assert!(report.held.is_empty());
+        assert_eq!(data(&board,old_record),Some(record.to_leaf_data()));
+    }
+
+    #[test]
+    /// Test that expired structured leaves, including ones with a field already redacted, are censored entirely.
+    fn test_structured_leaves() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let object = serde_json::json!({"name":"Fred","address":"123 Example Street"});
+        let structured = StructuredLeaf::new(object.as_object().unwrap()).unwrap();
+        let whole = board.add_leaf_at(&structured.to_leaf_data(),1000,true);
+        let partly_redacted = board.add_leaf_at(&structured.to_leaf_data(),1001,true);
+        board.redact_field(partly_redacted,"address","Privacy","Moderator").unwrap();
+        let report = board.apply_retention_policy(&policy(&[])).unwrap();
+        assert_eq!(report.redactions.iter().map(|r|r.leaf).collect::<Vec<_>>(),vec![whole,partly_redacted]);
+        assert_eq!(data(&board,whole),None);
+        assert_eq!(data(&board,partly_redacted),None);
+        board.uncensor_leaf(whole,&structured.to_leaf_data(),"Consent given","Fred").unwrap(); // still restorable from the original.
+    }
+}
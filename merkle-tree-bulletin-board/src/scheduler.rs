@@ -0,0 +1,322 @@
+//! Automatic publication of new roots.
+//!
+//! A [PublicationSchedule] says when a new root should be published: after some time has passed since the last
+//! publication, after some number of leaves have been added since the last publication, or whichever comes first.
+//! Publication is skipped if nothing has been added since the last root (see [crate::BulletinBoard::order_new_published_root_if_changed]).
+//!
+//! For a [crate::BulletinBoard], start a [PublicationScheduler], which runs in its own thread. For an
+//! [crate::async_board::AsyncBulletinBoard], spawn [run_publication_schedule_async] as a task.
+//!
+//! Leaves are counted via [crate::events], so only leaves added through the same bulletin board object count.
+//! Roots published other than by the scheduler, e.g. by calling [crate::BulletinBoard::order_new_published_root]
+//! directly, restart the schedule.
+//!
+//! A scheduled publication that fails is passed to an error handler supplied when the schedule is started, and the
+//! schedule restarts, so publication is attempted again when next due.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use crate::{BulletinBoard, BulletinBoardBackend, BulletinBoardError};
+use crate::events::BulletinBoardEvent;
+#[cfg(feature = "async")]
+use crate::async_board::{AsyncBulletinBoard, AsyncBulletinBoardBackend};
+
+/// When to publish a new root. If both are None, roots will never be published automatically.
+#[derive(Debug,Clone,Copy,Default,Eq,PartialEq)]
+pub struct PublicationSchedule {
+    /// Publish when this long has passed since the last publication.
+    pub interval : Option<Duration>,
+    /// Publish when this many leaves have been added since the last publication.
+    pub max_leaves : Option<usize>,
+}
+
+/// How far along the schedule is.
+struct ScheduleState {
+    schedule : PublicationSchedule,
+    /// When the last root was published, or the schedule started.
+    last_publication : Instant,
+    /// The number of leaves added since last_publication.
+    leaves_since_publication : usize,
+    /// Set to true to stop the scheduler.
+    stopped : bool,
+}
+
+impl ScheduleState {
+    fn new(schedule:PublicationSchedule) -> Self {
+        ScheduleState{ schedule, last_publication: Instant::now(), leaves_since_publication: 0, stopped: false }
+    }
+
+    /// Update for a change to the board.
+    fn on_event(&mut self,event:&BulletinBoardEvent) {
+        match event {
+            BulletinBoardEvent::LeafAdded{..} => self.leaves_since_publication+=1,
+            BulletinBoardEvent::RootPublished{..} => self.published(),
+            _ => {}
+        }
+    }
+
+    /// Restart the schedule, as a root has just been published (or there was nothing to publish).
+    fn published(&mut self) {
+        self.last_publication=Instant::now();
+        self.leaves_since_publication=0;
+    }
+
+    /// The time until the interval is up, or None if no interval.
+    fn time_until_due(&self) -> Option<Duration> {
+        self.schedule.interval.map(|interval|interval.saturating_sub(self.last_publication.elapsed()))
+    }
+
+    /// Whether a root should be published now.
+    fn is_due(&self) -> bool {
+        self.time_until_due()==Some(Duration::ZERO) || self.schedule.max_leaves.map(|max|self.leaves_since_publication>=max).unwrap_or(false)
+    }
+}
+
+/// Publishes new roots for a [BulletinBoard] according to a [PublicationSchedule], in a separate thread.
+/// The thread stops, and the listener it added to the board is removed, when this is dropped. As that needs
+/// the board's lock, don't drop this while holding it.
+///
+/// # Example
+///
+/// ```
+/// use merkle_tree_bulletin_board::BulletinBoard;
+/// use merkle_tree_bulletin_board::backend_memory::BackendMemory;
+/// use merkle_tree_bulletin_board::scheduler::{PublicationSchedule, PublicationScheduler};
+/// use std::sync::{Arc, Mutex};
+/// use std::time::Duration;
+///
+/// let board = Arc::new(Mutex::new(BulletinBoard::new(BackendMemory::default()).unwrap()));
+/// let schedule = PublicationSchedule{ interval: Some(Duration::from_secs(3600)), max_leaves: Some(2) };
+/// let scheduler = PublicationScheduler::new(board.clone(),schedule,|e|panic!("Could not publish : {}",e)).unwrap();
+/// board.lock().unwrap().submit_leaf("a").unwrap();
+/// board.lock().unwrap().submit_leaf("b").unwrap(); // this triggers a publication.
+/// for _ in 0..100 {
+///     if !board.lock().unwrap().get_all_published_roots().unwrap().is_empty() { break; }
+///     std::thread::sleep(Duration::from_millis(10));
+/// }
+/// assert_eq!(board.lock().unwrap().get_all_published_roots().unwrap().len(),1);
+/// drop(scheduler);
+/// ```
+pub struct PublicationScheduler {
+    state : Arc<(Mutex<ScheduleState>,Condvar)>,
+    thread : Option<JoinHandle<()>>,
+    /// Removes the listener counting leaves from the board.
+    remove_listener : Option<Box<dyn FnOnce()+Send>>,
+}
+
+impl PublicationScheduler {
+    /// Start publishing roots for the given board according to the given schedule.
+    /// Any error publishing a root is passed to `on_error`, which is called from the scheduler's thread.
+    pub fn new<B:BulletinBoardBackend+Send+'static>(board:Arc<Mutex<BulletinBoard<B>>>,schedule:PublicationSchedule,mut on_error:impl FnMut(BulletinBoardError)+Send+'static) -> Result<Self,BulletinBoardError> {
+        let state = Arc::new((Mutex::new(ScheduleState::new(schedule)),Condvar::new()));
+        let remove_listener : Box<dyn FnOnce()+Send> = {
+            let state = state.clone();
+            let handle = lock(&board)?.add_listener(move |event:&BulletinBoardEvent|{
+                if let Ok(mut guard) = state.0.lock() {
+                    guard.on_event(event);
+                    state.1.notify_all();
+                }
+            });
+            let board = board.clone();
+            Box::new(move ||{ if let Ok(mut board) = lock(&board) { board.remove_listener(handle); } })
+        };
+        let thread = {
+            let state = state.clone();
+            std::thread::spawn(move ||{
+                while wait_until_due(&state) {
+                    let res = lock(&board).and_then(|mut board|{
+                        let res = board.order_new_published_root_if_changed();
+                        // restart in case nothing was published, before any more leaves can be added.
+                        if let Ok(mut guard) = state.0.lock() { guard.published(); }
+                        res
+                    });
+                    if let Err(e) = res { on_error(e); }
+                }
+            })
+        };
+        Ok(PublicationScheduler{ state, thread: Some(thread), remove_listener: Some(remove_listener) })
+    }
+}
+
+impl Drop for PublicationScheduler {
+    fn drop(&mut self) {
+        if let Ok(mut guard) = self.state.0.lock() {
+            guard.stopped=true;
+            self.state.1.notify_all();
+        }
+        if let Some(thread) = self.thread.take() { let _ = thread.join(); }
+        if let Some(remove_listener) = self.remove_listener.take() { remove_listener(); }
+    }
+}
+
+fn lock<T>(mutex:&Mutex<T>) -> Result<std::sync::MutexGuard<'_,T>,BulletinBoardError> {
+    mutex.lock().map_err(|_|BulletinBoardError::BackendInconsistentError("A prior operation on the bulletin board panicked".to_string()))
+}
+
+/// Wait until a publication is due, returning true, or the scheduler is stopped, returning false.
+fn wait_until_due(state:&(Mutex<ScheduleState>,Condvar)) -> bool {
+    let Ok(mut guard) = state.0.lock() else { return false };
+    loop {
+        if guard.stopped { return false; }
+        if guard.is_due() { return true; }
+        guard = match guard.time_until_due() {
+            Some(timeout) => match state.1.wait_timeout(guard,timeout) { Ok((guard,_)) => guard, Err(_) => return false },
+            None => match state.1.wait(guard) { Ok(guard) => guard, Err(_) => return false },
+        };
+    }
+}
+
+/// Publish new roots for an [AsyncBulletinBoard] according to a [PublicationSchedule], until the board is dropped.
+/// Typically spawned as a task. Any error publishing a root is passed to `on_error`.
+///
+/// # Example
+///
+/// ```
+/// use merkle_tree_bulletin_board::async_board::AsyncBulletinBoard;
+/// use merkle_tree_bulletin_board::backend_memory::BackendMemory;
+/// use merkle_tree_bulletin_board::scheduler::{PublicationSchedule, run_publication_schedule_async};
+/// use async_std::sync::Mutex;
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// async_std::task::block_on(async {
+///     let board = Arc::new(Mutex::new(AsyncBulletinBoard::new(BackendMemory::default()).await.unwrap()));
+///     let schedule = PublicationSchedule{ interval: Some(Duration::from_millis(100)), max_leaves: None };
+///     async_std::task::spawn(run_publication_schedule_async(board.clone(),schedule,|e|panic!("Could not publish : {}",e)));
+///     board.lock().await.submit_leaf("a").await.unwrap();
+///     async_std::task::sleep(Duration::from_millis(500)).await;
+///     assert_eq!(board.lock().await.get_all_published_roots().await.unwrap().len(),1); // only published once, as nothing new since.
+/// });
+/// ```
+#[cfg(feature = "async")]
+pub async fn run_publication_schedule_async<B:AsyncBulletinBoardBackend+'static>(board:Arc<async_std::sync::Mutex<AsyncBulletinBoard<B>>>,schedule:PublicationSchedule,mut on_error:impl FnMut(BulletinBoardError)) {
+    let events = board.lock().await.subscribe();
+    let mut state = ScheduleState::new(schedule);
+    loop {
+        if state.is_due() {
+            let res = {
+                let mut board = board.lock().await;
+                let res = board.order_new_published_root_if_changed().await;
+                while let Ok(event) = events.try_recv() { state.on_event(&event); } // catch up, including our own publication, before any more leaves can be added.
+                res
+            };
+            state.published(); // in case nothing was published.
+            if let Err(e) = res { on_error(e); }
+            continue;
+        }
+        let event = match state.time_until_due() {
+            Some(timeout) => match async_std::future::timeout(timeout,events.recv()).await {
+                Ok(event) => event,
+                Err(_) => continue, // timed out, so now due.
+            },
+            None => events.recv().await,
+        };
+        match event {
+            Ok(event) => state.on_event(&event),
+            Err(_) => return, // board has gone away.
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use crate::{BulletinBoard, BulletinBoardBackend, BulletinBoardError, DatabaseTransaction};
+    use crate::backend_memory::BackendMemory;
+    use crate::hash_history::{HashInfo, HashSource};
+    use crate::events::BulletinBoardEvent;
+    use crate::hash::HashValue;
+    use crate::scheduler::{PublicationSchedule, PublicationScheduler, ScheduleState};
+
+    fn leaf_added() -> BulletinBoardEvent { BulletinBoardEvent::LeafAdded{ leaf: HashValue([1;32]), timestamp: 0, branches: vec![] } }
+    fn root_published() -> BulletinBoardEvent { BulletinBoardEvent::RootPublished{ root: HashValue([2;32]), timestamp: 0, prior: None, elements: vec![] } }
+
+    #[test]
+    /// Test that publication is due after the given number of leaves, counting only leaves, and restarting after a publication.
+    fn test_due_after_max_leaves() {
+        let mut state = ScheduleState::new(PublicationSchedule{ interval: None, max_leaves: Some(2) });
+        assert!(!state.is_due());
+        state.on_event(&leaf_added());
+        assert!(!state.is_due());
+        state.on_event(&BulletinBoardEvent::LeafUncensored{ leaf: HashValue([1;32]), record_leaf: HashValue([3;32]) });
+        assert!(!state.is_due());
+        state.on_event(&leaf_added());
+        assert!(state.is_due());
+        state.on_event(&root_published());
+        assert!(!state.is_due());
+        assert_eq!(state.time_until_due(),None);
+    }
+
+    #[test]
+    /// Test that publication is due once the interval has passed since the last publication, whichever comes first.
+    fn test_due_after_interval() {
+        let interval = Duration::from_secs(60);
+        let mut state = ScheduleState::new(PublicationSchedule{ interval: Some(interval), max_leaves: Some(100) });
+        assert!(!state.is_due());
+        assert!(state.time_until_due().unwrap()>Duration::from_secs(59));
+        state.last_publication = Instant::now()-interval; // backdate.
+        assert!(state.is_due());
+        assert_eq!(state.time_until_due(),Some(Duration::ZERO));
+        state.on_event(&root_published());
+        assert!(!state.is_due());
+        for _ in 0..100 { state.on_event(&leaf_added()); }
+        assert!(state.is_due());
+    }
+
+    #[test]
+    /// Test that a schedule with neither limit is never due.
+    fn test_never_due() {
+        let mut state = ScheduleState::new(PublicationSchedule::default());
+        state.last_publication = Instant::now()-Duration::from_secs(1000000);
+        for _ in 0..1000 { state.on_event(&leaf_added()); }
+        assert!(!state.is_due());
+    }
+
+    #[test]
+    /// Test that dropping the scheduler removes its listener from the board.
+    fn test_drop_removes_listener() {
+        let board = Arc::new(Mutex::new(BulletinBoard::new(BackendMemory::default()).unwrap()));
+        let scheduler = PublicationScheduler::new(board.clone(),PublicationSchedule::default(),|_|{}).unwrap();
+        let state = scheduler.state.clone();
+        assert_eq!(Arc::strong_count(&state),4); // the scheduler, the thread, the listener and here.
+        drop(scheduler);
+        assert_eq!(Arc::strong_count(&state),1);
+        assert_eq!(Arc::strong_count(&board),1);
+        board.lock().unwrap().submit_leaf("a").unwrap();
+        assert_eq!(state.0.lock().unwrap().leaves_since_publication,0);
+    }
+
+    /// A backend that can add leaves, but not publish roots.
+    #[derive(Default)]
+    struct CannotPublishRoots(BackendMemory);
+
+    impl BulletinBoardBackend for CannotPublishRoots {
+        fn get_all_published_roots(&self) -> Result<Vec<HashValue>, BulletinBoardError> { self.0.get_all_published_roots() }
+        fn get_most_recent_published_root(&self) -> Result<Option<HashValue>, BulletinBoardError> { self.0.get_most_recent_published_root() }
+        fn get_all_leaves_and_branches_without_a_parent(&self) -> Result<Vec<HashValue>, BulletinBoardError> { self.0.get_all_leaves_and_branches_without_a_parent() }
+        fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>, BulletinBoardError> { self.0.get_hash_info(query) }
+        fn publish(&mut self, transaction: &DatabaseTransaction) -> Result<(), BulletinBoardError> { self.0.publish(transaction) }
+        fn publish_if_unchanged(&mut self, transaction: &DatabaseTransaction, expected_parentless: &[HashValue]) -> Result<(), BulletinBoardError> {
+            if transaction.pending.iter().any(|(_,source)|matches!(source,HashSource::Root(_))) { Err(BulletinBoardError::BackendIOError("disk full".to_string())) }
+            else { self.0.publish_if_unchanged(transaction,expected_parentless) }
+        }
+        fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(), BulletinBoardError> { self.0.censor_leaf(leaf_to_censor) }
+    }
+
+    #[test]
+    /// Test that a failed publication is passed to the error handler, and attempted again when next due.
+    fn test_errors_reported() {
+        let board = Arc::new(Mutex::new(BulletinBoard::new(CannotPublishRoots::default()).unwrap()));
+        let (sender,receiver) = std::sync::mpsc::channel();
+        let scheduler = PublicationScheduler::new(board.clone(),PublicationSchedule{ interval: None, max_leaves: Some(1) },move|e|sender.send(e).unwrap()).unwrap();
+        for data in ["a","b"] {
+            board.lock().unwrap().submit_leaf(data).unwrap();
+            let error = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+            assert_eq!(error,BulletinBoardError::BackendIOError("disk full".to_string()));
+        }
+        drop(scheduler);
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,271 @@
+//! Share one backend between a single writer and many concurrent readers.
+
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+use crate::{BulletinBoard, BulletinBoardBackend, BulletinBoardError, DatabaseTransaction, first_including_root_with_proof, get_ancestry_proof, get_leaf_count_proof, get_ordering_proof, get_proof_chain, remove_published};
+use crate::backend_access::{run_ready, SyncAccess};
+use crate::growing_forest::GrowingForest;
+use crate::hash::HashValue;
+use crate::hash_history::{AncestryProof, FullProof, HashInfo, LeafCountProof, OrderingProof, Timestamp};
+use crate::inclusion::PublicationWatcher;
+use crate::receipt::{get_receipt_evidence_using, ReceiptEvidence, SubmissionReceipt};
+use crate::content_index::find_leaves_by_data_using;
+
+/// A backend that can be shared between threads. Reads are done under a shared lock, so
+/// can proceed in parallel; writes are done under an exclusive lock.
+///
+/// Cloning a SharedBackend produces another handle to the same underlying backend.
+///
+/// This is normally used via [BulletinBoard::new_shared], which makes a [BulletinBoard] that is
+/// the single writer, and from which any number of [BulletinBoardReader]s can be obtained.
+pub struct SharedBackend<B:BulletinBoardBackend> {
+    inner : Arc<RwLock<B>>,
+}
+
+impl <B:BulletinBoardBackend> Clone for SharedBackend<B> {
+    fn clone(&self) -> Self { SharedBackend { inner: self.inner.clone() } }
+}
+
+impl <B:BulletinBoardBackend> SharedBackend<B> {
+    /// Share a backend.
+    pub fn new(backend:B) -> Self { SharedBackend { inner: Arc::new(RwLock::new(backend)) } }
+
+    fn read(&self) -> Result<RwLockReadGuard<'_,B>,BulletinBoardError> {
+        self.inner.read().map_err(|_|BulletinBoardError::BackendInconsistentError("A prior operation on the backend panicked".to_string()))
+    }
+
+    fn write(&self) -> Result<RwLockWriteGuard<'_,B>,BulletinBoardError> {
+        self.inner.write().map_err(|_|BulletinBoardError::BackendInconsistentError("A prior operation on the backend panicked".to_string()))
+    }
+}
+
+impl <B:BulletinBoardBackend> BulletinBoardBackend for SharedBackend<B> {
+    fn get_all_published_roots(&self) -> Result<Vec<HashValue>,BulletinBoardError> { self.read()?.get_all_published_roots() }
+
+    fn get_most_recent_published_root(&self) -> Result<Option<HashValue>,BulletinBoardError> { self.read()?.get_most_recent_published_root() }
+
+    fn get_all_leaves_and_branches_without_a_parent(&self) -> Result<Vec<HashValue>,BulletinBoardError> { self.read()?.get_all_leaves_and_branches_without_a_parent() }
+
+    fn get_hash_info(&self, query: HashValue) -> Result<Option<HashInfo>,BulletinBoardError> { self.read()?.get_hash_info(query) }
+
+    fn publish(&mut self, transaction: &DatabaseTransaction) -> Result<(),BulletinBoardError> { self.write()?.publish(transaction) }
+
+    /// The check and publication are done under one exclusive lock, so are atomic with respect to other users of this SharedBackend.
+    fn publish_if_unchanged(&mut self, transaction: &DatabaseTransaction, expected_parentless: &[HashValue]) -> Result<(),BulletinBoardError> {
+        self.write()?.publish_if_unchanged(transaction,expected_parentless)
+    }
+
+    fn check_unchanged(&self, transaction: &DatabaseTransaction, expected_parentless: &[HashValue]) -> Result<(),BulletinBoardError> {
+        self.read()?.check_unchanged(transaction,expected_parentless)
+    }
+
+    fn censor_leaf(&mut self, leaf_to_censor: HashValue) -> Result<(),BulletinBoardError> { self.write()?.censor_leaf(leaf_to_censor) }
+
+    fn uncensor_leaf(&mut self, leaf_to_uncensor: HashValue, data: &str) -> Result<(),BulletinBoardError> { self.write()?.uncensor_leaf(leaf_to_uncensor,data) }
+
+    fn get_depth(&self, hash: HashValue) -> Result<usize,BulletinBoardError> { self.read()?.get_depth(hash) }
+
+    fn get_leaf_for_idempotency_key(&self, key: &str) -> Result<Option<HashValue>,BulletinBoardError> { self.read()?.get_leaf_for_idempotency_key(key) }
+
+    fn store_idempotency_key(&mut self, key: &str, leaf: HashValue) -> Result<(),BulletinBoardError> { self.write()?.store_idempotency_key(key,leaf) }
+
+    fn get_leaves_with_content_hash(&self, content_hash: HashValue) -> Result<Vec<HashValue>,BulletinBoardError> { self.read()?.get_leaves_with_content_hash(content_hash) }
+
+    fn get_leaf_count(&self) -> Result<u64,BulletinBoardError> { self.read()?.get_leaf_count() }
+
+    fn get_leaf_by_index(&self, index: u64) -> Result<Option<HashValue>,BulletinBoardError> { self.read()?.get_leaf_by_index(index) }
+
+    fn get_leaf_index(&self, leaf: HashValue) -> Result<Option<u64>,BulletinBoardError> { self.read()?.get_leaf_index(leaf) }
+
+    fn list_leaves(&self, start: u64, count: u64) -> Result<Vec<HashValue>,BulletinBoardError> { self.read()?.list_leaves(start,count) }
+
+    fn list_leaves_between(&self, t0: Timestamp, t1: Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> { self.read()?.list_leaves_between(t0,t1) }
+
+    fn get_published_root_at(&self, t: Timestamp) -> Result<Option<HashValue>,BulletinBoardError> { self.read()?.get_published_root_at(t) }
+
+    /// Done under one lock so that the forest is consistent.
+    fn compute_current_forest(&self) -> Result<GrowingForest,BulletinBoardError> { self.read()?.compute_current_forest() }
+}
+
+/// A cloneable, thread safe, read only handle to a bulletin board. Obtain one from [BulletinBoard::reader].
+///
+/// The read functions have the same meaning as in [BulletinBoard], and can be called from many threads
+/// at once, in parallel with the writer. Each call sees the board either before or after any given write,
+/// never in the middle of one.
+///
+/// # Example
+///
+/// ```
+/// use merkle_tree_bulletin_board::BulletinBoard;
+/// use merkle_tree_bulletin_board::backend_memory::BackendMemory;
+/// use merkle_tree_bulletin_board::verifier::verify_proof;
+///
+/// let mut board = BulletinBoard::new_shared(BackendMemory::default()).unwrap();
+/// let reader = board.reader();
+/// let hash = board.submit_leaf("a").unwrap();
+/// let root = board.order_new_published_root().unwrap();
+/// let proof = std::thread::spawn(move || reader.get_proof_chain(hash).unwrap()).join().unwrap();
+/// assert_eq!(verify_proof("a",root,&proof),None);
+/// ```
+pub struct BulletinBoardReader<B:BulletinBoardBackend> {
+    backend : SharedBackend<B>,
+}
+
+impl <B:BulletinBoardBackend> Clone for BulletinBoardReader<B> {
+    fn clone(&self) -> Self { BulletinBoardReader { backend: self.backend.clone() } }
+}
+
+impl <B:BulletinBoardBackend> BulletinBoardReader<B> {
+    /// Make a reader for a shared backend.
+    pub fn new(backend:SharedBackend<B>) -> Self { BulletinBoardReader { backend } }
+
+    /// See [BulletinBoard::get_most_recent_published_root].
+    pub fn get_most_recent_published_root(&self) -> Result<Option<HashValue>,BulletinBoardError> {
+        self.backend.get_most_recent_published_root()
+    }
+
+    /// See [BulletinBoard::get_all_published_roots].
+    pub fn get_all_published_roots(&self) -> Result<Vec<HashValue>,BulletinBoardError> {
+        self.backend.get_all_published_roots()
+    }
+
+    /// See [BulletinBoard::get_parentless_unpublished_hash_values].
+    /// As a reader does not track the current forest, this recomputes it from the backend.
+    pub fn get_parentless_unpublished_hash_values(&self) -> Result<Vec<HashValue>,BulletinBoardError> {
+        let backend = SyncAccess(self.backend.read()?);
+        run_ready(remove_published(&backend,backend.0.compute_current_forest()?.get_subtrees()))
+    }
+
+    /// See [BulletinBoard::get_hash_info].
+    pub fn get_hash_info(&self, query:HashValue) -> Result<HashInfo,BulletinBoardError> {
+        self.backend.get_hash_info(query)?.ok_or(BulletinBoardError::NoSuchHash)
+    }
+
+    /// See [BulletinBoard::get_proof_chain]. The whole chain is computed under one lock, so is consistent.
+    pub fn get_proof_chain(&self,query:HashValue) -> Result<FullProof,BulletinBoardError> {
+        run_ready(get_proof_chain(&SyncAccess(self.backend.read()?),query))
+    }
+
+    /// See [BulletinBoard::first_including_root]. Computed under one lock, so is consistent.
+    pub fn first_including_root(&self,query:HashValue) -> Result<Option<FullProof>,BulletinBoardError> {
+        run_ready(first_including_root_with_proof(&SyncAccess(self.backend.read()?),query))
+    }
+
+    /// See [BulletinBoard::get_receipt_evidence]. Computed under one lock, so is consistent.
+    pub fn get_receipt_evidence(&self,receipt:&SubmissionReceipt) -> Result<ReceiptEvidence,BulletinBoardError> {
+        run_ready(get_receipt_evidence_using(&SyncAccess(self.backend.read()?),receipt))
+    }
+
+    /// See [BulletinBoard::get_ordering_proof]. Computed under one lock, so both proofs are for the same root.
+    pub fn get_ordering_proof(&self,earlier:HashValue,later:HashValue) -> Result<OrderingProof,BulletinBoardError> {
+        run_ready(get_ordering_proof(&SyncAccess(self.backend.read()?),earlier,later))
+    }
+
+    /// See [BulletinBoard::get_ancestry_proof]. Computed under one lock, so is consistent.
+    pub fn get_ancestry_proof(&self,earlier:HashValue,later:HashValue) -> Result<AncestryProof,BulletinBoardError> {
+        run_ready(get_ancestry_proof(&SyncAccess(self.backend.read()?),earlier,later))
+    }
+
+    /// See [BulletinBoard::get_leaf_count_proof]. Computed under one lock, so is consistent.
+    pub fn get_leaf_count_proof(&self,published_root:HashValue) -> Result<LeafCountProof,BulletinBoardError> {
+        run_ready(get_leaf_count_proof(&SyncAccess(self.backend.read()?),published_root))
+    }
+
+    /// See [BulletinBoard::get_leaf_count].
+    pub fn get_leaf_count(&self) -> Result<u64,BulletinBoardError> {
+        self.backend.get_leaf_count()
+    }
+
+    /// See [BulletinBoard::get_leaf_by_index].
+    pub fn get_leaf_by_index(&self,index:u64) -> Result<Option<HashValue>,BulletinBoardError> {
+        self.backend.get_leaf_by_index(index)
+    }
+
+    /// See [BulletinBoard::list_leaves].
+    pub fn list_leaves(&self,start:u64,count:u64) -> Result<Vec<HashValue>,BulletinBoardError> {
+        self.backend.list_leaves(start,count)
+    }
+
+    /// See [BulletinBoard::list_leaves_between].
+    pub fn list_leaves_between(&self,t0:Timestamp,t1:Timestamp) -> Result<Vec<HashValue>,BulletinBoardError> {
+        self.backend.list_leaves_between(t0,t1)
+    }
+
+    /// See [BulletinBoard::get_published_root_at].
+    pub fn get_published_root_at(&self,t:Timestamp) -> Result<Option<HashValue>,BulletinBoardError> {
+        self.backend.get_published_root_at(t)
+    }
+
+    /// See [BulletinBoard::find_leaves_by_data].
+    pub fn find_leaves_by_data(&self,data:&str) -> Result<Vec<HashValue>,BulletinBoardError> {
+        run_ready(find_leaves_by_data_using(&SyncAccess(self.backend.read()?),data))
+    }
+
+    /// Wait until `leaf` is included in a published root, returning its proof chain. `watcher` should come from
+    /// the writer's [BulletinBoard::publication_watcher]. See [PublicationWatcher::wait_for_inclusion].
+    pub fn wait_for_inclusion(&self,watcher:&PublicationWatcher,leaf:HashValue,timeout:Duration) -> Result<FullProof,BulletinBoardError> {
+        watcher.wait_for_inclusion(leaf,timeout,|hash|self.get_proof_chain(hash))
+    }
+}
+
+impl <B:BulletinBoardBackend> BulletinBoard<SharedBackend<B>> {
+    /// Create a new bulletin board whose backend can be shared with [BulletinBoardReader]s obtained from [BulletinBoard::reader].
+    /// The board returned is the writer, and there should only be one.
+    pub fn new_shared(backend:B) -> Result<Self,BulletinBoardError> {
+        BulletinBoard::new(SharedBackend::new(backend))
+    }
+
+    /// Get a read only handle to this bulletin board that can be cloned and used from other threads.
+    pub fn reader(&self) -> BulletinBoardReader<B> {
+        BulletinBoardReader::new(self.backend.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::{BulletinBoard, BulletinBoardError};
+    use crate::backend_memory::BackendMemory;
+    use crate::verifier::verify_proof;
+
+    #[test]
+    /// Test readers on other threads getting proofs while the writer adds leaves and publishes roots.
+    fn test_readers_during_writes() {
+        let mut board = BulletinBoard::new_shared(BackendMemory::default()).unwrap();
+        let first = board.submit_leaf("first").unwrap();
+        board.order_new_published_root().unwrap();
+        let readers : Vec<_> = (0..4).map(|_|{
+            let reader = board.reader();
+            std::thread::spawn(move ||{
+                for _ in 0..100 {
+                    let proof = reader.get_proof_chain(first).unwrap();
+                    let root = proof.published_root.as_ref().unwrap().hash;
+                    assert_eq!(verify_proof("first",root,&proof),None);
+                }
+            })
+        }).collect();
+        for i in 0..100 {
+            board.submit_leaf(&i.to_string()).unwrap();
+            if i%10==0 { board.order_new_published_root().unwrap(); }
+        }
+        for reader in readers { reader.join().unwrap(); }
+        assert_eq!(board.reader().get_parentless_unpublished_hash_values().unwrap(),board.get_parentless_unpublished_hash_values().unwrap());
+    }
+
+    #[test]
+    /// Test a reader on another thread waiting for a leaf to be published by the writer.
+    fn test_wait_for_inclusion() {
+        let mut board = BulletinBoard::new_shared(BackendMemory::default()).unwrap();
+        let leaf = board.submit_leaf("a").unwrap();
+        let reader = board.reader();
+        let watcher = board.publication_watcher();
+        let waiter = std::thread::spawn(move ||reader.wait_for_inclusion(&watcher,leaf,Duration::from_secs(10)));
+        std::thread::sleep(Duration::from_millis(100));
+        board.submit_leaf("b").unwrap(); // does not wake the waiter.
+        let root = board.order_new_published_root().unwrap();
+        let proof = waiter.join().unwrap().unwrap();
+        assert_eq!(verify_proof("a",root,&proof),None);
+        let unpublished = board.submit_leaf("c").unwrap();
+        assert_eq!(board.reader().wait_for_inclusion(&board.publication_watcher(),unpublished,Duration::from_millis(50)).unwrap_err(),BulletinBoardError::InclusionTimeout);
+    }
+}
@@ -0,0 +1,154 @@
+//! Structured leaves, whose fields can be censored individually.
+//!
+//! Censoring a normal leaf removes all of its data. Often only one field of an entry is a problem, so a
+//! structured leaf commits to each of its fields separately, allowing one field to be redacted while the rest
+//! of the entry remains visible and verifiable.
+//!
+//! A structured leaf is a list of named fields. Each field's value is some JSON text. Each field has a commitment
+//!
+//! Field commitment = sha256(4|salt (32 bytes)|name|0|value)
+//!
+//! where the salt is random, so that a redacted field cannot be found by guessing likely values. The leaf hash is
+//!
+//! Hash = sha256(3|timestamp(bigendian 64 bits)|field commitments concatenated, in order)
+//!
+//! A structured leaf is stored as the leaf's data, being [STRUCTURED_LEAF_PREFIX] followed by the JSON encoding
+//! of the [StructuredLeaf]. A redacted field is stored as just its name and commitment, so the leaf's hash can
+//! still be computed. The leaf is marked as structured (see [crate::hash_history::LeafHashHistory::structured]), and
+//! only marked leaves are hashed as above; a plain leaf whose text happens to start with the prefix, such as one
+//! submitted before structured leaves existed, is hashed as a plain leaf. To avoid confusing people reading the
+//! board, new ordinary submissions starting with that prefix are rejected.
+
+use serde::{Serialize,Deserialize};
+use sha2::{Sha256, Digest};
+use crate::BulletinBoardError;
+use crate::hash::HashValue;
+use crate::hash_history::Timestamp;
+
+/// The start of the data of a structured leaf.
+pub const STRUCTURED_LEAF_PREFIX : &str = "bulletin-board-structured-leaf:";
+
+/// A leaf whose fields are committed to individually. See [crate::structured_leaf].
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub struct StructuredLeaf {
+    /// The fields, in the order they are hashed.
+    pub fields : Vec<StructuredField>,
+}
+
+/// One field of a [StructuredLeaf].
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+pub struct StructuredField {
+    /// The name of the field. May not contain a zero character.
+    pub name : String,
+    /// The value, or the commitment to it if it has been redacted.
+    #[serde(flatten)]
+    pub content : StructuredFieldContent,
+}
+
+/// The content of a [StructuredField].
+#[derive(Debug,Clone,Serialize,Deserialize,Eq,PartialEq)]
+#[serde(untagged)]
+pub enum StructuredFieldContent {
+    /// A field whose value is visible.
+    Visible {
+        /// 32 random bytes, (de)serialized the same way as a hash value.
+        salt : HashValue,
+        /// The JSON text of the value.
+        value : String,
+    },
+    /// A field whose value has been censored, leaving just the commitment to it.
+    Redacted {
+        commitment : HashValue,
+    },
+}
+
+impl StructuredField {
+    /// Field commitment = sha256(4|salt|name|0|value), or the stored commitment if redacted.
+    pub fn commitment(&self) -> HashValue {
+        match &self.content {
+            StructuredFieldContent::Visible { salt, value } => {
+                let mut hasher = Sha256::default();
+                hasher.update([4]);
+                hasher.update(salt.0);
+                hasher.update(self.name.as_bytes());
+                hasher.update([0]);
+                hasher.update(value.as_bytes());
+                HashValue(<[u8; 32]>::from(hasher.finalize()))
+            }
+            StructuredFieldContent::Redacted { commitment } => *commitment,
+        }
+    }
+}
+
+impl StructuredLeaf {
+    /// Make a structured leaf from the fields of a JSON object, with a random salt for each.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use merkle_tree_bulletin_board::structured_leaf::StructuredLeaf;
+    ///
+    /// let object = serde_json::json!({"name":"Fred","address":"123 Example Street"});
+    /// let leaf = StructuredLeaf::new(object.as_object().unwrap()).unwrap();
+    /// assert_eq!(leaf.visible_value("name"),Some("\"Fred\""));
+    /// let hash = leaf.compute_hash(1000);
+    /// let redacted = leaf.redact("address").unwrap();
+    /// assert_eq!(redacted.visible_value("address"),None);
+    /// assert_eq!(redacted.compute_hash(1000),hash);
+    /// assert_eq!(StructuredLeaf::from_leaf_data(&redacted.to_leaf_data()),Some(redacted));
+    /// ```
+    pub fn new(object:&serde_json::Map<String,serde_json::Value>) -> Result<Self,BulletinBoardError> {
+        let mut fields = vec![];
+        for (name,value) in object {
+            if name.contains('\0') { return Err(BulletinBoardError::InvalidStructuredLeaf(format!("Field name {:?} contains a zero character",name))); }
+            let mut salt = [0u8;32];
+            getrandom::getrandom(&mut salt).map_err(|_|BulletinBoardError::RandomNumberGeneratorUnavailable)?;
+            let value = serde_json::to_string(value).map_err(|e|BulletinBoardError::InvalidStructuredLeaf(e.to_string()))?;
+            fields.push(StructuredField{ name: name.clone(), content: StructuredFieldContent::Visible { salt: HashValue(salt), value } });
+        }
+        Ok(StructuredLeaf{ fields })
+    }
+
+    /// The data for the leaf containing this structure.
+    pub fn to_leaf_data(&self) -> String {
+        STRUCTURED_LEAF_PREFIX.to_string()+&serde_json::to_string(self).unwrap()
+    }
+
+    /// Get the structure from the data for a leaf, if it is a structured leaf.
+    pub fn from_leaf_data(data:&str) -> Option<Self> {
+        serde_json::from_str(data.strip_prefix(STRUCTURED_LEAF_PREFIX)?).ok()
+    }
+
+    /// Hash = sha256(3|timestamp(bigendian 64 bits)|field commitments concatenated)
+    pub fn compute_hash(&self,timestamp:Timestamp) -> HashValue {
+        let mut hasher = Sha256::default();
+        hasher.update([3]);
+        hasher.update(timestamp.to_be_bytes());
+        for field in &self.fields {
+            hasher.update(field.commitment().0);
+        }
+        HashValue(<[u8; 32]>::from(hasher.finalize()))
+    }
+
+    /// The JSON text of the value of a field, if it exists and is visible.
+    pub fn visible_value(&self,name:&str) -> Option<&str> {
+        self.fields.iter().find_map(|f| match &f.content {
+            StructuredFieldContent::Visible { value, .. } if f.name==name => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Whether any field is redacted.
+    pub fn has_redactions(&self) -> bool {
+        self.fields.iter().any(|f|matches!(f.content,StructuredFieldContent::Redacted{..}))
+    }
+
+    /// A copy of this with the named field replaced by its commitment. The hash is unchanged.
+    pub fn redact(&self,name:&str) -> Result<Self,BulletinBoardError> {
+        if self.visible_value(name).is_none() { return Err(BulletinBoardError::NoSuchVisibleField(name.to_string())); }
+        let fields = self.fields.iter().map(|f| if f.name==name {
+            StructuredField{ name: f.name.clone(), content: StructuredFieldContent::Redacted { commitment: f.commitment() } }
+        } else { f.clone() }).collect();
+        Ok(StructuredLeaf{ fields })
+    }
+}
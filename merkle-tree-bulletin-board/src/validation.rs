@@ -0,0 +1,205 @@
+//! Checks on submissions, done before they are added to the board.
+//!
+//! Register a [SubmissionValidator] with [crate::BulletinBoard::add_validator]. Each submission is checked by every
+//! registered validator, in the order they were added, before its hash is computed. If any validator rejects
+//! it, the submission fails with [BulletinBoardError::Rejected] and nothing is added to the board.
+//!
+//! For [crate::BulletinBoard::submit_structured_leaf], the validators are given the JSON text of the submitted object.
+//! Censorship records generated by the board itself are not validated.
+//!
+//! Any function or closure `Fn(&str) -> Result<(),String>` can be used as a validator, and there are some
+//! built in validators: [MaxLength], [CharacterSet] and [JsonObjectSchema].
+
+use std::collections::HashSet;
+use crate::BulletinBoardError;
+
+/// A check on data submitted to the bulletin board.
+pub trait SubmissionValidator : Send + Sync {
+    /// Check data to be submitted. Return Err with a human readable reason if it should be rejected.
+    fn validate(&self,data:&str) -> Result<(),String>;
+}
+
+impl <F:Fn(&str)->Result<(),String>+Send+Sync> SubmissionValidator for F {
+    fn validate(&self, data: &str) -> Result<(), String> { self(data) }
+}
+
+/// Run all the validators on some data, returning the first rejection.
+pub(crate) fn validate_submission(validators:&[Box<dyn SubmissionValidator>],data:&str) -> Result<(),BulletinBoardError> {
+    for validator in validators {
+        validator.validate(data).map_err(BulletinBoardError::Rejected)?;
+    }
+    Ok(())
+}
+
+/// Reject submissions longer than a given number of bytes.
+///
+/// # Example
+///
+/// ```
+/// use merkle_tree_bulletin_board::validation::MaxLength;
+/// use merkle_tree_bulletin_board::BulletinBoardError;
+///
+/// let mut board = merkle_tree_bulletin_board::BulletinBoard::new(
+///     merkle_tree_bulletin_board::backend_memory::BackendMemory::default()).unwrap();
+/// board.add_validator(MaxLength(5));
+/// assert!(board.submit_leaf("short").is_ok());
+/// assert_eq!(board.submit_leaf("too long"),Err(BulletinBoardError::Rejected("Submission is 8 bytes long; the maximum is 5".to_string())));
+/// assert_eq!(board.get_parentless_unpublished_hash_values().unwrap().len(),1);
+/// ```
+pub struct MaxLength(pub usize);
+
+impl SubmissionValidator for MaxLength {
+    fn validate(&self, data: &str) -> Result<(), String> {
+        if data.len()>self.0 { Err(format!("Submission is {} bytes long; the maximum is {}",data.len(),self.0)) } else { Ok(()) }
+    }
+}
+
+/// Reject submissions containing characters outside a given set.
+///
+/// # Example
+///
+/// ```
+/// use merkle_tree_bulletin_board::validation::{CharacterSet, SubmissionValidator};
+///
+/// assert!(CharacterSet::PrintableAscii.validate("Hello world!").is_ok());
+/// assert!(CharacterSet::PrintableAscii.validate("Hello\nworld!").is_err());
+/// assert!(CharacterSet::NoControlCharacters.validate("Grüß Gott\nand hello").is_ok());
+/// assert!(CharacterSet::NoControlCharacters.validate("Ding\u{7}").is_err());
+/// ```
+#[derive(Debug,Clone,Copy,Eq,PartialEq)]
+pub enum CharacterSet {
+    /// Only ASCII characters.
+    Ascii,
+    /// Only ASCII characters that are printable (including space), so no newlines, tabs etc.
+    PrintableAscii,
+    /// Any unicode character other than control characters, except newlines and tabs are allowed.
+    NoControlCharacters,
+}
+
+impl CharacterSet {
+    /// Whether a character is in this set.
+    pub fn allows(&self,c:char) -> bool {
+        match self {
+            CharacterSet::Ascii => c.is_ascii(),
+            CharacterSet::PrintableAscii => c.is_ascii() && !c.is_ascii_control(),
+            CharacterSet::NoControlCharacters => c=='\n' || c=='\t' || !c.is_control(),
+        }
+    }
+}
+
+impl SubmissionValidator for CharacterSet {
+    fn validate(&self, data: &str) -> Result<(), String> {
+        match data.char_indices().find(|(_,c)|!self.allows(*c)) {
+            Some((position,c)) => Err(format!("Submission contains disallowed character {:?} at position {}",c,position)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Require submissions to be a JSON object, with some fields required and optionally others allowed.
+///
+/// # Example
+///
+/// ```
+/// use merkle_tree_bulletin_board::validation::{JsonObjectSchema, SubmissionValidator};
+///
+/// let schema = JsonObjectSchema::new(&["question"],&["asked_by"],false);
+/// assert!(schema.validate(r#"{"question":"Why?"}"#).is_ok());
+/// assert!(schema.validate(r#"{"question":"Why?","asked_by":"Fred"}"#).is_ok());
+/// assert!(schema.validate(r#"{"asked_by":"Fred"}"#).is_err()); // missing required field
+/// assert!(schema.validate(r#"{"question":"Why?","colour":"blue"}"#).is_err()); // unexpected field
+/// assert!(schema.validate(r#"["question"]"#).is_err()); // not an object
+/// assert!(schema.validate("question").is_err()); // not JSON
+/// ```
+pub struct JsonObjectSchema {
+    /// Fields that must be present.
+    pub required_fields : HashSet<String>,
+    /// Fields that may be present.
+    pub optional_fields : HashSet<String>,
+    /// If true, fields other than the required and optional fields may be present.
+    pub other_fields_allowed : bool,
+}
+
+impl JsonObjectSchema {
+    pub fn new(required_fields:&[&str],optional_fields:&[&str],other_fields_allowed:bool) -> Self {
+        JsonObjectSchema{
+            required_fields: required_fields.iter().map(|s|s.to_string()).collect(),
+            optional_fields: optional_fields.iter().map(|s|s.to_string()).collect(),
+            other_fields_allowed,
+        }
+    }
+}
+
+impl SubmissionValidator for JsonObjectSchema {
+    fn validate(&self, data: &str) -> Result<(), String> {
+        let object = match serde_json::from_str::<serde_json::Value>(data) {
+            Ok(serde_json::Value::Object(object)) => object,
+            Ok(_) => return Err("Submission is not a JSON object".to_string()),
+            Err(e) => return Err(format!("Submission is not valid JSON : {}",e)),
+        };
+        if let Some(missing) = self.required_fields.iter().find(|f|!object.contains_key(*f)) { return Err(format!("Submission is missing required field {}",missing)); }
+        if !self.other_fields_allowed {
+            if let Some(unexpected) = object.keys().find(|f|!(self.required_fields.contains(*f)||self.optional_fields.contains(*f))) { return Err(format!("Submission has unexpected field {}",unexpected)); }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use crate::{BulletinBoard, BulletinBoardBackend, BulletinBoardError};
+    use crate::backend_memory::BackendMemory;
+    use crate::content_index::content_hash;
+    use crate::hash_history::{HashSource, LeafHashHistory};
+    use crate::validation::MaxLength;
+
+    #[test]
+    /// Test that a rejected submission changes nothing: not the forest, the backend, or the content and idempotency indexes.
+    fn test_rejection_changes_nothing() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        board.submit_leaf("a").unwrap();
+        board.submit_leaf("b").unwrap();
+        board.order_new_published_root().unwrap();
+        board.submit_leaf("c").unwrap();
+        board.add_validator(MaxLength(5));
+        let forest = board.state.current_forest.as_ref().unwrap().get_subtrees();
+        let parentless = board.backend.get_all_leaves_and_branches_without_a_parent().unwrap();
+        let roots = board.get_all_published_roots().unwrap();
+        let rejected = "too long";
+        let rejection = Err(BulletinBoardError::Rejected("Submission is 8 bytes long; the maximum is 5".to_string()));
+        assert_eq!(board.submit_leaf(rejected),rejection);
+        assert_eq!(board.submit_leaf_with_idempotency_key(rejected,"key"),rejection);
+        let object = serde_json::json!({"too":"long"});
+        assert!(matches!(board.submit_structured_leaf(object.as_object().unwrap()),Err(BulletinBoardError::Rejected(_))));
+        assert_eq!(board.state.current_forest.as_ref().unwrap().get_subtrees(),forest);
+        assert_eq!(board.backend.get_all_leaves_and_branches_without_a_parent().unwrap(),parentless);
+        assert_eq!(board.get_all_published_roots().unwrap(),roots);
+        assert_eq!(board.backend.get_leaves_with_content_hash(content_hash(rejected)).unwrap(),vec![]);
+        assert_eq!(board.backend.get_leaf_for_idempotency_key("key").unwrap(),None);
+        assert_eq!(board.find_leaves_by_data(rejected).unwrap(),vec![]);
+        assert_eq!(board.backend.get_leaf_count().unwrap(),3);
+    }
+
+    #[test]
+    /// Test that validators are run in order, see structured leaves as JSON, and are not run on censorship records.
+    fn test_what_is_validated() {
+        let mut board = BulletinBoard::new(BackendMemory::default()).unwrap();
+        let leaf = board.submit_leaf("a").unwrap();
+        let object = serde_json::json!({"name":"Fred","address":"123 Example Street"});
+        let structured = board.submit_structured_leaf(object.as_object().unwrap()).unwrap();
+        let seen = Arc::new(Mutex::new(vec![]));
+        let seen_by_validator = seen.clone();
+        board.add_validator(move |data:&str| { seen_by_validator.lock().unwrap().push(data.to_string()); Err("first".to_string()) });
+        board.add_validator(|_:&str| Err("second".to_string()));
+        assert_eq!(board.submit_leaf("b"),Err(BulletinBoardError::Rejected("first".to_string())));
+        assert!(board.submit_structured_leaf(object.as_object().unwrap()).is_err());
+        let record_leaf = board.censor_leaf(leaf,"Spam","Moderator").unwrap();
+        let redaction_record_leaf = board.redact_field(structured,"address","Privacy","Moderator").unwrap();
+        let uncensor_record_leaf = board.uncensor_leaf(leaf,"a","Not spam","Appeals panel").unwrap();
+        assert_eq!(*seen.lock().unwrap(),vec!["b".to_string(),serde_json::to_string(&object).unwrap()]);
+        for record in [record_leaf,redaction_record_leaf,uncensor_record_leaf] {
+            assert!(matches!(board.get_hash_info(record).unwrap().source,HashSource::Leaf(LeafHashHistory{data:Some(_),..})));
+        }
+    }
+}
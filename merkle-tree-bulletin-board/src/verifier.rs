@@ -4,7 +4,7 @@
 
 
 use crate::hash::HashValue;
-use crate::hash_history::{FullProof, HashSource, HashInfoWithHash, LeafHashHistory};
+use crate::hash_history::{FullProof, HashSource, HashInfoWithHash, LeafHashHistory, LeafCountProof, OrderingProof, AncestryProof};
 use std::path::Path;
 use crate::backend_flatfile::TransactionIterator;
 use std::fs::File;
@@ -28,6 +28,8 @@ use std::fs::File;
 ///    This means that if you were the one censored, you can at least check that your particular node was included, and that the
 ///    fact that censorship occurred is recorded, and that your censorship is not conflated with someone else's censorship
 ///    which would otherwise allow hiding the number of censored items.
+///  * Redacting fields of a structured leaf does not change its hash, so the proof can be checked with either the
+///    original data or the redacted data in the proof.
 ///
 /// See [bulk_verify_between_two_consecutive_published_roots] for bulk verification.
 ///
@@ -64,16 +66,31 @@ pub fn verify_proof(data_to_be_proven:&str,published_root:HashValue,proof:&FullP
     match &proof.chain[0].source {
         HashSource::Leaf(history) => {
             if let Some(history_data) = &history.data { // leaf is not censored.
-                if history_data!=data_to_be_proven  { return Some("The proof is not for the provided data".to_string()); }
-                if proof.chain[0].hash!=history.compute_hash().unwrap() { return Some("Leaf information in the proof chain does not hash to the correct value".to_string()); }
+                if history_data!=data_to_be_proven  {
+                    // a structured leaf may have had fields redacted. Check the provided data has the same hash.
+                    let provided = LeafHashHistory{data:Some(data_to_be_proven.to_string()) , ..history.clone() };
+                    if !(history.structured && provided.compute_hash()==Some(proof.chain[0].hash)) { return Some("The proof is not for the provided data".to_string()); }
+                }
+                if Some(proof.chain[0].hash)!=history.compute_hash() { return Some("Leaf information in the proof chain does not hash to the correct value".to_string()); }
             } else { // the leaf is censored. Need to compute hash using provided data.
-                let uncensored = LeafHashHistory{data:Some(data_to_be_proven.to_string()) , timestamp: history.timestamp };
-                if proof.chain[0].hash!=uncensored.compute_hash().unwrap() { return Some("Leaf information in the proof chain does not hash to the correct value even with the censorship undone by the provided data".to_string()); }
+                let uncensored = LeafHashHistory{data:Some(data_to_be_proven.to_string()) , ..history.clone() };
+                if Some(proof.chain[0].hash)!=uncensored.compute_hash() { return Some("Leaf information in the proof chain does not hash to the correct value even with the censorship undone by the provided data".to_string()); }
             }
         }
         _ => { return Some("First element in the proof chain is not actually a leaf".to_string()); }
     }
-    // check that each intermediate element in the proof chain is a branch and valid. Already checked element 0 above.
+    if let Some(problem) = verify_branches(proof) { return Some(problem); }
+    if let Some(problem) = verify_leaf_index(proof) { return Some(problem); }
+    // check that the root in the proof is the root we heard of
+    if proof.published_root.is_none() { return Some("No root information provided in the proof".to_string()); }
+    let published_root_info = proof.published_root.as_ref().unwrap();
+    if published_root_info.hash!=published_root { return Some("Root information in the proof is not for the desired root".to_string()); }
+    verify_published_root_in_proof(proof)
+}
+
+/// Check that each element of the proof chain after the first is a validly hashed branch referencing the prior element.
+/// Returns None if OK, otherwise a description of the problem.
+pub(crate) fn verify_branches(proof:&FullProof) -> Option<String> {
     for i in 1..proof.chain.len() {
         match &proof.chain[i].source {
             HashSource::Branch(history) => {
@@ -84,10 +101,123 @@ pub fn verify_proof(data_to_be_proven:&str,published_root:HashValue,proof:&FullP
             _ => { return Some("First element in the proof chain is not actually a leaf".to_string()); }
         }
     }
-    // check that the root in the proof is the root we heard of
-    if proof.published_root.is_none() { return Some("No root information provided in the proof".to_string()); }
-    let published_root_info = proof.published_root.as_ref().unwrap();
-    if published_root_info.hash!=published_root { return Some("Root information in the proof is not for the desired root".to_string()); }
+    None
+}
+
+/// Check that a provided *proof* shows how many leaves are covered by published_root, returning the number of leaves,
+/// or a string describing the problem.
+///
+/// Each path must be a validly hashed chain of branches from an element of the root down to an uncensored leaf whose data
+/// hashes to its hash, and the elements must be in decreasing order of depth, as is done by [crate::BulletinBoard].
+/// The number of leaves is then the sum of 2^depth over the elements.
+///
+/// This relies on each element being a perfect binary tree, with every path from it to a leaf the same length. This
+/// can't be shown by a short proof, but is checked by anyone who verifies all the branches in the board.
+///
+/// See [crate::BulletinBoard::get_leaf_count_proof] for an example.
+pub fn verify_leaf_count(published_root:HashValue,proof:&LeafCountProof) -> Result<u64,String> {
+    if proof.published_root.hash!=published_root { return Err("Root information in the proof is not for the desired root".to_string()); }
+    let HashSource::Root(history) = &proof.published_root.source else { return Err("Root information in the proof is not actually a root".to_string()) };
+    if published_root!=history.compute_hash() { return Err("Root information in the proof does not hash to the correct value".to_string()); }
+    if proof.paths.len()!=history.elements.len() { return Err("The proof does not have one path for each element of the root".to_string()); }
+    let mut count = 0u64;
+    let mut previous_depth : Option<usize> = None;
+    for (element,path) in history.elements.iter().zip(proof.paths.iter()) {
+        if path.first().map(|e|e.hash)!=Some(*element) { return Err(format!("The path for element {} does not start with it",element)); }
+        for i in 0..path.len()-1 {
+            match &path[i].source {
+                HashSource::Branch(branch) => {
+                    if path[i].hash!=branch.compute_hash() { return Err(format!("Branch {} in the path for element {} does not hash to the correct value",path[i].hash,element)); }
+                    if branch.left!=path[i+1].hash && branch.right!=path[i+1].hash { return Err(format!("Branch {} in the path for element {} does not reference the next entry",path[i].hash,element)); }
+                }
+                _ => return Err(format!("Entry {} in the path for element {} is not a branch",path[i].hash,element)),
+            }
+        }
+        let leaf = path.last().unwrap();
+        match &leaf.source {
+            HashSource::Leaf(history) if history.data.is_some() => {
+                if history.compute_hash()!=Some(leaf.hash) { return Err(format!("The leaf {} at the end of the path for element {} does not hash to the correct value",leaf.hash,element)); }
+            }
+            _ => return Err(format!("The path for element {} does not end in an uncensored leaf",element)),
+        }
+        let depth = path.len()-1;
+        if previous_depth.map(|d|d<=depth).unwrap_or(false) { return Err("The elements of the root are not in decreasing order of depth".to_string()); }
+        previous_depth = Some(depth);
+        count += 1u64.checked_shl(depth as u32).ok_or_else(||format!("Element {} is too deep",element))?;
+    }
+    Ok(count)
+}
+
+/// Check that a provided *proof* shows that the leaf with hash `earlier` was added to the board before the leaf with hash `later`,
+/// both being included in published_root.
+///
+/// Returns None if the proof is OK, otherwise returns a string describing the problem.
+///
+/// Each proof chain is checked as in [verify_proof], except that as the leaf hashes are given the data is not needed.
+/// The earlier leaf must then either be in an earlier element of the published root, or in the same element with a smaller
+/// [FullProof::path_position]. This relies on the left side of each branch predating the right side, as documented on
+/// [crate::hash_history::BranchHashHistory], which could only fail in the event of a hash collision.
+///
+/// See [crate::BulletinBoard::get_ordering_proof] for an example.
+pub fn verify_ordering_proof(published_root:HashValue,earlier:HashValue,later:HashValue,proof:&OrderingProof) -> Option<String> {
+    for (name,leaf,proof) in [("earlier",earlier,&proof.earlier),("later",later,&proof.later)] {
+        if proof.chain.first().map(|e|e.hash)!=Some(leaf) { return Some(format!("The {} proof is not for the {} leaf",name,name)); }
+        match &proof.chain[0].source {
+            HashSource::Leaf(history) => if history.data.is_some() && history.compute_hash()!=Some(leaf) { return Some(format!("The {} leaf does not hash to the correct value",name)); }
+            _ => return Some(format!("The {} proof does not start with a leaf",name)),
+        }
+        if let Some(problem) = verify_branches(proof) { return Some(problem); }
+        if proof.published_root.as_ref().map(|r|r.hash)!=Some(published_root) { return Some(format!("The {} proof is not for the desired root",name)); }
+        if let Some(problem) = verify_published_root_in_proof(proof) { return Some(problem); }
+    }
+    match (proof.earlier.position_in_published_root(),proof.later.position_in_published_root()) {
+        (Some((earlier_element,earlier_position)),Some((later_element,later_position))) => {
+            if earlier_element==later_element && proof.earlier.chain.len()!=proof.later.chain.len() { return Some("The proofs have different depths in the same tree".to_string()); }
+            if (earlier_element,earlier_position)>=(later_element,later_position) { return Some("The earlier leaf is not before the later leaf".to_string()); }
+            None
+        }
+        _ => Some("A proof chain is not a path up the tree to the published root".to_string()),
+    }
+}
+
+/// Check that a provided *proof* shows that the published root `earlier` was published before, or is, the published root `later`,
+/// that is, that `earlier` can be reached from `later` by following prior and skip references.
+///
+/// Returns None if the proof is OK, otherwise returns a string describing the problem.
+///
+/// Each root in the chain is checked to hash to its claimed value, using the format with skips where it has them
+/// (see [crate::hash_history::RootHashHistory]), and to reference the next root in the chain.
+///
+/// See [crate::BulletinBoard::get_ancestry_proof] for an example.
+pub fn verify_ancestry_proof(earlier:HashValue,later:HashValue,proof:&AncestryProof) -> Option<String> {
+    if proof.chain.first().map(|e|e.hash)!=Some(later) { return Some("The proof does not start with the later root".to_string()); }
+    if proof.chain.last().map(|e|e.hash)!=Some(earlier) { return Some("The proof does not end with the earlier root".to_string()); }
+    for (i,root) in proof.chain.iter().enumerate() {
+        let HashSource::Root(history) = &root.source else { return Some(format!("{} in the proof is not a published root",root.hash)) };
+        if history.compute_hash()!=root.hash { return Some(format!("The root {} does not hash to the correct value",root.hash)); }
+        if let Some(next) = proof.chain.get(i+1) {
+            if !history.references(next.hash) { return Some(format!("The root {} does not reference {}",root.hash,next.hash)); }
+        }
+    }
+    None
+}
+
+/// Check that the leaf index in the proof, if any, agrees with the leaf's position in the tree at the end of the chain.
+/// Only the low bits can be checked, as the number of leaves in earlier trees is not in the proof.
+/// Returns None if OK, otherwise a description of the problem.
+pub(crate) fn verify_leaf_index(proof:&FullProof) -> Option<String> {
+    let leaf_index = proof.leaf_index?; // nothing to check if there is no leaf index.
+    let Some(position) = proof.path_position() else { return Some("The proof chain is not a path up the tree".to_string()) };
+    let mask = 1u64.checked_shl(proof.chain.len().saturating_sub(1) as u32).map(|size|size-1).unwrap_or(u64::MAX);
+    if leaf_index&mask!=position { return Some("The leaf index in the proof does not agree with its position in the tree".to_string()); }
+    None
+}
+
+/// Check that the published root in the proof, if any, is validly hashed and contains the last element of the chain.
+/// Returns None if OK, otherwise a description of the problem.
+pub(crate) fn verify_published_root_in_proof(proof:&FullProof) -> Option<String> {
+    let Some(published_root_info) = &proof.published_root else { return None };
+    if proof.chain.is_empty()  { return Some("No hash chain in the proof".to_string()); }
     match &published_root_info.source {
         HashSource::Root(history) => {
             if published_root_info.hash!=history.compute_hash() { return Some("Root information in the proof does not hash to the correct value".to_string()); }